@@ -1,7 +1,8 @@
 use std::collections::{HashMap, VecDeque};
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use binance::config::Config;
 use binance::futures::account::FuturesAccount;
@@ -16,11 +17,28 @@ use binance::model::{
 use binance::{api::Binance, futures::websockets::*, general::General};
 use bybit::model::{Category, FastExecData, WsTrade};
 use tokio::sync::mpsc;
+use tungstenite::stream::MaybeTlsStream;
 
+use crate::util::heartbeat::{watch_blocking, Heartbeat, HEARTBEAT_TIMEOUT_MS};
 use crate::util::localorderbook::{LocalBook, ProcessAsks, ProcessBids};
 
 use super::exchange::{PrivateData, ProcessTrade, TaggedPrivate};
-#[derive(Clone, Debug)]
+
+// Number of attempts at fetching the instrument info of a symbol before giving up on it.
+const INSTRUMENT_FETCH_ATTEMPTS: u32 = 4;
+// Delay in milliseconds before the first retry, doubled on each following retry.
+const INSTRUMENT_RETRY_MS: u64 = 1_000;
+/// Depths of the partial book streams, the diff depth stream of the full book is always subscribed.
+pub const BINANCE_BOOK_DEPTHS: [usize; 3] = [5, 10, 20];
+// Delay in milliseconds before reconnecting a stream, doubled after each failed connection.
+const RECONNECT_BASE_MS: u64 = 600;
+// Time in milliseconds a connection has to stay up for the reconnect delay to go back to its base.
+const RECONNECT_RESET_MS: u64 = 60_000;
+// Base URLs of the USD-M futures streams on the mainnet and the testnet.
+const BINANCE_STREAM: &str = "wss://fstream.binance.com";
+const BINANCE_TESTNET_STREAM: &str = "wss://stream.binancefuture.com";
+
+#[derive(Clone, Debug, Default)]
 pub struct BinanceMarket {
     pub time: u64,
     pub books: Vec<(String, LocalBook)>,
@@ -33,19 +51,6 @@ pub struct BinanceMarket {
 unsafe impl Send for BinanceMarket {}
 unsafe impl Sync for BinanceMarket {}
 
-impl Default for BinanceMarket {
-    fn default() -> Self {
-        Self {
-            time: 0,
-            books: Vec::new(),
-            klines: Vec::new(),
-            trades: Vec::new(),
-            tickers: Vec::new(),
-            liquidations: Vec::new(),
-        }
-    }
-}
-
 #[derive(Clone, Debug)]
 pub struct BinancePrivate {
     pub time: u64,
@@ -70,76 +75,188 @@ impl Default for BinancePrivate {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// The delay before reconnecting a stream, doubled after each failed connection and reset to its
+/// base once a connection stayed up long enough.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectDelay {
+    base_ms: u64,
+    reset_after_ms: u64,
+    delay_ms: u64,
+}
+
+impl ReconnectDelay {
+    pub fn new(base_ms: u64, reset_after_ms: u64) -> Self {
+        Self {
+            base_ms,
+            reset_after_ms,
+            delay_ms: base_ms,
+        }
+    }
+
+    /// Returns the delay in milliseconds to wait after a connection that stayed up for
+    /// `uptime_ms` failed, and doubles it for the next failure.
+    pub fn next(&mut self, uptime_ms: u64) -> u64 {
+        if uptime_ms >= self.reset_after_ms {
+            self.delay_ms = self.base_ms;
+        }
+        let delay = self.delay_ms;
+        self.delay_ms = self.delay_ms.saturating_mul(2);
+        delay
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct BinanceClient {
     pub key: String,
     pub secret: String,
+    pub testnet: bool,
+    // Time in milliseconds a stream has to stay connected for its reconnect delay to be reset.
+    pub reconnect_reset_ms: Option<u64>,
+    // Time in milliseconds without market data after which the market stream is reconnected.
+    pub heartbeat_timeout_ms: Option<u64>,
 }
 
-impl Default for BinanceClient {
-    fn default() -> Self {
+impl BinanceClient {
+    /// Creates a client for the account of the API key, on the testnet if `testnet` is set.
+    pub fn init(key: String, secret: String, testnet: bool) -> Self {
         Self {
-            key: String::new(),
-            secret: String::new(),
+            key,
+            secret,
+            testnet,
+            reconnect_reset_ms: None,
+            heartbeat_timeout_ms: None,
         }
     }
-}
 
-impl BinanceClient {
-    pub fn init(key: String, secret: String) -> Self {
-        Self { key, secret }
+    /// Reconnects the market stream once it delivered no data for `timeout_ms`, `None`
+    /// reconnects it after 30 seconds.
+    pub fn with_heartbeat_timeout(mut self, timeout_ms: Option<u64>) -> Self {
+        self.heartbeat_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Resets the reconnect delay of the streams once they stayed connected for `reset_ms`,
+    /// `None` resets it after a minute.
+    pub fn with_reconnect_reset(mut self, reset_ms: Option<u64>) -> Self {
+        self.reconnect_reset_ms = reset_ms;
+        self
+    }
+
+    /// Returns the reconnect delay of a new stream.
+    pub fn reconnect_delay(&self) -> ReconnectDelay {
+        ReconnectDelay::new(
+            RECONNECT_BASE_MS,
+            self.reconnect_reset_ms.unwrap_or(RECONNECT_RESET_MS),
+        )
+    }
+
+    /// Returns the REST endpoints of the mainnet or the testnet.
+    pub fn config(&self) -> Config {
+        if self.testnet {
+            Config::testnet()
+        } else {
+            Config::default()
+        }
+    }
+
+    /// Returns the base URL of the USD-M futures streams.
+    fn stream_base(&self) -> &'static str {
+        if self.testnet {
+            BINANCE_TESTNET_STREAM
+        } else {
+            BINANCE_STREAM
+        }
+    }
+
+    /// Returns the URL of the combined stream of the given streams.
+    pub fn stream_url(&self, streams: &[String]) -> String {
+        format!(
+            "{}/stream?streams={}",
+            self.stream_base(),
+            streams.join("/")
+        )
+    }
+
+    /// Returns the URL of the user data stream of the listen key.
+    pub fn user_stream_url(&self, listen_key: &str) -> String {
+        format!("{}/ws/{}", self.stream_base(), listen_key)
+    }
+
+    /// Binance only cancels the orders of a symbol after a countdown that has to be refreshed
+    /// continuously, and the client has no endpoint for it, so this always fails.
+    #[allow(clippy::result_large_err)]
+    pub fn enable_cancel_on_disconnect(
+        &self,
+        _time_window_secs: u64,
+    ) -> binance::errors::Result<()> {
+        Err("Cancel on disconnect is not supported on binance".into())
     }
     pub fn exchange_time(&self) -> u64 {
-        let general: General = Binance::new(None, None);
+        let general: General = Binance::new_with_config(None, None, &self.config());
         match general.get_server_time() {
             Ok(v) => v.server_time,
             Err(_) => 0,
         }
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn market_subscribe(
         &self,
         symbol: Vec<String>,
+        depths: Vec<usize>,
         sender: mpsc::UnboundedSender<BinanceMarket>,
     ) {
-        let mut delay = 600;
-        let keep_running = AtomicBool::new(true);
-        let request = bin_build_requests(&symbol);
+        let mut delay = self.reconnect_delay();
+        // Stop the event loop to reconnect once the stream stays open but delivers no data.
+        let timeout_ms = self.heartbeat_timeout_ms.unwrap_or(HEARTBEAT_TIMEOUT_MS);
+        let heartbeat = Heartbeat::new();
+        let keep_running = Arc::new(AtomicBool::new(true));
+        watch_blocking(heartbeat.clone(), keep_running.clone(), timeout_ms);
+        let request = bin_build_requests(&symbol, &depths);
 
-        let mut market_data = BinanceMarket::default();
-        market_data.books = symbol
-            .iter()
-            .map(|s| (s.to_string(), LocalBook::new()))
-            .collect::<Vec<(String, LocalBook)>>();
+        let mut market_data = BinanceMarket {
+            books: symbol
+                .iter()
+                .map(|s| (s.to_string(), LocalBook::new()))
+                .collect::<Vec<(String, LocalBook)>>(),
+            ..Default::default()
+        };
         for (s, b) in &mut market_data.books {
-            let cl_symbol = format!("{}", s);
-            let cl: FuturesGeneral = Binance::new(None, None);
-            match cl.get_symbol_info(cl_symbol) {
-                Ok(v) => {
+            for attempt in 0..INSTRUMENT_FETCH_ATTEMPTS {
+                if attempt > 0 {
+                    // Back off exponentially between the attempts.
+                    thread::sleep(Duration::from_millis(INSTRUMENT_RETRY_MS << (attempt - 1)));
+                }
+                let cl_symbol = s.to_string();
+                let cl: FuturesGeneral = Binance::new_with_config(None, None, &self.config());
+                if let Ok(v) = cl.get_symbol_info(cl_symbol) {
                     let price_filter = match &v.filters[0] {
                         PriceFilter { tick_size, .. } => tick_size.parse().unwrap_or(0.0),
                         _ => 0.0,
                     };
                     b.tick_size = price_filter;
-                    b.min_order_size = {
-                        match &v.filters[1] {
-                            binance::model::Filters::LotSize { min_qty, .. } => {
-                                min_qty.parse().unwrap_or(0.0)
-                            }
-                            _ => 0.0,
-                        }
-                    };
+                    if let binance::model::Filters::LotSize {
+                        min_qty, step_size, ..
+                    } = &v.filters[1]
+                    {
+                        b.min_order_size = min_qty.parse().unwrap_or(0.0);
+                        b.lot_size = step_size.parse().unwrap_or(0.0);
+                    }
                     b.min_notional = match &v.filters[5] {
                         binance::model::Filters::MinNotional { notional, .. } => {
                             notional.clone().unwrap().parse().unwrap_or(0.0)
                         }
                         _ => 0.0,
                     };
-                }
-                Err(_) => {
-                    b.tick_size = 0.0;
+                    break;
                 }
             }
+            if !b.has_instrument_info() {
+                eprintln!(
+                    "Unable to fetch the instrument info of {}, it will not be quoted",
+                    s
+                );
+            }
         }
         market_data.klines = symbol
             .iter()
@@ -158,7 +275,9 @@ impl BinanceClient {
             .map(|s| (s.to_string(), VecDeque::with_capacity(10)))
             .collect::<Vec<(String, VecDeque<BookTickerEvent>)>>();
 
+        let events = heartbeat.clone();
         let handler = move |event| {
+            events.beat();
             match event {
                 FuturesWebsocketEvent::DepthOrderBook(DepthOrderBookEvent {
                     symbol,
@@ -188,13 +307,11 @@ impl BinanceClient {
                         }
                         arr
                     };
-                    if new_bids.len() == new_asks.len()
-                        && (new_bids.len() == 5 || new_bids.len() == 10 || new_bids.len() == 20)
-                    {
-                        // Process when the lengths are equal and equal to 5, 10, or 20
+                    if new_bids.len() == new_asks.len() && depths.contains(&new_bids.len()) {
+                        // Process when the lengths are equal and match a subscribed partial depth
                         book.update_binance_bba(new_bids.clone(), new_asks.clone(), event_time);
                     } else {
-                        // Process when the lengths are not equal or not equal to 5, 10, or 20
+                        // Process the diff depth updates of the full book
                         book.update(new_bids.clone(), new_asks.clone(), event_time);
                     }
 
@@ -274,33 +391,59 @@ impl BinanceClient {
             Ok(())
         };
 
+        // The client only connects to the testnet through a custom endpoint.
+        let stream_config = Config::default().set_ws_endpoint(self.stream_url(&request));
         let mut market: FuturesWebSockets<'_> = FuturesWebSockets::new(handler);
         loop {
             market
-                .connect_multiple_streams(&FuturesMarket::USDM, &request)
+                .connect_with_config(&FuturesMarket::USDM, "", &stream_config)
                 .unwrap();
+            // A silent connection times the read out instead of blocking the loop for good.
+            set_read_timeout(&market, Duration::from_millis(timeout_ms));
+            heartbeat.beat();
+            keep_running.store(true, Ordering::Relaxed);
+            let connected = Instant::now();
             // check error
-            if let Err(e) = market.event_loop(&keep_running) {
-                eprintln!("Error: {}", e);
-                thread::sleep(Duration::from_millis(delay));
-                delay *= 2;
+            match market.event_loop(&keep_running) {
+                Ok(()) => {
+                    eprintln!("No market data for {} ms, reconnecting", timeout_ms);
+                    let _ = market.disconnect();
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    let uptime = connected.elapsed().as_millis() as u64;
+                    thread::sleep(Duration::from_millis(delay.next(uptime)));
+                }
             }
         }
     }
+    /// Returns the USD-M futures symbols that are currently trading on Binance.
+    pub fn valid_symbols(&self) -> Vec<String> {
+        let general: FuturesGeneral = Binance::new_with_config(None, None, &self.config());
+        match general.exchange_info() {
+            Ok(info) => info
+                .symbols
+                .into_iter()
+                .filter(|symbol| symbol.status == "TRADING")
+                .map(|symbol| symbol.symbol)
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
     pub fn binance_trader(&self) -> FuturesAccount {
-        let config = {
-            let x = Config::default();
-            x.set_recv_window(600)
-        };
+        let config = self.config().set_recv_window(600);
         let trader: FuturesAccount =
             Binance::new_with_config(Some(self.key.clone()), Some(self.secret.clone()), &config);
         trader
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn private_subscribe(&self, sender: mpsc::UnboundedSender<TaggedPrivate>, symbol: String) {
-        let mut delay = 600;
+        let mut delay = self.reconnect_delay();
         let keep_running = AtomicBool::new(true); // Used to control the event loop
-        let user_stream: FuturesUserStream = Binance::new(Some(self.key.clone()), None);
+        let user_stream: FuturesUserStream =
+            Binance::new_with_config(Some(self.key.clone()), None, &self.config());
 
         let mut private_data = BinancePrivate::default();
         let mut orders_keys: VecDeque<u64> = VecDeque::new();
@@ -370,15 +513,18 @@ impl BinanceClient {
         if let Ok(answer) = user_stream.start() {
             println!("Data Stream Started ...");
             let listen_key = answer.listen_key;
+            let stream_config =
+                Config::default().set_ws_endpoint(self.user_stream_url(&listen_key));
             let mut web_socket: FuturesWebSockets<'_> = FuturesWebSockets::new(handler);
             loop {
                 web_socket
-                    .connect(&FuturesMarket::USDM, &listen_key)
+                    .connect_with_config(&FuturesMarket::USDM, "", &stream_config)
                     .unwrap(); // check error
+                let connected = Instant::now();
                 if let Err(e) = web_socket.event_loop(&keep_running) {
                     println!("Error: {}", e);
-                    thread::sleep(Duration::from_millis(delay));
-                    delay *= 2
+                    let uptime = connected.elapsed().as_millis() as u64;
+                    thread::sleep(Duration::from_millis(delay.next(uptime)));
                 }
             }
         } else {
@@ -387,14 +533,34 @@ impl BinanceClient {
     }
 
     pub fn fee_rate(&self) -> AccountInformation {
-        let client: FuturesAccount =
-            Binance::new(Some(self.key.clone()), Some(self.secret.clone()));
-        let info = client.account_information().unwrap();
-        info
+        let client: FuturesAccount = Binance::new_with_config(
+            Some(self.key.clone()),
+            Some(self.secret.clone()),
+            &self.config(),
+        );
+        client.account_information().unwrap()
+    }
+}
+
+/// Times the reads of the stream out after `timeout`, so a connection that went silent errors out
+/// of the event loop instead of blocking it.
+fn set_read_timeout(market: &FuturesWebSockets, timeout: Duration) {
+    let Some((socket, _)) = &market.socket else {
+        return;
+    };
+    let stream = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream,
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref(),
+        _ => return,
+    };
+    if let Err(e) = stream.set_read_timeout(Some(timeout)) {
+        eprintln!("Unable to time out the market stream reads: {}", e);
     }
 }
 
-fn bin_build_requests(symbol: &[String]) -> Vec<String> {
+/// Builds the streams to subscribe to, with a partial book stream per symbol and depth next to
+/// the diff depth stream of the full book.
+pub fn bin_build_requests(symbol: &[String], depths: &[usize]) -> Vec<String> {
     let mut request_args = vec![];
 
     // Agg Trades request
@@ -414,7 +580,7 @@ fn bin_build_requests(symbol: &[String]) -> Vec<String> {
     let best_book: Vec<String> = symbol
         .iter()
         .map(|sub| sub.to_lowercase())
-        .flat_map(|sym| vec![("5", sym.clone()), ("10", sym.clone()), ("20", sym.clone())])
+        .flat_map(|sym| depths.iter().map(move |&depth| (depth, sym.clone())))
         .map(|(depth, sub)| format!("{}@depth{}@100ms", sub, depth))
         .collect();
     request_args.extend(best_book);
@@ -461,7 +627,7 @@ impl BinancePrivate {
                 order_id: v.order_id.to_string(),
                 exec_id: v.trade_id.to_string(),
                 exec_price: v.average_price.to_string(),
-                exec_qty: v.accumulated_qty_filled_trades.to_string(),
+                exec_qty: v.qty_last_filled_trade.to_string(),
                 exec_time: v.trade_order_time.to_string(),
                 side: v.side.to_string(),
                 seq: v.trade_id as u64,
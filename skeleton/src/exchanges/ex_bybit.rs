@@ -1,25 +1,39 @@
 use bybit::{
     account::AccountManager,
-    api::Bybit,
+    api::{Bybit, Trade, API},
     config::Config,
+    errors::{BybitContentError, BybitError},
     general::General,
     market::MarketData,
     model::{
         Category, FastExecData, InstrumentRequest, KlineData, LinearTickerData, LiquidationData,
-        OrderBookUpdate, OrderData, PositionData, Subscription, Tickers, WalletData,
-        WebsocketEvents, WsTrade,
+        OrderBookUpdate, OrderData, OrderbookRequest, PositionData, Subscription, Tickers,
+        WalletData, WebsocketEvents, WsTrade,
     },
     trade::Trader,
     ws::Stream as BybitStream,
 };
+use serde::Deserialize;
 use std::{collections::VecDeque, time::Duration};
 use tokio::sync::mpsc;
 
-use crate::util::localorderbook::LocalBook;
+use crate::util::{
+    heartbeat::{watch, Heartbeat, HEARTBEAT_TIMEOUT_MS},
+    localorderbook::LocalBook,
+};
 
-use super::exchange::{PrivateData, TaggedPrivate};
+use super::exchange::{PrivateData, TaggedPrivate, Ticker};
 
-#[derive(Clone, Debug)]
+// Number of attempts at fetching the instrument info of a symbol before giving up on it.
+const INSTRUMENT_FETCH_ATTEMPTS: u32 = 4;
+// Delay in milliseconds before the first retry, doubled on each following retry.
+const INSTRUMENT_RETRY_MS: u64 = 1_000;
+/// Order book depths of the linear streams.
+pub const BYBIT_BOOK_DEPTHS: [usize; 4] = [1, 50, 200, 500];
+/// Order book depths subscribed to when none are configured.
+pub const DEFAULT_BYBIT_DEPTHS: [usize; 3] = [1, 50, 500];
+
+#[derive(Clone, Debug, Default)]
 pub struct BybitMarket {
     pub time: u64,
     pub books: Vec<(String, LocalBook)>,
@@ -29,6 +43,31 @@ pub struct BybitMarket {
     pub liquidations: Vec<(String, VecDeque<LiquidationData>)>,
 }
 
+impl BybitMarket {
+    /// Returns the next funding time in milliseconds from the latest ticker of the symbol.
+    pub fn next_funding_time(&self, symbol: &str) -> Option<u64> {
+        self.tickers
+            .iter()
+            .find(|(s, _)| s == symbol)
+            .and_then(|(_, tickers)| tickers.back())
+            .and_then(|ticker| ticker.next_funding_time.parse::<u64>().ok())
+    }
+
+    /// Returns the latest ticker of the symbol, with the fields the latest deltas left empty
+    /// taken from the older tickers.
+    pub fn latest_ticker(&self, symbol: &str) -> Option<Ticker> {
+        self.tickers
+            .iter()
+            .find(|(s, _)| s == symbol)
+            .filter(|(_, tickers)| !tickers.is_empty())
+            .map(|(_, tickers)| {
+                tickers
+                    .iter()
+                    .fold(Ticker::default(), |older, t| Ticker::from(t).or(older))
+            })
+    }
+}
+
 unsafe impl Send for BybitMarket {}
 unsafe impl Sync for BybitMarket {}
 
@@ -56,41 +95,87 @@ impl Default for BybitPrivate {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct BybitClient {
     pub key: String,
     pub secret: String,
+    pub testnet: bool,
+    // Time in milliseconds without market data after which the market stream is reconnected.
+    pub heartbeat_timeout_ms: Option<u64>,
 }
 
-impl Default for BybitMarket {
-    fn default() -> Self {
+/// The status of a request that returns no data.
+#[derive(Deserialize)]
+struct StatusResponse {
+    #[serde(rename = "retCode")]
+    ret_code: i16,
+    #[serde(rename = "retMsg")]
+    ret_msg: String,
+}
+
+impl BybitClient {
+    /// Creates a client for the account of the API key, on the testnet if `testnet` is set.
+    pub fn init(key: String, secret: String, testnet: bool) -> Self {
         Self {
-            time: 0,
-            books: Vec::new(),
-            klines: Vec::new(),
-            trades: Vec::new(),
-            tickers: Vec::new(),
-            liquidations: Vec::new(),
+            key,
+            secret,
+            testnet,
+            heartbeat_timeout_ms: None,
         }
     }
-}
 
-impl Default for BybitClient {
-    fn default() -> Self {
-        Self {
-            key: String::new(),
-            secret: String::new(),
+    /// Reconnects the market stream once it delivered no data for `timeout_ms`, `None`
+    /// reconnects it after 30 seconds.
+    pub fn with_heartbeat_timeout(mut self, timeout_ms: Option<u64>) -> Self {
+        self.heartbeat_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Returns the REST and WebSocket endpoints of the mainnet or the testnet.
+    pub fn config(&self) -> Config {
+        if self.testnet {
+            Config::testnet()
+        } else {
+            Config::default()
         }
     }
-}
 
-impl BybitClient {
-    pub fn init(key: String, secret: String) -> Self {
-        Self { key, secret }
+    /// Returns the base URL of the public and private streams.
+    pub fn stream_url(&self) -> &'static str {
+        self.config().ws_endpoint
+    }
+
+    /// Enables the disconnection protection of the account, which makes the exchange cancel
+    /// every derivatives order once the private stream has been disconnected for
+    /// `time_window_secs` seconds (3 to 300).
+    pub async fn enable_cancel_on_disconnect(
+        &self,
+        time_window_secs: u64,
+    ) -> Result<(), BybitError> {
+        let trader: Trader = Bybit::new_with_config(
+            &self.config(),
+            Some(self.key.clone()),
+            Some(self.secret.clone()),
+        );
+        let response: StatusResponse = trader
+            .client
+            .post_signed(
+                API::Trade(Trade::SetDisconnectCancelall),
+                trader.recv_window.into(),
+                Some(dcp_request(time_window_secs)),
+            )
+            .await?;
+        if response.ret_code != 0 {
+            return Err(BybitError::BybitError(BybitContentError {
+                code: response.ret_code,
+                msg: response.ret_msg,
+            }));
+        }
+        Ok(())
     }
 
     pub async fn exchange_time(&self) -> u64 {
-        let general: General = Bybit::new(None, None);
+        let general: General = Bybit::new_with_config(&self.config(), None, None);
         general
             .get_server_time()
             .await
@@ -99,7 +184,11 @@ impl BybitClient {
     }
 
     pub async fn fee_rate(&self, symbol: &str) -> f64 {
-        let account: AccountManager = Bybit::new(Some(self.key.clone()), Some(self.secret.clone()));
+        let account: AccountManager = Bybit::new_with_config(
+            &self.config(),
+            Some(self.key.clone()),
+            Some(self.secret.clone()),
+        );
         let rate;
         let response = account
             .get_fee_rate(Category::Linear, Some(symbol.to_string()))
@@ -111,25 +200,40 @@ impl BybitClient {
         }
         rate
     }
+    /// Returns the linear symbols that are currently trading on Bybit.
+    pub async fn valid_symbols(&self) -> Vec<String> {
+        let market: MarketData = Bybit::new_with_config(&self.config(), None, None);
+        let req = InstrumentRequest::new(Category::Linear, None, None, None, Some(1000));
+        match market.get_futures_instrument_info(req).await {
+            Ok(res) => res
+                .result
+                .list
+                .into_iter()
+                .filter(|instrument| instrument.status == "Trading")
+                .map(|instrument| instrument.symbol)
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
     pub fn bybit_trader(&self) -> Trader {
-        let config = {
-            let x = Config::default();
-            x.set_recv_window(2500)
-        };
+        let config = self.config().set_recv_window(2500);
         let trader: Trader =
             Bybit::new_with_config(&config, Some(self.key.clone()), Some(self.secret.clone()));
         trader
     }
 
+    #[allow(clippy::result_large_err)]
     pub async fn market_subscribe(
         &self,
         symbol: Vec<String>,
+        depths: Vec<usize>,
         sender: mpsc::UnboundedSender<BybitMarket>,
     ) {
         let delay = 50;
-        let market: BybitStream = Bybit::new(None, None);
+        let market: BybitStream = Bybit::new_with_config(&self.config(), None, None);
         let category: Category = Category::Linear;
-        let request_args = build_requests(&symbol);
+        let request_args = build_requests(&symbol, &depths);
         let mut market_data = BybitMarket::default();
         let request = Subscription::new(
             "subscribe",
@@ -140,21 +244,35 @@ impl BybitClient {
             .map(|s| (s.to_string(), LocalBook::new()))
             .collect::<Vec<(String, LocalBook)>>();
         for (s, b) in &mut market_data.books {
-            let cl: MarketData = Bybit::new(None, None);
-            let req = InstrumentRequest::new(category, Some(s), None, None, None);
-            if let Ok(res) = cl.get_futures_instrument_info(req).await {
-                b.tick_size = res.result.list[0].price_filter.tick_size;
-                if let Some(v) = &res.result.list[0].lot_size_filter.qty_step {
-                    b.lot_size = v.parse::<f64>().unwrap_or(0.0);
-                }
-                if let Some(v) = &res.result.list[0].lot_size_filter.post_only_max_order_qty {
-                    b.post_only_max = v.parse::<f64>().unwrap_or(0.0);
+            for attempt in 0..INSTRUMENT_FETCH_ATTEMPTS {
+                if attempt > 0 {
+                    // Back off exponentially between the attempts.
+                    let backoff = INSTRUMENT_RETRY_MS << (attempt - 1);
+                    tokio::time::sleep(Duration::from_millis(backoff)).await;
                 }
-                b.min_order_size = res.result.list[0].lot_size_filter.min_order_qty;
-                if let Some(v) = &res.result.list[0].lot_size_filter.min_order_amt {
-                    b.min_notional = v.parse::<f64>().unwrap_or(0.0);
+                let cl: MarketData = Bybit::new_with_config(&self.config(), None, None);
+                let req = InstrumentRequest::new(category, Some(s), None, None, None);
+                if let Ok(res) = cl.get_futures_instrument_info(req).await {
+                    b.tick_size = res.result.list[0].price_filter.tick_size;
+                    if let Some(v) = &res.result.list[0].lot_size_filter.qty_step {
+                        b.lot_size = v.parse::<f64>().unwrap_or(0.0);
+                    }
+                    if let Some(v) = &res.result.list[0].lot_size_filter.post_only_max_order_qty {
+                        b.post_only_max = v.parse::<f64>().unwrap_or(0.0);
+                    }
+                    b.min_order_size = res.result.list[0].lot_size_filter.min_order_qty;
+                    if let Some(v) = &res.result.list[0].lot_size_filter.min_order_amt {
+                        b.min_notional = v.parse::<f64>().unwrap_or(0.0);
+                    }
+                    break;
                 }
             }
+            if !b.has_instrument_info() {
+                eprintln!(
+                    "Unable to fetch the instrument info of {}, it will not be quoted",
+                    s
+                );
+            }
         }
         market_data.klines = symbol
             .iter()
@@ -173,142 +291,151 @@ impl BybitClient {
             .iter()
             .map(|s| (s.to_string(), VecDeque::with_capacity(10)))
             .collect::<Vec<(String, VecDeque<LinearTickerData>)>>();
-        let handler = move |event| {
-            match event {
-                WebsocketEvents::OrderBookEvent(OrderBookUpdate {
-                    topic,
-                    data,
-                    timestamp,
-                    ..
-                }) => {
-                    let sym = topic.split('.').nth(2).unwrap();
-                    let book = &mut market_data
-                        .books
-                        .iter_mut()
-                        .find(|(s, _)| s == sym)
-                        .unwrap()
-                        .1;
-
-                    if topic == format!("orderbook.1.{}", sym) {
-                        book.update_bba(data.bids, data.asks, timestamp);
-                        market_data.time = timestamp;
-                    } else {
-                        book.update(data.bids, data.asks, timestamp);
+        // The snapshot of the deepest stream covers every level the other streams update.
+        let deepest = depths.iter().copied().max().unwrap_or(1);
+        let heartbeat = Heartbeat::new();
+        let timeout_ms = self.heartbeat_timeout_ms.unwrap_or(HEARTBEAT_TIMEOUT_MS);
+        loop {
+            // Start from fresh books so nothing is left from the previous connection. They are
+            // seeded with a REST snapshot only as a fallback until the stream sends its own.
+            let mut market_data = market_data.clone();
+            load_book_snapshots(&self.config(), &mut market_data.books, deepest as u64).await;
+            let sender = sender.clone();
+            let events = heartbeat.clone();
+            let handler = move |event| {
+                events.beat();
+                match event {
+                    WebsocketEvents::OrderBookEvent(update) => {
+                        let mut topic = update.topic.split('.').skip(1);
+                        let depth: usize = topic.next().unwrap().parse().unwrap();
+                        let sym = topic.next().unwrap();
+                        let book = &mut market_data
+                            .books
+                            .iter_mut()
+                            .find(|(s, _)| s == sym)
+                            .unwrap()
+                            .1;
+
+                        if depth == 1 {
+                            market_data.time = update.timestamp;
+                        }
+                        apply_book_update(book, update, depth, deepest);
                     }
-                }
-                WebsocketEvents::KlineEvent(klines) => {
-                    let sym = klines.topic.split('.').nth(2).unwrap();
-                    let kline = &mut market_data
-                        .klines
-                        .iter_mut()
-                        .find(|(s, _)| s == sym)
-                        .unwrap()
-                        .1;
-                    if kline.len() == kline.capacity()
-                        || (kline.capacity() - kline.len()) <= klines.data.len()
-                    {
-                        for _ in 0..klines.data.len() {
-                            kline.pop_front();
+                    WebsocketEvents::KlineEvent(klines) => {
+                        let sym = klines.topic.split('.').nth(2).unwrap();
+                        let kline = &mut market_data
+                            .klines
+                            .iter_mut()
+                            .find(|(s, _)| s == sym)
+                            .unwrap()
+                            .1;
+                        if kline.len() == kline.capacity()
+                            || (kline.capacity() - kline.len()) <= klines.data.len()
+                        {
+                            for _ in 0..klines.data.len() {
+                                kline.pop_front();
+                            }
                         }
+                        kline.extend(klines.data);
                     }
-                    kline.extend(klines.data);
-                }
-                WebsocketEvents::TickerEvent(tick) => {
-                    let sym = tick.topic.split('.').nth(1).unwrap();
-                    let ticker = &mut market_data
-                        .tickers
-                        .iter_mut()
-                        .find(|(s, _)| s == sym)
-                        .unwrap()
-                        .1;
-                    if ticker.len() == ticker.capacity() || (ticker.capacity() - ticker.len()) <= 1
-                    {
-                        for _ in 0..2 {
-                            ticker.pop_front();
+                    WebsocketEvents::TickerEvent(tick) => {
+                        let sym = tick.topic.split('.').nth(1).unwrap();
+                        let ticker = &mut market_data
+                            .tickers
+                            .iter_mut()
+                            .find(|(s, _)| s == sym)
+                            .unwrap()
+                            .1;
+                        if ticker.len() == ticker.capacity()
+                            || (ticker.capacity() - ticker.len()) <= 1
+                        {
+                            for _ in 0..2 {
+                                ticker.pop_front();
+                            }
                         }
+                        let d = match tick.data {
+                            Tickers::Linear(data) => data,
+                            _ => unreachable!(),
+                        };
+                        ticker.push_back(d);
                     }
-                    let d = match tick.data {
-                        Tickers::Linear(data) => data,
-                        _ => unreachable!(),
-                    };
-                    ticker.push_back(d);
-                }
-                WebsocketEvents::TradeEvent(data) => {
-                    let sym = data.topic.split('.').nth(1).unwrap();
-                    let trades = &mut market_data
-                        .trades
-                        .iter_mut()
-                        .find(|(s, _)| s == sym)
-                        .unwrap()
-                        .1;
-                    if trades.len() == trades.capacity()
-                        || (trades.capacity() - trades.len()) <= data.data.len()
-                    {
-                        for _ in 0..data.data.len() {
-                            trades.pop_front();
+                    WebsocketEvents::TradeEvent(data) => {
+                        let sym = data.topic.split('.').nth(1).unwrap();
+                        let trades = &mut market_data
+                            .trades
+                            .iter_mut()
+                            .find(|(s, _)| s == sym)
+                            .unwrap()
+                            .1;
+                        if trades.len() == trades.capacity()
+                            || (trades.capacity() - trades.len()) <= data.data.len()
+                        {
+                            for _ in 0..data.data.len() {
+                                trades.pop_front();
+                            }
                         }
+                        trades.extend(data.data);
                     }
-                    trades.extend(data.data);
-                }
-                WebsocketEvents::LiquidationEvent(data) => {
-                    let sym = data.topic.split('.').nth(1).unwrap();
-                    let liquidations = &mut market_data
-                        .liquidations
-                        .iter_mut()
-                        .find(|(s, _)| s == sym)
-                        .unwrap()
-                        .1;
-                    if liquidations.len() == liquidations.capacity()
-                        || (liquidations.capacity() - liquidations.len()) <= 5
-                    {
-                        for _ in 0..5 {
-                            liquidations.pop_front();
+                    WebsocketEvents::LiquidationEvent(data) => {
+                        let sym = data.topic.split('.').nth(1).unwrap();
+                        let liquidations = &mut market_data
+                            .liquidations
+                            .iter_mut()
+                            .find(|(s, _)| s == sym)
+                            .unwrap()
+                            .1;
+                        if liquidations.len() == liquidations.capacity()
+                            || (liquidations.capacity() - liquidations.len()) <= 5
+                        {
+                            for _ in 0..5 {
+                                liquidations.pop_front();
+                            }
                         }
+                        liquidations.push_back(data.data);
+                    }
+                    _ => {
+                        eprintln!("Unhandled event: {:#?}", event);
                     }
-                    liquidations.push_back(data.data);
-                }
-                _ => {
-                    eprintln!("Unhandled event: {:#?}", event);
                 }
-            }
-            let _ = sender.send(market_data.clone());
-            Ok(())
-        };
-        loop {
-            match market
-                .ws_subscribe(request.clone(), category, handler.clone())
-                .await
-            {
-                Ok(_) => {
+                let _ = sender.send(market_data.clone());
+                Ok(())
+            };
+            // Reconnect when the connection stays open but stops delivering data.
+            let subscription = market.ws_subscribe(request.clone(), category, handler);
+            match watch(subscription, &heartbeat, timeout_ms).await {
+                Some(Ok(_)) => {
                     println!("Subscription successful");
                     tokio::time::sleep(Duration::from_millis(delay)).await;
                 }
-                Err(e) => {
+                Some(Err(e)) => {
                     eprintln!("Subscription error: {}", e);
                     tokio::time::sleep(Duration::from_millis(delay)).await;
                 }
+                None => {
+                    eprintln!("No market data for {} ms, reconnecting", timeout_ms);
+                }
             }
         }
     }
 
+    #[allow(clippy::result_large_err)]
     pub async fn private_subscribe(
         &self,
         sender: mpsc::UnboundedSender<TaggedPrivate>,
         symbol: String,
     ) {
         let delay = 50;
-        let user_stream: BybitStream = BybitStream::new(
+        let user_stream: BybitStream = Bybit::new_with_config(
+            &self.config(),
             Some(self.key.clone()),    // API key
             Some(self.secret.clone()), // Secret Key
         );
-        let request_args = {
-            let mut args = vec![];
-            args.push("position.linear".to_string());
-            args.push("execution.fast".to_string());
-            args.push("order.linear".to_string());
-            args.push("wallet".to_string());
-            args
-        };
+        let request_args = [
+            "position.linear".to_string(),
+            "execution.fast".to_string(),
+            "order.linear".to_string(),
+            "wallet".to_string(),
+        ];
         let mut private_data = BybitPrivate::default();
         let request = Subscription::new(
             "subscribe",
@@ -391,13 +518,62 @@ impl BybitClient {
     }
 }
 
-fn build_requests(symbol: &[String]) -> Vec<String> {
+/// Builds the body of the request enabling the disconnection protection of the derivatives.
+pub fn dcp_request(time_window_secs: u64) -> String {
+    format!(
+        "{{\"product\":\"DERIVATIVES\",\"timeWindow\":{}}}",
+        time_window_secs
+    )
+}
+
+/// Applies a message of the order book stream of `depth` levels to the book.
+///
+/// The snapshot of the `deepest` stream replaces the book, so the levels that left it before the
+/// snapshot are dropped. The other messages are merged into the book.
+pub fn apply_book_update(
+    book: &mut LocalBook,
+    update: OrderBookUpdate,
+    depth: usize,
+    deepest: usize,
+) {
+    let OrderBookUpdate {
+        event_type,
+        data,
+        timestamp,
+        ..
+    } = update;
+    if depth == 1 {
+        book.update_bba(data.bids, data.asks, timestamp);
+    } else if event_type == "snapshot" && depth == deepest {
+        book.load_snapshot(data.bids, data.asks, timestamp);
+    } else {
+        book.update(data.bids, data.asks, timestamp);
+    }
+}
+
+/// Seeds each book with a REST depth snapshot of `depth` levels per side, so it is usable before
+/// the stream sends its own snapshot.
+///
+/// A book whose snapshot can not be fetched is left as is and filled by the stream.
+async fn load_book_snapshots(config: &Config, books: &mut [(String, LocalBook)], depth: u64) {
+    let cl: MarketData = Bybit::new_with_config(config, None, None);
+    for (s, b) in books.iter_mut() {
+        let req = OrderbookRequest::new(s, Category::Linear, Some(depth));
+        match cl.get_depth(req).await {
+            Ok(res) => b.load_snapshot(res.result.bids, res.result.asks, res.result.timestamp),
+            Err(e) => eprintln!("Unable to fetch the order book snapshot of {}: {}", s, e),
+        }
+    }
+}
+
+/// Builds the topics to subscribe to, with an order book stream per symbol and depth.
+pub fn build_requests(symbol: &[String], depths: &[usize]) -> Vec<String> {
     let mut request_args = vec![];
 
     // Building book requests
     let book_req: Vec<String> = symbol
         .iter()
-        .flat_map(|sym| vec![(1, sym), (50, sym), (500, sym)])
+        .flat_map(|sym| depths.iter().map(move |&depth| (depth, sym)))
         .map(|(num, sym)| format!("orderbook.{}.{}", num, sym.to_uppercase()))
         .collect();
     request_args.extend(book_req);
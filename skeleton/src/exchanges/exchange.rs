@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 
 use binance::model::AggrTradesEvent;
-use bybit::model::WsTrade;
+use bybit::model::{LinearTickerData, WsTrade};
 
 use super::{
     ex_binance::{BinanceClient, BinanceMarket, BinancePrivate},
@@ -21,6 +21,20 @@ impl ExchangeClient {
             Self::Binance(v) => Box::new(v),
         }
     }
+
+    /// Makes the exchange cancel the orders of the account once it has been disconnected for
+    /// `time_window_secs` seconds.
+    pub async fn enable_cancel_on_disconnect(&self, time_window_secs: u64) -> Result<(), String> {
+        match self {
+            Self::Bybit(v) => v
+                .enable_cancel_on_disconnect(time_window_secs)
+                .await
+                .map_err(|e| e.to_string()),
+            Self::Binance(v) => v
+                .enable_cancel_on_disconnect(time_window_secs)
+                .map_err(|e| e.to_string()),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -77,6 +91,39 @@ impl MarketMessage {
     }
 }
 
+/// The fields of an exchange ticker used as features, `None` when the ticker does not carry them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Ticker {
+    /// Funding rate of the current funding period, as a fraction.
+    pub funding_rate: Option<f64>,
+    /// Open interest in contracts.
+    pub open_interest: Option<f64>,
+    /// Volume traded over the last 24 hours in contracts.
+    pub volume_24h: Option<f64>,
+}
+
+impl Ticker {
+    /// Fills the fields missing from this ticker with the ones of an older ticker.
+    pub fn or(self, older: Ticker) -> Ticker {
+        Ticker {
+            funding_rate: self.funding_rate.or(older.funding_rate),
+            open_interest: self.open_interest.or(older.open_interest),
+            volume_24h: self.volume_24h.or(older.volume_24h),
+        }
+    }
+}
+
+impl From<&LinearTickerData> for Ticker {
+    fn from(data: &LinearTickerData) -> Self {
+        // The deltas leave the fields that did not change empty.
+        Ticker {
+            funding_rate: data.funding_rate.parse().ok(),
+            open_interest: data.open_interest.parse().ok(),
+            volume_24h: data.volume_24h.parse().ok(),
+        }
+    }
+}
+
 pub trait ProcessTrade {
     fn process_trade(&self) -> WsTrade;
 }
@@ -9,19 +9,27 @@ pub fn add(left: usize, right: usize) -> usize {
 #[cfg(test)]
 mod tests {
 
-    use std::time::Duration;
+    use std::{collections::VecDeque, time::Duration};
 
     use binance::{api::Binance, futures::general::FuturesGeneral};
-    use exchanges::exchange::PrivateData;
+    use bybit::model::{Ask, Bid, OrderBookUpdate, WsOrderBook, WsTrade};
+    use exchanges::exchange::{ExchangeClient, PrivateData};
     use tokio::{sync::mpsc, task, time::Instant};
 
     use crate::{
         exchanges::{
-            ex_binance::{BinanceClient, BinanceMarket},
-            ex_bybit::BybitClient,
+            ex_binance::{BinanceClient, BinanceMarket, ReconnectDelay},
+            ex_bybit::{apply_book_update, BybitClient},
+        },
+        util::{
+            candles::{TickCandle, VolumeCandle},
+            helpers::{try_read_toml, Config, Leverage},
+            localorderbook::LocalBook,
+            logger::{LogFormat, LogLevel, Logger},
+            trade_tape::TradeTape,
         },
-        util::logger::Logger,
     };
+    use ordered_float::OrderedFloat;
 
     use super::*;
 
@@ -31,25 +39,598 @@ mod tests {
         assert_eq!(result, 4);
     }
 
+    #[test]
+    fn test_book_snapshot() {
+        let mut book = LocalBook::new();
+        for (price, qty) in [(99.0, 1.0), (98.0, 2.0), (99.5, 3.0)] {
+            book.bids.insert(OrderedFloat(price), qty);
+        }
+        for (price, qty) in [(101.0, 1.0), (100.5, 2.0), (102.0, 3.0)] {
+            book.asks.insert(OrderedFloat(price), qty);
+        }
+
+        let (bids, asks) = book.snapshot(2);
+        assert_eq!(bids, vec![(99.5, 3.0), (99.0, 1.0)]);
+        assert_eq!(asks, vec![(100.5, 2.0), (101.0, 1.0)]);
+
+        let (bids, asks) = book.snapshot(10);
+        assert_eq!(bids.len(), 3);
+        assert_eq!(asks.len(), 3);
+    }
+
+    #[test]
+    fn test_effective_spread_is_positive() {
+        let mut book = LocalBook::new();
+        book.best_bid = Bid {
+            price: 99.0,
+            qty: 1.0,
+        };
+        book.best_ask = Ask {
+            price: 100.0,
+            qty: 1.0,
+        };
+        book.mid_price = 99.6;
+
+        assert!((book.effective_spread(true) - 0.4).abs() < 1e-9);
+        assert!((book.effective_spread(false) - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_microprice_depth_capped_to_thin_side() {
+        let mut book = LocalBook::new();
+        let bids = vec![
+            Bid {
+                price: 99.9,
+                qty: 2.0,
+            },
+            Bid {
+                price: 99.8,
+                qty: 2.0,
+            },
+        ];
+        let asks = (1..=5)
+            .map(|i| Ask {
+                price: 100.0 + i as f64 / 10.0,
+                qty: 2.0,
+            })
+            .collect();
+        book.load_snapshot(bids, asks, 1);
+
+        // Only two levels of asks are weighed against the two levels of bids.
+        let microprice = book.get_microprice(5);
+        assert!((microprice - book.get_microprice(2)).abs() < 1e-9);
+        assert!((microprice - 100.0).abs() < 1e-9);
+        assert!(microprice > book.best_bid.price && microprice < book.best_ask.price);
+    }
+
+    #[test]
+    fn test_market_impact_walks_stepped_book() {
+        let mut book = LocalBook::new();
+        for (price, qty) in [(99.0, 1.0), (98.0, 2.0), (97.0, 3.0)] {
+            book.bids.insert(OrderedFloat(price), qty);
+        }
+        for (price, qty) in [(100.0, 1.0), (101.0, 2.0), (102.0, 3.0)] {
+            book.asks.insert(OrderedFloat(price), qty);
+        }
+        book.best_bid = Bid {
+            price: 99.0,
+            qty: 1.0,
+        };
+        book.best_ask = Ask {
+            price: 100.0,
+            qty: 1.0,
+        };
+
+        // Within the best level the order fills at the best price.
+        assert_eq!(book.market_impact(0.5, true), 100.0);
+        // 1 @ 100 + 2 @ 101 + 1 @ 102 = 404 for 4.
+        assert!((book.market_impact(4.0, true) - 101.0).abs() < 1e-9);
+        // 1 @ 99 + 2 @ 98 = 295 for 3.
+        assert!((book.market_impact(3.0, false) - 295.0 / 3.0).abs() < 1e-9);
+        // Beyond the visible depth the rest fills at the last level.
+        assert!((book.market_impact(8.0, false) - (295.0 + 5.0 * 97.0) / 8.0).abs() < 1e-9);
+        assert_eq!(book.market_impact(0.0, false), 99.0);
+    }
+
+    #[test]
+    fn test_book_invariants_across_updates() {
+        let bids = |levels: &[(f64, f64)]| -> Vec<Bid> {
+            levels
+                .iter()
+                .map(|&(price, qty)| Bid { price, qty })
+                .collect()
+        };
+        let asks = |levels: &[(f64, f64)]| -> Vec<Ask> {
+            levels
+                .iter()
+                .map(|&(price, qty)| Ask { price, qty })
+                .collect()
+        };
+        let mut book = LocalBook::new();
+        assert!(book.invariants_ok());
+
+        // Depth snapshot, then deltas that delete the best levels with a zero quantity.
+        book.update(
+            bids(&[(99.0, 1.0), (98.5, 2.0), (98.0, 3.0)]),
+            asks(&[(100.0, 1.0), (100.5, 2.0), (101.0, 3.0)]),
+            1,
+        );
+        assert!(book.invariants_ok());
+        assert_eq!(book.mid_price, 99.5);
+
+        book.update(bids(&[(99.0, 0.0)]), asks(&[(100.0, 0.0), (100.2, 4.0)]), 2);
+        assert!(book.invariants_ok());
+        assert_eq!(book.best_bid.price, 98.5);
+        assert_eq!(book.best_ask.price, 100.2);
+
+        // Best bid and offer updates drop the levels through the new best prices.
+        book.update_bba(bids(&[(99.8, 1.5)]), asks(&[(100.6, 0.5)]), 3);
+        assert!(book.invariants_ok());
+        assert_eq!(book.best_ask.price, 100.6);
+
+        book.update_binance_bba(bids(&[(99.9, 1.0)]), asks(&[(100.1, 1.0)]), 4);
+        assert!(book.invariants_ok());
+
+        // Emptying a side keeps the book consistent.
+        book.update(
+            bids(&[(99.9, 0.0), (99.8, 0.0), (98.5, 0.0), (98.0, 0.0)]),
+            vec![],
+            5,
+        );
+        assert!(book.bids.is_empty());
+        assert!(book.invariants_ok());
+    }
+
+    #[test]
+    fn test_snapshot_then_delta_merges() {
+        let mut book = LocalBook::new();
+        // Stale levels from a previous connection are dropped by the snapshot.
+        book.update(
+            vec![Bid {
+                price: 95.0,
+                qty: 9.0,
+            }],
+            vec![Ask {
+                price: 105.0,
+                qty: 9.0,
+            }],
+            1,
+        );
+
+        book.load_snapshot(
+            vec![
+                Bid {
+                    price: 99.0,
+                    qty: 1.0,
+                },
+                Bid {
+                    price: 98.0,
+                    qty: 2.0,
+                },
+            ],
+            vec![
+                Ask {
+                    price: 100.0,
+                    qty: 1.0,
+                },
+                Ask {
+                    price: 101.0,
+                    qty: 2.0,
+                },
+            ],
+            10,
+        );
+        assert!(book.invariants_ok());
+        assert_eq!(book.mid_price, 99.5);
+        assert_eq!(book.last_update, 10);
+
+        // The delta removes the best ask, resizes a bid and adds a level.
+        book.update(
+            vec![
+                Bid {
+                    price: 98.0,
+                    qty: 5.0,
+                },
+                Bid {
+                    price: 99.5,
+                    qty: 1.0,
+                },
+            ],
+            vec![Ask {
+                price: 100.0,
+                qty: 0.0,
+            }],
+            11,
+        );
+        assert!(book.invariants_ok());
+        let (bids, asks) = book.snapshot(5);
+        assert_eq!(bids, vec![(99.5, 1.0), (99.0, 1.0), (98.0, 5.0)]);
+        assert_eq!(asks, vec![(101.0, 2.0)]);
+        assert_eq!(book.mid_price, 100.25);
+    }
+
+    #[test]
+    fn test_stream_snapshot_replaces_rest_seed() {
+        let message = |event_type: &str, depth: usize, bids: Vec<Bid>, ts: u64| OrderBookUpdate {
+            topic: format!("orderbook.{}.BTCUSDT", depth),
+            event_type: event_type.to_string(),
+            timestamp: ts,
+            data: WsOrderBook {
+                symbol: "BTCUSDT".to_string(),
+                asks: vec![Ask {
+                    price: 101.0,
+                    qty: 1.0,
+                }],
+                bids,
+                update_id: ts,
+                seq: ts,
+            },
+            cts: ts,
+        };
+        // The REST seed has a bid that is cancelled before the stream sends its snapshot.
+        let mut book = LocalBook::new();
+        book.load_snapshot(
+            vec![
+                Bid {
+                    price: 99.0,
+                    qty: 1.0,
+                },
+                Bid {
+                    price: 98.0,
+                    qty: 1.0,
+                },
+            ],
+            vec![Ask {
+                price: 101.0,
+                qty: 1.0,
+            }],
+            1,
+        );
+
+        // The snapshot of a shallower stream is merged and keeps the level.
+        let bids = vec![Bid {
+            price: 99.0,
+            qty: 2.0,
+        }];
+        apply_book_update(&mut book, message("snapshot", 50, bids.clone(), 2), 50, 500);
+        assert_eq!(book.snapshot(5).0, vec![(99.0, 2.0), (98.0, 1.0)]);
+
+        // The snapshot of the deepest stream replaces the book and drops it.
+        apply_book_update(&mut book, message("snapshot", 500, bids, 3), 500, 500);
+        assert_eq!(book.snapshot(5).0, vec![(99.0, 2.0)]);
+        assert_eq!(book.last_update, 3);
+
+        // Its deltas are merged into the book.
+        let delta = vec![Bid {
+            price: 98.5,
+            qty: 1.0,
+        }];
+        apply_book_update(&mut book, message("delta", 500, delta, 4), 500, 500);
+        assert_eq!(book.snapshot(5).0, vec![(99.0, 2.0), (98.5, 1.0)]);
+        assert!(book.invariants_ok());
+    }
+
+    #[test]
+    fn test_cancel_on_disconnect_request() {
+        assert_eq!(
+            exchanges::ex_bybit::dcp_request(10),
+            r#"{"product":"DERIVATIVES","timeWindow":10}"#
+        );
+    }
+
+    #[test]
+    fn test_custom_book_depths_subscriptions() {
+        let symbols = vec!["BTCUSDT".to_string()];
+        let bybit = exchanges::ex_bybit::build_requests(&symbols, &[1, 200]);
+        let bybit_books: Vec<&String> = bybit
+            .iter()
+            .filter(|r| r.starts_with("orderbook"))
+            .collect();
+        assert_eq!(
+            bybit_books,
+            vec!["orderbook.1.BTCUSDT", "orderbook.200.BTCUSDT"]
+        );
+
+        let binance = exchanges::ex_binance::bin_build_requests(&symbols, &[10]);
+        let binance_books: Vec<&String> =
+            binance.iter().filter(|r| r.contains("@depth")).collect();
+        assert_eq!(
+            binance_books,
+            vec!["btcusdt@depth10@100ms", "btcusdt@depth@100ms"]
+        );
+
+        let mut state = ss::SharedState::new("both".to_string());
+        state.set_bybit_depths(vec![1, 200]);
+        state.set_binance_depths(vec![10]);
+        assert_eq!(state.bybit_depths, vec![1, 200]);
+        assert!(std::panic::catch_unwind(move || state.set_binance_depths(vec![50])).is_err());
+    }
+
+    #[test]
+    fn test_testnet_stream_urls() {
+        let bybit = BybitClient::init(String::new(), String::new(), true);
+        assert_eq!(bybit.stream_url(), "wss://stream-testnet.bybit.com/v5");
+        let bybit = BybitClient::init(String::new(), String::new(), false);
+        assert_eq!(bybit.stream_url(), "wss://stream.bybit.com/v5");
+
+        let streams = vec!["btcusdt@depth@100ms".to_string()];
+        let binance = BinanceClient::init(String::new(), String::new(), true);
+        assert_eq!(
+            binance.stream_url(&streams),
+            "wss://stream.binancefuture.com/stream?streams=btcusdt@depth@100ms"
+        );
+        assert_eq!(
+            binance.user_stream_url("key"),
+            "wss://stream.binancefuture.com/ws/key"
+        );
+        let binance = BinanceClient::init(String::new(), String::new(), false);
+        assert_eq!(
+            binance.stream_url(&streams),
+            "wss://fstream.binance.com/stream?streams=btcusdt@depth@100ms"
+        );
+
+        // Clients added to a testnet state connect to the testnet.
+        let mut state = ss::SharedState::new("bybit".to_string());
+        state.set_testnet(true);
+        state.add_clients(String::new(), String::new(), "BTCUSDT".to_string(), None);
+        assert_eq!(
+            state.clients["BTCUSDT"],
+            ExchangeClient::Bybit(BybitClient::init(String::new(), String::new(), true))
+        );
+    }
+
+    #[test]
+    fn test_book_invariants_detect_crossed_book() {
+        let mut book = LocalBook::new();
+        assert!(!book.is_crossed());
+        book.bids.insert(OrderedFloat(100.5), 1.0);
+        book.asks.insert(OrderedFloat(100.0), 1.0);
+        book.best_bid = Bid {
+            price: 100.5,
+            qty: 1.0,
+        };
+        book.best_ask = Ask {
+            price: 100.0,
+            qty: 1.0,
+        };
+        assert!(!book.invariants_ok());
+        assert!(book.is_crossed());
+    }
+
+    #[test]
+    fn test_reconnect_delay_resets_after_stable_connection() {
+        let mut delay = ReconnectDelay::new(600, 60_000);
+        // An early error doubles the delay of the next one.
+        assert_eq!(delay.next(100), 600);
+        assert_eq!(delay.next(100), 1_200);
+        // A connection that stayed up for a minute starts again from the base delay.
+        assert_eq!(delay.next(60_000), 600);
+        assert_eq!(delay.next(100), 1_200);
+
+        let client = BinanceClient::init(String::new(), String::new(), false);
+        assert_eq!(client.reconnect_delay(), ReconnectDelay::new(600, 60_000));
+        let client = client.with_reconnect_reset(Some(5_000));
+        assert_eq!(client.reconnect_delay(), ReconnectDelay::new(600, 5_000));
+    }
+
+    #[test]
+    fn test_trade_tape_candles() {
+        let trade = |timestamp: u64, id: &str, price: f64| WsTrade {
+            timestamp,
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            volume: 0.5,
+            price,
+            tick_direction: "PlusTick".to_string(),
+            id: id.to_string(),
+            buyer_is_maker: false,
+        };
+        let mut tape = TradeTape::new(4);
+        tape.record(
+            "BTCUSDT",
+            &VecDeque::from([trade(1, "a", 100.0), trade(2, "b", 101.0)]),
+        );
+        // The streams publish their whole buffer again, only the new trades are appended.
+        tape.record(
+            "BTCUSDT",
+            &VecDeque::from([
+                trade(2, "b", 101.0),
+                trade(2, "c", 99.0),
+                trade(3, "d", 102.0),
+                trade(4, "e", 98.0),
+            ]),
+        );
+        let trades: Vec<WsTrade> = tape.trades("BTCUSDT").unwrap().iter().cloned().collect();
+        let ids: Vec<&str> = trades.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c", "d", "e"]);
+
+        let candles = tape.to_candles_ticks("BTCUSDT", 3);
+        assert_eq!(candles, TickCandle::new(trades.clone(), 3));
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            (candles[0].open, candles[0].high, candles[0].low),
+            (101.0, 102.0, 99.0)
+        );
+        assert_eq!(
+            tape.to_candles_volume("BTCUSDT", 1.0),
+            VolumeCandle::new(trades, 1.0)
+        );
+        assert!(tape.to_candles_ticks("ETHUSDT", 3).is_empty());
+    }
+
+    #[test]
+    fn test_leverage_scalar_or_per_symbol() {
+        #[derive(serde::Deserialize)]
+        struct Leveraged {
+            leverage: Leverage,
+        }
+
+        let global: Leveraged = toml::from_str("leverage = 2.0").unwrap();
+        assert_eq!(global.leverage.for_symbol("BTCUSDT"), 2.0);
+
+        let per_symbol: Leveraged =
+            toml::from_str("leverage = { BTCUSDT = 5.0, ETHUSDT = 2.5 }").unwrap();
+        assert_eq!(per_symbol.leverage.for_symbol("BTCUSDT"), 5.0);
+        assert_eq!(per_symbol.leverage.for_symbol("ETHUSDT"), 2.5);
+        assert_eq!(per_symbol.leverage.for_symbol("SOLUSDT"), 1.0);
+    }
+
+    #[test]
+    fn test_log_level_and_file_sink() {
+        let path = std::env::temp_dir().join(format!("rs_smm_test_{}.log", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{}.1", path));
+
+        let logger = Logger::new(LogLevel::Warning).with_file(&path, 0).unwrap();
+        logger.debug("debug message");
+        logger.info("info message");
+        logger.warning("warning message");
+        logger.clone().error("error message");
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("debug message"));
+        assert!(!contents.contains("info message"));
+        assert!(contents.contains("warning message"));
+        assert!(contents.contains("error message"));
+
+        // Past the maximum size the file is moved aside and a new one is started.
+        let logger = Logger::new(LogLevel::Debug).with_file(&path, 64).unwrap();
+        logger.info("first message after rotation");
+        let rotated = std::fs::read_to_string(format!("{}.1", path)).unwrap();
+        assert!(rotated.contains("error message"));
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("first message after rotation"));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(format!("{}.1", path)).unwrap();
+    }
+
+    #[test]
+    fn test_json_log_event() {
+        let path =
+            std::env::temp_dir().join(format!("rs_smm_json_test_{}.log", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+
+        let logger = Logger::new(LogLevel::Info)
+            .with_format(LogFormat::Json)
+            .with_file(&path, 0)
+            .unwrap();
+        logger.event(
+            LogLevel::Info,
+            "BTCUSDT",
+            "fill",
+            &[("side", 1.0), ("price", 100.5), ("qty", 0.2)],
+        );
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let event: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        assert!(event["timestamp"].as_u64().unwrap() > 0);
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["symbol"], "BTCUSDT");
+        assert_eq!(event["event"], "fill");
+        assert_eq!(event["fields"]["price"], 100.5);
+        assert_eq!(event["fields"]["qty"], 0.2);
+    }
+
+    #[test]
+    fn test_api_keys_from_env() {
+        let path = std::env::temp_dir().join(format!("skeleton_env_{}.toml", std::process::id()));
+        let write = |secret: &str| {
+            let config = format!(
+                r#"
+                exchange = "bybit"
+                symbols = ["BTCUSDT"]
+                api_keys = [["env:SKELETON_TEST_KEY", "{}", "BTCUSDT"]]
+                balances = [["BTCUSDT", 1000.0]]
+                leverage = 1.0
+                orders_per_side = 3
+                final_order_distance = 5.0
+                depths = [5]
+                rate_limit = 10
+                bps = [25.0]
+                use_wmid = false
+                "#,
+                secret
+            );
+            std::fs::write(&path, config).unwrap();
+            try_read_toml(path.to_str().unwrap())
+        };
+        std::env::set_var("SKELETON_TEST_KEY", "key_from_env");
+
+        // Referenced values are read from the environment, plain values are kept.
+        let config = write("plain_secret").unwrap();
+        assert_eq!(
+            config.api_keys[0],
+            (
+                "key_from_env".to_string(),
+                "plain_secret".to_string(),
+                "BTCUSDT".to_string()
+            )
+        );
+
+        // A missing variable is reported by name.
+        let error = write("env:SKELETON_TEST_MISSING").unwrap_err();
+        assert!(error.contains("SKELETON_TEST_MISSING"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_config_depths_validation() {
+        let mut config: Config = toml::from_str(
+            r#"
+            exchange = "bybit"
+            symbols = ["BTCUSDT"]
+            api_keys = [["key", "secret", "BTCUSDT"]]
+            balances = [["BTCUSDT", 1000.0]]
+            leverage = 1.0
+            orders_per_side = 3
+            final_order_distance = 5.0
+            depths = [5, 50]
+            rate_limit = 10
+            bps = [25.0]
+            use_wmid = false
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        for depths in [vec![], vec![50, 5], vec![5, 5], vec![0, 5]] {
+            config.depths = depths;
+            assert!(config.validate().unwrap_err().contains("depths"));
+        }
+    }
+
+    #[test]
+    fn test_unknown_symbols() {
+        let valid = vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()];
+        let symbols = vec!["BTCUSDT".to_string(), "BTUSDT".to_string()];
+        assert_eq!(
+            ss::unknown_symbols(&symbols, &valid),
+            vec!["BTUSDT".to_string()]
+        );
+    }
+
     #[tokio::test]
     async fn test_orderbook_both() {
         let (tx, mut rx) = mpsc::unbounded_channel();
         let api_key = "key".to_string();
         let api_secret = "secret".to_string();
-        let bub = BybitClient::init(api_key.clone(), api_secret.clone());
+        let bub = BybitClient::init(api_key.clone(), api_secret.clone(), false);
         let symbol = vec!["NOTUSDT".to_string()];
         let clone_symbol = symbol.clone();
         let (tx2, mut rx2) = mpsc::unbounded_channel::<BinanceMarket>();
-        let bub_2 = BinanceClient::init(api_key, api_secret);
+        let bub_2 = BinanceClient::init(api_key, api_secret, false);
         let symbol_2 = vec!["NOTUSDT".to_string()];
         let clone_symbol_2 = symbol_2.clone();
 
         tokio::spawn(async move {
-            bub.market_subscribe(symbol, tx).await;
+            bub.market_subscribe(symbol, vec![1, 50, 500], tx).await;
         });
 
         let binance_task = tokio::task::spawn_blocking(move || {
-            bub_2.market_subscribe(symbol_2, tx2);
+            bub_2.market_subscribe(symbol_2, vec![5, 10, 20], tx2);
         });
 
         loop {
@@ -79,12 +660,12 @@ mod tests {
         let mut data;
         let api_key = "key".to_string();
         let api_secret = "secret".to_string();
-        let bub = BinanceClient::init(api_key, api_secret);
+        let bub = BinanceClient::init(api_key, api_secret, false);
         let symbol = vec!["ETHUSDT".to_string()];
         let symbol_clone = symbol.clone();
 
         let _webs = tokio::task::spawn_blocking(move || {
-            let _ = bub.market_subscribe(symbol, tx);
+            bub.market_subscribe(symbol, vec![5, 10, 20], tx);
         });
         let mut counter = 0;
 
@@ -104,7 +685,7 @@ mod tests {
     async fn test_time() {
         let api_key = "key".to_string();
         let api_secret = "secret".to_string();
-        let bub = BybitClient::init(api_key, api_secret);
+        let bub = BybitClient::init(api_key, api_secret, false);
         let time = bub.exchange_time().await;
         println!("Time: {}", time);
     }
@@ -113,7 +694,7 @@ mod tests {
     async fn test_bin_time() {
         let api_key = "key".to_string();
         let api_secret = "secret".to_string();
-        let bub = BinanceClient::init(api_key, api_secret);
+        let bub = BinanceClient::init(api_key, api_secret, false);
         let _ = task::spawn_blocking(move || {
             let time = bub.exchange_time();
             println!("Time: {}", time);
@@ -124,20 +705,17 @@ mod tests {
 
     #[tokio::test]
     async fn test_user_stream() {
-        let bub = BinanceClient::init("api".to_string(), "secret".to_string());
+        let bub = BinanceClient::init("api".to_string(), "secret".to_string(), false);
         let (tx, mut rx) = mpsc::unbounded_channel();
         let symbol = "BTCUSDT".to_string();
         tokio::task::spawn_blocking(move || {
-            let _ = bub.private_subscribe(tx, symbol);
+            bub.private_subscribe(tx, symbol);
         });
         while let Some(v) = rx.recv().await {
-            match v.data {
-                PrivateData::Binance(v) => {
-                    for (k, d) in v.orders.iter() {
-                        println!("Private data: {:#?}, {:#?}", k, d);
-                    }
+            if let PrivateData::Binance(v) = v.data {
+                for (k, d) in v.orders.iter() {
+                    println!("Private data: {:#?}, {:#?}", k, d);
                 }
-                _ => {}
             }
         }
     }
@@ -148,7 +726,7 @@ mod tests {
         let api_key = "api".to_string();
         let api_secret = "secret".to_string();
          let symbol = "BTCUSDT".to_string();
-        let bub = BybitClient::init(api_key, api_secret);
+        let bub = BybitClient::init(api_key, api_secret, false);
         tokio::spawn(async move {
             bub.private_subscribe(tx, symbol).await;
         });
@@ -164,7 +742,7 @@ mod tests {
         let _rate = task::spawn_blocking(move || {
             let api_key2 = "api".to_string();
             let api_secret2 = "secret".to_string();
-            let bub = BinanceClient::init(api_key2, api_secret2);
+            let bub = BinanceClient::init(api_key2, api_secret2, false);
             bub.fee_rate();
         })
         .await;
@@ -172,7 +750,7 @@ mod tests {
 
     #[tokio::test]
     pub async fn test_log() {
-        let logger = Logger;
+        let logger = Logger::default();
         logger.info("info");
         logger.success("success");
         logger.debug("debug");
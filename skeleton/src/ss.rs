@@ -3,8 +3,8 @@ use std::fmt::Debug;
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::{mpsc, Mutex};
 
-use crate::exchanges::ex_binance::BinancePrivate;
-use crate::exchanges::ex_bybit::BybitPrivate;
+use crate::exchanges::ex_binance::{BinancePrivate, BINANCE_BOOK_DEPTHS};
+use crate::exchanges::ex_bybit::{BybitPrivate, BYBIT_BOOK_DEPTHS, DEFAULT_BYBIT_DEPTHS};
 use crate::exchanges::exchange::TaggedPrivate;
 use crate::{
     exchanges::{
@@ -23,6 +23,13 @@ pub struct SharedState {
     pub private: HashMap<String, PrivateData>,
     pub markets: Vec<MarketMessage>,
     pub symbols: Vec<String>,
+    pub bybit_depths: Vec<usize>,
+    pub binance_depths: Vec<usize>,
+    pub testnet: bool,
+    // Time in milliseconds a Binance stream has to stay connected for its reconnect delay to reset.
+    pub reconnect_reset_ms: Option<u64>,
+    // Time in milliseconds without market data after which the market streams reconnect.
+    pub heartbeat_timeout_ms: Option<u64>,
 }
 
 impl SharedState {
@@ -37,14 +44,14 @@ impl SharedState {
     /// A new instance of `SharedState` with default values.
     pub fn new(exchange: String) -> Self {
         // Create a new logger
-        let log = Logger;
+        let log = Logger::default();
 
         // Initialize the `SharedState` struct with default values
         Self {
-            exchange: exchange.clone(),                // The exchange where the market is traded
-            logging: log,            // The logger for the application
-            clients: HashMap::new(), // A hashmap to store exchange clients
-            private: HashMap::new(), // A hashmap to store private data
+            exchange: exchange.clone(), // The exchange where the market is traded
+            logging: log,               // The logger for the application
+            clients: HashMap::new(),    // A hashmap to store exchange clients
+            private: HashMap::new(),    // A hashmap to store private data
             markets: match exchange.as_str() {
                 "bybit" => {
                     // If the exchange is "bybit", initialize the `markets` vector with a Bybit market
@@ -64,9 +71,68 @@ impl SharedState {
                 _ => panic!("Invalid exchange"), // Panic if the exchange is not valid
             },
             symbols: Vec::new(), // A vector to store symbols of markets
+            bybit_depths: DEFAULT_BYBIT_DEPTHS.to_vec(), // The Bybit book streams
+            binance_depths: BINANCE_BOOK_DEPTHS.to_vec(), // The Binance partial book streams
+            testnet: false,      // Connect to the mainnet of the exchanges
+            reconnect_reset_ms: None, // Reset the reconnect delays after the default uptime
+            heartbeat_timeout_ms: None, // Reconnect silent market streams after the default timeout
         }
     }
 
+    /// Replaces the logger, e.g. with one built from the config.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logging = logger;
+    }
+
+    /// Connects the clients added afterwards and the market streams to the testnet of the
+    /// exchanges instead of the mainnet.
+    pub fn set_testnet(&mut self, testnet: bool) {
+        self.testnet = testnet;
+    }
+
+    /// Resets the reconnect delay of the Binance streams of the clients added afterwards and of
+    /// the market streams once they stayed connected for `reset_ms`.
+    pub fn set_reconnect_reset(&mut self, reset_ms: Option<u64>) {
+        self.reconnect_reset_ms = reset_ms;
+    }
+
+    /// Reconnects the market streams once they delivered no data for `timeout_ms`.
+    pub fn set_heartbeat_timeout(&mut self, timeout_ms: Option<u64>) {
+        self.heartbeat_timeout_ms = timeout_ms;
+    }
+
+    /// Sets the depths of the Bybit order book streams.
+    ///
+    /// A depth of 1 streams the best bid and ask, the others update the book.
+    ///
+    /// # Panics
+    ///
+    /// If a depth is not one of the linear stream depths 1, 50, 200 or 500.
+    pub fn set_bybit_depths(&mut self, depths: Vec<usize>) {
+        assert!(
+            depths.iter().all(|d| BYBIT_BOOK_DEPTHS.contains(d)),
+            "Bybit book depths must be one of {:?}",
+            BYBIT_BOOK_DEPTHS
+        );
+        self.bybit_depths = depths;
+    }
+
+    /// Sets the depths of the Binance partial book streams.
+    ///
+    /// The diff depth stream of the full book is subscribed to regardless.
+    ///
+    /// # Panics
+    ///
+    /// If a depth is not one of the partial stream depths 5, 10 or 20.
+    pub fn set_binance_depths(&mut self, depths: Vec<usize>) {
+        assert!(
+            depths.iter().all(|d| BINANCE_BOOK_DEPTHS.contains(d)),
+            "Binance book depths must be one of {:?}",
+            BINANCE_BOOK_DEPTHS
+        );
+        self.binance_depths = depths;
+    }
+
     /// Adds clients to the `SharedState` struct.
     ///
     /// # Arguments
@@ -86,16 +152,18 @@ impl SharedState {
         symbol: String,
         exchange: Option<String>,
     ) {
+        let symbol = normalize_symbol(&self.exchange, &symbol);
         // Check the exchange and add the corresponding client.
         match self.exchange.as_str() {
             // If the exchange is "bybit", add a BybitClient.
             "bybit" => {
-                let client = BybitClient::init(key, secret);
+                let client = BybitClient::init(key, secret, self.testnet);
                 self.clients.insert(symbol, ExchangeClient::Bybit(client));
             }
             // If the exchange is "binance", add a BinanceClient.
             "binance" => {
-                let client = BinanceClient::init(key, secret);
+                let client = BinanceClient::init(key, secret, self.testnet)
+                    .with_reconnect_reset(self.reconnect_reset_ms);
                 self.clients.insert(symbol, ExchangeClient::Binance(client));
             }
             // If the exchange is "both", check the `exchange` argument and add the corresponding client.
@@ -104,12 +172,13 @@ impl SharedState {
                     match v.as_str() {
                         // If the `exchange` is "bybit", add a BybitClient.
                         "bybit" => {
-                            let client = BybitClient::init(key, secret);
+                            let client = BybitClient::init(key, secret, self.testnet);
                             self.clients.insert(symbol, ExchangeClient::Bybit(client));
                         }
                         // If the `exchange` is "binance", add a BinanceClient.
                         "binance" => {
-                            let client = BinanceClient::init(key, secret);
+                            let client = BinanceClient::init(key, secret, self.testnet)
+                                .with_reconnect_reset(self.reconnect_reset_ms);
                             self.clients.insert(symbol, ExchangeClient::Binance(client));
                         }
                         // If the `exchange` is neither "bybit" nor "binance", panic.
@@ -123,7 +192,38 @@ impl SharedState {
     }
 
     pub fn add_symbols(&mut self, markets: Vec<String>) {
-        self.symbols.extend(markets);
+        let exchange = self.exchange.clone();
+        self.symbols
+            .extend(markets.iter().map(|s| normalize_symbol(&exchange, s)));
+    }
+
+    /// Returns the configured symbols that are not trading on the selected exchange.
+    ///
+    /// When the exchange is "both", a symbol must be trading on Bybit and Binance. An exchange
+    /// whose instrument list could not be fetched is skipped rather than rejecting every symbol.
+    pub async fn invalid_symbols(&self) -> Vec<String> {
+        let mut invalid = Vec::new();
+        if self.exchange == "bybit" || self.exchange == "both" {
+            let client = BybitClient::init(String::new(), String::new(), self.testnet);
+            let valid = client.valid_symbols().await;
+            if !valid.is_empty() {
+                invalid.extend(unknown_symbols(&self.symbols, &valid));
+            }
+        }
+        if self.exchange == "binance" || self.exchange == "both" {
+            let client = BinanceClient::init(String::new(), String::new(), self.testnet);
+            let valid = tokio::task::spawn_blocking(move || client.valid_symbols())
+                .await
+                .unwrap_or_default();
+            if !valid.is_empty() {
+                for symbol in unknown_symbols(&self.symbols, &valid) {
+                    if !invalid.contains(&symbol) {
+                        invalid.push(symbol);
+                    }
+                }
+            }
+        }
+        invalid
     }
 
     pub fn setup_log(&self, msg: &str) {
@@ -131,6 +231,31 @@ impl SharedState {
     }
 }
 
+/// Returns `symbol` in the form `exchange` lists it, which is the key the symbol is tracked under.
+///
+/// Bybit and Binance list their perpetuals in upper case without a separator, so "btcusdt",
+/// "BTC-USDT" and "btc/usdt" are all "BTCUSDT". The symbols of other exchanges are only trimmed.
+pub fn normalize_symbol(exchange: &str, symbol: &str) -> String {
+    let symbol = symbol.trim();
+    match exchange {
+        "bybit" | "binance" | "both" => symbol
+            .chars()
+            .filter(|c| !matches!(c, '-' | '/'))
+            .collect::<String>()
+            .to_uppercase(),
+        _ => symbol.to_string(),
+    }
+}
+
+/// Returns the symbols that are missing from the list of valid symbols.
+pub fn unknown_symbols(symbols: &[String], valid: &[String]) -> Vec<String> {
+    symbols
+        .iter()
+        .filter(|symbol| !valid.contains(symbol))
+        .cloned()
+        .collect()
+}
+
 /// Asynchronously loads data from the shared state and sends it to the main thread using an unbounded
 /// sender.
 ///
@@ -184,6 +309,10 @@ async fn load_binance(state: SharedState, state_sender: mpsc::UnboundedSender<Sh
     // Clone the symbols and clients from the shared state
     let symbols = state.lock().await.symbols.clone();
     let clients = state.lock().await.clients.clone();
+    let depths = state.lock().await.binance_depths.clone();
+    let testnet = state.lock().await.testnet;
+    let reconnect_reset_ms = state.lock().await.reconnect_reset_ms;
+    let heartbeat_timeout_ms = state.lock().await.heartbeat_timeout_ms;
 
     // Create an unbounded channel to receive market data
     let (sender, mut receiver) = mpsc::unbounded_channel::<BinanceMarket>();
@@ -206,18 +335,20 @@ async fn load_binance(state: SharedState, state_sender: mpsc::UnboundedSender<Sh
                 _ => panic!("Invalid exchange"),
             };
 
-            let _ = subscriber.private_subscribe(sender_clone, symbol);
+            subscriber.private_subscribe(sender_clone, symbol);
         });
     }
 
     // Spawn a blocking task to handle the market subscription
     tokio::task::spawn_blocking(move || {
         // Create a new BinanceClient instance
-        let subscriber = BinanceClient::default();
+        let subscriber = BinanceClient::init(String::new(), String::new(), testnet)
+            .with_reconnect_reset(reconnect_reset_ms)
+            .with_heartbeat_timeout(heartbeat_timeout_ms);
 
         // Subscribe to the specified symbols and send the received data to the sender channel
 
-        let _ = subscriber.market_subscribe(symbols, sender);
+        subscriber.market_subscribe(symbols, depths, sender);
     });
 
     // Process the received market data and update the shared state
@@ -271,6 +402,9 @@ async fn load_bybit(state: SharedState, state_sender: mpsc::UnboundedSender<Shar
     // Clone the symbols and clients from the shared state
     let symbols = state.lock().await.symbols.clone();
     let clients = state.lock().await.clients.clone();
+    let depths = state.lock().await.bybit_depths.clone();
+    let testnet = state.lock().await.testnet;
+    let heartbeat_timeout_ms = state.lock().await.heartbeat_timeout_ms;
 
     // Create an unbounded channel to receive market data
     let (sender, mut receiver) = mpsc::unbounded_channel::<BybitMarket>();
@@ -301,9 +435,10 @@ async fn load_bybit(state: SharedState, state_sender: mpsc::UnboundedSender<Shar
     // Spawn a blocking task to handle the market subscription
     tokio::spawn(async move {
         // Create a new Bybit client and start the market subscription
-        let subscriber = BybitClient::default();
+        let subscriber = BybitClient::init(String::new(), String::new(), testnet)
+            .with_heartbeat_timeout(heartbeat_timeout_ms);
 
-        let _ = subscriber.market_subscribe(symbols, sender).await;
+        let _ = subscriber.market_subscribe(symbols, depths, sender).await;
     });
 
     // Process the received market data and update the shared state
@@ -363,6 +498,13 @@ async fn load_both(state: SharedState, state_sender: mpsc::UnboundedSender<Share
     // Clone the clients for use in the Bybit and Binance tasks.
     let clients = state.lock().await.clients.clone();
 
+    // Clone the book depths to subscribe to on each exchange.
+    let bybit_depths = state.lock().await.bybit_depths.clone();
+    let binance_depths = state.lock().await.binance_depths.clone();
+    let testnet = state.lock().await.testnet;
+    let reconnect_reset_ms = state.lock().await.reconnect_reset_ms;
+    let heartbeat_timeout_ms = state.lock().await.heartbeat_timeout_ms;
+
     // Create unbounded channels for receiving Bybit and Binance market data.
     let (bybit_sender, mut bybit_receiver) = mpsc::unbounded_channel::<BybitMarket>();
     let (binance_sender, mut binance_receiver) = mpsc::unbounded_channel::<BinanceMarket>();
@@ -409,14 +551,19 @@ async fn load_both(state: SharedState, state_sender: mpsc::UnboundedSender<Share
 
     // Spawn a task to subscribe to Bybit market data.
     tokio::spawn(async move {
-        let subscriber = BybitClient::default();
-        let _ = subscriber.market_subscribe(symbols, bybit_sender).await;
+        let subscriber = BybitClient::init(String::new(), String::new(), testnet)
+            .with_heartbeat_timeout(heartbeat_timeout_ms);
+        let _ = subscriber
+            .market_subscribe(symbols, bybit_depths, bybit_sender)
+            .await;
     });
 
     // Spawn a blocking task to subscribe to Binance market data.
     tokio::task::spawn_blocking(move || {
-        let subscriber = BinanceClient::default();
-        let _ = subscriber.market_subscribe(binance_symbols, binance_sender);
+        let subscriber = BinanceClient::init(String::new(), String::new(), testnet)
+            .with_reconnect_reset(reconnect_reset_ms)
+            .with_heartbeat_timeout(heartbeat_timeout_ms);
+        subscriber.market_subscribe(binance_symbols, binance_depths, binance_sender);
     });
 
     // Loop to receive market data from both exchanges.
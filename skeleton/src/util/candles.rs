@@ -10,6 +10,7 @@
 // - high: the highest price in the candle
 // - low: the lowest price in the candle
 // - volume: the total volume traded in the candle
+#[derive(Debug, Clone, PartialEq)]
 pub struct TickCandle {
     pub open: f64,
     pub close: f64,
@@ -97,6 +98,7 @@ impl TickCandle {
 // - high: the highest price in the candle
 // - low: the lowest price in the candle
 // - volume_threshold: the volume threshold for the candle
+#[derive(Debug, Clone, PartialEq)]
 pub struct VolumeCandle {
     pub open: f64,
     pub close: f64,
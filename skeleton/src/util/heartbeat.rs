@@ -0,0 +1,125 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::helpers::generate_timestamp;
+
+/// Time in milliseconds without any event after which a market subscription is reconnected by
+/// default.
+pub const HEARTBEAT_TIMEOUT_MS: u64 = 30_000;
+
+/// Tracks the last time a subscription received an event.
+///
+/// Clones share the same timestamp, so the event handler can record events while the
+/// subscription loop watches for a stall.
+#[derive(Clone, Debug, Default)]
+pub struct Heartbeat {
+    last_seen: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        let heartbeat = Self::default();
+        heartbeat.beat();
+        heartbeat
+    }
+
+    /// Records that an event was received now.
+    pub fn beat(&self) {
+        self.last_seen
+            .store(generate_timestamp(), Ordering::Relaxed);
+    }
+
+    /// Returns `true` if no event was received within `timeout_ms` of `now`.
+    pub fn is_stale(&self, now: u64, timeout_ms: u64) -> bool {
+        now.saturating_sub(self.last_seen.load(Ordering::Relaxed)) >= timeout_ms
+    }
+
+    /// Completes once no event has been received for `timeout_ms`.
+    pub async fn stalled(&self, timeout_ms: u64) {
+        let interval = Duration::from_millis((timeout_ms / 4).max(1));
+        loop {
+            tokio::time::sleep(interval).await;
+            if self.is_stale(generate_timestamp(), timeout_ms) {
+                return;
+            }
+        }
+    }
+}
+
+/// Runs a subscription until it returns or stops receiving events for `timeout_ms`.
+///
+/// A stalled subscription is dropped, which closes its connection, and `None` is returned so
+/// the caller can reconnect.
+pub async fn watch<F: Future>(
+    subscription: F,
+    heartbeat: &Heartbeat,
+    timeout_ms: u64,
+) -> Option<F::Output> {
+    heartbeat.beat();
+    tokio::select! {
+        output = subscription => Some(output),
+        _ = heartbeat.stalled(timeout_ms) => None,
+    }
+}
+
+/// Spawns a thread clearing `running` whenever no event was received for `timeout_ms`, so a
+/// blocking event loop polling it returns and can reconnect.
+///
+/// The thread stops once it holds the last reference to `running`.
+pub fn watch_blocking(heartbeat: Heartbeat, running: Arc<AtomicBool>, timeout_ms: u64) {
+    let interval = Duration::from_millis((timeout_ms / 4).max(1));
+    std::thread::spawn(move || {
+        while Arc::strong_count(&running) > 1 {
+            std::thread::sleep(interval);
+            if heartbeat.is_stale(generate_timestamp(), timeout_ms) {
+                running.store(false, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_silent_stall_triggers_reconnect() {
+        let heartbeat = Heartbeat::new();
+        let stalled = watch(std::future::pending::<()>(), &heartbeat, 50).await;
+        assert!(stalled.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_active_subscription_is_kept() {
+        let heartbeat = Heartbeat::new();
+        let beats = heartbeat.clone();
+        let subscription = async move {
+            // Keep receiving events for longer than the timeout.
+            for _ in 0..10 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                beats.beat();
+            }
+            "closed"
+        };
+        assert_eq!(watch(subscription, &heartbeat, 100).await, Some("closed"));
+    }
+
+    #[test]
+    fn test_silent_blocking_loop_stopped() {
+        let heartbeat = Heartbeat::new();
+        let running = Arc::new(AtomicBool::new(true));
+        watch_blocking(heartbeat, running.clone(), 50);
+        let start = std::time::Instant::now();
+        while running.load(Ordering::Relaxed) {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
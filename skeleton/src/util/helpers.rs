@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     io::Read,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -7,13 +8,16 @@ use num_traits::{Float, Signed};
 
 use serde::Deserialize;
 
+use super::logger::{LogFormat, LogLevel};
+use crate::ss::normalize_symbol;
+
 pub fn round_step<T: Float>(num: T, step: T) -> T {
     (num / step).round() * step
 }
 
 pub fn geometric_weights(ratio: f64, n: usize, reverse: bool) -> Vec<f64> {
     assert!(
-        ratio >= 0.0 && ratio <= 1.0,
+        (0.0..=1.0).contains(&ratio),
         "Ratio must be between 0 and 1"
     );
     // Generate the geometric series
@@ -39,11 +43,47 @@ pub fn generate_timestamp() -> u64 {
         .as_millis() as u64
 }
 
+/// Returns the first funding time strictly after `now` on a fixed schedule starting at midnight
+/// UTC, every `interval_ms` milliseconds.
+pub fn next_funding_time(now: u64, interval_ms: u64) -> u64 {
+    (now / interval_ms + 1) * interval_ms
+}
+
 pub fn calculate_exponent(n: f64) -> f64 {
     let exponent = -0.5 * n;
     f64::exp(exponent)
 }
 
+/// Decay of the weight given to each order book level by its distance from the top.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DepthWeight {
+    /// `exp(-rate * level)`.
+    Exponential { rate: f64 },
+    /// `1 - slope * level`, never below zero.
+    Linear { slope: f64 },
+    /// `(1 + level)^-exponent`.
+    PowerLaw { exponent: f64 },
+}
+
+impl Default for DepthWeight {
+    fn default() -> Self {
+        DepthWeight::Exponential { rate: 0.5 }
+    }
+}
+
+impl DepthWeight {
+    /// Returns the weight of the level `level` places from the top of the book.
+    pub fn weight(&self, level: usize) -> f64 {
+        let level = level as f64;
+        match *self {
+            DepthWeight::Exponential { rate } => f64::exp(-rate * level),
+            DepthWeight::Linear { slope } => (1.0 - slope * level).max(0.0),
+            DepthWeight::PowerLaw { exponent } => (1.0 + level).powf(-exponent),
+        }
+    }
+}
+
 /*
 This function generates a linearly spaced vector of f64 numbers.
 
@@ -191,20 +231,336 @@ pub fn read_toml(path: &str) -> Config {
     let mut contents = String::new();
     file.read_to_string(&mut contents)
         .expect("Unable to read file");
-    toml::from_str(&contents).expect("Unable to parse file")
+    let mut config: Config = toml::from_str(&contents).expect("Unable to parse file");
+    config.resolve_env().unwrap_or_else(|e| panic!("{}", e));
+    config.normalize_symbols();
+    config
+        .validate()
+        .unwrap_or_else(|e| panic!("Invalid config: {}", e));
+    config
+}
+
+/// Reads the config at `path` like `read_toml`, returning an error instead of panicking.
+pub fn try_read_toml(path: &str) -> Result<Config, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut config: Config = toml::from_str(&contents).map_err(|e| e.to_string())?;
+    config.resolve_env()?;
+    config.normalize_symbols();
+    config.validate()?;
+    Ok(config)
+}
+
+// Prefix of the config values read from the environment variable named by the rest of the value.
+const ENV_PREFIX: &str = "env:";
+
+/// Returns the value of the environment variable referenced by `value` as `env:NAME`, or `value`
+/// itself when it does not reference one.
+///
+/// # Errors
+///
+/// If the referenced variable is not set.
+pub fn resolve_env_value(value: &str) -> Result<String, String> {
+    match value.strip_prefix(ENV_PREFIX) {
+        Some(name) => std::env::var(name).map_err(|_| {
+            format!(
+                "Environment variable {} referenced in the config is not set",
+                name
+            )
+        }),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// How the size of each side of the grid is spread over its levels.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SizeDistribution {
+    /// Sizes growing geometrically away from the touch, with `ratio` on the passive side and
+    /// `1 - ratio` on the aggressive side.
+    Geometric { ratio: f64 },
+    /// The same size on every level.
+    Flat,
+    /// Sizes growing linearly away from the touch.
+    Linear,
+}
+
+impl Default for SizeDistribution {
+    fn default() -> Self {
+        SizeDistribution::Geometric { ratio: 0.37 }
+    }
+}
+
+impl SizeDistribution {
+    /// Returns the weights of `n` levels summing to 1, ordered from the touch outward.
+    pub fn weights(self, n: usize, passive: bool) -> Vec<f64> {
+        match self {
+            SizeDistribution::Geometric { ratio } => {
+                let ratio = if passive { ratio } else { 1.0 - ratio };
+                geometric_weights(ratio, n, true)
+            }
+            SizeDistribution::Flat => vec![1.0 / n as f64; n],
+            SizeDistribution::Linear => {
+                let sum = (n * (n + 1) / 2) as f64;
+                (1..=n).map(|i| i as f64 / sum).collect()
+            }
+        }
+    }
+}
+
+/// The unit the size of the grid is budgeted in.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SizingMode {
+    /// Sizes in USD notional derived from the leveraged balance, each level converted to a
+    /// quantity at its own price.
+    #[default]
+    Notional,
+    /// Sizes in coins with a maximum position of `max_position` coins, each level getting the
+    /// same quantity whatever its price.
+    Quantity { max_position: f64 },
+}
+
+/// Leverage given either as one value for every symbol or as a value per symbol.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Leverage {
+    Global(f64),
+    PerSymbol(HashMap<String, f64>),
+}
+
+impl Leverage {
+    /// Returns the leverage of `symbol`, or 1.0 when it is missing from a per-symbol map.
+    pub fn for_symbol(&self, symbol: &str) -> f64 {
+        match self {
+            Leverage::Global(leverage) => *leverage,
+            Leverage::PerSymbol(map) => map.get(symbol).copied().unwrap_or(1.0),
+        }
+    }
+}
+
+/// Where the orders generated inside the best bid and ask are moved to.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TouchClamp {
+    /// At the best bid or ask.
+    Join,
+    /// One tick behind the best bid or ask.
+    Behind,
+}
+
+/// The price the grid is quoted around.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuoteAnchor {
+    /// The mid price of the best bid and ask.
+    #[default]
+    Mid,
+    /// The microprice of the top `depth` levels, moved toward the side with less quantity. The
+    /// depth is capped to the levels both sides of the book hold.
+    Microprice { depth: usize },
+    /// The time weighted average of the mid price over the last `window_ms`.
+    Twap { window_ms: u64 },
+}
+
+/// How the quotes react to the direction of the recent aggressive trades.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeFlowMode {
+    /// Quote tighter on the side the trades aggress, buying closer to the mid price while buyers
+    /// lead.
+    Lean,
+    /// Quote wider on the side the trades aggress, expecting the move to revert.
+    Fade,
+}
+
+/// How long a limit order crossing the book may wait for its fills.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    /// Fills what it can at once and cancels the rest.
+    #[default]
+    Ioc,
+    /// Fills entirely at once or is cancelled.
+    Fok,
+}
+
+impl TimeInForce {
+    /// Returns the name of the time in force in exchange requests.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeInForce::Ioc => "IOC",
+            TimeInForce::Fok => "FOK",
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub exchange: String,
+    pub testnet: Option<bool>,
+    pub reconnect_reset_ms: Option<u64>,
+    pub heartbeat_timeout_ms: Option<u64>,
     pub symbols: Vec<String>,
+    pub disabled_symbols: Option<Vec<String>>,
     pub api_keys: Vec<(String, String, String)>,
+    pub hedge_exchange: Option<String>,
+    pub hedge_api_keys: Option<Vec<(String, String, String)>>,
     pub balances: Vec<(String, f64)>,
-    pub leverage: f64,
+    pub leverage: Leverage,
+    pub inventory_target: Option<f64>,
+    pub max_notional_usd: Option<f64>,
+    pub max_portfolio_notional: Option<f64>,
     pub orders_per_side: usize,
+    pub orders_per_bid: Option<usize>,
+    pub orders_per_ask: Option<usize>,
+    pub grid_layers: Option<Vec<(f64, usize, f64)>>,
     pub final_order_distance: f64,
+    pub requote_cooldown_ms: Option<u64>,
+    pub requote_on_fill: Option<bool>,
+    pub max_order_age_ms: Option<u64>,
+    pub hold_touch: Option<bool>,
+    pub placement_jitter_ms: Option<u64>,
+    pub replace_delay_ms: Option<u64>,
+    pub levels_per_tick: Option<usize>,
     pub depths: Vec<usize>,
+    pub bybit_book_depths: Option<Vec<usize>>,
+    pub binance_book_depths: Option<Vec<usize>>,
     pub rate_limit: u32,
     pub bps: Vec<f64>,
+    pub symbol_bps: Option<Vec<(String, f64)>>,
+    pub min_spread_ticks: Option<f64>,
+    pub maker_fee: Option<f64>,
+    pub spread_schedule: Option<Vec<(u32, u32, f64)>>,
+    pub aggression_min: Option<f64>,
+    pub aggression_max: Option<f64>,
+    pub passive_ratio: Option<f64>,
+    pub size_distribution: Option<SizeDistribution>,
+    pub sizing_mode: Option<SizingMode>,
+    pub skew_deadband: Option<f64>,
+    pub backfill_levels: Option<bool>,
+    pub min_level_notional: Option<f64>,
+    pub requote_threshold_mult: Option<f64>,
+    pub iceberg_display_fraction: Option<f64>,
+    pub flatten_slice_notional: Option<f64>,
+    pub flatten_max_slippage_bps: Option<f64>,
+    pub flatten_time_in_force: Option<TimeInForce>,
+    pub kill_switch: Option<bool>,
+    pub funding_pause_window_ms: Option<u64>,
+    pub risk_per_level_usd: Option<f64>,
+    pub max_drawdown_usd: Option<f64>,
+    pub drawdown_curve: Option<Vec<(f64, f64, f64)>>,
+    pub min_quoting_spread_bps: Option<f64>,
+    pub touch_clamp: Option<TouchClamp>,
+    pub thin_touch_qty: Option<f64>,
+    pub quote_anchor: Option<QuoteAnchor>,
+    pub trade_flow_bias: Option<f64>,
+    pub trade_flow_mode: Option<TradeFlowMode>,
+    pub cancel_on_tight_spread: Option<bool>,
+    pub max_quoting_spread_bps: Option<f64>,
+    pub cancel_on_crossed_book: Option<bool>,
+    pub last_look_bps: Option<f64>,
+    pub min_edge_bps: Option<f64>,
+    pub suspend_on_low_edge: Option<bool>,
+    pub cancel_on_disconnect: Option<bool>,
+    pub max_inflight_requests: Option<usize>,
+    pub fill_weighting: Option<bool>,
+    pub shared_batches: Option<bool>,
+    pub order_tag: Option<String>,
     pub use_wmid: bool,
+    pub depth_weight: Option<DepthWeight>,
+    pub imbalance_deadzone: Option<f64>,
+    pub momentum_weight: Option<f64>,
+    pub warmup_predict_weight: Option<f64>,
+    pub funding_weight: Option<f64>,
+    pub open_interest_weight: Option<f64>,
+    pub history_len: Option<usize>,
+    pub vol_window: Option<usize>,
+    pub markout_horizon_ms: Option<u64>,
+    pub warmup_updates: Option<usize>,
+    pub neutral_warmup: Option<bool>,
+    pub max_trade_age_ms: Option<u64>,
+    pub min_quote_interval_ms: Option<u64>,
+    pub max_private_age_ms: Option<u64>,
+    pub state_file: Option<String>,
+    pub log_level: Option<LogLevel>,
+    pub log_format: Option<LogFormat>,
+    pub log_file: Option<String>,
+    pub log_max_bytes: Option<u64>,
+}
+
+impl Config {
+    /// Returns the symbols that are listed and not disabled.
+    pub fn enabled_symbols(&self) -> Vec<String> {
+        let disabled = self.disabled_symbols.as_deref().unwrap_or_default();
+        self.symbols
+            .iter()
+            .filter(|s| !disabled.contains(s))
+            .cloned()
+            .collect()
+    }
+
+    /// Checks the settings the features index into.
+    ///
+    /// # Errors
+    ///
+    /// If `depths` is empty, holds a depth of 0 or is not strictly ascending, since the first
+    /// depth is the shallow one and the following ones the deeper ones.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.depths.is_empty() {
+            return Err("depths must not be empty".to_string());
+        }
+        if self.depths.contains(&0) {
+            return Err(format!("depths must be positive, got {:?}", self.depths));
+        }
+        if self.depths.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(format!(
+                "depths must be sorted in ascending order, got {:?}",
+                self.depths
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rewrites every symbol of the config in the form its exchange lists it, so a symbol is
+    /// keyed the same way whatever its casing or separator in the file.
+    pub fn normalize_symbols(&mut self) {
+        let exchange = self.exchange.clone();
+        let normalize = |symbol: &mut String| *symbol = normalize_symbol(&exchange, symbol);
+        self.symbols.iter_mut().for_each(normalize);
+        self.disabled_symbols
+            .iter_mut()
+            .flatten()
+            .for_each(normalize);
+        self.api_keys.iter_mut().for_each(|(_, _, s)| normalize(s));
+        self.balances.iter_mut().for_each(|(s, _)| normalize(s));
+        self.symbol_bps
+            .iter_mut()
+            .flatten()
+            .for_each(|(s, _)| normalize(s));
+        if let Leverage::PerSymbol(map) = &mut self.leverage {
+            *map = map
+                .drain()
+                .map(|(s, leverage)| (normalize_symbol(&exchange, &s), leverage))
+                .collect();
+        }
+        let hedge_exchange = self.hedge_exchange.clone().unwrap_or_default();
+        for (_, _, symbol) in self.hedge_api_keys.iter_mut().flatten() {
+            *symbol = normalize_symbol(&hedge_exchange, symbol);
+        }
+    }
+
+    /// Replaces the API keys and secrets written as `env:NAME` with the value of the environment
+    /// variable `NAME`, so they do not have to be stored in the config file.
+    ///
+    /// # Errors
+    ///
+    /// If a referenced variable is not set.
+    pub fn resolve_env(&mut self) -> Result<(), String> {
+        let hedge_keys = self.hedge_api_keys.iter_mut().flatten();
+        for (key, secret, _) in self.api_keys.iter_mut().chain(hedge_keys) {
+            *key = resolve_env_value(key)?;
+            *secret = resolve_env_value(secret)?;
+        }
+        Ok(())
+    }
 }
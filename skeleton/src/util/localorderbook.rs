@@ -3,6 +3,10 @@ use ordered_float::OrderedFloat;
 use std::collections::BTreeMap;
 
 use super::helpers::spread_price_in_bps;
+
+/// Price levels of one side of the book as `(price, qty)` tuples.
+pub type BookLevels = Vec<(f64, f64)>;
+
 #[derive(Debug, Clone)]
 pub struct LocalBook {
     pub asks: BTreeMap<OrderedFloat<f64>, f64>,
@@ -18,6 +22,12 @@ pub struct LocalBook {
     pub last_update: u64,
 }
 
+impl Default for LocalBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LocalBook {
     pub fn new() -> Self {
         Self {
@@ -50,7 +60,8 @@ impl LocalBook {
     /// For each ask in the input list, it updates the ask quantity at the corresponding price in the order book.
     /// If the price does not exist in the order book, it adds a new entry for the ask price and quantity.
     ///
-    /// After updating the bids and asks, it removes any entries with a quantity of 0 from both the bid and ask order books.
+    /// After updating the bids and asks, it removes any entries with a quantity of 0 from both the bid and ask order books
+    /// and recomputes the best bid, best ask and mid price.
     ///
     /// Finally, it updates the last_update timestamp to the input timestamp.
     pub fn update(&mut self, bids: Vec<Bid>, asks: Vec<Ask>, timestamp: u64) {
@@ -77,6 +88,28 @@ impl LocalBook {
         self.bids.retain(|_, &mut v| v != 0.0);
         self.asks.retain(|_, &mut v| v != 0.0);
 
+        self.refresh_bba();
+        self.last_update = timestamp;
+    }
+
+    /// Replaces the levels of the order book with a full depth snapshot, e.g. fetched over REST
+    /// before the deltas of the stream are applied.
+    ///
+    /// Levels with a quantity of 0 are skipped. The best bid, best ask and mid price are
+    /// recomputed and the last update is set to `timestamp`.
+    pub fn load_snapshot(&mut self, bids: Vec<Bid>, asks: Vec<Ask>, timestamp: u64) {
+        self.bids = bids
+            .iter()
+            .filter(|bid| bid.qty != 0.0)
+            .map(|bid| (OrderedFloat::from(bid.price), bid.qty))
+            .collect();
+        self.asks = asks
+            .iter()
+            .filter(|ask| ask.qty != 0.0)
+            .map(|ask| (OrderedFloat::from(ask.price), ask.qty))
+            .collect();
+
+        self.refresh_bba();
         self.last_update = timestamp;
     }
 
@@ -115,35 +148,8 @@ impl LocalBook {
         // Remove any asks with quantity equal to 0
         self.asks.retain(|_, &mut v| v != 0.0);
 
-        // Set the best bid based on the highest bid price and quantity in the order book
-        self.best_bid = self
-            .bids
-            .iter()
-            .next_back()
-            .map(|(price, qty)| Bid {
-                price: **price,
-                qty: *qty,
-            })
-            .unwrap_or_else(|| Bid {
-                price: 0.0,
-                qty: 0.0,
-            });
-        // Set the best ask based on the lowest ask price and quantity in the order boo
-        self.best_ask = self
-            .asks
-            .iter()
-            .next()
-            .map(|(price, qty)| Ask {
-                price: **price,
-                qty: *qty,
-            })
-            .unwrap_or_else(|| Ask {
-                price: 0.0,
-                qty: 0.0,
-            });
-
-        // Calculate the mid price
-        self.set_mid_price();
+        // Set the best bid, best ask and mid price from the order book
+        self.refresh_bba();
         // Update the last update timestamp
         self.last_update = timestamp;
     }
@@ -189,6 +195,14 @@ impl LocalBook {
         // Remove any asks with quantity equal to 0
         self.asks.retain(|_, &mut v| v != 0.0);
 
+        // Set the best bid, best ask and mid price from the order book
+        self.refresh_bba();
+        // Update the last update timestamp
+        self.last_update = timestamp;
+    }
+
+    /// Sets the best bid, best ask and mid price from the extremes of the order book.
+    fn refresh_bba(&mut self) {
         // Set the best bid based on the highest bid price and quantity in the order book
         self.best_bid = self
             .bids
@@ -216,10 +230,59 @@ impl LocalBook {
                 qty: 0.0,
             });
 
-        // Set the mid price
+        // Calculate the mid price
         self.set_mid_price();
-        // Update the last update timestamp
-        self.last_update = timestamp;
+
+        #[cfg(debug_assertions)]
+        if !self.invariants_ok() {
+            eprintln!("Order book invariants violated: {:?}", self.snapshot(1));
+        }
+    }
+
+    /// Checks that the order book is consistent.
+    ///
+    /// The book is consistent when it has no levels with a zero quantity, the highest bid is
+    /// below the lowest ask, and the best bid and best ask match the extremes of the book.
+    pub fn invariants_ok(&self) -> bool {
+        let positive = |(_, qty): (&OrderedFloat<f64>, &f64)| *qty > 0.0 && qty.is_finite();
+        if !self.bids.iter().all(positive) || !self.asks.iter().all(positive) {
+            return false;
+        }
+
+        let highest_bid = self.bids.iter().next_back();
+        let lowest_ask = self.asks.iter().next();
+        let bid_matches = match highest_bid {
+            Some((price, qty)) => self.best_bid.price == **price && self.best_bid.qty == *qty,
+            None => self.best_bid.price == 0.0,
+        };
+        let ask_matches = match lowest_ask {
+            Some((price, qty)) => self.best_ask.price == **price && self.best_ask.qty == *qty,
+            None => self.best_ask.price == 0.0,
+        };
+        let not_crossed = match (highest_bid, lowest_ask) {
+            (Some((bid, _)), Some((ask, _))) => bid < ask,
+            _ => true,
+        };
+
+        bid_matches && ask_matches && not_crossed
+    }
+
+    /// Returns whether the best bid is at or above the best ask.
+    ///
+    /// A crossed book can not be traded against and usually means a feed glitch, so quotes built
+    /// from it would have a negative spread. A book with an empty side is not crossed.
+    pub fn is_crossed(&self) -> bool {
+        self.best_bid.price > 0.0
+            && self.best_ask.price > 0.0
+            && self.best_bid.price >= self.best_ask.price
+    }
+
+    /// Returns whether the tick and lot sizes of the instrument are known.
+    ///
+    /// Both stay at zero when the instrument info could not be fetched, and orders can not be
+    /// rounded without them.
+    pub fn has_instrument_info(&self) -> bool {
+        self.tick_size > 0.0 && self.lot_size > 0.0
     }
 
     fn set_mid_price(&mut self) {
@@ -285,6 +348,56 @@ impl LocalBook {
         spread_price_in_bps(self.get_spread(), self.mid_price)
     }
 
+    /// Get the effective half-spread paid by an aggressor crossing the book.
+    ///
+    /// # Returns
+    ///
+    /// `best_ask - mid` for a buy order and `mid - best_bid` for a sell order, both positive on
+    /// an uncrossed book.
+    pub fn effective_spread(&self, is_buy_order: bool) -> f64 {
+        if is_buy_order {
+            self.best_ask.price - self.mid_price
+        } else {
+            self.mid_price - self.best_bid.price
+        }
+    }
+
+    /// Get the expected average fill price of a market order walking the book.
+    ///
+    /// The order consumes the levels from the best price until `qty` is filled. Any quantity
+    /// beyond the visible depth is assumed to fill at the last visible level.
+    ///
+    /// # Returns
+    ///
+    /// The volume-weighted average fill price, or the best price on that side when `qty` is not
+    /// positive or the side is empty.
+    pub fn market_impact(&self, qty: f64, is_buy: bool) -> f64 {
+        let best = if is_buy {
+            self.best_ask.price
+        } else {
+            self.best_bid.price
+        };
+        let levels: Box<dyn Iterator<Item = (&OrderedFloat<f64>, &f64)>> = if is_buy {
+            Box::new(self.asks.iter())
+        } else {
+            Box::new(self.bids.iter().rev())
+        };
+        let (mut remaining, mut turnover, mut last) = (qty, 0.0, best);
+        for (price, level_qty) in levels {
+            if remaining <= 0.0 {
+                break;
+            }
+            let filled = remaining.min(*level_qty);
+            turnover += filled * **price;
+            remaining -= filled;
+            last = **price;
+        }
+        if qty <= 0.0 || last == 0.0 {
+            return best;
+        }
+        (turnover + remaining.max(0.0) * last) / qty
+    }
+
     /// Get the bids and asks in the order book at the specified depth.
     pub fn get_book_depth(&self, depth: usize) -> (Vec<Ask>, Vec<Bid>) {
         let asks: Vec<Ask> = {
@@ -311,6 +424,44 @@ impl LocalBook {
         };
         (asks, bids)
     }
+
+    /// Get a snapshot of the book as plain `(price, qty)` tuples up to `levels` per side.
+    ///
+    /// # Returns
+    ///
+    /// The bids ordered from the highest price and the asks ordered from the lowest price.
+    pub fn snapshot(&self, levels: usize) -> (BookLevels, BookLevels) {
+        let bids = self
+            .bids
+            .iter()
+            .rev()
+            .take(levels)
+            .map(|(p, q)| (**p, *q))
+            .collect();
+        let asks = self
+            .asks
+            .iter()
+            .take(levels)
+            .map(|(p, q)| (**p, *q))
+            .collect();
+        (bids, asks)
+    }
+
+    /// Returns the microprice of the top `depth` levels, the mid price moved toward the side with
+    /// less quantity resting on it.
+    ///
+    /// The depth is capped to the levels both sides hold, so a thin side is not weighed against
+    /// more levels of the other one. Falls back to the mid price when the levels hold no quantity.
+    pub fn get_microprice(&self, depth: usize) -> f64 {
+        let depth = depth.min(self.bids.len()).min(self.asks.len());
+        let bid_qty: f64 = self.bids.values().rev().take(depth).sum();
+        let ask_qty: f64 = self.asks.values().take(depth).sum();
+        if bid_qty + ask_qty <= 0.0 {
+            return self.mid_price;
+        }
+        (self.best_bid.price * ask_qty + self.best_ask.price * bid_qty) / (bid_qty + ask_qty)
+    }
+
     pub fn get_wmid(&self) -> f64 {
         let imb = self.best_bid.qty / (self.best_bid.qty + self.best_ask.qty);
         self.best_bid.price * imb + self.best_ask.price * (1.0 - imb)
@@ -1,51 +1,257 @@
-use std::fmt;
-use super::helpers::generate_timestamp;
+use super::helpers::{generate_timestamp, Config};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
-#[derive(Debug, Clone)]
-pub struct Logger;
+// Size in bytes at which the log file is rotated when no size is configured.
+const DEFAULT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct Logger {
+    min_level: LogLevel,
+    format: LogFormat,
+    sink: Option<Arc<Mutex<FileSink>>>,
+}
 
 impl Logger {
+    /// Creates a logger that prints the messages at or above `min_level` to stdout.
+    pub fn new(min_level: LogLevel) -> Self {
+        Self {
+            min_level,
+            format: LogFormat::default(),
+            sink: None,
+        }
+    }
+
+    /// Writes every message in `format`.
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Also appends the logged messages to the file at `path`.
+    ///
+    /// Once the file grows past `max_bytes` it is moved to `<path>.1`, replacing the previous
+    /// one, and a new file is started. A `max_bytes` of 0 disables rotation.
+    pub fn with_file(mut self, path: &str, max_bytes: u64) -> io::Result<Self> {
+        self.sink = Some(Arc::new(Mutex::new(FileSink::open(path, max_bytes)?)));
+        Ok(self)
+    }
+
+    /// Creates the logger from the `log_level`, `log_format`, `log_file` and `log_max_bytes`
+    /// settings.
+    ///
+    /// # Panics
+    ///
+    /// If the log file cannot be opened.
+    pub fn from_config(config: &Config) -> Self {
+        let logger = Self::new(config.log_level.unwrap_or_default())
+            .with_format(config.log_format.unwrap_or_default());
+        match &config.log_file {
+            Some(path) => logger
+                .with_file(path, config.log_max_bytes.unwrap_or(DEFAULT_LOG_MAX_BYTES))
+                .expect("Unable to open log file"),
+            None => logger,
+        }
+    }
+
     pub fn log(level: LogLevel, msg: &str) {
+        println!("{}", Self::format(level, msg));
+    }
+
+    fn format(level: LogLevel, msg: &str) -> String {
         let now = generate_timestamp() / 1000; // Convert milliseconds to seconds
-        let (h, m, s) = (now / 3600, (now % 3600) / 60, now % 60);
-        println!("{:02}:{:02}:{:02} | {:<8} | {}", h, m, s, level, msg);
+        let (h, m, s) = ((now / 3600) % 24, (now % 3600) / 60, now % 60);
+        format!("{:02}:{:02}:{:02} | {:<8} | {}", h, m, s, level, msg)
+    }
+
+    /// Formats a message as a JSON object with its timestamp in milliseconds.
+    fn format_json(level: LogLevel, msg: &str) -> String {
+        json!({
+            "timestamp": generate_timestamp(),
+            "level": level.to_string(),
+            "message": msg,
+        })
+        .to_string()
+    }
+
+    /// Formats an event of `symbol` as a JSON object with its timestamp in milliseconds and its
+    /// fields nested under `fields`.
+    fn format_event_json(
+        level: LogLevel,
+        symbol: &str,
+        event: &str,
+        fields: &[(&str, f64)],
+    ) -> String {
+        let fields: Map<String, Value> = fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), json!(value)))
+            .collect();
+        json!({
+            "timestamp": generate_timestamp(),
+            "level": level.to_string(),
+            "symbol": symbol,
+            "event": event,
+            "fields": fields,
+        })
+        .to_string()
+    }
+
+    /// Logs an event of `symbol` with numeric fields, as a JSON object in the JSON format or as
+    /// `symbol event key=value ...` in the plain format.
+    pub fn event(&self, level: LogLevel, symbol: &str, event: &str, fields: &[(&str, f64)]) {
+        if !self.enabled(level) {
+            return;
+        }
+        let line = match self.format {
+            LogFormat::Plain => {
+                let mut msg = format!("{} {}", symbol, event);
+                for (key, value) in fields {
+                    msg.push_str(&format!(" {}={}", key, value));
+                }
+                Self::format(level, &msg)
+            }
+            LogFormat::Json => Self::format_event_json(level, symbol, event, fields),
+        };
+        self.write(&line);
+    }
+
+    fn enabled(&self, level: LogLevel) -> bool {
+        level.severity() >= self.min_level.severity()
+    }
+
+    /// Prints the message and writes it to the log file if it is at or above the minimum level.
+    fn emit(&self, level: LogLevel, msg: &str) {
+        if !self.enabled(level) {
+            return;
+        }
+        let line = match self.format {
+            LogFormat::Plain => Self::format(level, msg),
+            LogFormat::Json => Self::format_json(level, msg),
+        };
+        self.write(&line);
+    }
+
+    /// Prints a formatted line and appends it to the log file, if any.
+    fn write(&self, line: &str) {
+        println!("{}", line);
+        if let Some(sink) = &self.sink {
+            if let Ok(mut sink) = sink.lock() {
+                if let Err(e) = sink.write_line(line) {
+                    eprintln!("Unable to write to log file: {}", e);
+                }
+            }
+        }
     }
 
     pub fn success(&self, msg: &str) {
-        Self::log(LogLevel::Success, msg);
+        self.emit(LogLevel::Success, msg);
     }
 
     pub fn info(&self, msg: &str) {
-        Self::log(LogLevel::Info, msg);
+        self.emit(LogLevel::Info, msg);
     }
 
     pub fn debug(&self, msg: &str) {
-        Self::log(LogLevel::Debug, msg);
+        self.emit(LogLevel::Debug, msg);
     }
 
     pub fn warning(&self, msg: &str) {
-        Self::log(LogLevel::Warning, msg);
+        self.emit(LogLevel::Warning, msg);
     }
 
     pub fn error(&self, msg: &str) {
-        Self::log(LogLevel::Error, msg);
+        self.emit(LogLevel::Error, msg);
     }
 
     pub fn critical(&self, msg: &str) {
-        Self::log(LogLevel::Critical, msg);
+        self.emit(LogLevel::Critical, msg);
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// A log file that is rotated once it reaches a maximum size.
+#[derive(Debug)]
+struct FileSink {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+}
+
+impl FileSink {
+    fn open(path: &str, max_bytes: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: PathBuf::from(path),
+            file,
+            size,
+            max_bytes,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.max_bytes > 0 && self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// How the logged messages are written.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable `time | level | message` lines.
+    #[default]
+    Plain,
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Success,
     Info,
+    #[default]
     Debug,
     Warning,
     Error,
     Critical,
 }
 
+impl LogLevel {
+    /// Returns the rank of the level, messages below the minimum rank are not logged.
+    fn severity(self) -> u8 {
+        match self {
+            LogLevel::Debug => 0,
+            LogLevel::Info | LogLevel::Success => 1,
+            LogLevel::Warning => 2,
+            LogLevel::Error => 3,
+            LogLevel::Critical => 4,
+        }
+    }
+}
+
 impl fmt::Display for LogLevel {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         const LEVEL_NAMES: [&str; 6] = ["SUCCESS", "INFO", "DEBUG", "WARNING", "ERROR", "CRITICAL"];
@@ -1,5 +1,7 @@
+pub mod heartbeat;
 pub mod helpers;
 pub mod localorderbook;
 pub mod candles;
 pub mod logger;
-pub mod ema;
\ No newline at end of file
+pub mod ema;
+pub mod trade_tape;
\ No newline at end of file
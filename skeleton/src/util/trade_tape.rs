@@ -0,0 +1,79 @@
+use std::collections::{HashMap, VecDeque};
+
+use bybit::model::WsTrade;
+
+use super::candles::{TickCandle, VolumeCandle};
+
+/// A rolling window of the latest trades of each symbol, to build candles from.
+///
+/// The market streams publish their whole buffer of recent trades on every update, so recording
+/// the same trade twice is avoided by only appending the trades that are not on the tape yet.
+#[derive(Debug, Clone)]
+pub struct TradeTape {
+    capacity: usize,
+    trades: HashMap<String, VecDeque<WsTrade>>,
+}
+
+impl TradeTape {
+    /// Creates a tape keeping the latest `capacity` trades of each symbol.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Trade tape capacity must be positive");
+        Self {
+            capacity,
+            trades: HashMap::new(),
+        }
+    }
+
+    /// Appends the trades of `symbol` newer than the tape, dropping the oldest trades beyond the
+    /// capacity.
+    ///
+    /// Trades older than the last trade on the tape are skipped, and so are the trades at its
+    /// timestamp that were already recorded.
+    pub fn record(&mut self, symbol: &str, trades: &VecDeque<WsTrade>) {
+        let tape = self.trades.entry(symbol.to_string()).or_default();
+        for trade in trades {
+            if let Some(last) = tape.back() {
+                let recorded = trade.timestamp == last.timestamp
+                    && tape
+                        .iter()
+                        .rev()
+                        .take_while(|t| t.timestamp == last.timestamp)
+                        .any(|t| t.id == trade.id);
+                if trade.timestamp < last.timestamp || recorded {
+                    continue;
+                }
+            }
+            tape.push_back(trade.clone());
+        }
+        while tape.len() > self.capacity {
+            tape.pop_front();
+        }
+    }
+
+    /// Returns the trades of `symbol` on the tape, oldest first.
+    pub fn trades(&self, symbol: &str) -> Option<&VecDeque<WsTrade>> {
+        self.trades.get(symbol)
+    }
+
+    /// Builds candles of `ticks` trades from the tape of `symbol`, the last one possibly partial.
+    pub fn to_candles_ticks(&self, symbol: &str, ticks: usize) -> Vec<TickCandle> {
+        TickCandle::new(self.collect(symbol), ticks)
+    }
+
+    /// Builds candles of `threshold` traded volume from the tape of `symbol`, the last one
+    /// possibly partial.
+    pub fn to_candles_volume(&self, symbol: &str, threshold: f64) -> Vec<VolumeCandle> {
+        VolumeCandle::new(self.collect(symbol), threshold)
+    }
+
+    fn collect(&self, symbol: &str) -> Vec<WsTrade> {
+        self.trades
+            .get(symbol)
+            .map(|tape| tape.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
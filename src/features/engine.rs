@@ -1,11 +1,21 @@
 use std::collections::VecDeque;
 
 use bybit::model::WsTrade;
-use skeleton::util::localorderbook::LocalBook;
+use ndarray::{Array1, Array2};
+use skeleton::{
+    exchanges::exchange::Ticker,
+    util::{helpers::DepthWeight, localorderbook::LocalBook},
+};
 
 use super::{
-    imbalance::{imbalance_ratio, trade_imbalance, voi, wmid},
-    impact::{avg_trade_price, expected_return, mid_price_basis, price_flu, price_impact},
+    imbalance::{
+        get_weighted_mid, imbalance_ratio_weighted, trade_flow_windowed, trade_imbalance_windowed,
+        trade_momentum_windowed, voi, DEFAULT_IMBALANCE_DEADZONE,
+    },
+    impact::{
+        avg_trade_price, expected_return, mid_price_basis, price_flu, price_impact,
+        trade_volatility,
+    },
 };
 
 const IMB_WEIGHT: f64 = 0.25;
@@ -14,6 +24,24 @@ const EXP_RET_WEIGHT: f64 = 0.10;
 const DEEP_IMB_WEIGHT: f64 = 0.20;
 const MID_BASIS_WEIGHT: f64 = 0.10;
 const VOI_WEIGHT: f64 = 0.10;
+// Log return per tick beyond which the move is treated as a directional signal.
+const PREDICT_THRESHOLD: f64 = 0.0005;
+// Time horizon in milliseconds of the trades used for the trade imbalance.
+const TRADE_IMB_WINDOW_MS: u64 = 5_000;
+// Time horizon in milliseconds of the trades used for the trade volatility.
+const TRADE_VOL_WINDOW_MS: u64 = 5_000;
+// Number of samples kept in the history when no length is configured.
+const DEFAULT_HISTORY_LEN: usize = 1_000;
+// Funding rate, as a fraction, at which the funding term of the skew is at its full weight.
+const FUNDING_SCALE: f64 = 0.001;
+// Relative change of the open interest at which the open interest term of the skew is at its
+// full weight.
+const OI_CHANGE_SCALE: f64 = 0.01;
+
+/// A sample of the market state as (timestamp, mid price, spread in bps, skew).
+pub type HistorySample = (u64, f64, f64, f64);
+/// The features of a regression sample as (volume of interest, price impact, imbalance ratio).
+pub type RegressionFeatures = [f64; 3];
 
 #[derive(Clone, Debug)]
 pub struct Engine {
@@ -22,12 +50,42 @@ pub struct Engine {
     pub wmid: f64,
     pub voi: f64,
     pub trade_imb: f64,
+    pub trade_flow: f64,     // -1 to 1, positive when buyers are aggressing
+    pub trade_momentum: f64, // -1 to 1, net aggressive notional over the traded notional
     pub price_impact: f64,
     pub expected_return: f64,
+    pub predicted_value: f64,
     pub price_flu: (VecDeque<f64>, f64), // in bps
+    pub trade_vol: f64,                  // in bps
     pub mid_price_basis: f64,
     pub avg_trade_price: f64,
     pub skew: f64,
+    pub funding_rate: f64,
+    pub open_interest: f64,
+    pub oi_change: f64, // relative change of the open interest between its last two values
+    pub volume_24h: f64,
+    history: VecDeque<HistorySample>,
+    history_len: usize,
+    // The last `tick_window` mid prices with the features of the same update, oldest first.
+    regression_window: VecDeque<(f64, RegressionFeatures)>,
+    updates: usize,
+    depth_weight: DepthWeight,
+    imbalance_deadzone: f64,
+    momentum_weight: f64,
+    // Number of price fluctuations the volatility is averaged over, `tick_window` when unset.
+    vol_window: Option<usize>,
+    funding_weight: f64,
+    oi_weight: f64,
+    // Whether the regression window is full, and the weight of the prediction in the skew until
+    // then.
+    predictor_ready: bool,
+    warmup_predict_weight: f64,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
@@ -38,12 +96,35 @@ impl Engine {
             wmid: 0.0,
             voi: 0.0,
             trade_imb: 0.0,
+            trade_flow: 0.0,
+            trade_momentum: 0.0,
             price_impact: 0.0,
             expected_return: 0.0,
+            predicted_value: 0.0,
             price_flu: (VecDeque::new(), 0.0),
+            trade_vol: 0.0,
             avg_trade_price: 0.0,
             mid_price_basis: 0.0,
             skew: 0.0,
+            funding_rate: 0.0,
+            open_interest: 0.0,
+            oi_change: 0.0,
+            volume_24h: 0.0,
+            history: VecDeque::new(),
+            history_len: DEFAULT_HISTORY_LEN,
+            regression_window: VecDeque::new(),
+            updates: 0,
+            depth_weight: DepthWeight::default(),
+            imbalance_deadzone: DEFAULT_IMBALANCE_DEADZONE,
+            // Leave the trade momentum out of the skew until a weight is configured.
+            momentum_weight: 0.0,
+            vol_window: None,
+            // Leave the ticker out of the skew until weights are configured.
+            funding_weight: 0.0,
+            oi_weight: 0.0,
+            predictor_ready: false,
+            // Leave the prediction out of the skew during the warm-up.
+            warmup_predict_weight: 0.0,
         }
     }
 
@@ -57,8 +138,12 @@ impl Engine {
     /// * `prev_trades` - The previous trades data.
     /// * `prev_avg` - The average trade price of the previous order book.
     /// * `depth` - The depths at which to calculate imbalance and spread.
-    /// * `tick_window` - The number of ticks to consider when calculating `avg_trade_price`.
+    /// * `tick_window` - The number of ticks to consider when calculating `avg_trade_price`, also
+    ///   the number of samples kept for the regression and, unless a volatility window is set,
+    ///   for the price fluctuations.
     /// * `use_wmid` - Whether to use the weighted mid price for determining skew or not.
+    /// * `ticker` - The latest ticker of the symbol, if the exchange streams one.
+    #[allow(clippy::too_many_arguments)]
     pub fn update(
         &mut self,
         curr_book: &LocalBook,
@@ -69,15 +154,33 @@ impl Engine {
         depth: Vec<usize>,
         tick_window: usize,
         use_wmid: bool,
+        ticker: Option<&Ticker>,
     ) {
         // Update imbalance ratio
-        self.imbalance_ratio = imbalance_ratio(curr_book, Some(depth[0]));
+        self.imbalance_ratio = imbalance_ratio_weighted(
+            curr_book,
+            Some(depth[0]),
+            self.depth_weight,
+            self.imbalance_deadzone,
+        );
         // Update deep imbalance ratio
-        self.deep_imbalance_ratio = imbalance_ratio(curr_book, Some(depth[1]));
+        self.deep_imbalance_ratio = imbalance_ratio_weighted(
+            curr_book,
+            Some(depth[1]),
+            self.depth_weight,
+            self.imbalance_deadzone,
+        );
         // Update volume of interest
         self.voi = voi(curr_book, prev_book, Some(depth[0]));
-        // Update trade imbalance
-        self.trade_imb = trade_imbalance(curr_trades);
+        // Update trade imbalance over the trades within the last window
+        self.trade_imb =
+            trade_imbalance_windowed(curr_trades, curr_book.last_update, TRADE_IMB_WINDOW_MS);
+        // Update the direction of the aggressive trades over the same window
+        self.trade_flow =
+            trade_flow_windowed(curr_trades, curr_book.last_update, TRADE_IMB_WINDOW_MS);
+        // Update the net aggressive notional over the same window
+        self.trade_momentum =
+            trade_momentum_windowed(curr_trades, curr_book.last_update, TRADE_IMB_WINDOW_MS);
         // Update price impact
         self.price_impact = price_impact(curr_book, prev_book, Some(depth[0]));
         // Update price flu
@@ -87,10 +190,30 @@ impl Engine {
         // Update expected return
         self.expected_return = expected_return(prev_book.mid_price, curr_book.mid_price);
         
-        self.price_flu.1 = self.avg_flu_value(curr_book);
+        self.price_flu.1 = self.avg_flu_value(curr_book, tick_window);
+        // Update the realized volatility of the trade prices
+        self.trade_vol = trade_volatility(curr_trades, curr_book.last_update, TRADE_VOL_WINDOW_MS);
 
         // Update weighted mid price
-        self.wmid = (wmid(curr_book, self.imbalance_ratio) / curr_book.mid_price).ln();
+        let wmid_price = get_weighted_mid(
+            curr_book,
+            depth[0],
+            self.depth_weight,
+            self.imbalance_deadzone,
+        );
+        self.wmid = (wmid_price / curr_book.mid_price).ln();
+
+        // Update predicted value using where the weighted mid sits inside the spread, the return
+        // from an empty previous book is meaningless.
+        self.predicted_value = if prev_book.mid_price > 0.0 {
+            predicted_value(
+                self.expected_return,
+                wmid_price - curr_book.best_bid.price,
+                curr_book.best_ask.price - wmid_price,
+            )
+        } else {
+            0.0
+        };
 
         // Update average trade price
         self.avg_trade_price = avg_trade_price(
@@ -106,26 +229,196 @@ impl Engine {
             curr_book.get_mid_price(),
             self.avg_trade_price,
         );
+        // Update the funding and open interest
+        if let Some(ticker) = ticker {
+            self.update_ticker(ticker);
+        }
+        // Record the sample for the regression
+        self.record_regression_sample(curr_book.mid_price, tick_window);
+        self.predictor_ready = self.regression_window.len() >= tick_window;
         // Generate skew
         self.generate_skew(use_wmid);
+        // Record the sample for charting
+        self.record_history(curr_book);
+        self.updates += 1;
+    }
+
+    /// Returns the number of updates the features were computed from.
+    pub fn update_count(&self) -> usize {
+        self.updates
+    }
+
+    /// Returns whether the features were updated over a full tick window, so the skew is built
+    /// from enough data to trust.
+    pub fn is_ready(&self) -> bool {
+        self.predictor_ready
+    }
+
+    /// Sets the weighting of the order book levels in the imbalances and the weighted mid price.
+    pub fn set_depth_weight(&mut self, weighting: DepthWeight) {
+        self.depth_weight = weighting;
+    }
+
+    /// Sets the imbalance ratio within which the book is treated as balanced.
+    ///
+    /// # Panics
+    ///
+    /// If `deadzone` is outside of [0, 1).
+    pub fn set_imbalance_deadzone(&mut self, deadzone: f64) {
+        assert!(
+            (0.0..1.0).contains(&deadzone),
+            "Imbalance deadzone must be between 0 and 1"
+        );
+        self.imbalance_deadzone = deadzone;
+    }
+
+    /// Sets the weight of the trade momentum in the skew, 0 leaves it out.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is outside of [0, 1].
+    pub fn set_momentum_weight(&mut self, weight: f64) {
+        assert!(
+            (0.0..=1.0).contains(&weight),
+            "Momentum weight must be between 0 and 1"
+        );
+        self.momentum_weight = weight;
+    }
+
+    /// Sets the weight (0 to 1) of the predicted direction in the skew until the regression
+    /// window holds `tick_window` samples, 0 leaves it out during the warm-up.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is outside of [0, 1].
+    pub fn set_warmup_predict_weight(&mut self, weight: f64) {
+        assert!(
+            (0.0..=1.0).contains(&weight),
+            "Warm-up prediction weight must be between 0 and 1"
+        );
+        self.warmup_predict_weight = weight;
+    }
+
+    /// Sets the weights (0 to 1) in the skew of the funding rate and of the change of the open
+    /// interest, 0 leaves them out.
+    ///
+    /// A positive funding rate, longs paying shorts, leans the skew short. A rising open interest
+    /// leans the skew with the trade flow, since the aggressors are opening new positions, and a
+    /// falling one against it.
+    ///
+    /// # Panics
+    ///
+    /// If a weight is outside of [0, 1].
+    pub fn set_ticker_weights(&mut self, funding_weight: f64, oi_weight: f64) {
+        assert!(
+            (0.0..=1.0).contains(&funding_weight) && (0.0..=1.0).contains(&oi_weight),
+            "Ticker weights must be between 0 and 1"
+        );
+        self.funding_weight = funding_weight;
+        self.oi_weight = oi_weight;
+    }
+
+    /// Captures the fields of the ticker, keeping the previous values of the missing ones.
+    fn update_ticker(&mut self, ticker: &Ticker) {
+        if let Some(funding_rate) = ticker.funding_rate {
+            self.funding_rate = funding_rate;
+        }
+        if let Some(open_interest) = ticker.open_interest {
+            if self.open_interest > 0.0 && open_interest != self.open_interest {
+                self.oi_change = open_interest / self.open_interest - 1.0;
+            }
+            self.open_interest = open_interest;
+        }
+        if let Some(volume_24h) = ticker.volume_24h {
+            self.volume_24h = volume_24h;
+        }
+    }
+
+    /// Sets the number of price fluctuations the volatility is averaged over, `None` uses the
+    /// tick window.
+    ///
+    /// # Panics
+    ///
+    /// If `window` is 0.
+    pub fn set_vol_window(&mut self, window: Option<usize>) {
+        if let Some(window) = window {
+            assert!(window > 0, "Volatility window must be positive");
+        }
+        self.vol_window = window;
+    }
+
+    /// Sets the number of samples kept in the history, dropping the oldest ones beyond it.
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history_len = len;
+        while self.history.len() > len {
+            self.history.pop_front();
+        }
+    }
+
+    /// Returns the recent (timestamp, mid price, spread in bps, skew) samples, oldest first.
+    pub fn history(&self) -> &VecDeque<HistorySample> {
+        &self.history
+    }
+
+    /// Appends the current state of the book and skew to the history.
+    fn record_history(&mut self, book: &LocalBook) {
+        if self.history_len == 0 {
+            return;
+        }
+        remove_elements_at_capacity(&mut self.history, self.history_len);
+        self.history.push_back((
+            book.last_update,
+            book.mid_price,
+            book.get_spread_in_bps(),
+            self.skew,
+        ));
+    }
+
+    /// Appends the mid price with the current features, dropping the oldest sample once the
+    /// window holds `tick_window` of them.
+    fn record_regression_sample(&mut self, mid_price: f64, tick_window: usize) {
+        if tick_window == 0 {
+            return;
+        }
+        while self.regression_window.len() >= tick_window {
+            self.regression_window.pop_front();
+        }
+        self.regression_window.push_back((
+            mid_price,
+            [self.voi, self.price_impact, self.imbalance_ratio],
+        ));
+    }
+
+    /// Returns the mid prices and the feature rows of the regression window, oldest first.
+    ///
+    /// Row `i` of the features was computed on the same update as mid price `i`.
+    pub fn regression_samples(&self) -> (Array1<f64>, Array2<f64>) {
+        let mid_prices = self.regression_window.iter().map(|(mid, _)| *mid).collect();
+        let features = Array2::from_shape_fn((self.regression_window.len(), 3), |(i, j)| {
+            self.regression_window[i].1[j]
+        });
+        (mid_prices, features)
     }
 
     /// Calculates the average value of the price fluctuation values.
     ///
-    /// Removes elements from the `price_flu.0` VecDeque until its length is
-    /// less than or equal to 1500 and then calculates the average value of the
-    /// remaining elements.
+    /// Removes elements from the `price_flu.0` VecDeque until it holds no more than the
+    /// volatility window, `tick_window` when unset, and then calculates the average value of
+    /// the remaining elements.
     ///
     /// # Returns
     /// The average value of the price_flu values.
-    fn avg_flu_value(&mut self, book: &LocalBook) -> f64 {
+    fn avg_flu_value(&mut self, book: &LocalBook, tick_window: usize) -> f64 {
         // Check if the VecDeque is empty
         if self.price_flu.0.is_empty() {
             // Return 0.0 if the VecDeque is empty
             0.0
         } else {
-            // Remove elements from the VecDeque until its length is less than or equal to 1500
-            remove_elements_at_capacity(&mut self.price_flu.0, 600);
+            // Remove the oldest elements beyond the volatility window
+            let window = self.vol_window.unwrap_or(tick_window);
+            while self.price_flu.0.len() > window {
+                self.price_flu.0.pop_front();
+            }
             // Calculate the average value of the fluctuations
             self.price_flu.0.iter().sum::<f64>() * book.tick_size
         }
@@ -135,15 +428,12 @@ impl Engine {
         let imb = self.imbalance_ratio * IMB_WEIGHT; // -1 to 1
         let trade_imb = self.trade_imb * TRADE_IMB_WEIGHT; // 0 to 1
         let deep_imb = self.deep_imbalance_ratio * DEEP_IMB_WEIGHT; // -1 to 1
-        let exp_ret = {
-            if self.expected_return > 0.0 {
-                0.5 * EXP_RET_WEIGHT
-            } else if self.expected_return < 0.0 {
-                -0.5 * EXP_RET_WEIGHT
-            } else {
-                0.0
-            }
+        let predict_weight = if self.predictor_ready {
+            1.0
+        } else {
+            self.warmup_predict_weight
         };
+        let exp_ret = self.predicted_value * 0.5 * EXP_RET_WEIGHT * predict_weight;
         let voi = {
             if self.voi > 0.0 {
                 0.5 * VOI_WEIGHT
@@ -154,6 +444,11 @@ impl Engine {
             }
         };
         let wmid = self.wmid * EXP_RET_WEIGHT;
+        let momentum = self.trade_momentum * self.momentum_weight; // -1 to 1
+        let funding = -(self.funding_rate / FUNDING_SCALE).clamp(-1.0, 1.0) * self.funding_weight;
+        let oi_change = (self.oi_change / OI_CHANGE_SCALE).clamp(-1.0, 1.0);
+        let oi = oi_change * self.trade_flow * self.oi_weight;
+        let ticker = funding + oi; // -1 to 1
         let mid_b = {
             if self.mid_price_basis > 0.0 {
                 0.5 * MID_BASIS_WEIGHT
@@ -161,14 +456,39 @@ impl Engine {
                 -0.5 * MID_BASIS_WEIGHT
             }
         };
-        if use_wmid == true {
-            self.skew = imb + trade_imb + deep_imb + voi + mid_b + wmid;
+        if use_wmid {
+            self.skew = imb + trade_imb + deep_imb + voi + mid_b + wmid + momentum + ticker;
         } else {
-            self.skew = imb + trade_imb + deep_imb + voi + mid_b + exp_ret;
+            self.skew = imb + trade_imb + deep_imb + voi + mid_b + exp_ret + momentum + ticker;
         }
     }
 }
 
+/// Predicts the short-term direction of the mid price.
+///
+/// The expected return is split into mutually exclusive bands: strong up, strong down and neutral.
+/// A directional band is only used when the position of the weighted mid inside the spread does not
+/// contradict it.
+///
+/// # Arguments
+///
+/// * `expected_return` - The log return of the mid price since the last tick.
+/// * `distance_to_bid` - The distance from the weighted mid to the best bid.
+/// * `distance_to_ask` - The distance from the weighted mid to the best ask.
+///
+/// # Returns
+///
+/// `1.0` for an up prediction, `-1.0` for a down prediction and `0.0` otherwise.
+pub fn predicted_value(expected_return: f64, distance_to_bid: f64, distance_to_ask: f64) -> f64 {
+    let leaning_up = distance_to_ask < distance_to_bid;
+    let leaning_down = distance_to_bid < distance_to_ask;
+    match expected_return {
+        x if x >= PREDICT_THRESHOLD && !leaning_down => 1.0,
+        x if x <= -PREDICT_THRESHOLD && !leaning_up => -1.0,
+        _ => 0.0,
+    }
+}
+
 /// Removes elements from the front of `data` until the length is less than or equal to `capacity`.
 ///
 /// # Arguments
@@ -182,3 +502,189 @@ pub fn remove_elements_at_capacity<T>(data: &mut VecDeque<T>, capacity: usize) {
         data.pop_front();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_keeps_recent_samples() {
+        let mut engine = Engine::new();
+        engine.set_history_len(3);
+        let mut book = LocalBook::new();
+        for i in 0..5 {
+            book.last_update = i;
+            book.mid_price = 100.0 + i as f64;
+            engine.skew = i as f64 / 10.0;
+            engine.record_history(&book);
+        }
+
+        let samples: Vec<(u64, f64, f64)> = engine
+            .history()
+            .iter()
+            .map(|&(time, mid, _, skew)| (time, mid, skew))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![(2, 102.0, 0.2), (3, 103.0, 0.3), (4, 104.0, 0.4)]
+        );
+
+        engine.set_history_len(1);
+        assert_eq!(engine.history().len(), 1);
+        assert_eq!(engine.history()[0].0, 4);
+    }
+
+    #[test]
+    fn test_flu_buffer_keeps_vol_window() {
+        let mut engine = Engine::new();
+        let mut book = LocalBook::new();
+        book.tick_size = 0.01;
+        let push = |engine: &mut Engine, count: usize, tick_window: usize| {
+            for _ in 0..count {
+                engine.price_flu.0.push_back(1.0);
+                engine.avg_flu_value(&book, tick_window);
+            }
+        };
+
+        // The buffer follows the tick window by default.
+        push(&mut engine, 1_000, 610);
+        assert_eq!(engine.price_flu.0.len(), 610);
+
+        engine.set_vol_window(Some(900));
+        push(&mut engine, 1_000, 610);
+        assert_eq!(engine.price_flu.0.len(), 900);
+    }
+
+    #[test]
+    fn test_engine_captures_ticker_fields() {
+        let mut engine = Engine::new();
+        engine.update_ticker(&Ticker {
+            funding_rate: Some(0.0005),
+            open_interest: Some(1_000.0),
+            volume_24h: Some(50_000.0),
+        });
+        assert_eq!(engine.funding_rate, 0.0005);
+        assert_eq!(engine.open_interest, 1_000.0);
+        assert_eq!(engine.volume_24h, 50_000.0);
+        assert_eq!(engine.oi_change, 0.0);
+
+        // A delta without funding keeps the previous rate.
+        engine.update_ticker(&Ticker {
+            open_interest: Some(1_010.0),
+            ..Default::default()
+        });
+        assert_eq!(engine.funding_rate, 0.0005);
+        assert!((engine.oi_change - 0.01).abs() < 1e-12);
+
+        // The ticker only moves the skew once weighted.
+        engine.trade_flow = 1.0;
+        engine.generate_skew(false);
+        let unweighted = engine.skew;
+        engine.set_ticker_weights(0.2, 0.1);
+        engine.generate_skew(false);
+        assert!((engine.skew - unweighted - (-0.5 * 0.2 + 0.1)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_prediction_left_out_of_skew_during_warmup() {
+        let mut engine = Engine::new();
+        let skew = |engine: &mut Engine| {
+            engine.generate_skew(false);
+            engine.skew
+        };
+
+        // The first update has no previous mid to predict from.
+        let mut book = LocalBook::new();
+        book.mid_price = 100.0;
+        engine.update(
+            &book,
+            &LocalBook::new(),
+            &VecDeque::new(),
+            &VecDeque::new(),
+            &0.0,
+            vec![5, 50],
+            3,
+            false,
+            None,
+        );
+        assert_eq!(engine.predicted_value, 0.0);
+        assert!(!engine.predictor_ready);
+
+        // Until the window fills the prediction is left out, or weighted as configured.
+        engine.predicted_value = 1.0;
+        let warmup = skew(&mut engine);
+        engine.set_warmup_predict_weight(0.5);
+        assert!((skew(&mut engine) - warmup - 0.25 * EXP_RET_WEIGHT).abs() < 1e-12);
+        engine.predictor_ready = true;
+        assert!((skew(&mut engine) - warmup - 0.5 * EXP_RET_WEIGHT).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_engine_ready_after_tick_window() {
+        let mut engine = Engine::new();
+        let mut book = LocalBook::new();
+        book.mid_price = 100.0;
+        let update = |engine: &mut Engine| {
+            engine.update(
+                &book,
+                &book,
+                &VecDeque::new(),
+                &VecDeque::new(),
+                &0.0,
+                vec![5, 50],
+                3,
+                false,
+                None,
+            )
+        };
+        assert!(!engine.is_ready());
+        for _ in 0..2 {
+            update(&mut engine);
+            assert!(!engine.is_ready());
+        }
+        update(&mut engine);
+        assert!(engine.is_ready());
+    }
+
+    #[test]
+    fn test_regression_window_stays_aligned() {
+        let mut engine = Engine::new();
+        for i in 0..1_000 {
+            engine.voi = i as f64;
+            engine.price_impact = -(i as f64);
+            engine.imbalance_ratio = i as f64 / 1_000.0;
+            engine.record_regression_sample(100.0 + i as f64, 610);
+        }
+
+        let (mid_prices, features) = engine.regression_samples();
+        assert_eq!(mid_prices.len(), 610);
+        assert_eq!(features.nrows(), 610);
+        for (i, row) in features.rows().into_iter().enumerate() {
+            let tick = (390 + i) as f64;
+            assert_eq!(mid_prices[i], 100.0 + tick);
+            assert_eq!(row.to_vec(), vec![tick, -tick, tick / 1_000.0]);
+        }
+    }
+
+    #[test]
+    fn test_predicted_value_up() {
+        assert_eq!(predicted_value(0.001, 0.6, 0.4), 1.0);
+        assert_eq!(predicted_value(0.001, 0.5, 0.5), 1.0);
+    }
+
+    #[test]
+    fn test_predicted_value_down() {
+        assert_eq!(predicted_value(-0.001, 0.4, 0.6), -1.0);
+        assert_eq!(predicted_value(-0.001, 0.5, 0.5), -1.0);
+    }
+
+    #[test]
+    fn test_predicted_value_neutral() {
+        // Small returns sit in the neutral band regardless of the book.
+        assert_eq!(predicted_value(0.0001, 0.6, 0.4), 0.0);
+        assert_eq!(predicted_value(-0.0001, 0.4, 0.6), 0.0);
+        // A strong return contradicted by the book is not a prediction.
+        assert_eq!(predicted_value(0.001, 0.4, 0.6), 0.0);
+        assert_eq!(predicted_value(-0.001, 0.6, 0.4), 0.0);
+    }
+}
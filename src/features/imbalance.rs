@@ -1,7 +1,13 @@
 use std::collections::VecDeque;
 
 use bybit::model::WsTrade;
-use skeleton::util::{helpers::calculate_exponent, localorderbook::LocalBook};
+use skeleton::util::{
+    helpers::{calculate_exponent, DepthWeight},
+    localorderbook::LocalBook,
+};
+
+// Imbalance ratio within which the book is treated as balanced when no deadzone is configured.
+pub const DEFAULT_IMBALANCE_DEADZONE: f64 = 0.20;
 
 /// Calculate the imbalance ratio of a LocalBook.
 ///
@@ -19,6 +25,22 @@ use skeleton::util::{helpers::calculate_exponent, localorderbook::LocalBook};
 ///
 /// The imbalance ratio as a `f64`.
 pub fn imbalance_ratio(book: &LocalBook, depth: Option<usize>) -> f64 {
+    imbalance_ratio_weighted(
+        book,
+        depth,
+        DepthWeight::default(),
+        DEFAULT_IMBALANCE_DEADZONE,
+    )
+}
+
+/// Calculates the imbalance ratio like `imbalance_ratio`, weighting each level within `depth`
+/// with `weighting` and treating any ratio within `deadzone` of zero as no imbalance.
+pub fn imbalance_ratio_weighted(
+    book: &LocalBook,
+    depth: Option<usize>,
+    weighting: DepthWeight,
+    deadzone: f64,
+) -> f64 {
     // Extract the best ask and bid from the book.
     let (best_ask, best_bid) = (book.best_ask.qty, book.best_bid.qty);
 
@@ -33,16 +55,16 @@ pub fn imbalance_ratio(book: &LocalBook, depth: Option<usize>) -> f64 {
 
         // Calculate the weighted ask quantity using the specified depth.
         for (i, (_, qty)) in book.asks.iter().take(depth).enumerate() {
-            // Calculate the weight using the exponentiation function.
-            let weight = calculate_exponent(i as f64);
+            // Calculate the weight of the level.
+            let weight = weighting.weight(i);
             // Add the weighted quantity to the weighted ask quantity.
             weighted_ask_qty += weight * qty;
         }
 
         // Calculate the weighted bid quantity using the specified depth.
         for (i, (_, qty)) in book.bids.iter().rev().take(depth).enumerate() {
-            // Calculate the weight using the exponentiation function.
-            let weight = calculate_exponent(i as f64);
+            // Calculate the weight of the level.
+            let weight = weighting.weight(i);
             // Add the weighted quantity to the weighted bid quantity.
             weighted_bid_qty += weight * qty;
         }
@@ -58,8 +80,8 @@ pub fn imbalance_ratio(book: &LocalBook, depth: Option<usize>) -> f64 {
     // Return the imbalance ratio, checking for NaN and out-of-range values.
     match ratio {
         x if x.is_nan() => 0.0, // If NaN, return 0.
-        x if x > 0.20 => x,     // If positive and greater than 0.20, return the ratio.
-        x if x < -0.20 => x,    // If negative and less than -0.20, return the ratio.
+        x if x > deadzone => x, // If positive and beyond the deadzone, return the ratio.
+        x if x < -deadzone => x, // If negative and beyond the deadzone, return the ratio.
         _ => 0.0,               // Otherwise, return 0.
     }
 }
@@ -88,6 +110,20 @@ pub fn wmid(book: &LocalBook, imb: f64) -> f64 {
     }
 }
 
+/// Calculates the weighted mid price from the imbalance of the top `depth` levels, each
+/// weighted with `weighting`, ignoring imbalances within `deadzone`.
+pub fn get_weighted_mid(
+    book: &LocalBook,
+    depth: usize,
+    weighting: DepthWeight,
+    deadzone: f64,
+) -> f64 {
+    wmid(
+        book,
+        imbalance_ratio_weighted(book, Some(depth), weighting, deadzone),
+    )
+}
+
 /// Calculates the Volume at the Offset (VOI) of a given LocalBook and its previous state.
 ///
 /// # Arguments
@@ -169,8 +205,7 @@ pub fn voi(book: &LocalBook, prev_book: &LocalBook, depth: Option<usize>) -> f64
     };
 
     // Calculate the volume at the offset
-    let diff = bid_v - ask_v;
-    diff
+    bid_v - ask_v
 }
 
 pub fn trade_imbalance(trades: &VecDeque<WsTrade>) -> f64 {
@@ -182,14 +217,67 @@ pub fn trade_imbalance(trades: &VecDeque<WsTrade>) -> f64 {
         return 0.0;
     }
     // Calculate buy-sell ratio (avoid division by zero)
-    let ratio = buy_volume / total_volume;
-    ratio
+    buy_volume / total_volume
+}
+
+/// Calculates the trade imbalance using only the trades within the last `window_ms` milliseconds.
+///
+/// Trades with a timestamp older than `now - window_ms` are excluded, so the ratio covers the
+/// same time horizon regardless of how many trades arrived between ticks.
+pub fn trade_imbalance_windowed(trades: &VecDeque<WsTrade>, now: u64, window_ms: u64) -> f64 {
+    let start = now.saturating_sub(window_ms);
+    let (total_volume, buy_volume) =
+        calculate_volumes(trades.iter().filter(|trade| trade.timestamp >= start));
+    if total_volume == 0.0 {
+        return 0.0;
+    }
+    buy_volume / total_volume
+}
+
+/// Calculates the net aggressive volume within the last `window_ms` milliseconds as a fraction
+/// of the traded volume, from -1 when only sells traded to 1 when only buys traded.
+///
+/// Returns 0 when nothing traded within the window.
+pub fn trade_flow_windowed(trades: &VecDeque<WsTrade>, now: u64, window_ms: u64) -> f64 {
+    let start = now.saturating_sub(window_ms);
+    let (total_volume, buy_volume) =
+        calculate_volumes(trades.iter().filter(|trade| trade.timestamp >= start));
+    if total_volume == 0.0 {
+        return 0.0;
+    }
+    (2.0 * buy_volume - total_volume) / total_volume
 }
 
-fn calculate_volumes(trades: &VecDeque<WsTrade>) -> (f64, f64) {
+/// Calculates the net aggressive notional within the last `window_ms` milliseconds as a fraction
+/// of the traded notional, from -1 when only sells traded to 1 when only buys traded.
+///
+/// Unlike the trade flow, each trade is weighted by its notional, so a few large prints outweigh
+/// many small ones. Returns 0 when nothing traded within the window.
+pub fn trade_momentum_windowed(trades: &VecDeque<WsTrade>, now: u64, window_ms: u64) -> f64 {
+    let start = now.saturating_sub(window_ms);
+    let (total, net) = trades.iter().filter(|trade| trade.timestamp >= start).fold(
+        (0.0, 0.0),
+        |(total, net), trade| {
+            let notional = trade.price * trade.volume;
+            // Binance reports the sides in upper case.
+            let sign = if trade.side.eq_ignore_ascii_case("buy") {
+                1.0
+            } else {
+                -1.0
+            };
+            (total + notional, net + sign * notional)
+        },
+    );
+    if total == 0.0 {
+        return 0.0;
+    }
+    net / total
+}
+
+fn calculate_volumes<'a>(trades: impl IntoIterator<Item = &'a WsTrade>) -> (f64, f64) {
     let mut total_volume = 0.0;
     let mut buy_volume = 0.0;
-    for trade in trades.iter() {
+    for trade in trades {
         total_volume += trade.volume;
         if trade.side == "Buy" {
             buy_volume += trade.volume;
@@ -201,3 +289,117 @@ fn calculate_volumes(trades: &VecDeque<WsTrade>) -> (f64, f64) {
 pub fn map_range(value: f64) -> f64 {
     (value + 1.0) / 2.0
 }
+
+#[cfg(test)]
+mod tests {
+    use bybit::model::{Ask, Bid};
+
+    use super::*;
+
+    fn trade(timestamp: u64, side: &str, volume: f64) -> WsTrade {
+        WsTrade {
+            timestamp,
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            volume,
+            price: 100.0,
+            tick_direction: "PlusTick".to_string(),
+            id: timestamp.to_string(),
+            buyer_is_maker: false,
+        }
+    }
+
+    #[test]
+    fn test_weighted_mid_depends_on_weighting() {
+        let mut book = LocalBook::new();
+        book.update(
+            vec![
+                Bid {
+                    price: 99.9,
+                    qty: 1.0,
+                },
+                Bid {
+                    price: 99.8,
+                    qty: 1.0,
+                },
+                Bid {
+                    price: 99.7,
+                    qty: 20.0,
+                },
+            ],
+            vec![
+                Ask {
+                    price: 100.1,
+                    qty: 4.0,
+                },
+                Ask {
+                    price: 100.2,
+                    qty: 1.0,
+                },
+                Ask {
+                    price: 100.3,
+                    qty: 1.0,
+                },
+            ],
+            1,
+        );
+
+        // The deep bid weighs fully with a flat weighting, moving the weighted mid further.
+        let deadzone = DEFAULT_IMBALANCE_DEADZONE;
+        let exponential = get_weighted_mid(&book, 3, DepthWeight::default(), deadzone);
+        let linear = get_weighted_mid(&book, 3, DepthWeight::Linear { slope: 0.0 }, deadzone);
+        assert_eq!(exponential, wmid(&book, imbalance_ratio(&book, Some(3))));
+        assert!(exponential != book.mid_price && linear != book.mid_price);
+        assert!(exponential != linear);
+    }
+
+    #[test]
+    fn test_imbalance_deadzone() {
+        let mut book = LocalBook::new();
+        book.best_bid.qty = 1.15;
+        book.best_ask.qty = 0.85;
+
+        // A 0.15 imbalance is dropped by the default deadzone but kept by a narrower one.
+        assert_eq!(imbalance_ratio(&book, None), 0.0);
+        let ratio = imbalance_ratio_weighted(&book, None, DepthWeight::default(), 0.10);
+        assert!((ratio - 0.15).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trade_imbalance_windowed_excludes_old_trades() {
+        let trades: VecDeque<WsTrade> = VecDeque::from(vec![
+            trade(1_000, "Sell", 10.0),
+            trade(2_000, "Sell", 10.0),
+            trade(9_500, "Buy", 3.0),
+            trade(10_000, "Sell", 1.0),
+        ]);
+        // Only the last two trades fall within the last second.
+        assert_eq!(trade_imbalance_windowed(&trades, 10_000, 1_000), 0.75);
+        // The whole buffer matches the unwindowed imbalance.
+        assert_eq!(
+            trade_imbalance_windowed(&trades, 10_000, 10_000),
+            trade_imbalance(&trades)
+        );
+        // No trades in the window gives a neutral value.
+        assert_eq!(trade_imbalance_windowed(&trades, 20_000, 1_000), 0.0);
+    }
+
+    #[test]
+    fn test_trade_momentum_weighs_notional() {
+        let mut trades: VecDeque<WsTrade> = VecDeque::from(vec![
+            trade(1_000, "Buy", 50.0),
+            trade(9_000, "Buy", 1.0),
+            trade(9_500, "Sell", 2.0),
+            trade(9_800, "sell", 1.0),
+        ]);
+        trades[1].price = 300.0;
+        trades[2].price = 50.0;
+        trades[3].price = 100.0;
+        // 300 of buys against 200 of sells within the window, while more volume was sold.
+        let momentum = trade_momentum_windowed(&trades, 10_000, 5_000);
+        assert!((momentum - 0.2).abs() < 1e-9);
+        assert!(trade_flow_windowed(&trades, 10_000, 5_000) < 0.0);
+        // No trades in the window gives a neutral value.
+        assert_eq!(trade_momentum_windowed(&trades, 20_000, 1_000), 0.0);
+    }
+}
@@ -37,31 +37,27 @@ pub fn price_impact(new_book: &LocalBook, old_book: &LocalBook, depth: Option<us
         curr_ask_vol = 0.0;
 
         // Iterate over the depth asks and bids in the old and new order books
-        for (_, (_, qty)) in old_book.asks.iter().take(depth).enumerate() {
+        for (_, qty) in old_book.asks.iter().take(depth) {
             old_ask_vol += qty;
         }
-        for (_, (_, qty)) in new_book.asks.iter().take(depth).enumerate() {
+        for (_, qty) in new_book.asks.iter().take(depth) {
             curr_ask_vol += qty;
         }
-        for (_, (_, qty)) in old_book.bids.iter().rev().take(depth).enumerate() {
+        for (_, qty) in old_book.bids.iter().rev().take(depth) {
             old_bid_vol += qty;
         }
-        for (_, (_, qty)) in new_book.bids.iter().rev().take(depth).enumerate() {
+        for (_, qty) in new_book.bids.iter().rev().take(depth) {
             curr_bid_vol += qty;
         }
     }
 
     // Calculate the volume at the bid and ask offsets
-    let bid_impact = if curr_bid_price > old_bid_price || curr_bid_vol > old_bid_vol {
-        curr_bid_vol - old_bid_vol
-    } else if curr_bid_price < old_bid_price || curr_bid_vol < old_bid_vol {
+    let bid_impact = if curr_bid_price != old_bid_price || curr_bid_vol != old_bid_vol {
         curr_bid_vol - old_bid_vol
     } else {
         0.0
     };
-    let ask_impact = if curr_ask_price < old_ask_price || curr_ask_vol > old_ask_vol {
-        curr_ask_vol - old_ask_vol
-    } else if curr_ask_price > old_ask_price || curr_ask_vol < old_ask_vol {
+    let ask_impact = if curr_ask_price != old_ask_price || curr_ask_vol != old_ask_vol {
         curr_ask_vol - old_ask_vol
     } else {
         0.0
@@ -210,6 +206,34 @@ pub fn price_flu(old_price: f64, curr_price: f64) -> f64 {
     (diff / curr_price) * 10000.0
 }
 
+/// Calculates the realized volatility of the trade prices within the last `window_ms` milliseconds.
+///
+/// The volatility is the square root of the sum of the squared log returns between consecutive
+/// trade prices, so it follows the trade prints rather than the book updates.
+///
+/// # Arguments
+///
+/// * `trades` - The recent trades, oldest first.
+/// * `now` - The current timestamp in milliseconds.
+/// * `window_ms` - The time horizon of the trades to use.
+///
+/// # Returns
+///
+/// The realized volatility in bps, or 0.0 if fewer than two trades are in the window.
+pub fn trade_volatility(trades: &VecDeque<WsTrade>, now: u64, window_ms: u64) -> f64 {
+    let start = now.saturating_sub(window_ms);
+    let prices: Vec<f64> = trades
+        .iter()
+        .filter(|trade| trade.timestamp >= start && trade.price > 0.0)
+        .map(|trade| trade.price)
+        .collect();
+    let sum_sq: f64 = prices
+        .windows(2)
+        .map(|pair| (pair[1] / pair[0]).ln().powi(2))
+        .sum();
+    sum_sq.sqrt() * 10000.0
+}
+
 /// Calculates the average trade price based on the current mid price, the old trades,
 /// the current trades, the previous average trade price, and the tick window.
 ///
@@ -262,3 +286,41 @@ pub fn avg_trade_price(
         prev_avg
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: u64, price: f64) -> WsTrade {
+        WsTrade {
+            timestamp,
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            volume: 1.0,
+            price,
+            tick_direction: "PlusTick".to_string(),
+            id: timestamp.to_string(),
+            buyer_is_maker: false,
+        }
+    }
+
+    #[test]
+    fn test_trade_volatility() {
+        // Alternating log returns of +/-10 bps.
+        let up = 100.0 * 0.001f64.exp();
+        let trades: VecDeque<WsTrade> = VecDeque::from(vec![
+            trade(1_000, 50.0),
+            trade(9_000, 100.0),
+            trade(9_200, up),
+            trade(9_400, 100.0),
+            trade(9_600, up),
+            trade(9_800, 100.0),
+        ]);
+        // Four returns of 10 bps inside the window, the old trade is ignored.
+        assert!((trade_volatility(&trades, 10_000, 2_000) - 20.0).abs() < 1e-9);
+        // A single trade or no trade in the window has no volatility.
+        assert_eq!(trade_volatility(&trades, 10_000, 200), 0.0);
+        assert_eq!(trade_volatility(&trades, 20_000, 1_000), 0.0);
+        assert_eq!(trade_volatility(&VecDeque::new(), 10_000, 2_000), 0.0);
+    }
+}
@@ -3,29 +3,111 @@ use linfa::{
     Dataset,
 };
 use linfa_linear::LinearRegression;
-use ndarray::{Array1, Array2};
+use ndarray::{concatenate, Array1, Array2, Axis};
+
+// Variance below which a feature column is treated as constant, making the fit singular.
+const MIN_FEATURE_VARIANCE: f64 = 1e-12;
+// Number of spreads around the latest mid price the predicted price is capped to.
+const MAX_PREDICTION_SPREADS: f64 = 5.0;
+
+/// Errors returned when the regression inputs cannot produce a meaningful prediction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegressionError {
+    /// The spread used to normalize the features is zero, negative or not finite.
+    InvalidSpread,
+    /// There are no samples, or the number of mid prices and feature rows differ.
+    ShapeMismatch,
+    /// The mid prices or features contain NaN or infinite values.
+    NonFiniteInput,
+    /// A feature column is constant, e.g. while the book is frozen, so the fit is singular.
+    DegenerateFeatures,
+    /// The model could not be fitted, e.g. because the features are degenerate.
+    FitFailed,
+}
+
+/// Checks that the regression inputs are usable before normalizing and fitting them.
+fn validate_inputs(
+    mid_price_array: &Array1<f64>,
+    features: &Array2<f64>,
+    curr_spread: f64,
+) -> Result<(), RegressionError> {
+    if !curr_spread.is_finite() || curr_spread <= 0.0 {
+        return Err(RegressionError::InvalidSpread);
+    }
+    if features.nrows() == 0 || features.nrows() != mid_price_array.len() {
+        return Err(RegressionError::ShapeMismatch);
+    }
+    if mid_price_array
+        .iter()
+        .chain(features.iter())
+        .any(|x| !x.is_finite())
+    {
+        return Err(RegressionError::NonFiniteInput);
+    }
+    if features
+        .columns()
+        .into_iter()
+        .any(|column| column.var(0.0) / (curr_spread * curr_spread) < MIN_FEATURE_VARIANCE)
+    {
+        return Err(RegressionError::DegenerateFeatures);
+    }
+    Ok(())
+}
+
+/// Predicts the mid price with a regression, falling back to `fallback` when the inputs cannot
+/// be fitted.
+///
+/// The prediction is capped to `MAX_PREDICTION_SPREADS` spreads around the latest mid price, so
+/// a poorly conditioned fit can not move the price far from the book.
+///
+/// # Arguments
+///
+/// * `mid_price_array` - The array of mid prices to be used for regression, oldest first.
+/// * `features` - The array of features used for regression.
+/// * `curr_spread` - The current spread used to normalize the features.
+/// * `fallback` - The price used when the regression fails, e.g. the microprice.
+pub fn predict_mid_price(
+    mid_price_array: Array1<f64>,
+    features: Array2<f64>,
+    curr_spread: f64,
+    fallback: f64,
+) -> f64 {
+    let Some(&mid) = mid_price_array.last() else {
+        return fallback;
+    };
+    let prediction = mid_price_regression(mid_price_array, features, curr_spread)
+        .ok()
+        .filter(|p| p.is_finite())
+        .unwrap_or(fallback);
+    let band = if curr_spread.is_finite() && curr_spread > 0.0 {
+        curr_spread * MAX_PREDICTION_SPREADS
+    } else {
+        0.0
+    };
+    prediction.clamp(mid - band, mid + band)
+}
 
 /// Performs linear regression on the given mid price data using the provided features.
 ///
 /// # Arguments
 ///
-/// * `mid_price_array` - The array of mid prices to be used for regression.
+/// * `mid_price_array` - The array of mid prices to be used for regression, oldest first.
 /// * `features` - The array of features used for regression.
 /// * `curr_spread` - The current spread used to normalize the features.
 ///
 /// # Returns
 ///
-/// The mean of the prediction or 0.0 if the prediction is empty.
+/// The predicted mid price for the most recent sample, or a `RegressionError` if the inputs are
+/// invalid or the model could not be fitted.
 pub fn mid_price_regression(
     mid_price_array: Array1<f64>,
-    mut features: Array2<f64>,
+    features: Array2<f64>,
     curr_spread: f64,
-) -> f64 {
+) -> Result<f64, RegressionError> {
+    validate_inputs(&mid_price_array, &features, curr_spread)?;
+
     // Normalize the features by dividing each value in the feature columns by the current spread
-    for i in 0..3 {
-        let mut column = features.column_mut(i);
-        column.mapv_inplace(|x| x / curr_spread);
-    }
+    let features = features.mapv(|x| x / curr_spread);
 
     // Create a linfa dataset with the features and mid price array
     let dataset = Dataset::new(features, mid_price_array);
@@ -34,18 +116,66 @@ pub fn mid_price_regression(
     let lin_reg = LinearRegression::new();
 
     // Fit the model to the dataset and get the resulting model
-    let model = lin_reg.fit(&dataset).unwrap();
+    let model = lin_reg
+        .fit(&dataset)
+        .map_err(|_| RegressionError::FitFailed)?;
 
-    // Use the model to predict the mid price values
+    // Use the model to predict the mid price values and return the most recent one
     let prediction = model.predict(&dataset);
+    prediction
+        .last()
+        .copied()
+        .ok_or(RegressionError::ShapeMismatch)
+}
 
-    // Assuming you want to return some value related to the prediction here
-    // Return the mean of the prediction or 0.0 if the prediction is empty
-    if prediction.is_empty() {
-        0.0
-    } else {
-        prediction.mean().unwrap_or(0.0)
-    }
+/// Performs a weighted linear regression on the given mid price data, giving recent samples more weight.
+///
+/// Each sample is weighted by `decay^age`, where the most recent sample has an age of 0. The fit is
+/// done as a weighted least squares by scaling every row, including the intercept column, by the
+/// square root of its weight.
+///
+/// # Arguments
+///
+/// * `mid_price_array` - The array of mid prices to be used for regression, oldest first.
+/// * `features` - The array of features used for regression.
+/// * `curr_spread` - The current spread used to normalize the features.
+/// * `decay` - The decay factor between 0 and 1. A value of 1 weights every sample equally.
+///
+/// # Returns
+///
+/// The predicted mid price for the most recent sample, or a `RegressionError` if the inputs are
+/// invalid or the model could not be fitted.
+pub fn weighted_mid_price_regression(
+    mid_price_array: Array1<f64>,
+    features: Array2<f64>,
+    curr_spread: f64,
+    decay: f64,
+) -> Result<f64, RegressionError> {
+    validate_inputs(&mid_price_array, &features, curr_spread)?;
+    let samples = features.nrows();
+
+    // Normalize the features by dividing each value by the current spread
+    let features = features.mapv(|x| x / curr_spread);
+
+    // Add an explicit intercept column so that it is weighted with the features
+    let design = concatenate![Axis(1), features, Array2::ones((samples, 1))];
+
+    // Scale each row by the square root of its exponentially decaying weight
+    let sqrt_weights: Array1<f64> = (0..samples)
+        .map(|i| decay.powi((samples - 1 - i) as i32).sqrt())
+        .collect();
+    let weighted_design = &design * &sqrt_weights.view().insert_axis(Axis(1));
+    let weighted_target = &mid_price_array * &sqrt_weights;
+
+    // Fit the model without an implicit intercept since it is part of the design
+    let dataset = Dataset::new(weighted_design, weighted_target);
+    let lin_reg = LinearRegression::new().with_intercept(false);
+    let model = lin_reg
+        .fit(&dataset)
+        .map_err(|_| RegressionError::FitFailed)?;
+
+    // Predict the most recent sample with the unweighted design
+    Ok(design.row(samples - 1).dot(model.params()))
 }
 
 #[cfg(test)]
@@ -57,14 +187,74 @@ mod tests {
     fn test_mid_price_regression() {
         let mid_price = array![1.0, 2.0, 3.0, 4.0, 5.0];
         let features = array![
-            [1.0, 2.0, 3.0],
-            [4.0, 5.0, 6.0],
-            [7.0, 8.0, 9.0],
-            [10.0, 11.0, 12.0],
-            [13.0, 14.0, 15.0]
+            [1.0, 0.5, 2.0],
+            [2.0, 1.7, 1.0],
+            [3.0, 0.2, 5.0],
+            [4.0, 2.2, 3.0],
+            [5.0, 1.1, 4.0]
         ];
         let curr_spread = 2.0;
-        let result = mid_price_regression(mid_price, features, curr_spread);
-        assert_eq!(result, 3.0);
+        let result = mid_price_regression(mid_price, features, curr_spread).unwrap();
+        assert!((result - 5.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mid_price_regression_zero_spread() {
+        let mid_price = array![1.0, 2.0, 3.0];
+        let features = array![[1.0, 0.5], [2.0, 1.7], [3.0, 0.2]];
+        let result = mid_price_regression(mid_price.clone(), features.clone(), 0.0);
+        assert_eq!(result, Err(RegressionError::InvalidSpread));
+        let result = weighted_mid_price_regression(mid_price, features, 0.0, 0.5);
+        assert_eq!(result, Err(RegressionError::InvalidSpread));
+    }
+
+    #[test]
+    fn test_mid_price_regression_invalid_input() {
+        let features = array![[1.0, 0.5], [2.0, 1.7], [3.0, 0.2]];
+        let result = mid_price_regression(array![1.0, 2.0], features.clone(), 1.0);
+        assert_eq!(result, Err(RegressionError::ShapeMismatch));
+        let result = mid_price_regression(array![1.0, f64::NAN, 3.0], features, 1.0);
+        assert_eq!(result, Err(RegressionError::NonFiniteInput));
+    }
+
+    #[test]
+    fn test_constant_features_fall_back() {
+        // A frozen book repeats the same features on every sample.
+        let mid_price = array![100.0, 100.0, 100.1, 100.0, 100.1];
+        let features = Array2::from_elem((5, 3), 0.4);
+        let result = mid_price_regression(mid_price.clone(), features.clone(), 0.1);
+        assert_eq!(result, Err(RegressionError::DegenerateFeatures));
+
+        let predicted = predict_mid_price(mid_price.clone(), features.clone(), 0.1, 100.12);
+        assert_eq!(predicted, 100.12);
+
+        // A fallback far from the book is capped to 5 spreads around the latest mid price.
+        let predicted = predict_mid_price(mid_price, features, 0.1, 150.0);
+        assert!((predicted - 100.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_weighted_regression_tracks_recent_trend() {
+        // The first ten samples follow one relationship, the last five a much steeper one.
+        let samples = 15;
+        let mut mid_price = Array1::zeros(samples);
+        let mut features = Array2::zeros((samples, 2));
+        for i in 0..samples {
+            let x = i as f64;
+            features[[i, 0]] = x;
+            features[[i, 1]] = (x * 1.7).sin();
+            mid_price[i] = if i < 10 {
+                100.0 + x
+            } else {
+                100.0 + 9.0 + (x - 9.0) * 4.0
+            };
+        }
+        let latest = mid_price[samples - 1];
+
+        let weighted =
+            weighted_mid_price_regression(mid_price.clone(), features.clone(), 1.0, 0.5).unwrap();
+        let unweighted = weighted_mid_price_regression(mid_price, features, 1.0, 1.0).unwrap();
+
+        assert!((weighted - latest).abs() < (unweighted - latest).abs());
     }
 }
@@ -1,28 +1,61 @@
 use std::collections::HashMap;
 
-use rs_smm::{parameters::parameters::use_toml, strategy::market_maker::MarketMaker};
-use skeleton::ss;
+use rs_smm::{
+    parameters::parameters::{use_toml, watch_config},
+    strategy::market_maker::{relay_mids, MarketMaker},
+    trader::hedge::HedgeExecutor,
+};
+use skeleton::{ss, util::logger::Logger};
 use tokio::sync::mpsc;
 
+// Seconds the private stream may stay disconnected before the exchange cancels the orders.
+const CANCEL_ON_DISCONNECT_SECS: u64 = 10;
+
 #[tokio::main]
 async fn main() {
     let config = use_toml();
-    let mut state = ss::SharedState::new(config.exchange);
+    let logger = Logger::from_config(&config);
+    let mut state = ss::SharedState::new(config.exchange.clone());
+    state.set_logger(logger.clone());
+    state.set_testnet(config.testnet.unwrap_or(false));
+    state.set_reconnect_reset(config.reconnect_reset_ms);
+    state.set_heartbeat_timeout(config.heartbeat_timeout_ms);
+    if let Some(depths) = config.bybit_book_depths.clone() {
+        state.set_bybit_depths(depths);
+    }
+    if let Some(depths) = config.binance_book_depths.clone() {
+        state.set_binance_depths(depths);
+    }
     let symbols: Vec<String> = {
         let mut arr = vec![];
-        for v in config.symbols {
+        for v in config.symbols.clone() {
             arr.push(v);
         }
         arr
     };
     state.add_symbols(symbols);
-    let clients = config.api_keys;
+    let invalid = state.invalid_symbols().await;
+    if !invalid.is_empty() {
+        eprintln!("Symbols not trading on {}: {:?}", state.exchange, invalid);
+        std::process::exit(1);
+    }
+    let clients = config.api_keys.clone();
     for (key, secret, symbol) in clients {
         state.add_clients(key, secret, symbol, None);
     }
+    if config.cancel_on_disconnect.unwrap_or(false) {
+        for (symbol, client) in &state.clients {
+            if let Err(e) = client
+                .enable_cancel_on_disconnect(CANCEL_ON_DISCONNECT_SECS)
+                .await
+            {
+                eprintln!("Unable to enable cancel on disconnect for {}: {}", symbol, e);
+            }
+        }
+    }
     let balance = {
         let mut new_map = HashMap::new();
-        for (k, v) in config.balances {
+        for (k, v) in config.balances.clone() {
             new_map.insert(k, v);
         }
         new_map
@@ -30,18 +63,36 @@ async fn main() {
     let mut market_maker = MarketMaker::new(
         state.clone(),
         balance,
-        config.leverage,
+        config.leverage.clone(),
         config.orders_per_side,
         config.final_order_distance,
-        config.depths,
+        config.depths.clone(),
         config.rate_limit,
+        config.state_file.clone(),
     );
-    market_maker.set_spread_toml(config.bps);
+    market_maker.configure(&config);
+    market_maker.set_logger(logger);
+    if let Some(exchange) = &config.hedge_exchange {
+        for (key, secret, symbol) in config.hedge_api_keys.clone().unwrap_or_default() {
+            let testnet = config.testnet.unwrap_or(false);
+            let hedge = HedgeExecutor::init(exchange, key, secret, symbol.clone(), testnet);
+            market_maker.set_hedge(&symbol, hedge);
+        }
+    }
+    // Disabled symbols are still streamed, so they can be enabled again while running.
+    market_maker.apply_config(&config).await;
+    market_maker.fetch_maker_fees().await;
+    market_maker.reconcile_orders().await;
     let (sender, receiver) = mpsc::unbounded_channel();
     tokio::spawn(async move {
         ss::load_data(state, sender).await;
     });
+    // Publish the latest mid prices for the last look before the orders are submitted.
+    let (relay_sender, relayed) = mpsc::unbounded_channel();
+    tokio::spawn(relay_mids(receiver, market_maker.live_mids(), relay_sender));
+    let (config_sender, config_receiver) = mpsc::unbounded_channel();
+    tokio::spawn(watch_config("./config.toml".to_string(), config_sender));
     market_maker
-        .start_loop(receiver, config.use_wmid, config.rate_limit)
+        .start_loop(relayed, config_receiver, config.use_wmid, config.rate_limit)
         .await;
 }
@@ -1 +1,2 @@
-pub mod parameters;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod parameters;
@@ -1,10 +1,17 @@
 use std::{
     collections::HashMap,
+    fs,
     io::{self, Write},
+    time::{Duration, SystemTime},
 };
 
-use skeleton::util::helpers::{read_toml, Config};
+use skeleton::util::helpers::{read_toml, try_read_toml, Config};
+use tokio::{sync::mpsc::UnboundedSender, time::interval};
 
+use crate::strategy::market_maker::validate_config;
+
+// Time in milliseconds between two checks of the config file for changes.
+const CONFIG_POLL_MS: u64 = 1000;
 
 
 pub fn watch(prompt: &str) -> String {
@@ -41,7 +48,7 @@ pub fn symbol_params() -> Vec<&'static str> {
     loop {
         let prompt = "Markets to Make!!! \nPlease enter a symbol: ";
         let symbol = watch_static(prompt);
-        if symbol == "" {
+        if symbol.is_empty() {
             break;
         }
         symbol_arr.push(symbol);
@@ -58,17 +65,17 @@ pub fn api_key_params() -> HashMap<String, (String, String)> {
     loop {
         let prompt = "Please enter a symbol for Account Keys:";
         let symbol = watch(prompt).to_uppercase();
-        if symbol == "" {
+        if symbol.is_empty() {
             break;
         }
         let prompt = format!("Please enter your API Key for {}: ", symbol.clone());
         let api_key = watch(&prompt);
-        if api_key == "" {
+        if api_key.is_empty() {
             break;
         }
         let prompt = format!("Please enter your Secret Key for {}: ", symbol.clone());
         let secret_key = watch(&prompt);
-        if secret_key == "" {
+        if secret_key.is_empty() {
             break;
         }
         api_keys.insert(symbol.clone(), (api_key, secret_key));
@@ -85,12 +92,12 @@ pub fn acct_balance_params() -> HashMap<String, f64> {
     loop {
         let prompt = "Please enter a symbol for account balances:";
         let symbol = watch(prompt).to_uppercase();
-        if symbol == "" {
+        if symbol.is_empty() {
             break;
         }
         let prompt = format!("Please enter your balance for {}: ", symbol.clone());
         let balance = watch(&prompt);
-        if balance == "" {
+        if balance.is_empty() {
             break;
         }
         balances.insert(symbol.clone(), balance.parse::<f64>().unwrap());
@@ -118,39 +125,47 @@ pub fn maker_params() -> MakerParams {
     let rate_limit = watch("Parameter for rate limit. Please enter rate limit: ")
         .parse::<u32>()
         .unwrap();
-    let params = MakerParams::new(
+    MakerParams::new(
         leverage,
         orders_per_side,
         final_order_distance,
         depths,
         rebalance_ratio,
         rate_limit,
-    );
-    params
+    )
 }
 
 
 pub fn  use_toml() -> Config {
     let path = "./config.toml";
-    let result = read_toml(path);
-    result
+    let config = read_toml(path);
+    validate_config(&config).unwrap_or_else(|e| panic!("Invalid config: {}", e));
+    config
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_exch_params() {
-        let exch = exch_params();
-        assert_eq!(exch, "bybit");
-    }
-
-    #[test]
-    fn test_toml() {
-        let config = use_toml();
-        assert_eq!(config.exchange, "bybit");
-        println!("{:#?}", config);
+/// Sends the config at `path` again each time the file is modified.
+///
+/// A config that fails to parse or holds a setting out of bounds is reported and skipped, the
+/// next valid edit is sent. Returns once the receiver is dropped.
+pub async fn watch_config(path: String, sender: UnboundedSender<Config>) {
+    let modified = |path: &str| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+    let mut last_modified = modified(&path);
+    let mut poll = interval(Duration::from_millis(CONFIG_POLL_MS));
+    loop {
+        poll.tick().await;
+        let current = modified(&path);
+        if current.is_none() || current == last_modified {
+            continue;
+        }
+        last_modified = current;
+        match try_read_toml(&path).and_then(|config| validate_config(&config).map(|_| config)) {
+            Ok(config) => {
+                if sender.send(config).is_err() {
+                    return;
+                }
+            }
+            Err(e) => eprintln!("Ignoring invalid config {}: {}", path, e),
+        }
     }
 }
 
@@ -185,3 +200,21 @@ impl MakerParams {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exch_params() {
+        let exch = exch_params();
+        assert_eq!(exch, "bybit");
+    }
+
+    #[test]
+    fn test_toml() {
+        let config = use_toml();
+        assert_eq!(config.exchange, "bybit");
+        println!("{:#?}", config);
+    }
+}
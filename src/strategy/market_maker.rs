@@ -1,25 +1,76 @@
 use bybit::model::WsTrade;
-use skeleton::exchanges::exchange::{ExchangeClient, PrivateData};
-use skeleton::util::localorderbook::LocalBook;
+use skeleton::exchanges::exchange::{ExchangeClient, PrivateData, Ticker};
+use skeleton::util::{
+    heartbeat::HEARTBEAT_TIMEOUT_MS,
+    helpers::{
+        generate_timestamp, next_funding_time, Config, DepthWeight, Leverage, QuoteAnchor,
+        SizeDistribution, SizingMode, TimeInForce, TouchClamp, TradeFlowMode,
+    },
+    localorderbook::LocalBook,
+    logger::Logger,
+    trade_tape::TradeTape,
+};
 use skeleton::{exchanges::exchange::MarketMessage, ss::SharedState};
-use std::collections::{HashMap, VecDeque};
-use std::time::Duration;
-use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::time::interval;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    Semaphore,
+};
 
 use crate::features::engine::Engine;
 use crate::features::imbalance::imbalance_ratio;
 use crate::parameters::parameters::watch;
-use crate::trader::quote_gen::QuoteGenerator;
+use crate::trader::hedge::HedgeExecutor;
+use crate::trader::quote_gen::{
+    BatchOrder, GridLayer, LiveOrder, QuoteGenerator, MAX_BATCH_ORDERS, MAX_ORDER_TAG_LEN,
+    MAX_PLACEMENT_JITTER_MS, MAX_REPLACE_DELAY_MS,
+};
+use crate::trader::state::{load_state, save_state};
+
+// Time in milliseconds between two fundings on the default funding schedule.
+const FUNDING_INTERVAL_MS: u64 = 8 * 60 * 60 * 1000;
+// Number of ticks the average trade price is computed over.
+const TICK_WINDOW: usize = 610;
+// Time in milliseconds between two saves of the state file.
+const STATE_SAVE_INTERVAL_MS: u64 = 10_000;
 
 pub struct MarketMaker {
     pub features: HashMap<String, Engine>,
     pub old_books: HashMap<String, LocalBook>,
     pub old_trades: HashMap<String, VecDeque<WsTrade>>,
     pub curr_trades: HashMap<String, VecDeque<WsTrade>>,
+    // Latest trades of each symbol without duplicates, to build candles from.
+    pub tape: TradeTape,
     pub prev_avg_trade_price: HashMap<String, f64>,
     pub generators: HashMap<String, QuoteGenerator>,
+    // Generators of the symbols disabled in the config, kept to resume them when re-enabled.
+    disabled: HashMap<String, QuoteGenerator>,
     pub depths: Vec<usize>,
+    max_portfolio_notional: Option<f64>,
+    // Maker fee rate of every symbol overriding the rate fetched from the exchange.
+    maker_fee: Option<f64>,
+    // Number of feature updates a symbol needs before it is quoted.
+    warmup_updates: usize,
+    // Whether the skew is held at 0 until the features of the symbol are ready, which only
+    // matters when the symbol is quoted after fewer updates than the tick window.
+    neutral_warmup: bool,
+    // Age in milliseconds beyond which the trades are dropped before computing the features.
+    max_trade_age_ms: Option<u64>,
+    // Minimum time in milliseconds between two quote updates of a symbol.
+    min_quote_interval_ms: u64,
+    // Book time of the last quote update of each symbol.
+    last_quote_update: HashMap<String, u64>,
+    // Age in milliseconds beyond which the private data of a symbol is too stale to quote it.
+    max_private_age_ms: Option<u64>,
+    // Symbols whose quoting is deferred until their private data is fresh again.
+    stale_private: HashSet<String>,
+    // Whether the orders of every symbol are placed together in shared batches.
+    shared_batches: bool,
+    // Whether every position is flattened instead of quoting.
+    kill_switch: bool,
+    state_file: Option<String>,
+    last_state_save: u64,
 }
 
 impl MarketMaker {
@@ -29,25 +80,32 @@ impl MarketMaker {
     ///
     /// * `ss` - The shared state containing information about the markets.
     /// * `assets` - The assets and their corresponding leverage.
-    /// * `leverage` - The leverage for each asset.
+    /// * `leverage` - The leverage for every symbol or for each symbol.
     /// * `orders_per_side` - The number of orders to place on each side of the order book.
     /// * `final_order_distance` - The distance of the final order from the mid price.
     /// * `depths` - The depths at which to calculate imbalance ratios it uses vec![5, 50].
+    /// * `state_file` - The file the state of each symbol is resumed from and saved to.
     ///
     /// # Returns
     ///
     /// A new `MarketMaker` instance.
+    ///
+    /// # Panics
+    ///
+    /// If the state file exists but cannot be read.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         ss: SharedState,
         assets: HashMap<String, f64>,
-        leverage: f64,
+        leverage: Leverage,
         orders_per_side: usize,
         final_order_distance: f64,
         depths: Vec<usize>,
         rate_limit: u32,
+        state_file: Option<String>,
     ) -> Self {
         // Construct the `MarketMaker` instance with the provided arguments.
-        MarketMaker {
+        let mut market_maker = MarketMaker {
             // Initialize the `features` field with the features for each symbol.
             features: MarketMaker::build_features(ss.symbols.clone()),
             // Initialize the `old_books` field with an empty hashmap.
@@ -56,6 +114,8 @@ impl MarketMaker {
             old_trades: HashMap::new(),
             // Initialize the `curr_trades` field with an empty hashmap.
             curr_trades: HashMap::new(),
+            // Keep as many trades as the tick window.
+            tape: TradeTape::new(TICK_WINDOW),
             // Initialize the `prev_avg_trade_price` field with an empty hashmap.
             prev_avg_trade_price: HashMap::new(),
             // Initialize the `generators` field with quote generators for each symbol.
@@ -67,8 +127,65 @@ impl MarketMaker {
                 final_order_distance,
                 rate_limit,
             ),
+            disabled: HashMap::new(),
             // Initialize the `depths` field with the provided depths.
             depths,
+            // Let each symbol deploy up to its own maximum until a portfolio cap is configured.
+            max_portfolio_notional: None,
+            // Fetch the maker fee of each symbol until a rate is configured.
+            maker_fee: None,
+            // Wait for enough updates to fill the tick window of each symbol.
+            warmup_updates: TICK_WINDOW,
+            // Quote a symmetric grid until the skew is built from a full tick window.
+            neutral_warmup: true,
+            // Keep every streamed trade until an age limit is configured.
+            max_trade_age_ms: None,
+            // Requote on every book update until an interval is configured.
+            min_quote_interval_ms: 0,
+            last_quote_update: HashMap::new(),
+            // Quote on whatever private data was received until an age limit is configured.
+            max_private_age_ms: None,
+            stale_private: HashSet::new(),
+            // Place the orders of each symbol in its own batches.
+            shared_batches: false,
+            kill_switch: false,
+            state_file,
+            last_state_save: 0,
+        };
+        // Resume the position and PnL of each symbol from the previous run.
+        if let Some(path) = &market_maker.state_file {
+            let state = load_state(path).expect("Unable to read state file");
+            for (symbol, state) in state {
+                if let Some(generator) = market_maker.generators.get_mut(&symbol) {
+                    generator.restore(&state);
+                }
+            }
+        }
+        market_maker
+    }
+
+    /// Writes the state of each symbol to the state file, if one is configured.
+    pub fn save_state(&self) -> std::io::Result<()> {
+        let Some(path) = &self.state_file else {
+            return Ok(());
+        };
+        let state = self
+            .generators
+            .iter()
+            .chain(self.disabled.iter())
+            .map(|(k, v)| (k.clone(), v.state()))
+            .collect();
+        save_state(path, &state)
+    }
+
+    /// Saves the state once the save interval has elapsed since the last save.
+    fn save_state_periodically(&mut self, now: u64) {
+        if now.saturating_sub(self.last_state_save) < STATE_SAVE_INTERVAL_MS {
+            return;
+        }
+        self.last_state_save = now;
+        if let Err(e) = self.save_state() {
+            eprintln!("Unable to save state: {}", e);
         }
     }
 
@@ -77,6 +194,7 @@ impl MarketMaker {
     /// # Arguments
     ///
     /// * `receiver` - An unbounded receiver for receiving `SharedState` updates.
+    /// * `config_updates` - An unbounded receiver for the config each time it is modified.
     ///
     /// # Returns
     ///
@@ -84,13 +202,24 @@ impl MarketMaker {
     pub async fn start_loop(
         &mut self,
         mut receiver: UnboundedReceiver<SharedState>,
+        mut config_updates: UnboundedReceiver<Config>,
         use_wmid: bool,
         rate_limit: u32,
     ) {
-        let mut send = 0;
-        let mut wait = interval(Duration::from_millis(600));
-        // Continuously receive and process shared state updates.
-        while let Some(data) = receiver.recv().await {
+        // Continuously receive and process shared state and config updates.
+        loop {
+            let data = tokio::select! {
+                // Apply a modified config before the market data that follows it.
+                biased;
+                Some(config) = config_updates.recv() => {
+                    self.apply_config(&config).await;
+                    continue;
+                }
+                data = receiver.recv() => match data {
+                    Some(data) => data,
+                    None => break,
+                },
+            };
             // Match the exchange in the received data.
             match data.exchange.as_str() {
                 "bybit" | "binance" => {
@@ -99,17 +228,13 @@ impl MarketMaker {
                         data.markets[0].clone(),
                         self.depths.clone(),
                         use_wmid,
-                        610,
+                        TICK_WINDOW,
                     );
 
-                    // Update the strategy with the new market data and private data.
-                    if send > 300 {
-                        self.potentially_update(data.private, data.markets[0].clone(), rate_limit)
-                            .await;
-                    } else {
-                        wait.tick().await;
-                        send += 1;
-                    }
+                    // Update the strategy of the symbols that are warmed up.
+                    self.potentially_update(data.private, data.markets[0].clone(), rate_limit)
+                        .await;
+                    self.save_state_periodically(generate_timestamp());
                 }
 
                 "both" => {}
@@ -121,6 +246,183 @@ impl MarketMaker {
         }
     }
 
+    /// Applies the settings of `config` to every quoted symbol.
+    ///
+    /// The hedges and the state file are only set up once, when the market maker starts.
+    pub fn configure(&mut self, config: &Config) {
+        self.set_depth_weight(config.depth_weight.unwrap_or_default());
+        self.set_imbalance_deadzone(config.imbalance_deadzone.unwrap_or(0.20));
+        self.set_momentum_weight(config.momentum_weight.unwrap_or(0.0));
+        self.set_warmup_predict_weight(config.warmup_predict_weight.unwrap_or(0.0));
+        self.set_ticker_weights(
+            config.funding_weight.unwrap_or(0.0),
+            config.open_interest_weight.unwrap_or(0.0),
+        );
+        if let Some(len) = config.history_len {
+            self.set_history_len(len);
+        }
+        self.set_max_notional(config.max_notional_usd);
+        self.set_max_portfolio_notional(config.max_portfolio_notional);
+        self.set_vol_window(config.vol_window);
+        self.set_warmup_updates(config.warmup_updates.unwrap_or(TICK_WINDOW));
+        self.set_neutral_warmup(config.neutral_warmup.unwrap_or(true));
+        self.set_max_trade_age(config.max_trade_age_ms);
+        self.set_min_quote_interval(config.min_quote_interval_ms.unwrap_or(0));
+        self.set_max_private_age(config.max_private_age_ms);
+        self.set_orders_per_side(
+            config.orders_per_bid.unwrap_or(config.orders_per_side),
+            config.orders_per_ask.unwrap_or(config.orders_per_side),
+        );
+        if let Some(layers) = config.grid_layers.clone() {
+            self.set_grid_layers(layers);
+        }
+        self.set_inventory_target(config.inventory_target.unwrap_or(0.0));
+        self.set_requote_cooldown(config.requote_cooldown_ms.unwrap_or(0));
+        self.set_requote_on_fill(config.requote_on_fill.unwrap_or(false));
+        self.set_max_order_age(config.max_order_age_ms.unwrap_or(0));
+        self.set_hold_touch(config.hold_touch.unwrap_or(false));
+        self.set_placement_jitter(config.placement_jitter_ms.unwrap_or(0));
+        self.set_replace_delay(config.replace_delay_ms.unwrap_or(0));
+        self.set_heartbeat_timeout(config.heartbeat_timeout_ms.unwrap_or(HEARTBEAT_TIMEOUT_MS));
+        self.set_spread_toml(&config.symbols, &config.bps, config.symbol_bps.as_deref());
+        self.set_min_spread_ticks(config.min_spread_ticks.unwrap_or(0.0));
+        self.set_maker_fee(config.maker_fee);
+        self.set_spread_schedule(config.spread_schedule.clone().unwrap_or_default());
+        self.set_aggression(
+            config.aggression_min.unwrap_or(0.0),
+            config.aggression_max.unwrap_or(1.0),
+        );
+        // A passive ratio left from older configs is a geometric distribution.
+        let legacy = config
+            .passive_ratio
+            .map(|ratio| SizeDistribution::Geometric { ratio });
+        self.set_size_distribution(config.size_distribution.or(legacy).unwrap_or_default());
+        self.set_sizing_mode(config.sizing_mode.unwrap_or_default());
+        self.set_skew_deadband(config.skew_deadband.unwrap_or(0.0));
+        self.set_backfill_levels(config.backfill_levels.unwrap_or(false));
+        self.set_min_level_notional(config.min_level_notional);
+        self.set_requote_threshold(config.requote_threshold_mult.unwrap_or(1.5));
+        self.set_iceberg_display_fraction(config.iceberg_display_fraction);
+        self.set_flatten_slice_notional(config.flatten_slice_notional);
+        self.set_flatten_limit(
+            config
+                .flatten_max_slippage_bps
+                .map(|bps| (bps, config.flatten_time_in_force.unwrap_or_default())),
+        );
+        self.set_kill_switch(config.kill_switch.unwrap_or(false));
+        self.set_funding_pause_window(config.funding_pause_window_ms.unwrap_or(0));
+        self.set_min_quoting_spread(
+            config.min_quoting_spread_bps.unwrap_or(0.0),
+            config.cancel_on_tight_spread.unwrap_or(false),
+        );
+        self.set_max_quoting_spread(config.max_quoting_spread_bps);
+        self.set_cancel_on_crossed_book(config.cancel_on_crossed_book.unwrap_or(false));
+        self.set_min_edge(
+            config.min_edge_bps,
+            config.suspend_on_low_edge.unwrap_or(false),
+        );
+        self.set_last_look(config.last_look_bps);
+        self.set_touch_clamp(config.touch_clamp);
+        self.set_thin_touch_qty(config.thin_touch_qty);
+        self.set_anchor(config.quote_anchor.unwrap_or_default());
+        self.set_trade_flow_bias(
+            config.trade_flow_bias.unwrap_or(0.0),
+            config.trade_flow_mode.unwrap_or(TradeFlowMode::Lean),
+        );
+        self.set_risk_per_level(config.risk_per_level_usd);
+        self.set_drawdown_curve(
+            config.max_drawdown_usd,
+            config.drawdown_curve.clone().unwrap_or_default(),
+        );
+        if let Some(horizon) = config.markout_horizon_ms {
+            self.set_markout_horizon(horizon);
+        }
+        if let Some(max_requests) = config.max_inflight_requests {
+            self.set_max_inflight_requests(max_requests);
+        }
+        self.set_fill_weighting(config.fill_weighting.unwrap_or(false));
+        self.set_levels_per_tick(config.levels_per_tick);
+        if let Some(tag) = config.order_tag.clone() {
+            self.set_order_tag(tag);
+        }
+        let shared_batches = config.shared_batches.unwrap_or(false);
+        if shared_batches && config.exchange != "bybit" {
+            eprintln!("Shared batches are only supported on bybit, placing the orders per symbol");
+        }
+        self.set_shared_batches(shared_batches && config.exchange == "bybit");
+    }
+
+    /// Starts and stops quoting symbols to match a modified config, then applies its settings.
+    ///
+    /// Symbols that are removed or disabled have their orders cancelled and stop being quoted.
+    /// Symbols that are added get a new generator, which quotes once the data feeds carry the
+    /// symbol, so a symbol that was not listed when the feeds started needs a restart.
+    pub async fn apply_config(&mut self, config: &Config) {
+        let enabled = config.enabled_symbols();
+
+        let stopped: Vec<String> = self
+            .generators
+            .keys()
+            .filter(|k| !enabled.contains(k))
+            .cloned()
+            .collect();
+        for symbol in stopped {
+            let mut generator = self.generators.remove(&symbol).unwrap();
+            if !generator.stop(&symbol).await {
+                eprintln!("Unable to cancel the orders of {}", symbol);
+            }
+            self.disabled.insert(symbol, generator);
+        }
+
+        let mut clients = SharedState::new(config.exchange.clone());
+        clients.set_testnet(config.testnet.unwrap_or(false));
+        for symbol in enabled {
+            if self.generators.contains_key(&symbol) {
+                continue;
+            }
+            if let Some(generator) = self.disabled.remove(&symbol) {
+                self.generators.insert(symbol, generator);
+                continue;
+            }
+            match config.api_keys.iter().find(|(_, _, s)| *s == symbol) {
+                Some((key, secret, _)) => {
+                    clients.add_clients(key.clone(), secret.clone(), symbol, None)
+                }
+                None => eprintln!("No API key for {}, not quoting it", symbol),
+            }
+        }
+
+        let assets: HashMap<String, f64> = config.balances.iter().cloned().collect();
+        let added = MarketMaker::build_generators(
+            clients
+                .clients
+                .into_iter()
+                .filter(|(k, _)| assets.contains_key(k))
+                .collect(),
+            assets,
+            config.orders_per_side,
+            config.leverage.clone(),
+            config.final_order_distance,
+            config.rate_limit,
+        );
+        let state = match &self.state_file {
+            Some(path) => load_state(path).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        for (symbol, mut generator) in added {
+            if let Some(state) = state.get(&symbol) {
+                generator.restore(state);
+            }
+            if self.maker_fee.is_none() {
+                generator.fetch_maker_fee(&symbol).await;
+            }
+            self.features.entry(symbol.clone()).or_default();
+            self.generators.insert(symbol, generator);
+        }
+
+        self.configure(config);
+    }
+
     /// Builds features for each symbol in the received data.
     ///
     /// # Arguments
@@ -151,7 +453,7 @@ impl MarketMaker {
     /// * `clients` - A `HashMap` containing the symbol names as keys and `ExchangeClient` instances as values.
     /// * `assets` - A `HashMap` containing the symbol names as keys and asset values as floats.
     /// * `orders_per_side` - The number of orders to place on each side of the order book.
-    /// * `leverage` - The leverage to use for trading.
+    /// * `leverage` - The leverage for every symbol or for each symbol.
     /// * `final_order_distance` - The distance between the final order and the mid price.
     ///
     /// # Returns
@@ -161,7 +463,7 @@ impl MarketMaker {
         clients: HashMap<String, ExchangeClient>,
         assets: HashMap<String, f64>,
         orders_per_side: usize,
-        leverage: f64,
+        leverage: Leverage,
         final_order_distance: f64,
         rate_limit: u32,
     ) -> HashMap<String, QuoteGenerator> {
@@ -170,8 +472,9 @@ impl MarketMaker {
 
         // Iterate over each client and insert a new `QuoteGenerator` instance into the HashMap.
         for (k, v) in clients {
-            // Get the asset value for the current symbol.
-            let asset = assets.get(&k).unwrap().clone();
+            // Get the asset value and leverage for the current symbol.
+            let asset = *assets.get(&k).unwrap();
+            let symbol_leverage = leverage.for_symbol(&k);
 
             // Insert a new `QuoteGenerator` instance into the HashMap.
             hash.insert(
@@ -179,7 +482,7 @@ impl MarketMaker {
                 QuoteGenerator::new(
                     v,
                     asset,
-                    leverage,
+                    symbol_leverage,
                     orders_per_side,
                     final_order_distance,
                     rate_limit,
@@ -215,13 +518,26 @@ impl MarketMaker {
         match data {
             // Update features for Bybit messages.
             MarketMessage::Bybit(v) => {
+                // Take the latest ticker of each book before the message is consumed.
+                let tickers: Vec<Option<Ticker>> =
+                    v.books.iter().map(|(k, _)| v.latest_ticker(k)).collect();
+
                 // Update the current trades with the received trades.
                 for (k, t) in v.trades {
+                    self.tape.record(&k, &t);
                     self.curr_trades.insert(k, t);
                 }
 
                 // Update the features for each order book.
-                for (k, b) in v.books {
+                for ((k, b), ticker) in v.books.into_iter().zip(tickers) {
+                    // Drop the trades too old to describe the current market.
+                    self.prune_stale_trades(&k, b.last_update);
+
+                    // Keep the features and the last good book while the book is crossed.
+                    if b.is_crossed() {
+                        continue;
+                    }
+
                     // Get the feature for the current symbol.
                     let feature = self.features.get_mut(&k).unwrap();
 
@@ -244,6 +560,7 @@ impl MarketMaker {
                             depth.clone(),
                             tick_window,
                             use_wmid,
+                            ticker.as_ref(),
                         );
                     }
 
@@ -261,11 +578,20 @@ impl MarketMaker {
             MarketMessage::Binance(v) => {
                 // Update the current trades with the received trades.
                 for (k, t) in v.trades {
+                    self.tape.record(&k, &t);
                     self.curr_trades.insert(k, t);
                 }
 
                 // Update the features for each order book.
                 for (k, b) in v.books {
+                    // Drop the trades too old to describe the current market.
+                    self.prune_stale_trades(&k, b.last_update);
+
+                    // Keep the features and the last good book while the book is crossed.
+                    if b.is_crossed() {
+                        continue;
+                    }
+
                     // Get the feature for the current symbol.
                     let feature = self.features.get_mut(&k).unwrap();
 
@@ -288,6 +614,8 @@ impl MarketMaker {
                             depth.clone(),
                             tick_window,
                             use_wmid,
+                            // The book ticker stream carries no funding or open interest.
+                            None,
                         );
                     }
 
@@ -318,19 +646,57 @@ impl MarketMaker {
         match data {
             // If the market data is from Bybit
             MarketMessage::Bybit(v) => {
+                let funding_times: Vec<Option<u64>> = v
+                    .books
+                    .iter()
+                    .map(|(symbol, _)| v.next_funding_time(symbol))
+                    .collect();
                 // Update the strategy for each symbol
-                for (symbol, book) in v.books {
-                    // Get the skew and imbalance for the current symbol
-                    let skew = self.features.get(&symbol).unwrap().skew;
+                for ((symbol, book), funding_time) in v.books.into_iter().zip(funding_times) {
+                    // Disabled symbols are still streamed but no longer quoted.
+                    if !self.generators.contains_key(&symbol)
+                        || !(self.kill_switch || self.is_warmed_up(&symbol))
+                    {
+                        continue;
+                    }
+                    // Sizing against an unknown position could breach the limits.
+                    if !self.private_fresh(&symbol, private_data.get(&symbol), book.last_update) {
+                        continue;
+                    }
+                    if !self.quote_due(&symbol, book.last_update) {
+                        continue;
+                    }
+                    // Get the skew, volatility and imbalance for the current symbol
+                    let feature = self.features.get(&symbol).unwrap();
+                    let (skew, volatility, flow) = (
+                        self.quoted_skew(feature),
+                        feature.trade_vol,
+                        feature.trade_flow,
+                    );
                     let imbalance = imbalance_ratio(&book, Some(self.depths[0] * 3));
 
+                    // Leave the symbol the part of the portfolio budget the others do not use.
+                    self.apply_portfolio_budget(&symbol);
+
                     // Get the symbol quoter for the current symbol
                     let symbol_quoter = self.generators.get_mut(&symbol).unwrap();
+                    symbol_quoter.set_trade_flow(flow);
+                    if let Some(time) = funding_time {
+                        symbol_quoter.set_next_funding_time(time);
+                    }
 
                     if let Some(p) = private_data.get(&symbol) {
                         // Update the symbol quoter
                         symbol_quoter
-                            .update_grid(p.clone(), skew, imbalance, book, symbol, rate_limit)
+                            .update_grid(
+                                p.clone(),
+                                skew,
+                                imbalance,
+                                volatility,
+                                book,
+                                symbol,
+                                rate_limit,
+                            )
                             .await;
                     }
                 }
@@ -339,22 +705,99 @@ impl MarketMaker {
             MarketMessage::Binance(v) => {
                 // Update the strategy for each symbol
                 for (symbol, book) in v.books {
-                    // Get the skew and imbalance for the current symbol
-                    let skew = self.features.get(&symbol).unwrap().skew;
+                    // Disabled symbols are still streamed but no longer quoted.
+                    if !self.generators.contains_key(&symbol)
+                        || !(self.kill_switch || self.is_warmed_up(&symbol))
+                    {
+                        continue;
+                    }
+                    // Sizing against an unknown position could breach the limits.
+                    if !self.private_fresh(&symbol, private_data.get(&symbol), book.last_update) {
+                        continue;
+                    }
+                    if !self.quote_due(&symbol, book.last_update) {
+                        continue;
+                    }
+                    // Get the skew, volatility and imbalance for the current symbol
+                    let feature = self.features.get(&symbol).unwrap();
+                    let (skew, volatility, flow) = (
+                        self.quoted_skew(feature),
+                        feature.trade_vol,
+                        feature.trade_flow,
+                    );
                     let imbalance = imbalance_ratio(&book, Some(self.depths[0] * 3));
 
+                    // Leave the symbol the part of the portfolio budget the others do not use.
+                    self.apply_portfolio_budget(&symbol);
+
                     // Get the symbol quoter for the current symbol
                     let symbol_quoter = self.generators.get_mut(&symbol).unwrap();
+                    symbol_quoter.set_trade_flow(flow);
+                    // The subscribed streams carry no funding time, so follow the default
+                    // 8-hourly schedule.
+                    symbol_quoter.set_next_funding_time(next_funding_time(
+                        book.last_update,
+                        FUNDING_INTERVAL_MS,
+                    ));
 
                     if let Some(p) = private_data.get(&symbol) {
                         // Update the symbol quoter
                         symbol_quoter
-                            .update_grid(p.clone(), skew, imbalance, book, symbol, rate_limit)
+                            .update_grid(
+                                p.clone(),
+                                skew,
+                                imbalance,
+                                volatility,
+                                book,
+                                symbol,
+                                rate_limit,
+                            )
                             .await;
                     }
                 }
             }
         }
+        self.place_shared_batches(generate_timestamp()).await;
+    }
+
+    /// Places the orders the generators held during the update in batches mixing their symbols.
+    ///
+    /// The symbols share the account, so the client of any of them can place a batch. The
+    /// placed orders are routed back to the generator of their symbol.
+    async fn place_shared_batches(&mut self, now: u64) {
+        if !self.shared_batches {
+            return;
+        }
+        let mut orders = Vec::new();
+        for generator in self.generators.values_mut() {
+            orders.extend(generator.take_deferred_orders());
+        }
+
+        for chunk in orders.chunks(MAX_BATCH_ORDERS) {
+            let mut symbols: Vec<String> = chunk.iter().map(|o| o.2.clone()).collect();
+            symbols.dedup();
+            let placed = match self.generators.get(&symbols[0]) {
+                Some(sender) => sender.place_batch(chunk.to_vec()).await,
+                None => continue,
+            };
+
+            match placed {
+                Ok(queues) => {
+                    for (symbol, placed) in route_placed_orders(chunk, queues) {
+                        if let Some(generator) = self.generators.get_mut(&symbol) {
+                            generator.apply_placement(Ok(placed), now);
+                        }
+                    }
+                }
+                Err(e) => {
+                    for symbol in symbols {
+                        if let Some(generator) = self.generators.get_mut(&symbol) {
+                            generator.apply_placement(Err(e.clone()), now);
+                        }
+                    }
+                }
+            }
+        }
     }
 
     pub fn set_spread_bps_input(&mut self) {
@@ -365,28 +808,1426 @@ impl MarketMaker {
         }
     }
 
-    pub fn set_spread_toml(&mut self, bps: Vec<f64>) {
-        let mut index = 0;
+    pub fn set_depth_weight(&mut self, weighting: DepthWeight) {
+        for (_, v) in self.features.iter_mut() {
+            v.set_depth_weight(weighting);
+        }
+    }
+
+    pub fn set_imbalance_deadzone(&mut self, deadzone: f64) {
+        for (_, v) in self.features.iter_mut() {
+            v.set_imbalance_deadzone(deadzone);
+        }
+    }
+
+    pub fn set_ticker_weights(&mut self, funding_weight: f64, oi_weight: f64) {
+        for (_, v) in self.features.iter_mut() {
+            v.set_ticker_weights(funding_weight, oi_weight);
+        }
+    }
+
+    pub fn set_momentum_weight(&mut self, weight: f64) {
+        for (_, v) in self.features.iter_mut() {
+            v.set_momentum_weight(weight);
+        }
+    }
+
+    pub fn set_warmup_predict_weight(&mut self, weight: f64) {
+        for (_, v) in self.features.iter_mut() {
+            v.set_warmup_predict_weight(weight);
+        }
+    }
+
+    pub fn set_history_len(&mut self, len: usize) {
+        for (_, v) in self.features.iter_mut() {
+            v.set_history_len(len);
+        }
+    }
+
+    pub fn set_max_notional(&mut self, max_notional_usd: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_max_notional(max_notional_usd);
+        }
+    }
+
+    pub fn set_vol_window(&mut self, window: Option<usize>) {
+        for (_, v) in self.features.iter_mut() {
+            v.set_vol_window(window);
+        }
+    }
+
+    /// Sets the number of feature updates each symbol needs before it is quoted.
+    pub fn set_warmup_updates(&mut self, updates: usize) {
+        self.warmup_updates = updates;
+    }
+
+    /// Sets whether a symbol quotes a symmetric grid, its skew held at 0, until its features
+    /// are ready.
+    ///
+    /// The features are ready after a full tick window of updates, so this has no effect unless
+    /// the warm-up set by `set_warmup_updates` is shorter.
+    pub fn set_neutral_warmup(&mut self, neutral: bool) {
+        self.neutral_warmup = neutral;
+    }
+
+    /// Drops the trades older than `age_ms` before the features are computed, so quiet symbols
+    /// are not driven by trades far older than the tick window.
+    ///
+    /// # Panics
+    ///
+    /// If `age_ms` is 0.
+    pub fn set_max_trade_age(&mut self, age_ms: Option<u64>) {
+        if let Some(age_ms) = age_ms {
+            assert!(age_ms > 0, "Maximum trade age must be positive");
+        }
+        self.max_trade_age_ms = age_ms;
+    }
+
+    /// Drops the trades of `symbol` older than the maximum trade age at `now`.
+    fn prune_stale_trades(&mut self, symbol: &str, now: u64) {
+        let (Some(age_ms), Some(trades)) =
+            (self.max_trade_age_ms, self.curr_trades.get_mut(symbol))
+        else {
+            return;
+        };
+        trades.retain(|t| t.timestamp + age_ms >= now);
+    }
+
+    /// Sets the minimum time in milliseconds between two quote updates of each symbol.
+    ///
+    /// The features keep updating on every book, so the warm-up does not depend on it.
+    pub fn set_min_quote_interval(&mut self, interval_ms: u64) {
+        self.min_quote_interval_ms = interval_ms;
+    }
+
+    /// Defers the quoting of a symbol while its private data is older than `age_ms` at the time
+    /// of the book, or while nothing was received on its private stream yet, `None` quotes on
+    /// whatever private data was received.
+    ///
+    /// The private streams only publish on account events, so the age has to cover the quiet
+    /// periods of the account.
+    ///
+    /// # Panics
+    ///
+    /// If `age_ms` is 0.
+    pub fn set_max_private_age(&mut self, age_ms: Option<u64>) {
+        if let Some(age_ms) = age_ms {
+            assert!(age_ms > 0, "Maximum private data age must be positive");
+        }
+        self.max_private_age_ms = age_ms;
+    }
+
+    /// Returns whether the private data of `symbol` is recent enough at `now` to size the quotes
+    /// against, logging when its quoting is deferred and resumed.
+    fn private_fresh(&mut self, symbol: &str, data: Option<&PrivateData>, now: u64) -> bool {
+        let Some(age_ms) = self.max_private_age_ms else {
+            return true;
+        };
+        let time = match data {
+            Some(PrivateData::Bybit(data)) => data.time,
+            Some(PrivateData::Binance(data)) => data.time,
+            None => 0,
+        };
+        // A time of 0 is the default private data, inserted before anything was received.
+        let fresh = time > 0 && now.saturating_sub(time) <= age_ms;
+        if !fresh && self.stale_private.insert(symbol.to_string()) {
+            eprintln!("Private data of {} is stale, deferring its quotes", symbol);
+        } else if fresh && self.stale_private.remove(symbol) {
+            eprintln!(
+                "Private data of {} is fresh again, resuming its quotes",
+                symbol
+            );
+        }
+        fresh
+    }
+
+    /// Returns whether `symbol` may be requoted at `now`, recording the update if so.
+    fn quote_due(&mut self, symbol: &str, now: u64) -> bool {
+        if let Some(last) = self.last_quote_update.get(symbol) {
+            if now < last + self.min_quote_interval_ms {
+                return false;
+            }
+        }
+        self.last_quote_update.insert(symbol.to_string(), now);
+        true
+    }
+
+    /// Returns whether the features of `symbol` have had enough updates to quote it.
+    fn is_warmed_up(&self, symbol: &str) -> bool {
+        self.features
+            .get(symbol)
+            .is_some_and(|f| f.update_count() >= self.warmup_updates)
+    }
+
+    /// Returns the skew to quote with, 0 while the features are not ready if the warm-up is
+    /// neutral.
+    fn quoted_skew(&self, feature: &Engine) -> f64 {
+        if self.neutral_warmup && !feature.is_ready() {
+            0.0
+        } else {
+            feature.skew
+        }
+    }
+
+    /// Caps the notional in USD deployed in positions and orders across all the symbols.
+    ///
+    /// # Panics
+    ///
+    /// If `max_notional` is not positive.
+    pub fn set_max_portfolio_notional(&mut self, max_notional: Option<f64>) {
+        if let Some(max_notional) = max_notional {
+            assert!(max_notional > 0.0, "Portfolio notional must be positive");
+        }
+        self.max_portfolio_notional = max_notional;
+    }
+
+    /// Gives `symbol` the portfolio budget left after the notional the other symbols deploy.
+    fn apply_portfolio_budget(&mut self, symbol: &str) {
+        let budget = self.max_portfolio_notional.map(|max| {
+            let others: f64 = self
+                .generators
+                .iter()
+                .filter(|(k, _)| k.as_str() != symbol)
+                .map(|(_, v)| v.deployed_notional())
+                .sum();
+            (max - others).max(0.0)
+        });
+        if let Some(generator) = self.generators.get_mut(symbol) {
+            generator.set_portfolio_budget(budget);
+        }
+    }
+
+    pub fn set_orders_per_side(&mut self, bids: usize, asks: usize) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_orders_per_side(bids, asks);
+        }
+    }
+
+    pub fn set_grid_layers(&mut self, layers: Vec<(f64, usize, f64)>) {
+        let layers: Vec<GridLayer> = layers
+            .into_iter()
+            .map(|(spread_multiplier, orders, size_fraction)| {
+                GridLayer::new(spread_multiplier, orders, size_fraction)
+            })
+            .collect();
+        for (_, v) in self.generators.iter_mut() {
+            v.set_grid_layers(layers.clone());
+        }
+    }
+
+    pub fn set_inventory_target(&mut self, target: f64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_inventory_target(target);
+        }
+    }
+
+    pub fn set_hold_touch(&mut self, enabled: bool) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_hold_touch(enabled);
+        }
+    }
+
+    pub fn set_max_order_age(&mut self, max_age_ms: u64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_max_order_age(max_age_ms);
+        }
+    }
+
+    pub fn set_requote_cooldown(&mut self, cooldown_ms: u64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_requote_cooldown(cooldown_ms);
+        }
+    }
+
+    pub fn set_requote_on_fill(&mut self, requote: bool) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_requote_on_fill(requote);
+        }
+    }
+
+    pub fn set_min_spread_ticks(&mut self, ticks: f64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_min_spread_ticks(ticks);
+        }
+    }
+
+    pub fn set_spread_schedule(&mut self, schedule: Vec<(u32, u32, f64)>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_spread_schedule(schedule.clone());
+        }
+    }
+
+    pub fn set_aggression(&mut self, aggression_min: f64, aggression_max: f64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_aggression(aggression_min, aggression_max);
+        }
+    }
+
+    pub fn set_sizing_mode(&mut self, mode: SizingMode) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_sizing_mode(mode);
+        }
+    }
+
+    pub fn set_min_level_notional(&mut self, notional: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_min_level_notional(notional);
+        }
+    }
+
+    pub fn set_backfill_levels(&mut self, backfill: bool) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_backfill_levels(backfill);
+        }
+    }
+
+    pub fn set_size_distribution(&mut self, distribution: SizeDistribution) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_size_distribution(distribution);
+        }
+    }
+
+    pub fn set_skew_deadband(&mut self, deadband: f64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_skew_deadband(deadband);
+        }
+    }
+
+    pub fn set_requote_threshold(&mut self, mult: f64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_requote_threshold(mult);
+        }
+    }
+
+    pub fn set_iceberg_display_fraction(&mut self, fraction: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_iceberg_display_fraction(fraction);
+        }
+    }
+
+    /// Hedges the fills of `symbol` through `hedge`.
+    ///
+    /// # Panics
+    ///
+    /// If `symbol` is not quoted.
+    pub fn set_hedge(&mut self, symbol: &str, hedge: HedgeExecutor) {
+        self.generators
+            .get_mut(symbol)
+            .expect("Hedged symbol is not quoted")
+            .set_hedge(hedge);
+    }
+
+    /// Logs the order events of every symbol with `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        for (symbol, v) in self.generators.iter_mut() {
+            v.set_event_logger(logger.clone(), symbol.clone());
+        }
+    }
+
+    pub fn set_placement_jitter(&mut self, jitter_ms: u64) {
         for (_, v) in self.generators.iter_mut() {
-            v.set_spread(bps[index]);
-            index += 1;
+            v.set_placement_jitter(jitter_ms);
         }
     }
+
+    pub fn set_replace_delay(&mut self, delay_ms: u64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_replace_delay(delay_ms);
+        }
+    }
+
+    pub fn set_heartbeat_timeout(&mut self, timeout_ms: u64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_heartbeat_timeout(timeout_ms);
+        }
+    }
+
+    pub fn set_flatten_limit(&mut self, limit: Option<(f64, TimeInForce)>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_flatten_limit(limit);
+        }
+    }
+
+    pub fn set_flatten_slice_notional(&mut self, notional: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_flatten_slice_notional(notional);
+        }
+    }
+
+    /// Pulls every grid and flattens every position instead of quoting while `enabled`, without
+    /// waiting for the features to warm up.
+    pub fn set_kill_switch(&mut self, enabled: bool) {
+        if enabled && !self.kill_switch {
+            eprintln!("Kill switch on, flattening every position");
+        }
+        self.kill_switch = enabled;
+        for (_, v) in self.generators.iter_mut() {
+            v.set_kill_switch(enabled);
+        }
+    }
+
+    pub fn set_funding_pause_window(&mut self, window_ms: u64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_funding_pause_window(window_ms);
+        }
+    }
+
+    pub fn set_min_quoting_spread(&mut self, spread_bps: f64, cancel: bool) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_min_quoting_spread(spread_bps, cancel);
+        }
+    }
+
+    pub fn set_min_edge(&mut self, min_edge_bps: Option<f64>, suspend: bool) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_min_edge(min_edge_bps, suspend);
+        }
+    }
+
+    pub fn set_max_quoting_spread(&mut self, spread_bps: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_max_quoting_spread(spread_bps);
+        }
+    }
+
+    pub fn set_cancel_on_crossed_book(&mut self, cancel: bool) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_cancel_on_crossed_book(cancel);
+        }
+    }
+
+    pub fn set_last_look(&mut self, tolerance_bps: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_last_look(tolerance_bps);
+        }
+    }
+
+    /// Gives each symbol a channel its generator reads the latest mid price from before
+    /// submitting orders, returning the senders to publish the mid prices with.
+    pub fn live_mids(&mut self) -> HashMap<String, tokio::sync::watch::Sender<f64>> {
+        let mut senders = HashMap::new();
+        for (symbol, v) in self.generators.iter_mut() {
+            let (sender, receiver) = tokio::sync::watch::channel(0.0);
+            v.set_live_mid(receiver);
+            senders.insert(symbol.clone(), sender);
+        }
+        senders
+    }
+
+    pub fn set_touch_clamp(&mut self, clamp: Option<TouchClamp>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_touch_clamp(clamp);
+        }
+    }
+
+    pub fn set_thin_touch_qty(&mut self, qty: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_thin_touch_qty(qty);
+        }
+    }
+
+    pub fn set_markout_horizon(&mut self, horizon_ms: u64) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_markout_horizon(horizon_ms);
+        }
+    }
+
+    pub fn set_anchor(&mut self, anchor: QuoteAnchor) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_anchor(anchor);
+        }
+    }
+
+    pub fn set_trade_flow_bias(&mut self, bias: f64, mode: TradeFlowMode) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_trade_flow_bias(bias, mode);
+        }
+    }
+
+    pub fn set_drawdown_curve(
+        &mut self,
+        max_drawdown_usd: Option<f64>,
+        curve: Vec<(f64, f64, f64)>,
+    ) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_drawdown_curve(max_drawdown_usd, curve.clone());
+        }
+    }
+
+    pub fn set_risk_per_level(&mut self, risk: Option<f64>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_risk_per_level(risk);
+        }
+    }
+
+    /// Limits the order requests in flight at once across all the symbols.
+    ///
+    /// # Panics
+    ///
+    /// If `max_requests` is 0.
+    pub fn set_max_inflight_requests(&mut self, max_requests: usize) {
+        assert!(
+            max_requests > 0,
+            "At least one request must be allowed in flight"
+        );
+        let limiter = Arc::new(Semaphore::new(max_requests));
+        for (_, v) in self.generators.iter_mut() {
+            v.set_request_limiter(limiter.clone());
+        }
+    }
+
+    /// Places the orders of every symbol together, in batches mixing their symbols, instead of
+    /// each symbol placing its own batches.
+    ///
+    /// Every symbol has to trade on the same account.
+    pub fn set_shared_batches(&mut self, enabled: bool) {
+        self.shared_batches = enabled;
+        for (_, v) in self.generators.iter_mut() {
+            v.set_defer_placement(enabled);
+        }
+    }
+
+    pub fn set_levels_per_tick(&mut self, per_side: Option<usize>) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_levels_per_tick(per_side);
+        }
+    }
+
+    pub fn set_fill_weighting(&mut self, enabled: bool) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_fill_weighting(enabled);
+        }
+    }
+
+    pub fn set_order_tag(&mut self, tag: String) {
+        for (_, v) in self.generators.iter_mut() {
+            v.set_order_tag(tag.clone());
+        }
+    }
+
+    pub async fn reconcile_orders(&mut self) {
+        for (k, v) in self.generators.iter_mut() {
+            v.reconcile(k).await;
+        }
+    }
+
+    /// Fetches the maker fee of each symbol from the exchange, unless a fee is configured.
+    pub async fn fetch_maker_fees(&mut self) {
+        if self.maker_fee.is_some() {
+            return;
+        }
+        for (k, v) in self.generators.iter_mut() {
+            v.fetch_maker_fee(k).await;
+        }
+    }
+
+    /// Sets the maker fee rate of every symbol as a decimal, negative for a rebate, instead of
+    /// the rate fetched from the exchange.
+    ///
+    /// `None` keeps the rates already fetched.
+    pub fn set_maker_fee(&mut self, maker_fee: Option<f64>) {
+        self.maker_fee = maker_fee;
+        if let Some(fee) = maker_fee {
+            for (_, v) in self.generators.iter_mut() {
+                v.set_maker_fee(fee);
+            }
+        }
+    }
+
+    /// Sets the spread of each symbol from the config.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - The configured symbols, in the order of `bps`.
+    /// * `bps` - The spread in bps of the symbol at the same position, the symbols past its end
+    ///   use its last spread.
+    /// * `overrides` - Spreads in bps by symbol, taking precedence over `bps`.
+    pub fn set_spread_toml(
+        &mut self,
+        symbols: &[String],
+        bps: &[f64],
+        overrides: Option<&[(String, f64)]>,
+    ) {
+        for (index, symbol) in symbols.iter().enumerate() {
+            let spread = overrides
+                .and_then(|o| {
+                    o.iter()
+                        .rev()
+                        .find(|(s, _)| s == symbol)
+                        .map(|(_, bps)| bps)
+                })
+                .or(bps.get(index))
+                .or(bps.last());
+            if let (Some(generator), Some(spread)) = (self.generators.get_mut(symbol), spread) {
+                generator.set_spread(*spread);
+            }
+        }
+    }
+}
+
+/// Checks that every setting `MarketMaker::configure` applies is within the bounds its setter
+/// accepts, so a bad edit of the config can be rejected instead of panicking while quoting.
+///
+/// # Errors
+///
+/// With the message of the first setting out of bounds.
+pub fn validate_config(config: &Config) -> Result<(), String> {
+    let positive = |v: Option<f64>| v.is_none_or(|v| v > 0.0);
+    let unit = |v: Option<f64>| v.is_none_or(|v| (0.0..=1.0).contains(&v));
+    let aggression = (
+        config.aggression_min.unwrap_or(0.0),
+        config.aggression_max.unwrap_or(1.0),
+    );
+    let passive_ratio = match config.size_distribution {
+        Some(SizeDistribution::Geometric { ratio }) => Some(ratio),
+        Some(_) => None,
+        None => config.passive_ratio,
+    };
+    let anchor = config.quote_anchor.unwrap_or_default();
+    let max_position = match config.sizing_mode {
+        Some(SizingMode::Quantity { max_position }) => Some(max_position),
+        _ => None,
+    };
+    let schedule = config.spread_schedule.as_deref().unwrap_or_default();
+    let curve = config.drawdown_curve.as_deref().unwrap_or_default();
+    let increasing_fractions = curve
+        .iter()
+        .try_fold(0.0, |previous, &(fraction, _, _)| {
+            (fraction > previous).then_some(fraction)
+        })
+        .is_some();
+    let replace_delay = format!("Replace delay must not exceed {} ms", MAX_REPLACE_DELAY_MS);
+    let placement_jitter = format!(
+        "Placement jitter must not exceed {} ms",
+        MAX_PLACEMENT_JITTER_MS
+    );
+    let order_tag = format!(
+        "Order tag must be 1 to {} alphanumeric characters",
+        MAX_ORDER_TAG_LEN
+    );
+    // The shared batches are all sent with the client of one symbol.
+    let enabled = config.enabled_symbols();
+    let accounts: HashSet<(&String, &String)> = config
+        .api_keys
+        .iter()
+        .filter(|(_, _, symbol)| enabled.contains(symbol))
+        .map(|(key, secret, _)| (key, secret))
+        .collect();
+    let checks: &[(bool, &str)] = &[
+        (
+            (0.0..1.0).contains(&config.imbalance_deadzone.unwrap_or(0.0)),
+            "Imbalance deadzone must be between 0 and 1",
+        ),
+        (
+            unit(config.momentum_weight),
+            "Momentum weight must be between 0 and 1",
+        ),
+        (
+            unit(config.warmup_predict_weight),
+            "Warm-up prediction weight must be between 0 and 1",
+        ),
+        (
+            unit(config.funding_weight) && unit(config.open_interest_weight),
+            "Ticker weights must be between 0 and 1",
+        ),
+        (
+            config.vol_window != Some(0),
+            "Volatility window must be positive",
+        ),
+        (
+            positive(config.max_portfolio_notional),
+            "Portfolio notional must be positive",
+        ),
+        (
+            config.max_trade_age_ms != Some(0),
+            "Maximum trade age must be positive",
+        ),
+        (
+            config.max_private_age_ms != Some(0),
+            "Maximum private data age must be positive",
+        ),
+        (
+            config.heartbeat_timeout_ms != Some(0),
+            "Heartbeat timeout must be positive",
+        ),
+        (
+            (-1.0..=1.0).contains(&config.inventory_target.unwrap_or(0.0)),
+            "Inventory target must be between -1 and 1",
+        ),
+        (
+            config.placement_jitter_ms.unwrap_or(0) <= MAX_PLACEMENT_JITTER_MS,
+            &placement_jitter,
+        ),
+        (
+            config.replace_delay_ms.unwrap_or(0) <= MAX_REPLACE_DELAY_MS,
+            &replace_delay,
+        ),
+        (
+            schedule
+                .iter()
+                .all(|&(start, end, _)| start <= 24 && end <= 24),
+            "Schedule hours must be between 0 and 24",
+        ),
+        (
+            schedule.iter().all(|&(_, _, multiplier)| multiplier > 0.0),
+            "Spread multipliers must be positive",
+        ),
+        (
+            unit(Some(aggression.0)) && unit(Some(aggression.1)),
+            "Aggression bounds must be between 0 and 1",
+        ),
+        (
+            aggression.0 <= aggression.1,
+            "Aggression minimum must not be above the maximum",
+        ),
+        (unit(passive_ratio), "Passive ratio must be between 0 and 1"),
+        (positive(max_position), "Maximum position must be positive"),
+        (
+            config.skew_deadband.unwrap_or(0.0) >= 0.0,
+            "Skew deadband must not be negative",
+        ),
+        (
+            positive(config.min_level_notional),
+            "Minimum level notional must be positive",
+        ),
+        (
+            config.requote_threshold_mult.unwrap_or(1.5) > 1.0,
+            "Requote threshold multiplier must be greater than 1.0",
+        ),
+        (
+            config
+                .iceberg_display_fraction
+                .is_none_or(|f| f > 0.0 && f <= 1.0),
+            "Iceberg display fraction must be within (0, 1]",
+        ),
+        (
+            positive(config.flatten_slice_notional),
+            "Flatten slice notional must be positive",
+        ),
+        (
+            positive(config.flatten_max_slippage_bps),
+            "Flatten slippage must be positive",
+        ),
+        (
+            config.min_quoting_spread_bps.unwrap_or(0.0) >= 0.0,
+            "Minimum quoting spread must not be negative",
+        ),
+        (
+            positive(config.max_quoting_spread_bps),
+            "Maximum quoting spread must be positive",
+        ),
+        (
+            positive(config.last_look_bps),
+            "Last look tolerance must be positive",
+        ),
+        (
+            positive(config.thin_touch_qty),
+            "Thin touch quantity must be positive",
+        ),
+        (
+            anchor != QuoteAnchor::Microprice { depth: 0 },
+            "Microprice depth must be positive",
+        ),
+        (
+            anchor != QuoteAnchor::Twap { window_ms: 0 },
+            "TWAP window must be positive",
+        ),
+        (
+            unit(config.trade_flow_bias),
+            "Trade flow bias must be between 0 and 1",
+        ),
+        (
+            positive(config.risk_per_level_usd),
+            "Risk per level must be positive",
+        ),
+        (
+            positive(config.max_drawdown_usd),
+            "Maximum drawdown must be positive",
+        ),
+        (
+            increasing_fractions,
+            "Drawdown fractions must be positive and increasing",
+        ),
+        (
+            curve.iter().all(|&(_, spread, _)| spread >= 1.0),
+            "Drawdown spread multipliers must be at least 1.0",
+        ),
+        (
+            curve
+                .iter()
+                .all(|&(_, _, size)| (0.0..=1.0).contains(&size)),
+            "Drawdown size multipliers must be between 0.0 and 1.0",
+        ),
+        (
+            config.markout_horizon_ms != Some(0),
+            "Markout horizon must be positive",
+        ),
+        (
+            config.max_inflight_requests != Some(0),
+            "At least one request must be allowed in flight",
+        ),
+        (
+            config.levels_per_tick != Some(0),
+            "At least one level must be placed per tick",
+        ),
+        (
+            config.order_tag.as_deref().is_none_or(|tag| {
+                !tag.is_empty()
+                    && tag.len() <= MAX_ORDER_TAG_LEN
+                    && tag.chars().all(|c| c.is_ascii_alphanumeric())
+            }),
+            &order_tag,
+        ),
+        (
+            !config.shared_batches.unwrap_or(false) || accounts.len() <= 1,
+            "Shared batches need every quoted symbol to use the same API key",
+        ),
+    ];
+    match checks.iter().find(|(valid, _)| !valid) {
+        Some((_, msg)) => Err(msg.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Publishes the mid price of each streamed book to `mids` and forwards the shared state to the
+/// market maker.
+///
+/// It runs apart from the market maker loop, so the generators can see the latest mid prices
+/// while they are placing orders.
+pub async fn relay_mids(
+    mut receiver: UnboundedReceiver<SharedState>,
+    mids: HashMap<String, tokio::sync::watch::Sender<f64>>,
+    forward: UnboundedSender<SharedState>,
+) {
+    while let Some(data) = receiver.recv().await {
+        for market in &data.markets {
+            let books = match market {
+                MarketMessage::Bybit(v) => &v.books,
+                MarketMessage::Binance(v) => &v.books,
+            };
+            for (symbol, book) in books {
+                if let Some(mid) = mids.get(symbol) {
+                    mid.send_replace(book.mid_price);
+                }
+            }
+        }
+        if forward.send(data).is_err() {
+            break;
+        }
+    }
+}
+
+/// Splits the buy and sell queues placed for a batch mixing symbols into the buy and sell queues
+/// of each symbol.
+///
+/// The backends keep the order of the requested orders within each side, so the placed orders
+/// are matched to the requests by position. When orders are missing from a side, they are
+/// matched by their client order id instead.
+fn route_placed_orders(
+    requested: &[BatchOrder],
+    placed: Vec<VecDeque<LiveOrder>>,
+) -> HashMap<String, Vec<VecDeque<LiveOrder>>> {
+    let mut routed: HashMap<String, Vec<VecDeque<LiveOrder>>> = HashMap::new();
+    // The buy queue comes first, followed by the sell queue.
+    for (index, queue) in placed.into_iter().enumerate() {
+        let side = if index == 0 { 1 } else { -1 };
+        let side_requests: Vec<&BatchOrder> = requested.iter().filter(|o| o.3 == side).collect();
+        let positional = side_requests.len() == queue.len();
+        for (i, order) in queue.into_iter().enumerate() {
+            let request = if positional {
+                Some(side_requests[i])
+            } else {
+                side_requests
+                    .iter()
+                    .find(|o| !o.4.is_empty() && o.4 == order.link_id)
+                    .copied()
+            };
+            match request {
+                Some(request) => routed
+                    .entry(request.2.clone())
+                    .or_insert_with(|| vec![VecDeque::new(), VecDeque::new()])[index]
+                    .push_back(order),
+                None => eprintln!("Unable to route placed order {}", order.order_id),
+            }
+        }
+    }
+    routed
 }
 
 #[cfg(test)]
 mod tests {
-    use skeleton::util::logger::Logger;
+    use bybit::model::{Ask, Bid};
+    use skeleton::exchanges::ex_bybit::{BybitClient, BybitMarket, BybitPrivate};
+
+    use crate::trader::mock_backend::{MockBackend, MockRequest};
+    use crate::trader::state::SymbolState;
+    use skeleton::util::helpers::try_read_toml;
+    use tokio::sync::mpsc;
     use tokio::time::Duration;
 
     use tokio::time;
 
     use super::*;
 
+    #[test]
+    fn test_per_symbol_leverage() {
+        let symbols = ["BTCUSDT", "ETHUSDT", "SOLUSDT"];
+        let clients = symbols
+            .iter()
+            .map(|s| (s.to_string(), ExchangeClient::Bybit(BybitClient::default())))
+            .collect();
+        let assets = symbols.iter().map(|s| (s.to_string(), 100.0)).collect();
+        let leverage = Leverage::PerSymbol(HashMap::from([
+            ("BTCUSDT".to_string(), 5.0),
+            ("ETHUSDT".to_string(), 2.0),
+        ]));
+
+        let generators = MarketMaker::build_generators(clients, assets, 3, leverage, 5.0, 10);
+        assert_eq!(generators["BTCUSDT"].leverage(), 5.0);
+        assert_eq!(generators["ETHUSDT"].leverage(), 2.0);
+        // Symbols missing from the map are not leveraged.
+        assert_eq!(generators["SOLUSDT"].leverage(), 1.0);
+
+        let clients = symbols
+            .iter()
+            .map(|s| (s.to_string(), ExchangeClient::Bybit(BybitClient::default())))
+            .collect();
+        let assets = symbols.iter().map(|s| (s.to_string(), 100.0)).collect();
+        let generators =
+            MarketMaker::build_generators(clients, assets, 3, Leverage::Global(3.0), 5.0, 10);
+        assert!(generators.values().all(|g| g.leverage() == 3.0));
+    }
+
+    /// Creates a market maker quoting BTCUSDT and ETHUSDT with 1000 USD each.
+    fn test_market_maker(state_file: Option<String>) -> MarketMaker {
+        let mut ss = SharedState::new("bybit".to_string());
+        for symbol in ["BTCUSDT", "ETHUSDT"] {
+            ss.clients.insert(
+                symbol.to_string(),
+                ExchangeClient::Bybit(BybitClient::default()),
+            );
+        }
+        ss.add_symbols(vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()]);
+        let assets = HashMap::from([
+            ("BTCUSDT".to_string(), 1000.0),
+            ("ETHUSDT".to_string(), 1000.0),
+        ]);
+        let leverage = Leverage::Global(1.0);
+        MarketMaker::new(ss, assets, leverage, 3, 5.0, vec![5], 10, state_file)
+    }
+
+    #[test]
+    fn test_portfolio_budget_throttles_second_symbol() {
+        let mut market_maker = test_market_maker(None);
+        market_maker.set_max_portfolio_notional(Some(1000.0));
+
+        let mut book = LocalBook::new();
+        book.tick_size = 0.01;
+        book.lot_size = 0.001;
+        book.mid_price = 100.0;
+        let quoted = |market_maker: &mut MarketMaker, symbol: &str| -> f64 {
+            market_maker.apply_portfolio_budget(symbol);
+            let generator = market_maker.generators.get_mut(symbol).unwrap();
+            generator
+                .generate_quotes(symbol.to_string(), &book, 0.0, 0.0, 0.0)
+                .iter()
+                .map(|o| o.0 * o.1)
+                .sum()
+        };
+
+        // The first symbol quotes its full grid within the budget.
+        let full = quoted(&mut market_maker, "BTCUSDT");
+        assert!(full > 0.0 && full <= 1000.0);
+
+        // Once the first symbol holds most of the budget, the second one quotes the rest only.
+        market_maker.generators.get_mut("BTCUSDT").unwrap().position = 900.0;
+        let throttled = quoted(&mut market_maker, "ETHUSDT");
+        assert!(throttled > 0.0 && throttled <= 100.0);
+
+        // Nothing is quoted once the budget is exhausted.
+        market_maker.generators.get_mut("BTCUSDT").unwrap().position = -1000.0;
+        assert_eq!(quoted(&mut market_maker, "ETHUSDT"), 0.0);
+    }
+
+    #[test]
+    fn test_state_restored_after_restart() {
+        let path = std::env::temp_dir().join(format!("rs_smm_state_{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+
+        let mut market_maker = test_market_maker(Some(path.clone()));
+        let saved = SymbolState {
+            position: 250.0,
+            position_qty: 2.5,
+            avg_entry_price: 100.0,
+            realized_pnl: 12.5,
+            captured_bps: 30.0,
+            captured_qty: 1.5,
+        };
+        market_maker
+            .generators
+            .get_mut("BTCUSDT")
+            .unwrap()
+            .restore(&saved);
+        market_maker.save_state().unwrap();
+
+        let restarted = test_market_maker(Some(path.clone()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(restarted.generators["BTCUSDT"].state(), saved);
+        assert_eq!(restarted.generators["BTCUSDT"].position, 250.0);
+        assert_eq!(restarted.generators["BTCUSDT"].realized_pnl(), 12.5);
+        assert_eq!(
+            restarted.generators["ETHUSDT"].state(),
+            SymbolState::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mixed_case_symbols_share_keys() {
+        let path = std::env::temp_dir().join(format!("rs_smm_symbols_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            exchange = "bybit"
+            symbols = ["btcusdt", "Eth-Usdt"]
+            api_keys = [["key", "secret", "BtcUsdt"], ["key", "secret", "ETH/USDT"]]
+            balances = [["btcusdt", 1000.0], ["eth-usdt", 1000.0]]
+            leverage = { btcusdt = 2.0 }
+            orders_per_side = 3
+            final_order_distance = 5.0
+            depths = [5]
+            rate_limit = 10
+            bps = [25.0, 25.0]
+            use_wmid = false
+            "#,
+        )
+        .unwrap();
+        let config = try_read_toml(path.to_str().unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(config.symbols, vec!["BTCUSDT", "ETHUSDT"]);
+        assert_eq!(config.leverage.for_symbol("BTCUSDT"), 2.0);
+
+        let mut ss = SharedState::new(config.exchange.clone());
+        ss.add_symbols(config.symbols.clone());
+        for (key, secret, symbol) in config.api_keys.clone() {
+            ss.add_clients(key, secret, symbol, None);
+        }
+        let assets = config.balances.iter().cloned().collect();
+        let mut market_maker = MarketMaker::new(
+            ss,
+            assets,
+            config.leverage.clone(),
+            config.orders_per_side,
+            config.final_order_distance,
+            config.depths.clone(),
+            config.rate_limit,
+            None,
+        );
+        market_maker.configure(&config);
+
+        // Every symbol is looked up under the key the exchange reports it with.
+        for symbol in ["BTCUSDT", "ETHUSDT"] {
+            assert!(market_maker.generators.get_mut(symbol).is_some());
+            assert!(market_maker.features.contains_key(symbol));
+        }
+        // Reapplying the config keeps quoting the same symbols.
+        market_maker.apply_config(&config).await;
+        assert_eq!(market_maker.generators.len(), 2);
+        assert!(market_maker.disabled.is_empty());
+    }
+
+    #[test]
+    fn test_out_of_bounds_settings_rejected() {
+        let path = std::env::temp_dir().join(format!("rs_smm_bounds_{}.toml", std::process::id()));
+        let read = |setting: &str| {
+            let contents = format!(
+                r#"
+                exchange = "bybit"
+                symbols = ["BTCUSDT"]
+                api_keys = [["key", "secret", "BTCUSDT"]]
+                balances = [["BTCUSDT", 1000.0]]
+                leverage = 1.0
+                orders_per_side = 3
+                final_order_distance = 5.0
+                depths = [5]
+                rate_limit = 10
+                bps = [25.0]
+                use_wmid = false
+                {}
+                "#,
+                setting
+            );
+            std::fs::write(&path, contents).unwrap();
+            let config = try_read_toml(path.to_str().unwrap()).unwrap();
+            validate_config(&config)
+        };
+
+        assert_eq!(read(""), Ok(()));
+        assert_eq!(read("order_tag = \"bot1\""), Ok(()));
+        // Each of these would panic the setter it is applied with.
+        for setting in [
+            "requote_threshold_mult = 1.0",
+            "markout_horizon_ms = 0",
+            "max_inflight_requests = 0",
+            "order_tag = \"toolongtag\"",
+            "skew_deadband = -0.1",
+            "replace_delay_ms = 501",
+            "drawdown_curve = [[0.5, 2.0, 0.5], [0.4, 3.0, 0.2]]",
+            "quote_anchor = { kind = \"microprice\", depth = 0 }",
+        ] {
+            assert!(read(setting).is_err(), "{} was accepted", setting);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_shared_batches_need_one_account() {
+        let path = std::env::temp_dir().join(format!("rs_smm_shared_{}.toml", std::process::id()));
+        let read = |eth_key: &str, disabled: &str| {
+            let contents = format!(
+                r#"
+                exchange = "bybit"
+                symbols = ["BTCUSDT", "ETHUSDT"]
+                disabled_symbols = [{}]
+                api_keys = [["key", "secret", "BTCUSDT"], ["{}", "secret", "ETHUSDT"]]
+                balances = [["BTCUSDT", 1000.0], ["ETHUSDT", 1000.0]]
+                leverage = 1.0
+                orders_per_side = 3
+                final_order_distance = 5.0
+                depths = [5]
+                rate_limit = 10
+                bps = [25.0]
+                use_wmid = false
+                shared_batches = true
+                "#,
+                disabled, eth_key
+            );
+            std::fs::write(&path, contents).unwrap();
+            let config = try_read_toml(path.to_str().unwrap()).unwrap();
+            validate_config(&config)
+        };
+
+        assert_eq!(read("key", ""), Ok(()));
+        // The batches of one account would carry the orders of the other.
+        assert!(read("key2", "").is_err());
+        // Unless the symbol of the other account is not quoted.
+        assert_eq!(read("key2", "\"ETHUSDT\""), Ok(()));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_symbol_cancelled() {
+        let mut market_maker = test_market_maker(None);
+        let mock = MockBackend::new();
+        market_maker
+            .generators
+            .get_mut("ETHUSDT")
+            .unwrap()
+            .set_backend(Box::new(mock.clone()));
+
+        let path = std::env::temp_dir().join(format!("rs_smm_config_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            exchange = "bybit"
+            symbols = ["BTCUSDT", "ETHUSDT"]
+            disabled_symbols = ["ETHUSDT"]
+            api_keys = [["key", "secret", "BTCUSDT"], ["key", "secret", "ETHUSDT"]]
+            balances = [["BTCUSDT", 1000.0], ["ETHUSDT", 1000.0]]
+            leverage = 1.0
+            orders_per_side = 3
+            final_order_distance = 5.0
+            depths = [5]
+            rate_limit = 10
+            bps = [25.0, 25.0]
+            use_wmid = false
+            "#,
+        )
+        .unwrap();
+        let config = try_read_toml(path.to_str().unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // The market data feed closes once the config is applied, which ends the loop.
+        let (_, receiver) = mpsc::unbounded_channel();
+        let (config_sender, config_receiver) = mpsc::unbounded_channel();
+        config_sender.send(config).unwrap();
+        drop(config_sender);
+        market_maker
+            .start_loop(receiver, config_receiver, false, 10)
+            .await;
+
+        let cancel = MockRequest::CancelAll {
+            symbol: "ETHUSDT".to_string(),
+        };
+        assert_eq!(mock.requests(), vec![cancel]);
+        assert!(!market_maker.generators.contains_key("ETHUSDT"));
+        assert!(market_maker.generators.contains_key("BTCUSDT"));
+    }
+
+    #[tokio::test]
+    async fn test_shared_batch_combines_symbols() {
+        let mut market_maker = test_market_maker(None);
+        market_maker.set_shared_batches(true);
+        let mocks = [MockBackend::new(), MockBackend::new()];
+        let mut book = LocalBook::new();
+        book.tick_size = 0.01;
+        book.lot_size = 0.001;
+        book.mid_price = 100.0;
+        book.last_update = 1;
+        for (symbol, mock) in ["BTCUSDT", "ETHUSDT"].into_iter().zip(&mocks) {
+            let generator = market_maker.generators.get_mut(symbol).unwrap();
+            generator.set_backend(Box::new(mock.clone()));
+            generator.set_orders_per_side(1, 1);
+            generator
+                .update_grid(
+                    PrivateData::Bybit(Default::default()),
+                    0.0,
+                    0.0,
+                    0.0,
+                    book.clone(),
+                    symbol.to_string(),
+                    10,
+                )
+                .await;
+        }
+        // Nothing is placed until the orders of both symbols are collected.
+        assert!(mocks.iter().all(|m| m.requests().is_empty()));
+
+        // A single client places the orders of both symbols.
+        market_maker.place_shared_batches(1).await;
+        let (used, idle): (Vec<&MockBackend>, Vec<&MockBackend>) =
+            mocks.iter().partition(|m| !m.requests().is_empty());
+        assert_eq!((used.len(), idle.len()), (1, 1));
+        let mut symbols: Vec<String> = used[0]
+            .requests()
+            .into_iter()
+            .filter_map(|r| match r {
+                MockRequest::Place { symbol, .. } => Some(symbol),
+                _ => None,
+            })
+            .collect();
+        symbols.dedup();
+        assert_eq!(symbols.len(), 2);
+        for generator in market_maker.generators.values() {
+            assert_eq!(generator.live_buys_orders.len(), 1);
+            assert_eq!(generator.live_sells_orders.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_spreads_follow_configured_symbols() {
+        let mut market_maker = test_market_maker(None);
+        let spread =
+            |market_maker: &MarketMaker, symbol: &str| market_maker.generators[symbol].spread();
+
+        // The spreads follow the order of the symbols in the config, whatever the map order.
+        for symbols in [["BTCUSDT", "ETHUSDT"], ["ETHUSDT", "BTCUSDT"]] {
+            let symbols: Vec<String> = symbols.iter().map(|s| s.to_string()).collect();
+            market_maker.set_spread_toml(&symbols, &[5.0, 30.0], None);
+            assert_eq!(spread(&market_maker, &symbols[0]), 5.0);
+            assert_eq!(spread(&market_maker, &symbols[1]), 30.0);
+        }
+
+        // An override by symbol takes precedence and a short list repeats its last spread.
+        let symbols = vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()];
+        let overrides = [("ETHUSDT".to_string(), 12.0)];
+        market_maker.set_spread_toml(&symbols, &[8.0], Some(&overrides));
+        assert_eq!(spread(&market_maker, "BTCUSDT"), 8.0);
+        assert_eq!(spread(&market_maker, "ETHUSDT"), 12.0);
+    }
+
+    #[test]
+    fn test_stale_trades_dropped_before_features() {
+        let mut market_maker = test_market_maker(None);
+        market_maker.set_max_trade_age(Some(5_000));
+        let trade = |timestamp: u64| WsTrade {
+            timestamp,
+            symbol: "BTCUSDT".to_string(),
+            side: "Buy".to_string(),
+            volume: 1.0,
+            price: 100.0,
+            tick_direction: "PlusTick".to_string(),
+            id: timestamp.to_string(),
+            buyer_is_maker: false,
+        };
+        let mut book = LocalBook::new();
+        book.update(
+            vec![Bid {
+                price: 99.0,
+                qty: 1.0,
+            }],
+            vec![Ask {
+                price: 101.0,
+                qty: 1.0,
+            }],
+            60_000,
+        );
+        let market = BybitMarket {
+            books: vec![("BTCUSDT".to_string(), book)],
+            trades: vec![(
+                "BTCUSDT".to_string(),
+                VecDeque::from([trade(1_000), trade(54_000), trade(58_000)]),
+            )],
+            ..Default::default()
+        };
+        market_maker.update_features(MarketMessage::Bybit(market), vec![5, 50], false, 10);
+
+        // Only the trade within 5 seconds of the book is kept for the features.
+        let kept: Vec<u64> = market_maker.curr_trades["BTCUSDT"]
+            .iter()
+            .map(|t| t.timestamp)
+            .collect();
+        assert_eq!(kept, vec![58_000]);
+        assert_eq!(market_maker.old_trades["BTCUSDT"].len(), 1);
+        // The tape keeps the trades dropped from the features.
+        assert_eq!(market_maker.tape.trades("BTCUSDT").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_symbol_quoted_after_own_warmup() {
+        let mut market_maker = test_market_maker(None);
+        market_maker.set_warmup_updates(3);
+        let mut book = LocalBook::new();
+        book.update(
+            vec![Bid {
+                price: 99.0,
+                qty: 1.0,
+            }],
+            vec![Ask {
+                price: 101.0,
+                qty: 1.0,
+            }],
+            1,
+        );
+        let feed = |market_maker: &mut MarketMaker, symbol: &str| {
+            let market = BybitMarket {
+                books: vec![(symbol.to_string(), book.clone())],
+                trades: vec![(symbol.to_string(), VecDeque::new())],
+                ..Default::default()
+            };
+            market_maker.update_features(MarketMessage::Bybit(market), vec![5, 50], false, 10);
+        };
+
+        // The first book only seeds the previous data of the symbol.
+        for _ in 0..3 {
+            feed(&mut market_maker, "BTCUSDT");
+        }
+        feed(&mut market_maker, "ETHUSDT");
+        assert!(!market_maker.is_warmed_up("BTCUSDT"));
+
+        feed(&mut market_maker, "BTCUSDT");
+        assert!(market_maker.is_warmed_up("BTCUSDT"));
+        // The updates of another symbol do not warm this one up.
+        assert!(!market_maker.is_warmed_up("ETHUSDT"));
+        for _ in 0..3 {
+            feed(&mut market_maker, "ETHUSDT");
+        }
+        assert!(market_maker.is_warmed_up("ETHUSDT"));
+    }
+
+    #[test]
+    fn test_skew_neutral_until_features_ready() {
+        let mut market_maker = test_market_maker(None);
+        let mut feature = Engine::new();
+        feature.skew = 0.3;
+        assert!(!feature.is_ready());
+        assert_eq!(market_maker.quoted_skew(&feature), 0.0);
+
+        market_maker.set_neutral_warmup(false);
+        assert_eq!(market_maker.quoted_skew(&feature), 0.3);
+    }
+
+    #[test]
+    fn test_short_warmup_quotes_neutral_until_ready() {
+        let mut market_maker = test_market_maker(None);
+        market_maker.set_warmup_updates(3);
+        let mut book = LocalBook::new();
+        book.update(
+            vec![Bid {
+                price: 99.0,
+                qty: 1.0,
+            }],
+            vec![Ask {
+                price: 101.0,
+                qty: 1.0,
+            }],
+            1,
+        );
+        let feed = |market_maker: &mut MarketMaker| {
+            let market = BybitMarket {
+                books: vec![("BTCUSDT".to_string(), book.clone())],
+                trades: vec![("BTCUSDT".to_string(), VecDeque::new())],
+                ..Default::default()
+            };
+            market_maker.update_features(MarketMessage::Bybit(market), vec![5, 50], false, 10);
+        };
+
+        // Quoted after the short warm-up, but with a neutral skew until the tick window fills.
+        for _ in 0..4 {
+            feed(&mut market_maker);
+        }
+        assert!(market_maker.is_warmed_up("BTCUSDT"));
+        let feature = &market_maker.features["BTCUSDT"];
+        assert!(!feature.is_ready());
+        assert_eq!(market_maker.quoted_skew(feature), 0.0);
+
+        while !market_maker.features["BTCUSDT"].is_ready() {
+            assert!(market_maker.features["BTCUSDT"].update_count() < 20);
+            feed(&mut market_maker);
+        }
+        let feature = &market_maker.features["BTCUSDT"];
+        assert_eq!(market_maker.quoted_skew(feature), feature.skew);
+    }
+
+    #[test]
+    fn test_quotes_throttled_to_min_interval() {
+        let mut market_maker = test_market_maker(None);
+        market_maker.set_min_quote_interval(500);
+
+        // Books arrive every 100ms but each symbol is requoted at most every 500ms.
+        let updates: Vec<u64> = (0..20)
+            .map(|i| 1_000 + i * 100)
+            .filter(|&now| market_maker.quote_due("BTCUSDT", now))
+            .collect();
+        assert_eq!(updates, vec![1_000, 1_500, 2_000, 2_500]);
+        assert!(updates.windows(2).all(|w| w[1] - w[0] >= 500));
+        // The interval is tracked per symbol.
+        assert!(market_maker.quote_due("ETHUSDT", 2_950));
+
+        // Without an interval every book is quoted.
+        market_maker.set_min_quote_interval(0);
+        assert!(market_maker.quote_due("BTCUSDT", 2_950));
+        assert!(market_maker.quote_due("BTCUSDT", 2_950));
+    }
+
+    #[test]
+    fn test_stale_private_data_defers_quotes() {
+        let mut market_maker = test_market_maker(None);
+        let private = |time: u64| {
+            PrivateData::Bybit(BybitPrivate {
+                time,
+                ..Default::default()
+            })
+        };
+        // Any private data is quoted on without an age limit.
+        assert!(market_maker.private_fresh("BTCUSDT", Some(&private(0)), 10_000));
+
+        market_maker.set_max_private_age(Some(5_000));
+        // Nothing was received on the private stream yet.
+        assert!(!market_maker.private_fresh("BTCUSDT", Some(&private(0)), 10_000));
+        assert!(!market_maker.private_fresh("BTCUSDT", None, 10_000));
+        // The private data is 6 seconds old.
+        assert!(!market_maker.private_fresh("BTCUSDT", Some(&private(4_000)), 10_000));
+        assert!(market_maker.stale_private.contains("BTCUSDT"));
+
+        assert!(market_maker.private_fresh("BTCUSDT", Some(&private(5_000)), 10_000));
+        assert!(market_maker.stale_private.is_empty());
+    }
+
     #[tokio::test]
     async fn test_tick() {
         let mut interval = time::interval(Duration::from_millis(500));
-        let log = Logger;
+        let log = Logger::default();
         let arr: VecDeque<f64> = VecDeque::with_capacity(100);
         loop {
             interval.tick().await;
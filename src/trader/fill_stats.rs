@@ -0,0 +1,83 @@
+/// Counts of placed and filled orders bucketed by their distance from the mid price.
+///
+/// The fill probability of a bucket is estimated with a uniform Beta(1, 1) prior, so a bucket
+/// without any history starts at 0.5 and converges to the observed fill rate as orders are
+/// placed.
+#[derive(Debug, Clone)]
+pub struct FillStats {
+    bucket_bps: f64,
+    // (placed, filled) per bucket, indexed by distance / bucket_bps.
+    buckets: Vec<(u64, u64)>,
+}
+
+impl FillStats {
+    /// Creates an empty accumulator.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_bps` - The width of each distance bucket in bps.
+    pub fn new(bucket_bps: f64) -> Self {
+        assert!(bucket_bps > 0.0, "Bucket width must be positive");
+        FillStats {
+            bucket_bps,
+            buckets: Vec::new(),
+        }
+    }
+
+    fn bucket_mut(&mut self, distance_bps: f64) -> &mut (u64, u64) {
+        let index = (distance_bps.abs() / self.bucket_bps) as usize;
+        if index >= self.buckets.len() {
+            self.buckets.resize(index + 1, (0, 0));
+        }
+        &mut self.buckets[index]
+    }
+
+    /// Records an order placed `distance_bps` away from the mid price.
+    pub fn record_placement(&mut self, distance_bps: f64) {
+        self.bucket_mut(distance_bps).0 += 1;
+    }
+
+    /// Records a fill of an order placed `distance_bps` away from the mid price.
+    pub fn record_fill(&mut self, distance_bps: f64) {
+        self.bucket_mut(distance_bps).1 += 1;
+    }
+
+    /// Returns the estimated probability that an order placed `distance_bps` away from the mid
+    /// price gets filled.
+    pub fn fill_probability(&self, distance_bps: f64) -> f64 {
+        let index = (distance_bps.abs() / self.bucket_bps) as usize;
+        let (placed, filled) = self.buckets.get(index).copied().unwrap_or((0, 0));
+        // Fills can outnumber placements when orders are partially filled several times.
+        let filled = filled.min(placed);
+        (filled as f64 + 1.0) / (placed as f64 + 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_probability_converges() {
+        let mut stats = FillStats::new(5.0);
+        // Nothing has been observed yet, so every distance uses the prior.
+        assert_eq!(stats.fill_probability(3.0), 0.5);
+
+        // Orders close to the mid fill 80% of the time, deep orders 10% of the time.
+        for i in 0..1000 {
+            stats.record_placement(2.0);
+            stats.record_placement(27.0);
+            if i % 10 < 8 {
+                stats.record_fill(2.0);
+            }
+            if i % 10 == 0 {
+                stats.record_fill(27.0);
+            }
+        }
+
+        assert!((stats.fill_probability(2.0) - 0.8).abs() < 0.01);
+        assert!((stats.fill_probability(-4.9) - 0.8).abs() < 0.01);
+        assert!((stats.fill_probability(27.0) - 0.1).abs() < 0.01);
+        assert_eq!(stats.fill_probability(12.0), 0.5);
+    }
+}
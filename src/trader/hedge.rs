@@ -0,0 +1,99 @@
+use skeleton::exchanges::{
+    ex_binance::BinanceClient, ex_bybit::BybitClient, exchange::ExchangeClient,
+};
+
+use super::order_backend::{self, OrderBackend};
+
+/// Offsets the fills of the quoting exchange with market orders on a second exchange.
+///
+/// Fills are netted until the next flush, so a buy and a sell filled between two updates only
+/// hedge their difference. A failed hedge stays pending and is retried on the next flush.
+pub struct HedgeExecutor {
+    client: Box<dyn OrderBackend>,
+    symbol: String,
+    // Signed quantity still to hedge, positive to buy on the hedge exchange.
+    pending: f64,
+}
+
+impl HedgeExecutor {
+    /// Creates an executor hedging on the exchange of `client`.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client of the hedge exchange.
+    /// * `symbol` - The symbol traded on the hedge exchange.
+    pub fn new(client: ExchangeClient, symbol: String) -> Self {
+        Self::with_backend(order_backend::from_client(client), symbol)
+    }
+
+    /// Creates an executor hedging on "bybit" or "binance" with the given API key, on the testnet
+    /// of the exchange if `testnet` is set.
+    ///
+    /// # Panics
+    ///
+    /// If the exchange is neither "bybit" nor "binance".
+    pub fn init(
+        exchange: &str,
+        key: String,
+        secret: String,
+        symbol: String,
+        testnet: bool,
+    ) -> Self {
+        let client = match exchange {
+            "bybit" => ExchangeClient::Bybit(BybitClient::init(key, secret, testnet)),
+            "binance" => ExchangeClient::Binance(BinanceClient::init(key, secret, testnet)),
+            _ => panic!("Invalid hedge exchange"),
+        };
+        Self::new(client, symbol)
+    }
+
+    pub(crate) fn with_backend(client: Box<dyn OrderBackend>, symbol: String) -> Self {
+        HedgeExecutor {
+            client,
+            symbol,
+            pending: 0.0,
+        }
+    }
+
+    /// Queues the offsetting order of a fill on the quoting exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - 1 for a buy fill and -1 for a sell fill.
+    /// * `qty` - The filled quantity.
+    pub fn record_fill(&mut self, side: i32, qty: f64) {
+        self.pending -= side as f64 * qty;
+    }
+
+    /// Returns the signed quantity waiting to be hedged, positive to buy.
+    pub fn pending(&self) -> f64 {
+        self.pending
+    }
+
+    /// Sends the pending hedge as a single market order.
+    ///
+    /// # Returns
+    ///
+    /// `true` if nothing was pending or the order was placed.
+    pub async fn flush(&mut self) -> bool {
+        let qty = self.pending.abs();
+        if qty <= f64::EPSILON {
+            return true;
+        }
+        let result = if self.pending > 0.0 {
+            self.client.market_buy(qty, &self.symbol).await
+        } else {
+            self.client.market_sell(qty, &self.symbol).await
+        };
+        match result {
+            Ok(_) => {
+                self.pending = 0.0;
+                true
+            }
+            Err(e) => {
+                eprintln!("Hedge order error on {}: {}", self.symbol, e);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bybit::model::FastExecData;
+use skeleton::{
+    exchanges::{ex_bybit::BybitPrivate, exchange::PrivateData},
+    util::helpers::TimeInForce,
+};
+
+use super::{
+    order_backend::{BackendFuture, OrderBackend, OrderError},
+    quote_gen::{BatchOrder, LiveOrder},
+};
+
+/// A request received by the mock exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockRequest {
+    /// A limit order resting on the book, `side` is 1 for a buy and -1 for a sell.
+    Place {
+        side: i32,
+        price: f64,
+        qty: f64,
+        symbol: String,
+    },
+    /// A limit order filled at once, `side` is 1 for a buy and -1 for a sell.
+    Immediate {
+        side: i32,
+        price: f64,
+        qty: f64,
+        symbol: String,
+    },
+    /// A market order, `side` is 1 for a buy and -1 for a sell.
+    Market {
+        side: i32,
+        qty: f64,
+        symbol: String,
+    },
+    Amend {
+        order_id: String,
+    },
+    Cancel {
+        order_id: String,
+    },
+    CancelAll {
+        symbol: String,
+    },
+}
+
+#[derive(Debug, Default)]
+struct MockExchange {
+    // Resting orders as (symbol, side, order), in the order they were placed.
+    resting: Vec<(String, i32, LiveOrder)>,
+    requests: Vec<MockRequest>,
+    // Market and immediate orders awaiting their executions as (symbol, side, order).
+    taking: Vec<(String, i32, LiveOrder)>,
+    // Quantity executed so far for each order id.
+    executed: HashMap<String, f64>,
+    next_id: u64,
+    next_exec_id: u64,
+    // Delay in milliseconds before each response, and the requests awaiting one now and at most.
+    latency_ms: u64,
+    in_flight: usize,
+    max_in_flight: usize,
+}
+
+impl MockExchange {
+    fn next_order_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("mock-{}", self.next_id)
+    }
+
+    fn place(&mut self, symbol: &str, side: i32, price: f64, qty: f64, link_id: &str) -> LiveOrder {
+        let order =
+            LiveOrder::new(price, qty, self.next_order_id()).with_link_id(link_id.to_string());
+        self.requests.push(MockRequest::Place {
+            side,
+            price,
+            qty,
+            symbol: symbol.to_string(),
+        });
+        self.resting.push((symbol.to_string(), side, order.clone()));
+        order
+    }
+
+    fn cancel(&mut self, order_id: &str) -> Option<LiveOrder> {
+        self.requests.push(MockRequest::Cancel {
+            order_id: order_id.to_string(),
+        });
+        let index = self
+            .resting
+            .iter()
+            .position(|(_, _, o)| o.order_id == order_id)?;
+        Some(self.resting.remove(index).2)
+    }
+
+    /// Executes up to `qty` of `order` at `price`, returning the execution and the quantity left
+    /// on the order.
+    fn execute(
+        &mut self,
+        symbol: &str,
+        side: i32,
+        order: &mut LiveOrder,
+        price: f64,
+        qty: f64,
+        time: u64,
+    ) -> FastExecData {
+        self.next_exec_id += 1;
+        let qty = qty.min(order.remaining);
+        order.remaining -= qty;
+        *self.executed.entry(order.order_id.clone()).or_default() += qty;
+        FastExecData {
+            category: "linear".to_string(),
+            symbol: symbol.to_string(),
+            exec_id: format!("exec-{}", self.next_exec_id),
+            exec_price: price.to_string(),
+            exec_qty: qty.to_string(),
+            order_id: order.order_id.clone(),
+            order_link_id: order.link_id.clone(),
+            side: if side > 0 { "Buy" } else { "Sell" }.to_string(),
+            exec_time: time.to_string(),
+            seq: 0,
+        }
+    }
+
+    /// Returns the resting orders of `symbol` as the buy queue followed by the sell queue.
+    fn queues(&self, symbol: &str) -> Vec<VecDeque<LiveOrder>> {
+        let side = |side: i32| {
+            self.resting
+                .iter()
+                .filter(|(s, order_side, _)| s == symbol && *order_side == side)
+                .map(|(_, _, o)| o.clone())
+                .collect()
+        };
+        vec![side(1), side(-1)]
+    }
+}
+
+/// An order backend keeping the orders of an exchange in memory, so the quoting logic can be
+/// tested offline.
+///
+/// The clones share the same exchange, so a test can keep one to inspect the requests and inject
+/// fills while the quote generator owns another.
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend(Arc<Mutex<MockExchange>>);
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays every response by `latency_ms`, so overlapping requests can be observed.
+    pub fn set_latency(&self, latency_ms: u64) {
+        self.0.lock().unwrap().latency_ms = latency_ms;
+    }
+
+    /// Returns the most requests awaiting a response at the same time so far.
+    pub fn max_in_flight(&self) -> usize {
+        self.0.lock().unwrap().max_in_flight
+    }
+
+    /// Rests `order` on a side of `symbol` without a request, as an order placed before the test
+    /// started. `side` is 1 for a bid and -1 for an ask.
+    pub fn rest(&self, symbol: &str, side: i32, order: LiveOrder) {
+        self.0
+            .lock()
+            .unwrap()
+            .resting
+            .push((symbol.to_string(), side, order));
+    }
+
+    /// Returns the requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.0.lock().unwrap().requests.clone()
+    }
+
+    /// Returns the resting orders of a side, 1 for the bids and -1 for the asks.
+    pub fn resting(&self, side: i32) -> Vec<LiveOrder> {
+        let exchange = self.0.lock().unwrap();
+        exchange
+            .resting
+            .iter()
+            .filter(|(_, order_side, _)| *order_side == side)
+            .map(|(_, _, o)| o.clone())
+            .collect()
+    }
+
+    /// Fills `qty` of the resting order `order_id` at its price and returns the private data
+    /// carrying the execution, as the private stream would.
+    ///
+    /// The order stops resting once it is completely filled.
+    ///
+    /// # Panics
+    ///
+    /// If no order `order_id` is resting.
+    pub fn fill(&self, order_id: &str, qty: f64, time: u64) -> PrivateData {
+        let mut exchange = self.0.lock().unwrap();
+        let index = exchange
+            .resting
+            .iter()
+            .position(|(_, _, o)| o.order_id == order_id)
+            .expect("Filled order is not resting");
+        let (symbol, side, mut order) = exchange.resting.remove(index);
+        let price = order.price;
+        let execution = exchange.execute(&symbol, side, &mut order, price, qty, time);
+        if order.remaining > f64::EPSILON {
+            exchange.resting.insert(index, (symbol, side, order));
+        }
+        private_data(execution, time)
+    }
+
+    /// Executes `qty` of the market or immediate order `order_id` at `price` and returns the
+    /// private data carrying the execution, as the private stream would.
+    ///
+    /// # Panics
+    ///
+    /// If no market or immediate order `order_id` is awaiting its executions.
+    pub fn execute(&self, order_id: &str, price: f64, qty: f64, time: u64) -> PrivateData {
+        let mut exchange = self.0.lock().unwrap();
+        let index = exchange
+            .taking
+            .iter()
+            .position(|(_, _, o)| o.order_id == order_id)
+            .expect("Executed order is not awaiting executions");
+        let (symbol, side, mut order) = exchange.taking.remove(index);
+        let execution = exchange.execute(&symbol, side, &mut order, price, qty, time);
+        if order.remaining > f64::EPSILON {
+            exchange.taking.insert(index, (symbol, side, order));
+        }
+        private_data(execution, time)
+    }
+
+    /// Removes the resting order `order_id` without an execution, as a post-only reject or a
+    /// cancel on the exchange side would.
+    pub fn drop_order(&self, order_id: &str) {
+        self.0
+            .lock()
+            .unwrap()
+            .resting
+            .retain(|(_, _, o)| o.order_id != order_id);
+    }
+}
+
+/// Wraps an execution in the private data of the stream.
+fn private_data(execution: FastExecData, time: u64) -> PrivateData {
+    PrivateData::Bybit(BybitPrivate {
+        time,
+        executions: VecDeque::from([execution]),
+        ..Default::default()
+    })
+}
+
+impl MockBackend {
+    /// Resolves to `result` once the configured latency has passed.
+    fn respond<'a, T: Send + 'a>(&self, result: Result<T, OrderError>) -> BackendFuture<'a, T> {
+        let exchange = self.0.clone();
+        Box::pin(async move {
+            let latency_ms = {
+                let mut exchange = exchange.lock().unwrap();
+                exchange.in_flight += 1;
+                exchange.max_in_flight = exchange.max_in_flight.max(exchange.in_flight);
+                exchange.latency_ms
+            };
+            if latency_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+            }
+            exchange.lock().unwrap().in_flight -= 1;
+            result
+        })
+    }
+}
+
+impl OrderBackend for MockBackend {
+    fn maker_fee<'a>(&'a self, _symbol: &'a str) -> BackendFuture<'a, f64> {
+        self.respond(Ok(0.0))
+    }
+
+    fn place_buy_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        let order = self.0.lock().unwrap().place(symbol, 1, price, qty, "");
+        self.respond(Ok(order))
+    }
+
+    fn place_sell_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        let order = self.0.lock().unwrap().place(symbol, -1, price, qty, "");
+        self.respond(Ok(order))
+    }
+
+    fn market_buy<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder> {
+        let mut exchange = self.0.lock().unwrap();
+        exchange.requests.push(MockRequest::Market {
+            side: 1,
+            qty,
+            symbol: symbol.to_string(),
+        });
+        let order = LiveOrder::new(0.0, qty, exchange.next_order_id());
+        exchange.taking.push((symbol.to_string(), 1, order.clone()));
+        self.respond(Ok(order))
+    }
+
+    fn market_sell<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder> {
+        let mut exchange = self.0.lock().unwrap();
+        exchange.requests.push(MockRequest::Market {
+            side: -1,
+            qty,
+            symbol: symbol.to_string(),
+        });
+        let order = LiveOrder::new(0.0, qty, exchange.next_order_id());
+        exchange
+            .taking
+            .push((symbol.to_string(), -1, order.clone()));
+        self.respond(Ok(order))
+    }
+
+    fn place_immediate_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        is_buy: bool,
+        _time_in_force: TimeInForce,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        let side = if is_buy { 1 } else { -1 };
+        let mut exchange = self.0.lock().unwrap();
+        exchange.requests.push(MockRequest::Immediate {
+            side,
+            price,
+            qty,
+            symbol: symbol.to_string(),
+        });
+        let order = LiveOrder::new(price, qty, exchange.next_order_id());
+        exchange
+            .taking
+            .push((symbol.to_string(), side, order.clone()));
+        self.respond(Ok(order))
+    }
+
+    fn amend_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        qty: f64,
+        price: Option<f64>,
+        _symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        let mut exchange = self.0.lock().unwrap();
+        exchange.requests.push(MockRequest::Amend {
+            order_id: order.order_id.clone(),
+        });
+        let amended = exchange
+            .resting
+            .iter_mut()
+            .find(|(_, _, o)| o.order_id == order.order_id)
+            .map(|(_, _, o)| {
+                o.qty = qty;
+                o.remaining = qty;
+                o.price = price.unwrap_or(o.price);
+                o.clone()
+            });
+        self.respond(amended.ok_or_else(|| OrderError::Other("Order is not resting".to_string())))
+    }
+
+    fn cancel_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        _symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        let cancelled = self.0.lock().unwrap().cancel(&order.order_id);
+        self.respond(cancelled.ok_or_else(|| OrderError::Other("Order is not resting".to_string())))
+    }
+
+    fn cancel_all<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<LiveOrder>> {
+        let mut exchange = self.0.lock().unwrap();
+        exchange.requests.push(MockRequest::CancelAll {
+            symbol: symbol.to_string(),
+        });
+        let (cancelled, resting) = std::mem::take(&mut exchange.resting)
+            .into_iter()
+            .partition(|(s, _, _)| s == symbol);
+        exchange.resting = resting;
+        let cancelled: Vec<LiveOrder> = cancelled.into_iter().map(|(_, _, o)| o).collect();
+        self.respond(Ok(cancelled))
+    }
+
+    fn batch_cancel<'a>(
+        &'a self,
+        orders: Vec<LiveOrder>,
+        _symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>> {
+        let mut exchange = self.0.lock().unwrap();
+        let cancelled: Vec<LiveOrder> = orders
+            .iter()
+            .filter_map(|o| exchange.cancel(&o.order_id))
+            .collect();
+        self.respond(Ok(cancelled))
+    }
+
+    fn batch_place_order(
+        &self,
+        order_array: Vec<BatchOrder>,
+    ) -> BackendFuture<'_, Vec<VecDeque<LiveOrder>>> {
+        let mut exchange = self.0.lock().unwrap();
+        let mut placed = vec![VecDeque::new(), VecDeque::new()];
+        for o in &order_array {
+            let order = exchange.place(&o.2, o.3, o.1, o.0, &o.4);
+            placed[if o.3 > 0 { 0 } else { 1 }].push_back(order);
+        }
+        self.respond(Ok(placed))
+    }
+
+    fn batch_amend<'a>(
+        &'a self,
+        orders: Vec<LiveOrder>,
+        _symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>> {
+        let mut exchange = self.0.lock().unwrap();
+        let mut amended = Vec::new();
+        for order in orders {
+            exchange.requests.push(MockRequest::Amend {
+                order_id: order.order_id.clone(),
+            });
+            if let Some((_, _, resting)) = exchange
+                .resting
+                .iter_mut()
+                .find(|(_, _, o)| o.order_id == order.order_id)
+            {
+                resting.price = order.price;
+                resting.qty = order.qty;
+                resting.remaining = order.qty;
+                amended.push(resting.clone());
+            }
+        }
+        self.respond(Ok(amended))
+    }
+
+    fn open_orders<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<VecDeque<LiveOrder>>> {
+        let queues = self.0.lock().unwrap().queues(symbol);
+        self.respond(Ok(queues))
+    }
+
+    fn executed_qty<'a>(&'a self, order_id: &'a str, _symbol: &'a str) -> BackendFuture<'a, f64> {
+        let executed = self
+            .0
+            .lock()
+            .unwrap()
+            .executed
+            .get(order_id)
+            .copied()
+            .unwrap_or(0.0);
+        self.respond(Ok(executed))
+    }
+}
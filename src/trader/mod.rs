@@ -1 +1,7 @@
-pub mod quote_gen;
\ No newline at end of file
+pub mod fill_stats;
+pub mod hedge;
+#[cfg(test)]
+pub(crate) mod mock_backend;
+pub mod order_backend;
+pub mod quote_gen;
+pub mod state;
\ No newline at end of file
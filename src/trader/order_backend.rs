@@ -0,0 +1,1067 @@
+use std::{borrow::Cow, collections::VecDeque, fmt, future::Future, pin::Pin, sync::Once};
+
+use binance::{
+    account::OrderSide,
+    errors::{Error as BinanceError, ErrorKind as BinanceErrorKind},
+    futures::account::CustomOrderRequest,
+};
+use bybit::{
+    errors::BybitError,
+    model::{
+        AmendOrderRequest, BatchAmendRequest, BatchCancelRequest, BatchPlaceRequest,
+        CancelOrderRequest, CancelallRequest, OpenOrdersRequest, OrderHistoryRequest, OrderRequest,
+        Side,
+    },
+};
+use skeleton::{
+    exchanges::{ex_binance::BinanceClient, ex_bybit::BybitClient, exchange::ExchangeClient},
+    util::helpers::TimeInForce,
+};
+use tokio::task;
+
+use super::quote_gen::{BatchOrder, LiveOrder};
+
+// Warnings that iceberg orders are unsupported, so they are only printed once per exchange.
+static BYBIT_ICEBERG_WARNING: Once = Once::new();
+static BINANCE_ICEBERG_WARNING: Once = Once::new();
+
+/// Warns once that `exchange` cannot hide part of the size of post-only limit orders when some
+/// of `orders` have a display size below their full size.
+fn warn_iceberg_unsupported(warning: &Once, exchange: &str, orders: &[BatchOrder]) {
+    if orders.iter().any(|o| o.5 < o.0) {
+        warning.call_once(|| {
+            println!(
+                "{} does not support a display size on post-only limit orders, placing the full size",
+                exchange
+            );
+        });
+    }
+}
+
+/// The reason an order request failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    /// The exchange rejected the request for exceeding its rate limits.
+    RateLimited,
+    /// The API key was rejected or lacks the permission for the request.
+    AuthFailed,
+    /// A post-only order would have taken liquidity.
+    PostOnlyReject,
+    /// The account lacks the margin for the order.
+    InsufficientMargin,
+    /// The request did not reach the exchange or got no usable response.
+    Network(String),
+    /// Any other failure, with the exchange message.
+    Other(String),
+}
+
+impl OrderError {
+    /// Classifies a request rejected by the exchange from its error code and message.
+    fn from_rejection(code: i64, msg: &str) -> Self {
+        let msg_lower = msg.to_lowercase();
+        match code {
+            // Bybit: too many visits, IP or UID rate limited.
+            10006 | 10018 => OrderError::RateLimited,
+            // Binance: too many requests or orders.
+            -1003 | -1015 => OrderError::RateLimited,
+            // Bybit: invalid key, signature, permission or key type.
+            10003 | 10004 | 10005 | 10007 | 33004 => OrderError::AuthFailed,
+            // Binance: invalid signature, key format or key permissions.
+            -1022 | -2014 | -2015 => OrderError::AuthFailed,
+            // Binance: GTX order would execute immediately as a taker.
+            -5022 => OrderError::PostOnlyReject,
+            // Binance: margin is insufficient.
+            -2019 => OrderError::InsufficientMargin,
+            _ if msg_lower.contains("post only") || msg_lower.contains("postonly") => {
+                OrderError::PostOnlyReject
+            }
+            _ if msg_lower.contains("insufficient") => OrderError::InsufficientMargin,
+            _ if msg_lower.contains("too many") || msg_lower.contains("rate limit") => {
+                OrderError::RateLimited
+            }
+            _ => OrderError::Other(format!("{}: {}", code, msg)),
+        }
+    }
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderError::RateLimited => write!(f, "rate limited"),
+            OrderError::AuthFailed => write!(f, "authentication failed"),
+            OrderError::PostOnlyReject => write!(f, "post-only order rejected"),
+            OrderError::InsufficientMargin => write!(f, "insufficient margin"),
+            OrderError::Network(msg) => write!(f, "network error: {}", msg),
+            OrderError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<BybitError> for OrderError {
+    fn from(e: BybitError) -> Self {
+        match e {
+            BybitError::BybitError(content) => {
+                OrderError::from_rejection(content.code.into(), &content.msg)
+            }
+            BybitError::Unauthorized | BybitError::StatusCode(401) => OrderError::AuthFailed,
+            BybitError::StatusCode(403) | BybitError::StatusCode(429) => OrderError::RateLimited,
+            BybitError::ReqError(_)
+            | BybitError::IoError(_)
+            | BybitError::Tungstenite(_)
+            | BybitError::InternalServerError
+            | BybitError::ServiceUnavailable
+            | BybitError::StatusCode(500..=599) => OrderError::Network(e.to_string()),
+            _ => OrderError::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<BinanceError> for OrderError {
+    fn from(e: BinanceError) -> Self {
+        match e.kind() {
+            BinanceErrorKind::BinanceError(content) => {
+                OrderError::from_rejection(content.code.into(), &content.msg)
+            }
+            BinanceErrorKind::ReqError(_)
+            | BinanceErrorKind::IoError(_)
+            | BinanceErrorKind::Tungstenite(_) => OrderError::Network(e.to_string()),
+            BinanceErrorKind::Msg(msg) => match msg.as_str() {
+                "Unauthorized" => OrderError::AuthFailed,
+                "Internal Server Error" | "Service Unavailable" => OrderError::Network(msg.clone()),
+                // Binance answers 429 when rate limited and 418 once the IP is banned.
+                _ if msg.contains("429") || msg.contains("418") => OrderError::RateLimited,
+                _ => OrderError::Other(msg.clone()),
+            },
+            _ => OrderError::Other(e.to_string()),
+        }
+    }
+}
+
+/// The future returned by every `OrderBackend` request.
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, OrderError>> + Send + 'a>>;
+
+/// The order operations an exchange has to provide to be quoted on.
+///
+/// Each exchange client implements this trait once, so the quote generator can hold any of them
+/// as a `Box<dyn OrderBackend>` without matching on the exchange for every request.
+pub trait OrderBackend: Send + Sync {
+    /// Fetches the maker fee rate for the symbol as a decimal.
+    fn maker_fee<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, f64>;
+
+    fn place_buy_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder>;
+
+    fn place_sell_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder>;
+
+    fn market_buy<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder>;
+
+    fn market_sell<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder>;
+
+    /// Places a limit order that is cancelled unless it fills at once, entirely with
+    /// `TimeInForce::Fok`.
+    fn place_immediate_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        is_buy: bool,
+        time_in_force: TimeInForce,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder>;
+
+    fn amend_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        qty: f64,
+        price: Option<f64>,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder>;
+
+    fn cancel_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder>;
+
+    fn cancel_all<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<LiveOrder>>;
+
+    fn batch_cancel<'a>(
+        &'a self,
+        orders: Vec<LiveOrder>,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>>;
+
+    /// Places a batch of orders and returns the buy queue followed by the sell queue of the
+    /// placed live orders.
+    fn batch_place_order(
+        &self,
+        order_array: Vec<BatchOrder>,
+    ) -> BackendFuture<'_, Vec<VecDeque<LiveOrder>>>;
+
+    fn batch_amend<'a>(
+        &'a self,
+        orders: Vec<LiveOrder>,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>>;
+
+    /// Fetches the resting orders of the symbol as the buy queue followed by the sell queue.
+    fn open_orders<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<VecDeque<LiveOrder>>>;
+
+    /// Fetches the quantity executed so far of an order, whether it is still open or not.
+    fn executed_qty<'a>(&'a self, order_id: &'a str, symbol: &'a str) -> BackendFuture<'a, f64>;
+}
+
+/// Returns the order backend of the exchange client.
+pub fn from_client(client: ExchangeClient) -> Box<dyn OrderBackend> {
+    match client {
+        ExchangeClient::Bybit(cl) => Box::new(cl),
+        ExchangeClient::Binance(cl) => Box::new(cl),
+    }
+}
+
+/// Builds the Bybit limit order that is cancelled unless it fills at once.
+pub fn bybit_immediate_request(
+    symbol: &str,
+    qty: f64,
+    price: f64,
+    is_buy: bool,
+    time_in_force: TimeInForce,
+) -> OrderRequest<'static> {
+    OrderRequest {
+        category: bybit::model::Category::Linear,
+        symbol: Cow::Owned(symbol.to_string()),
+        side: if is_buy { Side::Buy } else { Side::Sell },
+        order_type: bybit::model::OrderType::Limit,
+        qty,
+        price: Some(price),
+        time_in_force: Some(Cow::Borrowed(time_in_force.as_str())),
+        ..Default::default()
+    }
+}
+
+impl OrderBackend for BybitClient {
+    fn maker_fee<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, f64> {
+        Box::pin(async move { Ok(self.fee_rate(symbol).await) })
+    }
+
+    fn place_buy_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            match client
+                .place_futures_limit_order(
+                    bybit::model::Category::Linear,
+                    symbol,
+                    Side::Buy,
+                    qty,
+                    price,
+                    0,
+                )
+                .await
+            {
+                Ok(v) => Ok(LiveOrder::new(price, qty, v.result.order_id)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn place_sell_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            match client
+                .place_futures_limit_order(
+                    bybit::model::Category::Linear,
+                    symbol,
+                    Side::Sell,
+                    qty,
+                    price,
+                    2,
+                )
+                .await
+            {
+                Ok(v) => Ok(LiveOrder::new(price, qty, v.result.order_id)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn market_buy<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let req = OrderRequest {
+                category: bybit::model::Category::Linear,
+                symbol: Cow::Owned(symbol.to_string()),
+                side: Side::Buy,
+                order_type: bybit::model::OrderType::Market,
+                qty,
+                ..Default::default()
+            };
+            match client.place_custom_order(req).await {
+                Ok(v) => Ok(LiveOrder::new(0.0, qty, v.result.order_id)),
+                Err(e) => {
+                    println!("Could not place market order for {} qty: {}", qty, e);
+                    Err(e.into())
+                }
+            }
+        })
+    }
+
+    fn market_sell<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let req = OrderRequest {
+                category: bybit::model::Category::Linear,
+                symbol: Cow::Owned(symbol.to_string()),
+                side: Side::Sell,
+                order_type: bybit::model::OrderType::Market,
+                qty,
+                time_in_force: Some(Cow::Borrowed("IOC")),
+                ..Default::default()
+            };
+            match client.place_custom_order(req).await {
+                Ok(v) => Ok(LiveOrder::new(0.0, qty, v.result.order_id)),
+                Err(e) => {
+                    println!("Could not place market order for {} qty: {}", qty, e);
+                    Err(e.into())
+                }
+            }
+        })
+    }
+
+    fn place_immediate_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        is_buy: bool,
+        time_in_force: TimeInForce,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let req = bybit_immediate_request(symbol, qty, price, is_buy, time_in_force);
+            match client.place_custom_order(req).await {
+                Ok(v) => Ok(LiveOrder::new(price, qty, v.result.order_id)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn amend_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        qty: f64,
+        price: Option<f64>,
+        _symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let req = AmendOrderRequest {
+                category: bybit::model::Category::Linear,
+                order_id: Some(Cow::Borrowed(order.order_id.as_str())),
+                price,
+                qty,
+                ..Default::default()
+            };
+            match client.amend_order(req).await {
+                Ok(v) => Ok(LiveOrder::new(
+                    price.unwrap_or(order.price),
+                    qty,
+                    v.result.order_id,
+                )),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn cancel_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let req = CancelOrderRequest {
+                category: bybit::model::Category::Linear,
+                symbol: Cow::Borrowed(symbol),
+                order_id: Some(Cow::Borrowed(order.order_id.as_str())),
+                order_filter: None,
+                order_link_id: None,
+            };
+            match client.cancel_order(req).await {
+                Ok(v) => Ok(LiveOrder::new(order.price, order.qty, v.result.order_id)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn cancel_all<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<LiveOrder>> {
+        Box::pin(async move {
+            let mut arr = vec![];
+            let client = self.clone().bybit_trader();
+            let req = CancelallRequest {
+                category: bybit::model::Category::Linear,
+                symbol,
+                ..Default::default()
+            };
+            match client.cancel_all_orders(req).await {
+                Ok(v) => {
+                    for d in v.result.list {
+                        arr.push(LiveOrder::new(0.0, 0.0, d.order_id));
+                    }
+                    Ok(arr)
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn batch_cancel<'a>(
+        &'a self,
+        orders: Vec<LiveOrder>,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>> {
+        Box::pin(async move {
+            let mut arr = vec![];
+            let client = self.clone().bybit_trader();
+            let req = BatchCancelRequest {
+                category: bybit::model::Category::Linear,
+                requests: {
+                    let mut li = vec![];
+                    for v in orders {
+                        let order_id_string = v.order_id.clone();
+                        li.push(CancelOrderRequest {
+                            category: bybit::model::Category::Linear,
+                            symbol: Cow::Borrowed(symbol),
+                            order_id: Some(Cow::Owned(order_id_string)), // Changed to Cow::Owned
+                            order_filter: None,
+                            order_link_id: None,
+                        });
+                    }
+                    li
+                },
+            };
+            match client.batch_cancel_order(req).await {
+                Ok(v) => {
+                    for d in v.result.list {
+                        arr.push(LiveOrder::new(0.0, 0.0, d.order_id));
+                    }
+                    Ok(arr)
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn batch_place_order(
+        &self,
+        order_array: Vec<BatchOrder>,
+    ) -> BackendFuture<'_, Vec<VecDeque<LiveOrder>>> {
+        Box::pin(async move {
+            // Linear post-only limit orders always display their full size.
+            warn_iceberg_unsupported(&BYBIT_ICEBERG_WARNING, "Bybit", &order_array);
+            // Clone the order array for later use
+            let order_array_clone = order_array.clone();
+
+            // Create the order requests for Bybit
+            let order_arr = {
+                let mut arr = vec![];
+                for BatchOrder(qty, price, symbol, side, link_id, _) in order_array_clone {
+                    arr.push(OrderRequest {
+                        category: bybit::model::Category::Linear,
+                        symbol: Cow::Owned(symbol),
+                        order_type: bybit::model::OrderType::Limit,
+                        side: {
+                            if side < 0 {
+                                bybit::model::Side::Sell
+                            } else {
+                                bybit::model::Side::Buy
+                            }
+                        },
+                        qty,
+                        price: Some(price),
+                        time_in_force: Some(Cow::Borrowed("PostOnly")),
+                        order_link_id: (!link_id.is_empty()).then_some(Cow::Owned(link_id)),
+                        ..Default::default()
+                    });
+                }
+                arr
+            };
+
+            // Place the orders with Bybit
+            let client = self.clone().bybit_trader();
+            let od_clone = order_array.clone();
+            let req = BatchPlaceRequest {
+                category: bybit::model::Category::Linear,
+                requests: order_arr,
+            };
+            match client.batch_place_order(req).await {
+                Ok(v) => {
+                    let mut arr = vec![];
+                    let mut buy_array = VecDeque::new();
+                    let mut sell_array = VecDeque::new();
+                    for (i, d) in v.result.list.iter().enumerate() {
+                        let order =
+                            LiveOrder::new(od_clone[i].1, od_clone[i].0, d.order_id.to_string())
+                                .with_link_id(d.order_link_id.to_string());
+                        if od_clone[i].3 < 0 {
+                            sell_array.push_back(order);
+                        } else {
+                            buy_array.push_back(order);
+                        }
+                    }
+                    arr.push(buy_array);
+                    arr.push(sell_array);
+                    Ok(arr)
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn batch_amend<'a>(
+        &'a self,
+        orders: Vec<LiveOrder>,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let order_clone = orders.clone();
+            let req = BatchAmendRequest {
+                category: bybit::model::Category::Linear,
+                requests: {
+                    let mut arr = vec![];
+                    for v in orders {
+                        arr.push(AmendOrderRequest {
+                            category: bybit::model::Category::Linear,
+                            symbol: Cow::Borrowed(symbol),
+                            order_id: Some(Cow::Owned(v.order_id)),
+                            ..Default::default()
+                        });
+                    }
+                    arr
+                },
+            };
+            match client.batch_amend_order(req).await {
+                Ok(v) => {
+                    let mut arr = vec![];
+                    for (i, d) in v.result.list.iter().enumerate() {
+                        arr.push(LiveOrder::new(
+                            order_clone[i].price,
+                            order_clone[i].qty,
+                            d.order_id.clone().to_string(),
+                        ));
+                    }
+                    Ok(arr)
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn open_orders<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<VecDeque<LiveOrder>>> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let req = OpenOrdersRequest::custom(
+                bybit::model::Category::Linear,
+                symbol,
+                None,
+                None,
+                None,
+                None,
+                0,
+                None,
+                Some(50),
+            );
+            match client.get_open_orders(req).await {
+                Ok(v) => {
+                    let mut buy_array = VecDeque::new();
+                    let mut sell_array = VecDeque::new();
+                    for d in v.result.list {
+                        let mut order = LiveOrder::new(d.price, d.qty, d.order_id)
+                            .with_link_id(d.order_link_id);
+                        order.remaining = d.leaves_qty;
+                        match d.side {
+                            Side::Sell => sell_array.push_back(order),
+                            _ => buy_array.push_back(order),
+                        }
+                    }
+                    Ok(vec![buy_array, sell_array])
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn executed_qty<'a>(&'a self, order_id: &'a str, symbol: &'a str) -> BackendFuture<'a, f64> {
+        Box::pin(async move {
+            let client = self.clone().bybit_trader();
+            let req = OrderHistoryRequest::new(
+                bybit::model::Category::Linear,
+                Some(symbol),
+                None,
+                None,
+                Some(order_id),
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(1),
+            );
+            match client.get_order_history(req).await {
+                Ok(v) => v
+                    .result
+                    .list
+                    .into_iter()
+                    .find(|o| o.order_id == order_id)
+                    .map(|o| o.cum_exec_qty)
+                    .ok_or_else(|| OrderError::Other(format!("Order {} not found", order_id))),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+}
+
+impl OrderBackend for BinanceClient {
+    fn maker_fee<'a>(&'a self, _symbol: &'a str) -> BackendFuture<'a, f64> {
+        // The client has no endpoint for the commission rate, which depends on the VIP tier and
+        // the BNB discount, so it has to be configured.
+        Box::pin(async {
+            Err(OrderError::Other(
+                "Binance does not report the maker fee, set maker_fee in the config".to_string(),
+            ))
+        })
+    }
+
+    fn place_buy_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                match client.binance_trader().limit_buy(
+                    symbol,
+                    qty,
+                    price,
+                    binance::futures::account::TimeInForce::GTC,
+                ) {
+                    Ok(v) => Ok(LiveOrder::new(price, qty, v.order_id.to_string())),
+                    Err(e) => Err(e.into()),
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn place_sell_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                match client.binance_trader().limit_sell(
+                    symbol,
+                    qty,
+                    price,
+                    binance::futures::account::TimeInForce::GTC,
+                ) {
+                    Ok(v) => Ok(LiveOrder::new(price, qty, v.order_id.to_string())),
+                    Err(e) => Err(e.into()),
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn market_buy<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                match client.binance_trader().market_buy(symbol, qty) {
+                    Ok(v) => Ok(LiveOrder::new(v.avg_price, qty, v.order_id.to_string())),
+                    Err(e) => {
+                        println!("Could not place market order for {} qty: {}", qty, e);
+                        Err(e.into())
+                    }
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn market_sell<'a>(&'a self, qty: f64, symbol: &'a str) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                match client.binance_trader().market_sell(symbol, qty) {
+                    Ok(v) => Ok(LiveOrder::new(v.avg_price, qty, v.order_id.to_string())),
+                    Err(e) => {
+                        println!("Could not place market order for {} qty: {}", qty, e);
+                        Err(e.into())
+                    }
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn place_immediate_limit<'a>(
+        &'a self,
+        qty: f64,
+        price: f64,
+        is_buy: bool,
+        time_in_force: TimeInForce,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let req = CustomOrderRequest {
+                symbol: symbol.to_owned(),
+                qty: Some(qty),
+                side: if is_buy {
+                    OrderSide::Buy
+                } else {
+                    OrderSide::Sell
+                },
+                price: Some(price),
+                order_type: binance::futures::account::OrderType::Limit,
+                time_in_force: Some(match time_in_force {
+                    TimeInForce::Ioc => binance::futures::account::TimeInForce::IOC,
+                    TimeInForce::Fok => binance::futures::account::TimeInForce::FOK,
+                }),
+                position_side: None,
+                stop_price: None,
+                close_position: None,
+                activation_price: None,
+                callback_rate: None,
+                working_type: None,
+                price_protect: None,
+                reduce_only: None,
+            };
+            let client = self.clone();
+            let task =
+                task::spawn_blocking(move || match client.binance_trader().custom_order(req) {
+                    Ok(v) => Ok(LiveOrder::new(price, qty, v.order_id.to_string())),
+                    Err(e) => Err(e.into()),
+                });
+            task.await.unwrap()
+        })
+    }
+
+    fn amend_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        qty: f64,
+        price: Option<f64>,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            // TODO: binance crate doesn't have an amend_order fn. so this cancels the current and places a new one then returns the new order id
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                client
+                    .binance_trader()
+                    .cancel_order(symbol.clone(), order.order_id.parse::<u64>().unwrap())?;
+                match client.binance_trader().limit_sell(
+                    symbol,
+                    qty,
+                    price.unwrap(),
+                    binance::futures::account::TimeInForce::GTC,
+                ) {
+                    Ok(v) => Ok(LiveOrder::new(price.unwrap(), qty, v.order_id.to_string())),
+                    Err(e) => Err(e.into()),
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn cancel_order<'a>(
+        &'a self,
+        order: LiveOrder,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, LiveOrder> {
+        Box::pin(async move {
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                match client
+                    .binance_trader()
+                    .cancel_order(symbol, order.order_id.parse::<u64>().unwrap())
+                {
+                    Ok(v) => Ok(LiveOrder::new(
+                        order.price,
+                        order.qty,
+                        v.order_id.to_string(),
+                    )),
+                    Err(e) => Err(e.into()),
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn cancel_all<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<LiveOrder>> {
+        Box::pin(async move {
+            // TODO
+            let arr = vec![];
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                match client.binance_trader().cancel_all_open_orders(symbol) {
+                    Ok(_) => Ok(arr),
+                    Err(e) => Err(e.into()),
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn batch_cancel<'a>(
+        &'a self,
+        orders: Vec<LiveOrder>,
+        symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>> {
+        // The client has no batch cancel, so the orders are cancelled one at a time and the
+        // first failure fails the batch.
+        Box::pin(async move {
+            let mut cancelled = Vec::with_capacity(orders.len());
+            for order in orders {
+                cancelled.push(self.cancel_order(order, symbol).await?);
+            }
+            Ok(cancelled)
+        })
+    }
+
+    fn batch_place_order(
+        &self,
+        order_array: Vec<BatchOrder>,
+    ) -> BackendFuture<'_, Vec<VecDeque<LiveOrder>>> {
+        Box::pin(async move {
+            // Futures limit orders have no iceberg quantity and display their full size.
+            warn_iceberg_unsupported(&BINANCE_ICEBERG_WARNING, "Binance", &order_array);
+            // Place the orders with Binance
+            let client = self.clone();
+            let order_vec = order_array.clone();
+            let order_requests = {
+                let mut arr = vec![];
+                // The batch request has no client order id, so Binance orders are not tagged.
+                for BatchOrder(qty, price, symbol, side, _, _) in order_vec {
+                    arr.push(CustomOrderRequest {
+                        symbol,
+                        qty: Some(qty),
+                        side: if side < 0 {
+                            OrderSide::Sell
+                        } else {
+                            OrderSide::Buy
+                        },
+                        price: Some(price),
+                        order_type: binance::futures::account::OrderType::Limit,
+                        time_in_force: Some(binance::futures::account::TimeInForce::GTC),
+                        position_side: None,
+                        stop_price: None,
+                        close_position: None,
+                        activation_price: None,
+                        callback_rate: None,
+                        working_type: None,
+                        price_protect: None,
+                        reduce_only: None,
+                    });
+                }
+                arr
+            };
+            let task = task::spawn_blocking(move || {
+                match client
+                    .binance_trader()
+                    .custom_batch_orders(order_array.len().try_into().unwrap(), order_requests)
+                {
+                    Ok(_) => {
+                        // TODO: Implement live order tracking for Binance
+                        let arr = vec![];
+                        Ok(arr)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            });
+            task.await.unwrap()
+        })
+    }
+
+    fn batch_amend<'a>(
+        &'a self,
+        _orders: Vec<LiveOrder>,
+        _symbol: &'a str,
+    ) -> BackendFuture<'a, Vec<LiveOrder>> {
+        Box::pin(async move {
+            Err(OrderError::Other(
+                "Batch amend is not supported on Binance".to_string(),
+            ))
+        })
+    }
+
+    fn open_orders<'a>(&'a self, symbol: &'a str) -> BackendFuture<'a, Vec<VecDeque<LiveOrder>>> {
+        Box::pin(async move {
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            let task = task::spawn_blocking(move || {
+                client
+                    .binance_trader()
+                    .get_all_open_orders(symbol)
+                    .map_err(OrderError::from)
+            });
+            match task.await {
+                Ok(Ok(orders)) => {
+                    let mut buy_array = VecDeque::new();
+                    let mut sell_array = VecDeque::new();
+                    for d in orders {
+                        let mut order = LiveOrder::new(d.price, d.orig_qty, d.order_id.to_string())
+                            .with_link_id(d.client_order_id);
+                        order.remaining = d.orig_qty - d.executed_qty;
+                        if d.side == "SELL" {
+                            sell_array.push_back(order);
+                        } else {
+                            buy_array.push_back(order);
+                        }
+                    }
+                    Ok(vec![buy_array, sell_array])
+                }
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(OrderError::Other(e.to_string())),
+            }
+        })
+    }
+
+    fn executed_qty<'a>(&'a self, order_id: &'a str, symbol: &'a str) -> BackendFuture<'a, f64> {
+        Box::pin(async move {
+            let id = order_id
+                .parse::<u64>()
+                .map_err(|_| OrderError::Other(format!("Invalid order id {}", order_id)))?;
+            let symbol = symbol.to_owned();
+            let client = self.clone();
+            // The orders are listed from the given id onwards, so the order comes first.
+            let task = task::spawn_blocking(move || {
+                client
+                    .binance_trader()
+                    .get_all_orders(symbol, Some(id), None, None, Some(1))
+                    .map_err(OrderError::from)
+            });
+            match task.await {
+                Ok(Ok(orders)) => orders
+                    .into_iter()
+                    .find(|o| o.order_id == id)
+                    .map(|o| o.executed_qty)
+                    .ok_or_else(|| OrderError::Other(format!("Order {} not found", id))),
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(OrderError::Other(e.to_string())),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::errors::BinanceContentError;
+    use bybit::errors::BybitContentError;
+
+    use super::*;
+
+    #[test]
+    fn test_exchange_errors_map_to_order_errors() {
+        let bybit = |code: i16, msg: &str| {
+            OrderError::from(BybitError::BybitError(BybitContentError {
+                code,
+                msg: msg.to_string(),
+            }))
+        };
+        let binance = |code: i16, msg: &str| {
+            OrderError::from(BinanceError::from(BinanceErrorKind::BinanceError(
+                BinanceContentError {
+                    code,
+                    msg: msg.to_string(),
+                },
+            )))
+        };
+
+        assert_eq!(bybit(10006, "Too many visits!"), OrderError::RateLimited);
+        assert_eq!(bybit(10003, "API key is invalid."), OrderError::AuthFailed);
+        assert_eq!(
+            bybit(10001, "ab not enough for new order, insufficient balance"),
+            OrderError::InsufficientMargin
+        );
+        assert_eq!(
+            OrderError::from(BybitError::StatusCode(429)),
+            OrderError::RateLimited
+        );
+        assert!(matches!(
+            OrderError::from(BybitError::ServiceUnavailable),
+            OrderError::Network(_)
+        ));
+
+        assert_eq!(
+            binance(-1003, "Too many requests queued."),
+            OrderError::RateLimited
+        );
+        assert_eq!(
+            binance(-2015, "Invalid API-key, IP, or permissions for action."),
+            OrderError::AuthFailed
+        );
+        assert_eq!(
+            binance(-5022, "Due to the order could not be executed as maker, the Post Only order will be rejected."),
+            OrderError::PostOnlyReject
+        );
+        assert_eq!(
+            binance(-2019, "Margin is insufficient."),
+            OrderError::InsufficientMargin
+        );
+        assert_eq!(
+            OrderError::from(BinanceError::from("Unauthorized")),
+            OrderError::AuthFailed
+        );
+        assert_eq!(
+            binance(-1121, "Invalid symbol."),
+            OrderError::Other("-1121: Invalid symbol.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_immediate_limit_request() {
+        let req = bybit_immediate_request("BTCUSDT", 0.5, 100.1, true, TimeInForce::Ioc);
+        assert_eq!(req.symbol, "BTCUSDT");
+        assert!(matches!(req.side, Side::Buy));
+        assert!(matches!(req.order_type, bybit::model::OrderType::Limit));
+        assert_eq!(req.qty, 0.5);
+        assert_eq!(req.price, Some(100.1));
+        assert_eq!(req.time_in_force.as_deref(), Some("IOC"));
+
+        let req = bybit_immediate_request("BTCUSDT", 0.5, 99.9, false, TimeInForce::Fok);
+        assert!(matches!(req.side, Side::Sell));
+        assert_eq!(req.time_in_force.as_deref(), Some("FOK"));
+    }
+}
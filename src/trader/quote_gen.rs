@@ -1,52 +1,278 @@
-use std::{borrow::Cow, collections::VecDeque};
-
-use binance::{account::OrderSide, futures::account::CustomOrderRequest};
-use bybit::model::{
-    AmendOrderRequest, BatchAmendRequest, BatchCancelRequest, BatchPlaceRequest,
-    CancelOrderRequest, CancelallRequest, FastExecData, OrderRequest, Side,
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Deref,
+    sync::Arc,
 };
+
+use bybit::model::FastExecData;
+use rand::Rng;
 use skeleton::{
-    exchanges::{
-        ex_binance::BinanceClient,
-        ex_bybit::BybitClient,
-        exchange::{ExchangeClient, PrivateData},
-    },
+    exchanges::exchange::{ExchangeClient, PrivateData},
     util::{
-        helpers::{geometric_weights, geomspace, round_step, Round},
+        heartbeat::HEARTBEAT_TIMEOUT_MS,
+        helpers::{
+            generate_timestamp, geomspace, round_step, QuoteAnchor, Round, SizeDistribution,
+            SizingMode, TimeInForce, TouchClamp, TradeFlowMode,
+        },
         localorderbook::LocalBook,
+        logger::{LogLevel, Logger},
     },
 };
-use tokio::task;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+
+use super::{
+    fill_stats::FillStats,
+    hedge::HedgeExecutor,
+    order_backend::{self, OrderBackend, OrderError},
+    state::SymbolState,
+};
+
+// Inventory delta beyond which the side that would grow the position stops quoting.
+const MAX_INVENTORY_DELTA: f64 = 0.90;
+// Spread in bps used when no spread is configured.
+const DEFAULT_SPREAD_BPS: f64 = 25.0;
+// Margin in bps kept above the round-trip maker fee.
+const FEE_MARGIN_BPS: f64 = 2.0;
+// Maximum number of orders a single level is split into to respect the post-only size limit.
+const MAX_ORDERS_PER_LEVEL: usize = 3;
+// Consecutive failed exchange requests after which order placement is paused.
+const MAX_CONSECUTIVE_ERRORS: u32 = 5;
+// Time in milliseconds that order placement stays paused once the breaker trips.
+const ERROR_BACKOFF_MS: u64 = 30_000;
+// Width in bps of the distance buckets used to estimate fill probabilities.
+const FILL_BUCKET_BPS: f64 = 5.0;
+// Prefix of the client order ids of the orders placed by the bot until a tag is configured.
+const DEFAULT_ORDER_TAG: &str = "smm";
+// Time in milliseconds between two reconciliations of the live orders with the exchange.
+const RECONCILE_INTERVAL_MS: u64 = 60_000;
+// Longest delay in milliseconds allowed before placing orders, so the quotes do not go stale.
+pub(crate) const MAX_PLACEMENT_JITTER_MS: u64 = 500;
+// Longest wait in milliseconds between cancelling a side and placing its new orders, so the side
+// is not left without quotes for long.
+pub(crate) const MAX_REPLACE_DELAY_MS: u64 = 500;
+// Longest order tag, keeping the client order ids within the exchange limit of 36 characters.
+pub(crate) const MAX_ORDER_TAG_LEN: usize = 8;
+// Distance in bps from the best bid or ask within which a live order counts as quoting.
+const UPTIME_DISTANCE_BPS: f64 = 10.0;
+// Fraction of the maximum position the tracked position may drift from the exchange position.
+const POSITION_SYNC_TOLERANCE: f64 = 0.01;
+/// Most orders the exchange accepts in a single batch request.
+pub const MAX_BATCH_ORDERS: usize = 10;
+// Delay in milliseconds after a fill at which its realized spread is measured by default.
+const DEFAULT_MARKOUT_HORIZON_MS: u64 = 5_000;
+// Limit orders that may leave the position unchanged before it is flattened with a market order.
+const FLATTEN_LIMIT_ATTEMPTS: u32 = 3;
+// Time in milliseconds an order flattening the position waits for its fills before the next one.
+const FLATTEN_FILL_TIMEOUT_MS: u64 = 2_000;
+// Number of fills the prior of the expected edge, earning the full quoted spread, weighs as.
+const EDGE_PRIOR_FILLS: f64 = 10.0;
 
-// [qty, price, symbol, side] side is -1 for sell and 1 for buy
+// [qty, price, symbol, side, link_id, display_qty] side is -1 for sell and 1 for buy, link_id is
+// the client order id and empty until the order is tagged, display_qty is the visible part of qty
 #[derive(Debug, Clone)]
-pub struct BatchOrder(f64, f64, String, i32);
+pub struct BatchOrder(pub f64, pub f64, pub String, pub i32, pub String, pub f64);
 
 impl BatchOrder {
     pub fn new(qty: f64, price: f64, side: i32) -> Self {
-        BatchOrder(qty, price, "".to_string(), side)
+        BatchOrder(qty, price, "".to_string(), side, "".to_string(), qty)
+    }
+}
+
+/// A set of grid parameters quoted as one layer of the combined grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridLayer {
+    /// Multiplier applied to the adjusted spread for this layer.
+    pub spread_multiplier: f64,
+    /// Number of buy orders in this layer.
+    pub orders_per_bid: usize,
+    /// Number of sell orders in this layer.
+    pub orders_per_ask: usize,
+    /// Fraction of the maximum position allocated to this layer.
+    pub size_fraction: f64,
+}
+
+impl GridLayer {
+    pub fn new(spread_multiplier: f64, orders_per_side: usize, size_fraction: f64) -> Self {
+        GridLayer {
+            spread_multiplier,
+            orders_per_bid: orders_per_side,
+            orders_per_ask: orders_per_side,
+            size_fraction,
+        }
+    }
+}
+
+/// The exchange backend used to manage the generator's orders.
+struct OrderManagement(Box<dyn OrderBackend>);
+
+impl OrderManagement {
+    fn new(client: ExchangeClient) -> Self {
+        OrderManagement(order_backend::from_client(client))
+    }
+
+    /// Crosses the book with an IOC or FOK limit order priced `max_slippage_bps` away from the
+    /// mid price, so the order never fills beyond that slippage.
+    pub async fn flatten_with_ioc(
+        &self,
+        qty: f64,
+        is_buy: bool,
+        book: &LocalBook,
+        max_slippage_bps: f64,
+        time_in_force: TimeInForce,
+        symbol: &str,
+    ) -> Result<LiveOrder, OrderError> {
+        let price = slippage_bound(book, is_buy, max_slippage_bps);
+        self.place_immediate_limit(qty, price, is_buy, time_in_force, symbol)
+            .await
     }
 }
 
-enum OrderManagement {
-    Bybit(BybitClient),
-    Binance(BinanceClient),
+impl Deref for OrderManagement {
+    type Target = dyn OrderBackend;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
 }
 pub struct QuoteGenerator {
     asset: f64,
+    leverage: f64,
     client: OrderManagement,
     minimum_spread: f64,
+    min_spread_ticks: f64,
+    spread_schedule: Vec<(u32, u32, f64)>,
+    clock: fn() -> u64,
+    aggression_min: f64,
+    aggression_max: f64,
+    size_distribution: SizeDistribution,
+    sizing_mode: SizingMode,
+    skew_deadband: f64,
+    requote_threshold_mult: f64,
+    iceberg_display_fraction: Option<f64>,
+    flatten_slice_notional: Option<f64>,
+    // Slippage in bps and time in force of the limit orders flattening the position.
+    flatten_limit: Option<(f64, TimeInForce)>,
+    // The position when the limit orders started flattening it and the orders sent since.
+    flatten_attempts: (f64, u32),
+    // The last order sent towards a flat position and the time it was sent at.
+    flatten_order: Option<(LiveOrder, u64)>,
+    // Whether the grid is pulled and the position flattened instead of quoting.
+    kill_switch: bool,
+    min_quoting_spread_bps: f64,
+    cancel_on_tight_spread: bool,
+    max_quoting_spread_bps: Option<f64>,
+    // Whether quoting is suspended until the spread of the book narrows again.
+    spread_blown_out: bool,
+    cancel_on_crossed_book: bool,
+    // Whether quoting is suspended until the book uncrosses.
+    book_crossed: bool,
+    // Move in bps of the live mid from the quoted mid beyond which a submission is aborted.
+    last_look_bps: Option<f64>,
+    // The latest mid price published by the market data feed.
+    live_mid: Option<watch::Receiver<f64>>,
+    // The mid price of the book the orders being placed were generated from.
+    quoted_mid: f64,
+    touch_clamp: Option<TouchClamp>,
+    thin_touch_qty: Option<f64>,
+    anchor: QuoteAnchor,
+    // Recent (time, mid price) samples averaged by the TWAP anchor.
+    anchor_samples: VecDeque<(u64, f64)>,
+    trade_flow_bias: Option<(f64, TradeFlowMode)>,
+    // Direction of the recent aggressive trades, from -1 for sells only to 1 for buys only.
+    trade_flow: f64,
+    risk_per_level_usd: Option<f64>,
+    volatility: f64,
+    request_limiter: Option<Arc<Semaphore>>,
+    // Whether the generated orders are held for the market maker to place in shared batches.
+    defer_placement: bool,
+    deferred_orders: Vec<BatchOrder>,
+    // Number of levels placed per side on each update, placing the whole grid at once when unset.
+    levels_per_tick: Option<usize>,
+    // Levels of the current grid that are still to be placed.
+    pending_levels: Vec<BatchOrder>,
+    // Orders held back by the placement delay and the book time they are sent at.
+    scheduled_orders: Option<(u64, Vec<BatchOrder>)>,
+    hedge: Option<HedgeExecutor>,
+    // Logger of the order events and the symbol they are logged for.
+    event_logger: Option<(Logger, String)>,
+    maker_fee: f64,
     pub live_buys_orders: VecDeque<LiveOrder>,
     pub live_sells_orders: VecDeque<LiveOrder>,
     pub position: f64,
+    position_qty: f64,
+    avg_entry_price: f64,
+    realized_pnl: f64,
+    // Highest PnL reached so far, the drawdown is measured from it.
+    peak_pnl: f64,
+    max_drawdown_usd: Option<f64>,
+    // (drawdown fraction, spread multiplier, size multiplier) breakpoints of the de-risking curve.
+    drawdown_curve: Vec<(f64, f64, f64)>,
+    // Maker rebates in USD earned by the fills, kept apart from the PnL.
+    rebates_earned: f64,
+    // Fills awaiting their markout as (side, price, due time).
+    pending_markouts: VecDeque<(i32, f64, u64)>,
+    markout_horizon_ms: u64,
+    // Sum and count of the realized spreads measured so far.
+    realized_spread: (f64, u64),
+    // Sum and count of the realized spreads in bps of the fill prices.
+    realized_spread_bps: (f64, u64),
+    // Expected net edge in bps below which the spread is widened or quoting is suspended.
+    min_edge_bps: Option<f64>,
+    suspend_on_low_edge: bool,
+    last_exchange_position: Option<f64>,
     max_position_usd: f64,
+    max_notional_usd: Option<f64>,
+    portfolio_budget: Option<f64>,
     pub inventory_delta: f64,
-    total_order: usize,
+    inventory_target: f64,
+    orders_per_bid: usize,
+    orders_per_ask: usize,
+    grid_layers: Vec<GridLayer>,
+    requote_cooldown_ms: u64,
+    // Whether a fill requotes its side at once, and the bid and ask sides awaiting that requote.
+    requote_on_fill: bool,
+    filled_sides: [bool; 2],
+    max_order_age_ms: u64,
+    // Whether the grid is left resting while its best orders hold the touch.
+    hold_touch: bool,
+    placement_jitter_ms: u64,
+    // Time in milliseconds waited between cancelling a side and placing its new orders.
+    replace_delay_ms: u64,
+    // Gap in milliseconds between book updates read as a reconnect of the market streams.
+    heartbeat_timeout_ms: u64,
+    recent_fills: VecDeque<(f64, u64)>,
+    open_fills: VecDeque<(i32, f64, f64)>,
+    captured_bps: f64,
+    captured_qty: f64,
+    fill_stats: FillStats,
+    fill_weighting: bool,
     final_order_distance: f64,
     last_update_price: f64,
     rate_limit: u32,
     time_limit: u64,
     cancel_limit: u32,
+    consecutive_errors: u32,
+    paused_until: u64,
+    order_tag: String,
+    order_seq: u64,
+    last_reconcile: u64,
+    // Whether orders were adopted from the exchange and not yet matched against the grid.
+    fit_adopted: bool,
+    last_book_update: u64,
+    missing_instrument_logged: bool,
+    // Whether the size of the levels below the minimum notional moves to the adjacent levels.
+    backfill_levels: bool,
+    // Notional in USD every level is raised to, out of the size of its side.
+    min_level_notional: Option<f64>,
+    // Whether a grid with levels below the minimum notional was logged.
+    short_grid_logged: bool,
+    // Time in milliseconds spent quoting near the touch and in total.
+    uptime_ms: (u64, u64),
+    // The time of the last uptime sample and whether orders were near the touch then.
+    last_uptime_sample: Option<(u64, bool)>,
+    funding_pause_window_ms: u64,
+    // The upcoming and the previous funding times in milliseconds.
+    funding_times: (u64, u64),
 }
 
 impl QuoteGenerator {
@@ -70,14 +296,13 @@ impl QuoteGenerator {
         rate_limit: u32,
     ) -> Self {
         // Create the appropriate trader based on the exchange client.
-        let trader = match client {
-            ExchangeClient::Bybit(cl) => OrderManagement::Bybit(cl),
-            ExchangeClient::Binance(cl) => OrderManagement::Binance(cl),
-        };
+        let trader = OrderManagement::new(client);
         // Create a new `QuoteGenerator` instance.
         QuoteGenerator {
             // Set the asset value multiplied by the leverage.
             asset: asset * leverage,
+            // Keep the leverage to rescale live wallet updates.
+            leverage,
             // Set the client to the created trader.
             client: trader,
             // Create empty VecDeque for live buy orders with a capacity of 5.
@@ -86,14 +311,126 @@ impl QuoteGenerator {
             live_sells_orders: VecDeque::new(),
             // Position
             position: 0.0,
+            position_qty: 0.0,
+            avg_entry_price: 0.0,
+            realized_pnl: 0.0,
+            // Quote the same spreads and sizes whatever the drawdown.
+            peak_pnl: 0.0,
+            max_drawdown_usd: None,
+            drawdown_curve: Vec::new(),
+            rebates_earned: 0.0,
+            // Measure the realized spread of each fill once the horizon has passed.
+            pending_markouts: VecDeque::new(),
+            markout_horizon_ms: DEFAULT_MARKOUT_HORIZON_MS,
+            realized_spread: (0.0, 0),
+            realized_spread_bps: (0.0, 0),
+            // Quote whatever the expected edge until a minimum is configured.
+            min_edge_bps: None,
+            suspend_on_low_edge: false,
+            // No position has been streamed by the exchange yet.
+            last_exchange_position: None,
             // Set the inventory delta to 0.0.
             inventory_delta: 0.0,
+            // Target a flat position until another target is configured.
+            inventory_target: 0.0,
             // Set the maximum position USD to 0.0.
             max_position_usd: 0.0,
-            // Set the total order to 10.
-            total_order: orders_per_side * 2,
+            // Only limit the position by the leverage until a notional cap is configured.
+            max_notional_usd: None,
+            // Quote without a portfolio budget until the market maker sets one.
+            portfolio_budget: None,
+            // Use the same number of orders on both sides until configured otherwise.
+            orders_per_bid: orders_per_side,
+            orders_per_ask: orders_per_side,
+            // Quote a single grid until layers are configured.
+            grid_layers: Vec::new(),
+            // Requote filled levels immediately until a cooldown is configured.
+            requote_cooldown_ms: 0,
+            requote_on_fill: false,
+            filled_sides: [false; 2],
+            // Keep the orders within the bounds resting until an age limit is configured.
+            max_order_age_ms: 0,
+            hold_touch: false,
+            placement_jitter_ms: 0,
+            replace_delay_ms: 0,
+            heartbeat_timeout_ms: HEARTBEAT_TIMEOUT_MS,
+            recent_fills: VecDeque::new(),
+            // No round trips have been completed yet.
+            open_fills: VecDeque::new(),
+            captured_bps: 0.0,
+            captured_qty: 0.0,
+            // Every level starts with the same fill probability and is not weighted by it.
+            fill_stats: FillStats::new(FILL_BUCKET_BPS),
+            fill_weighting: false,
             // Set the preferred spread to the provided value.
             minimum_spread: 0.0,
+            // Only use the bps floor until a floor in ticks is configured.
+            min_spread_ticks: 0.0,
+            // Quote the same spread at every hour of the day until a schedule is configured.
+            spread_schedule: Vec::new(),
+            clock: generate_timestamp,
+            // Use the order book imbalance as the aggression without limits by default.
+            aggression_min: 0.0,
+            aggression_max: 1.0,
+            // Grow the sizes geometrically away from the touch until a distribution is configured.
+            size_distribution: SizeDistribution::default(),
+            // Budget the grid in USD notional.
+            sizing_mode: SizingMode::Notional,
+            // Follow the sign of every skew until a deadband is configured.
+            skew_deadband: 0.0,
+            // Requote once the mid price moves 1.5 times the minimum spread.
+            requote_threshold_mult: 1.5,
+            // Display the full size of every order.
+            iceberg_display_fraction: None,
+            // Flatten the whole position with a single market order.
+            flatten_slice_notional: None,
+            // Flatten with market orders.
+            flatten_limit: None,
+            flatten_attempts: (0.0, 0),
+            flatten_order: None,
+            // Quote until the kill switch is turned on.
+            kill_switch: false,
+            // Quote whatever the spread of the book.
+            min_quoting_spread_bps: 0.0,
+            cancel_on_tight_spread: false,
+            // Keep quoting however wide the spread of the book.
+            max_quoting_spread_bps: None,
+            spread_blown_out: false,
+            // Leave the resting orders alone while the book is crossed.
+            cancel_on_crossed_book: false,
+            book_crossed: false,
+            // Submit the generated orders whatever the mid does until a tolerance is configured.
+            last_look_bps: None,
+            live_mid: None,
+            quoted_mid: 0.0,
+            // Quote inside the best bid and ask until a clamp is configured.
+            touch_clamp: None,
+            // Never improve on the best bid or ask.
+            thin_touch_qty: None,
+            // Quote around the mid price.
+            anchor: QuoteAnchor::Mid,
+            anchor_samples: VecDeque::new(),
+            // Place the quotes regardless of the direction of the trades.
+            trade_flow_bias: None,
+            trade_flow: 0.0,
+            // Size the levels from the maximum position only.
+            risk_per_level_usd: None,
+            volatility: 0.0,
+            // Send the order requests without waiting on other generators.
+            request_limiter: None,
+            // Place the orders of this symbol in its own batches.
+            defer_placement: false,
+            deferred_orders: Vec::new(),
+            // Place the whole grid at once.
+            levels_per_tick: None,
+            pending_levels: Vec::new(),
+            scheduled_orders: None,
+            // Keep the fills unhedged until a hedge exchange is configured.
+            hedge: None,
+            // Leave the order events unlogged until a logger is set.
+            event_logger: None,
+            // Assume no fees until the maker fee is fetched.
+            maker_fee: 0.0,
             // final order distance
             final_order_distance,
 
@@ -104,17 +441,109 @@ impl QuoteGenerator {
             time_limit: 0,
 
             cancel_limit: rate_limit,
+
+            consecutive_errors: 0,
+
+            paused_until: 0,
+            // Tag the placed orders so they can be recognized after a restart.
+            order_tag: DEFAULT_ORDER_TAG.to_string(),
+            order_seq: 0,
+            // Reconcile the live orders with the exchange on the first update.
+            last_reconcile: 0,
+            fit_adopted: false,
+            last_book_update: 0,
+            missing_instrument_logged: false,
+            // Drop the levels below the minimum notional with their size.
+            backfill_levels: false,
+            // Leave the level sizes to the size distribution.
+            min_level_notional: None,
+            short_grid_logged: false,
+            uptime_ms: (0, 0),
+            last_uptime_sample: None,
+            // Keep quoting through funding until a window is configured.
+            funding_pause_window_ms: 0,
+            funding_times: (0, 0),
         }
     }
 
     /// Updates the maximum position USD by multiplying the asset value by 0.95.
     ///
     /// This function is used to update the maximum position USD, which is the maximum
-    /// amount of USD that can be allocated for the trading position.
+    /// amount of USD that can be allocated for the trading position. It never exceeds the
+    /// notional cap when one is set.
     pub fn update_max(&mut self) {
         // Calculate the maximum position USD by multiplying the asset value by 0.95.
         // This leaves 5% of the total asset value as safety margin.
-        self.max_position_usd = self.asset * 0.95;
+        let leverage_based = self.asset * 0.95;
+        self.max_position_usd = match self.max_notional_usd {
+            Some(cap) => leverage_based.min(cap),
+            None => leverage_based,
+        };
+    }
+
+    /// Sets a hard cap in USD on the maximum position, independent of the leverage.
+    pub fn set_max_notional(&mut self, max_notional_usd: Option<f64>) {
+        self.max_notional_usd = max_notional_usd;
+        self.update_max();
+    }
+
+    /// Sets the notional in USD the symbol may deploy, shared with the other symbols of the
+    /// portfolio. The position and the new orders together stay within the budget.
+    pub fn set_portfolio_budget(&mut self, budget: Option<f64>) {
+        self.portfolio_budget = budget;
+    }
+
+    /// Returns the notional in USD tied up in the position and the live orders.
+    pub fn deployed_notional(&self) -> f64 {
+        let orders: f64 = self
+            .live_buys_orders
+            .iter()
+            .chain(self.live_sells_orders.iter())
+            .map(|o| o.remaining * o.price)
+            .sum();
+        self.position.abs() + orders
+    }
+
+    /// Returns the leverage applied to the asset value.
+    pub fn leverage(&self) -> f64 {
+        self.leverage
+    }
+
+    /// Updates the asset value and maximum position USD from the latest wallet balance.
+    ///
+    /// Wallet updates without a positive equity are ignored, since partial updates can
+    /// briefly report a zero balance.
+    pub fn update_wallet(&mut self, data: &PrivateData) {
+        if let Some(equity) = wallet_equity(data) {
+            self.asset = equity * self.leverage;
+            self.update_max();
+        }
+    }
+
+    /// Snaps the tracked position to the position streamed by the exchange.
+    ///
+    /// Only a new exchange position is compared, so fills booked locally before the position
+    /// stream catches up are not reverted. The tracked position is replaced when it differs by
+    /// more than 1% of the maximum position, for example after missed fills or a manual trade.
+    pub fn sync_position(&mut self, data: &PrivateData, symbol: &str) {
+        self.snap_position(exchange_position(data, symbol), symbol);
+    }
+
+    fn snap_position(&mut self, exchange: Option<f64>, symbol: &str) {
+        let exchange = match exchange {
+            Some(v) if self.last_exchange_position != Some(v) => v,
+            _ => return,
+        };
+        self.last_exchange_position = Some(exchange);
+        let tolerance = self.max_position_usd * POSITION_SYNC_TOLERANCE;
+        if (self.position - exchange).abs() > tolerance {
+            eprintln!(
+                "Position mismatch on {}: tracked {:.2} USD, exchange {:.2} USD, using the exchange position",
+                symbol, self.position, exchange
+            );
+            self.position = exchange;
+            self.inventory_delta();
+        }
     }
 
     /// Set preferred spread based on mid price in the order book.
@@ -122,1073 +551,4969 @@ impl QuoteGenerator {
         self.minimum_spread = spread_in_bps;
     }
 
-    /// Updates the inventory delta based on the quantity and price.
-    ///
-    /// This function calculates the inventory delta by dividing the amount by the maximum position qty.
-    /// The result is then assigned to the `inventory_delta` field.
-    ///
-    /// # Parameters
-    ///
-    /// * `mid_price`: The mid price of the asset.
-    ///
-    /// # Details
-    ///
-    /// The inventory delta is a measure of the position's exposure to the market. It is calculated
-    /// by dividing the amount multiplied by the mid price by the maximum position qty. The maximum
-    /// position qty is the maximum amount of qty that can be allocated for the trading position,
-    /// after considering the safety margin of 5%.
-    ///
-    /// The result is then assigned to the `inventory_delta` field, which is a measure of the
-    /// position's exposure to the market.
-    pub fn inventory_delta(&mut self) {
-        // Calculate the inventory delta by dividing the price multiplied by the quantity by the
-        // maximum position USD.
-        self.inventory_delta = self.position / self.max_position_usd;
+    /// Returns the preferred spread in bps, 0 when none was set.
+    pub fn spread(&self) -> f64 {
+        self.minimum_spread
     }
 
-    /// Adjusts the spread by clipping it to a minimum spread and a maximum spread.
+    /// Sets the tag the client order ids of the placed orders start with.
     ///
-    /// This function calculates the adjusted spread by calling the `get_spread` method on the
-    /// `book` parameter and clipping the result to a minimum spread and a maximum spread.
-    /// 1 bps = 0.01% = 0.0001
-    /// Calculate the preferred spread as a percentage of the start price.
+    /// # Panics
     ///
-    /// # Parameters
+    /// If the tag is empty, longer than 8 characters or not alphanumeric.
+    pub fn set_order_tag(&mut self, tag: String) {
+        assert!(
+            !tag.is_empty()
+                && tag.len() <= MAX_ORDER_TAG_LEN
+                && tag.chars().all(|c| c.is_ascii_alphanumeric()),
+            "Order tag must be 1 to 8 alphanumeric characters"
+        );
+        self.order_tag = tag;
+    }
+
+    /// Rebuilds the live queues from the open orders on the exchange.
     ///
-    /// * `preferred_spread`: The preferred spread as a `f64`.
-    /// * `book`: The order book to get the spread from.
+    /// Orders that are partially filled on the exchange are booked for the filled quantity, so
+    /// the position catches up with fills that were missed while disconnected. Local orders that
+    /// are no longer open are only booked for the quantity the exchange reports as executed, the
+    /// rest was cancelled or rejected. Open orders carrying the order tag that are not tracked
+    /// yet, e.g. after a restart, are adopted. Orders placed manually or by another tool are left
+    /// alone.
+    pub async fn reconcile(&mut self, symbol: &str) {
+        if let Ok(open) = self.client.open_orders(symbol).await {
+            let mut executed = HashMap::new();
+            for order in self.live_buys_orders.iter().chain(&self.live_sells_orders) {
+                if open.iter().flatten().all(|o| o.order_id != order.order_id) {
+                    // An order whose executions cannot be fetched is treated as cancelled.
+                    if let Ok(qty) = self.client.executed_qty(&order.order_id, symbol).await {
+                        executed.insert(order.order_id.clone(), qty);
+                    }
+                }
+            }
+            let now = (self.clock)();
+            self.apply_open_orders(open, &executed, now);
+        }
+    }
+
+    /// Rebuilds the live queues from the open orders returned by `open_orders`.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// The adjusted spread as a `f64`.
-    fn adjusted_spread(preferred_spread: f64, book: &LocalBook) -> f64 {
-        // Calculate the minimum spread by converting the preferred spread to decimal format.
-        let min_spread = {
-            if preferred_spread == 0.0 {
-                bps_to_decimal(25.0) * book.get_mid_price()
+    /// * `open` - The buy queue followed by the sell queue of the open orders.
+    /// * `executed` - The executed quantity of the local orders that are no longer open, by
+    ///   order id.
+    /// * `now` - The current time in milliseconds.
+    fn apply_open_orders(
+        &mut self,
+        open: Vec<VecDeque<LiveOrder>>,
+        executed: &HashMap<String, f64>,
+        now: u64,
+    ) {
+        let mut open = open.into_iter();
+        let open_buys = open.next().unwrap_or_default();
+        let open_sells = open.next().unwrap_or_default();
+        for (side, open_orders) in [(1, open_buys), (-1, open_sells)] {
+            let local = if side > 0 {
+                std::mem::take(&mut self.live_buys_orders)
+            } else {
+                std::mem::take(&mut self.live_sells_orders)
+            };
+            let mut live = VecDeque::with_capacity(open_orders.len());
+            for mut order in local {
+                let open_order = open_orders.iter().find(|o| o.order_id == order.order_id);
+                let filled = match open_order {
+                    Some(o) => order.remaining - o.remaining,
+                    // Only the executions not booked yet count, the rest was never filled.
+                    None => executed.get(&order.order_id).map_or(0.0, |qty| {
+                        (qty - (order.qty - order.remaining)).min(order.remaining)
+                    }),
+                };
+                if filled > f64::EPSILON {
+                    if order.exec_ids.is_empty() {
+                        self.fill_stats
+                            .record_fill(self.distance_from_mid(order.price));
+                    }
+                    self.book_fill(side, order.price, filled, now);
+                }
+                if let Some(o) = open_order {
+                    order.remaining = o.remaining;
+                    live.push_back(order);
+                }
+            }
+            for order in open_orders {
+                if !live.contains(&order)
+                    && parse_client_order_id(&order.link_id, &self.order_tag).is_some()
+                {
+                    // The placement time is unknown, so the order ages from its adoption.
+                    live.push_back(LiveOrder {
+                        created_at: now,
+                        ..order
+                    });
+                    self.fit_adopted = true;
+                }
+            }
+            if side > 0 {
+                self.live_buys_orders = sort_grid(live, -1);
             } else {
-                bps_to_decimal(preferred_spread) * book.get_mid_price()
+                self.live_sells_orders = sort_grid(live, 1);
             }
+        }
+    }
+
+    /// Matches the adopted orders against the grid generated for the book, cancelling those that
+    /// are not on one of its levels and placing the levels no live order is on.
+    ///
+    /// Without it the adopted orders would rest next to a fresh grid, doubling the exposure.
+    async fn fit_adopted_orders(&mut self, symbol: &str, grid: Vec<BatchOrder>, book: &LocalBook) {
+        self.fit_adopted = false;
+        let tick = book.tick_size + f64::EPSILON;
+        let on_level = |price: f64, side: i32, levels: &[BatchOrder]| {
+            levels
+                .iter()
+                .any(|o| o.3 == side && (o.1 - price).abs() < tick)
         };
 
-        // Get the spread from the order book and clip it to the minimum spread and a maximum
-        // spread of 3.7 times the minimum spread.
-        book.get_spread().clip(min_spread, min_spread * 3.7)
+        let misfits: Vec<LiveOrder> = self
+            .live_buys_orders
+            .iter()
+            .filter(|o| !on_level(o.price, 1, &grid))
+            .chain(
+                self.live_sells_orders
+                    .iter()
+                    .filter(|o| !on_level(o.price, -1, &grid)),
+            )
+            .cloned()
+            .collect();
+        for chunk in misfits.chunks(MAX_BATCH_ORDERS) {
+            let permit = self.request_permit().await;
+            let cancelled = self
+                .client
+                .batch_cancel(chunk.to_vec(), symbol)
+                .await
+                .is_ok();
+            drop(permit);
+            self.record_request(cancelled, book.last_update);
+            if cancelled {
+                self.live_buys_orders.retain(|o| !chunk.contains(o));
+                self.live_sells_orders.retain(|o| !chunk.contains(o));
+            }
+        }
+
+        let live: Vec<BatchOrder> = self
+            .live_buys_orders
+            .iter()
+            .map(|o| BatchOrder::new(o.qty, o.price, 1))
+            .chain(
+                self.live_sells_orders
+                    .iter()
+                    .map(|o| BatchOrder::new(o.qty, o.price, -1)),
+            )
+            .collect();
+        let missing: Vec<BatchOrder> = grid
+            .into_iter()
+            .filter(|o| !on_level(o.1, o.3, &live))
+            .collect();
+        println!(
+            "Kept {} adopted orders of {}, cancelled {} and placing {} levels",
+            live.len(),
+            symbol,
+            misfits.len(),
+            missing.len()
+        );
+        self.last_update_price = self.anchor_price(book);
+        if !missing.is_empty() {
+            self.place_orders(missing, book.last_update, 0).await;
+        }
     }
 
-    /// Generates quotes based on the given parameters.
-    ///
-    /// # Parameters
-    ///
-    /// * `symbol`: The symbol of the quote.
-    /// * `book`: The order book to get the mid price from.
-    /// * `imbalance`: The imbalance of the order book.
-    /// * `skew`: The skew value.
-    /// * `price_flu`: The price fluctuation.
-    ///
-    /// # Returns
+    /// Sets whether the size of the levels below the minimum notional is moved to the next level
+    /// away from the touch instead of being dropped with them.
+    pub fn set_backfill_levels(&mut self, backfill: bool) {
+        self.backfill_levels = backfill;
+    }
+
+    /// Sets the notional in USD every level of the grid is raised to, `None` leaves the sizes to
+    /// the size distribution.
     ///
-    /// A vector of `BatchOrder` objects representing the generated quotes.
+    /// The levels are raised out of the size of their side, so a side that can not cover all its
+    /// levels at the floor quotes fewer levels, keeping the ones closest to the touch. A floor
+    /// below the minimum notional of the instrument is raised to it.
     ///
-    /// This function generates quotes based on the given parameters. It calculates the adjusted
-    /// spread, half spread, aggression, and generates the orders based on the skew value. It
-    /// also adds the symbol to each order.
+    /// # Panics
     ///
-    /// NOTES: From Cartea, 2018
-    /// If imbalance is buy heavy use positive skew quotes, for sell heavy use negative skew quotes
-    /// but for liquidations use the opposite, buy = negative skew & sell = positive skew meaning
-    /// sell orders are easily filled in these periods and buy orders also
-    fn generate_quotes(
-        &mut self,
-        symbol: String,
+    /// If `notional` is not positive.
+    pub fn set_min_level_notional(&mut self, notional: Option<f64>) {
+        if let Some(notional) = notional {
+            assert!(notional > 0.0, "Minimum level notional must be positive");
+        }
+        self.min_level_notional = notional;
+    }
+
+    /// Raises the levels to the minimum level notional, then drops or backfills the levels below
+    /// the minimum notional.
+    fn fit_grid(
+        &self,
+        orders: Vec<BatchOrder>,
+        notional: f64,
+        layer: &GridLayer,
         book: &LocalBook,
-        imbalance: f64,
-        skew: f64,
     ) -> Vec<BatchOrder> {
-        // Get the start price from the order book.
-        let start = book.get_mid_price();
-
-        // Calculate the preferred spread as a percentage of the start price.
-        let preferred_spread = self.minimum_spread;
-
-        // Calculate the adjusted spread by calling the `adjusted_spread` method.
-        let curr_spread = QuoteGenerator::adjusted_spread(preferred_spread, book);
-
-        // Calculate the half spread by dividing the spread by 2.
-        let half_spread = curr_spread / 2.0;
-
-        let notional = book.min_notional;
+        let orders = match self.min_level_notional {
+            Some(floor) => floored_levels(orders, floor.max(notional), book),
+            None => orders,
+        };
+        fit_levels(orders, notional, layer, self.backfill_levels, book)
+    }
 
-        // Generate the orders based on the skew value.
-        let mut orders = if skew >= 0.0 {
-            self.positive_skew_orders(half_spread, curr_spread, start, imbalance.abs(), notional, book)
+    /// Returns the number of bid and ask levels `layer` quotes at the current inventory.
+    fn expected_levels(&self, layer: &GridLayer) -> (usize, usize) {
+        let bids = if self.inventory_delta >= MAX_INVENTORY_DELTA {
+            0
+        } else {
+            layer.orders_per_bid
+        };
+        let asks = if self.inventory_delta <= -MAX_INVENTORY_DELTA {
+            0
         } else {
-            self.negative_skew_orders(half_spread, curr_spread, start, imbalance.abs(), notional, book)
+            layer.orders_per_ask
         };
+        (bids, asks)
+    }
 
-        // Add the symbol to each order.
-        for v in orders.iter_mut() {
-            v.2 = symbol.clone();
+    /// Logs when the grid starts and stops quoting fewer levels than configured.
+    fn log_short_grid(&mut self, short: bool, symbol: &str) {
+        if short && !self.short_grid_logged {
+            eprintln!(
+                "Levels of {} below the minimum notional are not quoted, the grid is thinner than configured",
+                symbol
+            );
+        } else if !short && self.short_grid_logged {
+            eprintln!("Every level of {} is quoted again", symbol);
         }
+        self.short_grid_logged = short;
+    }
 
-        orders
+    /// Sets the skew below which, in absolute value, a neutral symmetric grid is quoted.
+    ///
+    /// # Panics
+    ///
+    /// If `deadband` is negative.
+    pub fn set_skew_deadband(&mut self, deadband: f64) {
+        assert!(deadband >= 0.0, "Skew deadband must not be negative");
+        self.skew_deadband = deadband;
     }
 
-    /// Generates a list of batch orders for positive skew.
+    /// Sets how far, as a multiple of the minimum spread, the mid price must move before the grid
+    /// is requoted.
     ///
-    /// # Arguments
+    /// Lower values track the mid price more tightly, higher values reduce the churn.
     ///
-    /// * `half_spread` - The half spread.
-    /// * `curr_spread` - The current spread.
-    /// * `skew` - The skew value.
-    /// * `start` - The start price.
-    /// * `aggression` - The aggression value.
+    /// # Panics
     ///
-    /// # Returns
+    /// If `mult` is not greater than 1.0.
+    pub fn set_requote_threshold(&mut self, mult: f64) {
+        assert!(
+            mult > 1.0,
+            "Requote threshold multiplier must be greater than 1.0"
+        );
+        self.requote_threshold_mult = mult;
+    }
+
+    /// Sets the fraction of each order's size displayed on the book, `None` displays the full
+    /// size.
     ///
-    /// A vector of batch orders.
-    fn positive_skew_orders(
-        &self,
-        half_spread: f64,
-        curr_spread: f64,
-        start: f64,
-        aggression: f64,
-        notional: f64,
-        book: &LocalBook,
-    ) -> Vec<BatchOrder> {
-        // Calculate the best bid and ask prices.
-        let best_bid = start - (half_spread * (1.0 - aggression));
-        let best_ask = best_bid + curr_spread;
+    /// The display size is only sent on venues and order types supporting it, other orders are
+    /// placed with their full size visible.
+    ///
+    /// # Panics
+    ///
+    /// If `fraction` is not within (0, 1].
+    pub fn set_iceberg_display_fraction(&mut self, fraction: Option<f64>) {
+        if let Some(fraction) = fraction {
+            assert!(
+                fraction > 0.0 && fraction <= 1.0,
+                "Iceberg display fraction must be within (0, 1]"
+            );
+        }
+        self.iceberg_display_fraction = fraction;
+    }
 
-        // Calculate the end prices for bid and ask prices.
-        let end = curr_spread * self.final_order_distance;
-        let bid_end = best_bid - end;
-        let ask_end = best_ask + end;
+    /// Sets the largest notional in USD flattened by a single market order, `None` flattens the
+    /// whole position at once.
+    ///
+    /// # Panics
+    ///
+    /// If `notional` is not positive.
+    pub fn set_flatten_slice_notional(&mut self, notional: Option<f64>) {
+        if let Some(notional) = notional {
+            assert!(notional > 0.0, "Flatten slice notional must be positive");
+        }
+        self.flatten_slice_notional = notional;
+    }
 
-        // Generate the bid and ask prices.
-        let bid_prices = geomspace(best_bid, bid_end, self.total_order / 2);
-        let mut ask_prices = geomspace(ask_end, best_ask, self.total_order / 2);
-        ask_prices.reverse();
+    /// Flattens the position with limit orders crossing the book up to `max_slippage_bps` from
+    /// the mid price instead of market orders, `None` flattens with market orders.
+    ///
+    /// A market order is sent once a few limit orders in a row left the position unchanged.
+    ///
+    /// # Panics
+    ///
+    /// If the slippage is not positive.
+    pub fn set_flatten_limit(&mut self, limit: Option<(f64, TimeInForce)>) {
+        if let Some((max_slippage_bps, _)) = limit {
+            assert!(max_slippage_bps > 0.0, "Flatten slippage must be positive");
+        }
+        self.flatten_limit = limit;
+    }
 
-        // Generate the bid sizes.
-        let bid_sizes = if bid_prices.is_empty() {
-            vec![]
-        } else {
-            // Calculate the maximum buy quantity.
-            let max_buy_qty = (self.max_position_usd / 2.0) - self.position;
-            // Calculate the size weights.
-            let size_weights = geometric_weights(0.63, self.total_order / 2, true);
-            // Calculate the sizes.
-            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_buy_qty).collect();
+    /// Counts a limit order sent towards a flat position, starting over whenever the position
+    /// moved since the last one.
+    ///
+    /// Returns `false` once too many limit orders left the position unchanged.
+    fn retry_flatten_limit(&mut self) -> bool {
+        if self.flatten_attempts.0 != self.position {
+            self.flatten_attempts = (self.position, 0);
+        }
+        self.flatten_attempts.1 += 1;
+        self.flatten_attempts.1 <= FLATTEN_LIMIT_ATTEMPTS
+    }
 
-            sizes
+    /// Returns the quantity and side of the next market order flattening the position.
+    ///
+    /// The slice is limited to the configured notional, so a large position is flattened over
+    /// several updates.
+    ///
+    /// # Returns
+    ///
+    /// `(qty, is_buy)`, or `None` when the position is below one lot.
+    fn flatten_slice(&self, book: &LocalBook) -> Option<(f64, bool)> {
+        if book.mid_price <= 0.0 {
+            return None;
+        }
+        let notional = match self.flatten_slice_notional {
+            Some(slice) => self.position.abs().min(slice),
+            None => self.position.abs(),
         };
+        let qty = round_size(notional / book.mid_price, book);
+        (qty > 0.0).then_some((qty, self.position < 0.0))
+    }
 
-        // Generate the ask sizes.
-        let ask_sizes = if ask_prices.is_empty() {
-            vec![]
+    /// Sends one market order slice towards a flat position, or a limit order crossing the book
+    /// when a flatten limit is configured.
+    ///
+    /// The expected slippage of the slice against the mid price is logged before it is sent.
+    /// Call it on every update until it returns `true`. The next slice is only sized once the
+    /// previous order is filled, or `FLATTEN_FILL_TIMEOUT_MS` after it was sent when its fills do
+    /// not arrive, as for an IOC order that expired.
+    ///
+    /// # Returns
+    ///
+    /// `true` once there is nothing left to flatten.
+    pub async fn flatten_position(&mut self, book: &LocalBook, symbol: &str) -> bool {
+        let now = book.last_update;
+        if let Some((order, sent_at)) = &self.flatten_order {
+            if order.remaining > f64::EPSILON
+                && now.saturating_sub(*sent_at) < FLATTEN_FILL_TIMEOUT_MS
+            {
+                return false;
+            }
+        }
+        self.flatten_order = None;
+        let Some((qty, is_buy)) = self.flatten_slice(book) else {
+            return true;
+        };
+        let expected = book.market_impact(qty, is_buy);
+        let slippage = (expected - book.mid_price).abs() / book.mid_price * 10_000.0;
+        println!(
+            "Flattening {} {} of {} at an expected {:.5}, {:.2} bps from mid",
+            if is_buy { "buying" } else { "selling" },
+            qty,
+            symbol,
+            expected,
+            slippage
+        );
+        let limit = self.flatten_limit.filter(|_| self.retry_flatten_limit());
+        let permit = self.request_permit().await;
+        let sent = if let Some((max_slippage_bps, time_in_force)) = limit {
+            self.client
+                .flatten_with_ioc(qty, is_buy, book, max_slippage_bps, time_in_force, symbol)
+                .await
+        } else if is_buy {
+            self.client.market_buy(qty, symbol).await
         } else {
-            // Calculate the maximum sell quantity.
-            let max_sell_qty = (self.max_position_usd / 2.0) + self.position;
-            // Calculate the size weights.
-            let size_weights = geometric_weights(0.37, self.total_order / 2, false);
-            // Calculate the sizes.
-            let mut sizes: Vec<f64> = size_weights.iter().map(|w| w * max_sell_qty).collect();
-
-            sizes.reverse();
-            sizes
+            self.client.market_sell(qty, symbol).await
         };
+        drop(permit);
+        self.record_request(sent.is_ok(), now);
+        if let Ok(order) = sent {
+            self.flatten_order = Some((LiveOrder::new(expected, qty, order.order_id), now));
+        }
+        false
+    }
 
-        // Generate the batch orders.
-        let mut orders = vec![];
-        for (i, bid) in bid_prices.iter().enumerate() {
-            // Create a new batch order with the bid size, price, and quantity.
-            orders.push(BatchOrder::new(
-                round_size(bid_sizes[i] / *bid, book),
-                round_price(book, *bid),
-                1,
-            ));
-            // Create a new batch order with the ask size, price, and quantity.
-            orders.push(BatchOrder::new(
-                round_size(ask_sizes[i] / ask_prices[i], book),
-                round_price(book, ask_prices[i]),
-                -1,
-            ));
+    /// Books an execution of the order flattening the position at its execution price.
+    ///
+    /// # Returns
+    ///
+    /// `false` when the execution belongs to another order.
+    fn apply_flatten_fill(
+        &mut self,
+        side: i32,
+        order_id: &str,
+        exec_id: &str,
+        price: f64,
+        exec_qty: f64,
+        fill_time: u64,
+    ) -> bool {
+        let Some((order, _)) = self
+            .flatten_order
+            .as_mut()
+            .filter(|(o, _)| o.order_id == order_id)
+        else {
+            return false;
+        };
+        if order.exec_ids.iter().any(|id| id == exec_id) {
+            return true;
         }
+        let filled = exec_qty.min(order.remaining);
+        order.remaining -= filled;
+        order.exec_ids.push(exec_id.to_string());
+        self.book_fill(side, price, filled, fill_time);
+        true
+    }
 
-        // filter orders  based on notional
-        orders.retain(|o| (o.0 * o.1) > notional);
+    /// Pulls the grid and flattens the position on every update instead of quoting while
+    /// `enabled`.
+    pub fn set_kill_switch(&mut self, enabled: bool) {
+        self.kill_switch = enabled;
+    }
 
-        orders
+    /// Keeps the generated orders out of the spread by moving the bids above the best bid and
+    /// the asks below the best ask to the touch, or one tick behind it. `None` quotes inside the
+    /// spread.
+    pub fn set_touch_clamp(&mut self, clamp: Option<TouchClamp>) {
+        self.touch_clamp = clamp;
     }
 
-    /// Generate a list of batch orders based on negative skew.
+    /// Improves the best bid or ask by a tick when it holds less than `qty`, and joins it
+    /// otherwise, for the orders generated at or inside the touch.
     ///
-    /// # Arguments
-    ///
-    /// * `half_spread` - The half spread between best ask and best bid prices.
-    /// * `curr_spread` - The current spread between best ask and best bid prices.
-    /// * `skew` - The skew value.
-    /// * `start` - The starting price.
-    /// * `aggression` - The aggression value.
+    /// A touch clamp still applies to the thick side, so `Behind` quotes one tick behind it.
     ///
-    /// # Returns
+    /// # Panics
     ///
-    /// A vector of batch orders.
-    fn negative_skew_orders(
-        &self,
-        half_spread: f64,
-        curr_spread: f64,
-        start: f64,
-        aggression: f64,
-        notional: f64,
-        book: &LocalBook,
-    ) -> Vec<BatchOrder> {
-        // Calculate the best bid and ask prices.
-        let best_ask = start + (half_spread * (1.0 - aggression));
-        let best_bid = best_ask - curr_spread;
+    /// If `qty` is not positive.
+    pub fn set_thin_touch_qty(&mut self, qty: Option<f64>) {
+        if let Some(qty) = qty {
+            assert!(qty > 0.0, "Thin touch quantity must be positive");
+        }
+        self.thin_touch_qty = qty;
+    }
 
-        // Calculate the end prices for bid and ask prices.
-        let end = curr_spread * self.final_order_distance;
-        let bid_end = best_bid - end;
-        let ask_end = best_ask + end;
+    /// Sets the delay in milliseconds after a fill at which its realized spread is measured.
+    ///
+    /// # Panics
+    ///
+    /// If `horizon_ms` is 0.
+    pub fn set_markout_horizon(&mut self, horizon_ms: u64) {
+        assert!(horizon_ms > 0, "Markout horizon must be positive");
+        self.markout_horizon_ms = horizon_ms;
+    }
 
-        // Generate the bid and ask prices.
-        let bid_prices = geomspace(best_bid, bid_end, self.total_order / 2);
-        let mut ask_prices = geomspace(ask_end, best_ask, self.total_order / 2);
-        ask_prices.reverse();
+    /// Sets the price the grid is quoted around and the requote bounds are measured from.
+    ///
+    /// # Panics
+    ///
+    /// If the microprice depth or the TWAP window is 0.
+    pub fn set_anchor(&mut self, anchor: QuoteAnchor) {
+        match anchor {
+            QuoteAnchor::Microprice { depth } => {
+                assert!(depth > 0, "Microprice depth must be positive")
+            }
+            QuoteAnchor::Twap { window_ms } => {
+                assert!(window_ms > 0, "TWAP window must be positive")
+            }
+            QuoteAnchor::Mid => {}
+        }
+        if anchor != self.anchor {
+            self.anchor = anchor;
+            self.anchor_samples.clear();
+        }
+    }
 
-        // Generate the bid sizes.
-        let bid_sizes = if bid_prices.is_empty() {
-            vec![]
-        } else {
-            let max_bid_qty = (self.max_position_usd / 2.0) - self.position;
-            let size_weights = geometric_weights(0.37 ,self.total_order / 2, true);
-            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_bid_qty).collect();
+    /// Returns the price the grid is quoted around.
+    fn anchor_price(&self, book: &LocalBook) -> f64 {
+        match self.anchor {
+            QuoteAnchor::Mid => book.get_mid_price(),
+            QuoteAnchor::Microprice { depth } => book.get_microprice(depth),
+            QuoteAnchor::Twap { .. } => {
+                // Weight each sample by the time until the next one, the last until the book time.
+                let mut weighted = 0.0;
+                let mut total = 0;
+                for (i, &(time, mid)) in self.anchor_samples.iter().enumerate() {
+                    let next = self
+                        .anchor_samples
+                        .get(i + 1)
+                        .map_or(book.last_update, |s| s.0);
+                    let elapsed = next.saturating_sub(time);
+                    weighted += mid * elapsed as f64;
+                    total += elapsed;
+                }
+                if total == 0 {
+                    book.get_mid_price()
+                } else {
+                    weighted / total as f64
+                }
+            }
+        }
+    }
 
-            sizes
+    /// Samples the mid price for the TWAP anchor, dropping the samples older than its window.
+    fn record_anchor_sample(&mut self, book: &LocalBook) {
+        let QuoteAnchor::Twap { window_ms } = self.anchor else {
+            return;
         };
-        // Generate the ask sizes.
-        let ask_sizes = if ask_prices.is_empty() {
-            vec![]
-        } else {
-            let max_sell_qty = (self.max_position_usd / 2.0) + self.position;
-            let size_weights = geometric_weights(0.63, self.total_order / 2, false);
-            let mut sizes: Vec<f64> = size_weights.iter().map(|w| w * max_sell_qty).collect();
-            sizes.reverse();
+        if book.mid_price <= 0.0 {
+            return;
+        }
+        self.anchor_samples
+            .push_back((book.last_update, book.mid_price));
+        while let Some(&(time, _)) = self.anchor_samples.front() {
+            if time + window_ms >= book.last_update {
+                break;
+            }
+            self.anchor_samples.pop_front();
+        }
+    }
+
+    /// Moves the best quote on the side of the recent aggressive trades by up to `bias` of the
+    /// half spread, toward the mid price to lean into the flow or away from it to fade it.
+    ///
+    /// This applies on top of the skew. A bias of 0 disables it.
+    ///
+    /// # Panics
+    ///
+    /// If `bias` is not between 0 and 1.
+    pub fn set_trade_flow_bias(&mut self, bias: f64, mode: TradeFlowMode) {
+        assert!(
+            (0.0..=1.0).contains(&bias),
+            "Trade flow bias must be between 0 and 1"
+        );
+        self.trade_flow_bias = (bias > 0.0).then_some((bias, mode));
+    }
+
+    /// Updates the direction of the recent aggressive trades, from -1 when only sells traded to
+    /// 1 when only buys traded.
+    pub fn set_trade_flow(&mut self, flow: f64) {
+        self.trade_flow = flow.clip(-1.0, 1.0);
+    }
+
+    /// Sets the spread of the book in bps below which no new quotes are placed.
+    ///
+    /// With `cancel` set, the resting orders are also cancelled while the spread is too tight. A
+    /// spread of 0 always quotes.
+    ///
+    /// # Panics
+    ///
+    /// If `spread_bps` is negative.
+    pub fn set_min_quoting_spread(&mut self, spread_bps: f64, cancel: bool) {
+        assert!(
+            spread_bps >= 0.0,
+            "Minimum quoting spread must not be negative"
+        );
+        self.min_quoting_spread_bps = spread_bps;
+        self.cancel_on_tight_spread = cancel;
+    }
+
+    /// Returns `true` if the spread of the book is below the minimum quoting spread.
+    fn spread_too_tight(&self, book: &LocalBook) -> bool {
+        book.get_spread_in_bps() < self.min_quoting_spread_bps
+    }
 
-            sizes
+    /// Sets the spread of the book in bps above which the grid is cancelled and quoting is
+    /// suspended until the spread narrows again, `None` quotes whatever the spread.
+    ///
+    /// # Panics
+    ///
+    /// If `spread_bps` is not positive.
+    pub fn set_max_quoting_spread(&mut self, spread_bps: Option<f64>) {
+        if let Some(spread_bps) = spread_bps {
+            assert!(spread_bps > 0.0, "Maximum quoting spread must be positive");
+        }
+        self.max_quoting_spread_bps = spread_bps;
+    }
+
+    /// Returns `true` if the spread of the book is above the maximum quoting spread, logging
+    /// when quoting is suspended and resumed.
+    fn spread_blown_out(&mut self, book: &LocalBook, symbol: &str) -> bool {
+        let spread = book.get_spread_in_bps();
+        let blown_out = self.max_quoting_spread_bps.is_some_and(|max| spread > max);
+        if blown_out && !self.spread_blown_out {
+            eprintln!(
+                "Spread of {} blew out to {:.2} bps, suspending quoting",
+                symbol, spread
+            );
+        } else if !blown_out && self.spread_blown_out {
+            eprintln!(
+                "Spread of {} is back to {:.2} bps, resuming quoting",
+                symbol, spread
+            );
+        }
+        self.spread_blown_out = blown_out;
+        blown_out
+    }
+
+    /// Sets whether the resting orders are cancelled while the book is crossed.
+    ///
+    /// No new quotes are placed from a crossed book either way.
+    pub fn set_cancel_on_crossed_book(&mut self, cancel: bool) {
+        self.cancel_on_crossed_book = cancel;
+    }
+
+    /// Returns `true` if the best bid of the book is at or above its best ask, logging when
+    /// quoting is suspended and resumed.
+    fn crossed_book(&mut self, book: &LocalBook, symbol: &str) -> bool {
+        let crossed = book.is_crossed();
+        if crossed && !self.book_crossed {
+            eprintln!(
+                "Book of {} is crossed at {} / {}, suspending quoting",
+                symbol, book.best_bid.price, book.best_ask.price
+            );
+        } else if !crossed && self.book_crossed {
+            eprintln!("Book of {} is no longer crossed, resuming quoting", symbol);
+        }
+        self.book_crossed = crossed;
+        crossed
+    }
+
+    /// Aborts the submission of the generated orders once the live mid has moved more than
+    /// `tolerance_bps` from the mid they were generated from, `None` always submits them.
+    ///
+    /// The check needs the live mid from `set_live_mid`, without it the orders are submitted.
+    ///
+    /// # Panics
+    ///
+    /// If `tolerance_bps` is not positive.
+    pub fn set_last_look(&mut self, tolerance_bps: Option<f64>) {
+        if let Some(tolerance_bps) = tolerance_bps {
+            assert!(tolerance_bps > 0.0, "Last look tolerance must be positive");
+        }
+        self.last_look_bps = tolerance_bps;
+    }
+
+    /// Reads the latest mid price of the symbol from `mid` before submitting orders.
+    pub fn set_live_mid(&mut self, mid: watch::Receiver<f64>) {
+        self.live_mid = Some(mid);
+    }
+
+    /// Returns `true` if the live mid has moved beyond the last look tolerance from the mid the
+    /// orders were generated from.
+    fn quotes_stale(&self) -> bool {
+        let (Some(tolerance), Some(live_mid)) = (self.last_look_bps, &self.live_mid) else {
+            return false;
         };
+        let live_mid = *live_mid.borrow();
+        if live_mid <= 0.0 || self.quoted_mid <= 0.0 {
+            return false;
+        }
+        (live_mid - self.quoted_mid).abs() / self.quoted_mid * 10_000.0 > tolerance
+    }
 
-        // Generate the batch orders.
-        let mut orders = vec![];
-        for (i, bid) in bid_prices.iter().enumerate() {
-            // Create a new batch order with the bid size, price, and quantity.
-            orders.push(BatchOrder::new(
-                round_size(bid_sizes[i] / *bid, book),
-                round_price(book, *bid),
-                1,
-            ));
+    /// Sets the dollar volatility targeted per level, `None` sizes the levels from the maximum
+    /// position only.
+    ///
+    /// # Panics
+    ///
+    /// If `risk` is not positive.
+    pub fn set_risk_per_level(&mut self, risk: Option<f64>) {
+        if let Some(risk) = risk {
+            assert!(risk > 0.0, "Risk per level must be positive");
+        }
+        self.risk_per_level_usd = risk;
+    }
 
-            // Create a new batch order with the ask size, price, and quantity.
-            orders.push(BatchOrder::new(
-                round_size(ask_sizes[i] / ask_prices[i], book),
-                round_price(book, ask_prices[i]),
-                -1,
-            ));
+    /// Shares a limit on the order requests in flight with the other generators holding the same
+    /// semaphore.
+    pub fn set_request_limiter(&mut self, limiter: Arc<Semaphore>) {
+        self.request_limiter = Some(limiter);
+    }
+
+    /// Offsets every fill of the grid with a market order sent through `hedge`.
+    pub fn set_hedge(&mut self, hedge: HedgeExecutor) {
+        self.hedge = Some(hedge);
+    }
+
+    /// Logs the orders placed, fills, cancels, reconnects and circuit breaker trips of `symbol`
+    /// as structured events with `logger`.
+    pub fn set_event_logger(&mut self, logger: Logger, symbol: String) {
+        self.event_logger = Some((logger, symbol));
+    }
+
+    /// Logs an order event with its numeric fields, if an event logger is set.
+    fn log_event(&self, level: LogLevel, event: &str, fields: &[(&str, f64)]) {
+        if let Some((logger, symbol)) = &self.event_logger {
+            logger.event(level, symbol, event, fields);
         }
+    }
 
-        // filter orders  based on notional      // filter orders  based on notional
-        orders.retain(|o| (o.0 * o.1) > notional);
+    /// Waits for a permit of the request limiter, if any, before an order request is sent.
+    ///
+    /// The request may be sent once the permit is returned and stays in flight until it is
+    /// dropped.
+    async fn request_permit(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.request_limiter {
+            Some(limiter) => limiter.clone().acquire_owned().await.ok(),
+            None => None,
+        }
+    }
 
-        orders
+    /// Holds the generated orders instead of placing them, so the market maker can place the
+    /// orders of several symbols in shared batches.
+    pub fn set_defer_placement(&mut self, enabled: bool) {
+        self.defer_placement = enabled;
     }
 
-    /// Sends a batch of orders to the exchange asynchronously.
+    /// Places the grid over several updates, `per_side` levels of each side at a time with the
+    /// innermost levels first, instead of placing it at once.
+    ///
+    /// # Panics
+    ///
+    /// If `per_side` is 0.
+    pub fn set_levels_per_tick(&mut self, per_side: Option<usize>) {
+        if let Some(per_side) = per_side {
+            assert!(per_side > 0, "At least one level must be placed per tick");
+        }
+        self.levels_per_tick = per_side;
+        if per_side.is_none() {
+            self.pending_levels.clear();
+        }
+    }
+
+    /// Returns the orders held since the last call.
+    pub fn take_deferred_orders(&mut self) -> Vec<BatchOrder> {
+        std::mem::take(&mut self.deferred_orders)
+    }
+
+    /// Places a batch of orders, possibly of other symbols sharing the account, with the client
+    /// of this generator.
+    pub async fn place_batch(
+        &self,
+        orders: Vec<BatchOrder>,
+    ) -> Result<Vec<VecDeque<LiveOrder>>, OrderError> {
+        let _permit = self.request_permit().await;
+        self.client.batch_place_order(orders).await
+    }
+
+    /// Enables weighting the size of each level by its estimated fill probability.
+    pub fn set_fill_weighting(&mut self, enabled: bool) {
+        self.fill_weighting = enabled;
+    }
+
+    /// Sets the bounds of the aggression.
     ///
     /// # Arguments
     ///
-    /// * `orders` - A vector of `BatchOrder` containing the orders to send.
+    /// * `aggression_min` - The lowest aggression used when placing the best quotes.
+    /// * `aggression_max` - The highest aggression used when placing the best quotes.
     ///
-    /// This function sends a batch of orders to the exchange asynchronously. It awaits the response
-    /// from the exchange and then iterates over each response and pushes it to the appropriate
-    /// queue. If the response is successful, it sorts the queues and updates the live orders. If
-    /// there is an error, it prints the error message.
-    async fn send_batch_orders(&mut self, orders: Vec<BatchOrder>) {
-        // Send the batch orders to the exchange and await the response.
-        let count = (orders.len() as f64 / 10.0).ceil() as usize;
-        if orders.len() <= 10 {
-            let order_response = self.client.batch_place_order(orders).await;
-
-            match order_response {
-                // If the response is successful, process the orders.
-                Ok(v) => {
-                    // Push the orders from the first response to the live buys queue.
-                    for order in v[0].clone() {
-                        self.live_buys_orders.push_back(order);
-                    }
-                    // Sort the live buys queue and update it.
-                    let sorted_buys = sort_grid(self.live_buys_orders.clone(), -1);
-                    self.live_buys_orders = sorted_buys;
+    /// # Panics
+    ///
+    /// If any value is outside of [0, 1] or `aggression_min` is above `aggression_max`.
+    pub fn set_aggression(&mut self, aggression_min: f64, aggression_max: f64) {
+        assert!(
+            (0.0..=1.0).contains(&aggression_min) && (0.0..=1.0).contains(&aggression_max),
+            "Aggression bounds must be between 0 and 1"
+        );
+        assert!(
+            aggression_min <= aggression_max,
+            "Aggression minimum must not be above the maximum"
+        );
+        self.aggression_min = aggression_min;
+        self.aggression_max = aggression_max;
+    }
+
+    /// Sets how the size of each side is spread over its levels.
+    ///
+    /// # Panics
+    ///
+    /// If the ratio of a geometric distribution is outside of [0, 1].
+    pub fn set_size_distribution(&mut self, distribution: SizeDistribution) {
+        if let SizeDistribution::Geometric { ratio } = distribution {
+            assert!(
+                (0.0..=1.0).contains(&ratio),
+                "Passive ratio must be between 0 and 1"
+            );
+        }
+        self.size_distribution = distribution;
+    }
+
+    /// Sets the unit the size of the grid is budgeted in.
+    ///
+    /// In `Quantity` mode every level of a side gets its share of the maximum position in coins,
+    /// which is still capped by the leveraged balance and the notional cap.
+    ///
+    /// # Panics
+    ///
+    /// If the maximum position of the `Quantity` mode is not positive.
+    pub fn set_sizing_mode(&mut self, mode: SizingMode) {
+        if let SizingMode::Quantity { max_position } = mode {
+            assert!(max_position > 0.0, "Maximum position must be positive");
+        }
+        self.sizing_mode = mode;
+        self.update_max();
+    }
+
+    /// Prices the maximum position in coins of the `Quantity` mode at `price`.
+    fn price_max_position(&mut self, price: f64) {
+        if let SizingMode::Quantity { max_position } = self.sizing_mode {
+            self.update_max();
+            self.max_position_usd = self.max_position_usd.min(max_position * price);
+        }
+    }
+
+    /// Returns the price every level is converted to a quantity at in `Quantity` mode, `None`
+    /// converts each level at its own price.
+    fn unit_price(&self, start: f64) -> Option<f64> {
+        matches!(self.sizing_mode, SizingMode::Quantity { .. }).then_some(start)
+    }
+
+    /// Sets the minimum spread in ticks, used when it is wider than the bps floor.
+    pub fn set_min_spread_ticks(&mut self, ticks: f64) {
+        self.min_spread_ticks = ticks;
+    }
+
+    /// Sets the maker fee rate as a decimal, negative for a rebate.
+    pub fn set_maker_fee(&mut self, maker_fee: f64) {
+        self.maker_fee = maker_fee;
+    }
+
+    /// Fetches the maker fee rate for the symbol from the exchange.
+    ///
+    /// Falls back to no fee when the fee cannot be fetched.
+    pub async fn fetch_maker_fee(&mut self, symbol: &str) {
+        match self.client.maker_fee(symbol).await {
+            Ok(fee) => self.maker_fee = fee,
+            Err(e) => eprintln!(
+                "Could not fetch the maker fee of {}, assuming no fee: {}",
+                symbol, e
+            ),
+        }
+    }
+
+    /// Returns the smallest spread in bps that is quoted.
+    ///
+    /// This is the configured spread, or the default when none is set, raised to the round-trip
+    /// maker fee plus a margin so that quotes are never inside the break-even spread.
+    fn min_profitable_spread(&self) -> f64 {
+        let configured = if self.minimum_spread == 0.0 {
+            DEFAULT_SPREAD_BPS
+        } else {
+            self.minimum_spread
+        } * self.spread_multiplier();
+        let fee_floor = 2.0 * self.maker_fee * 10000.0 + FEE_MARGIN_BPS;
+        configured.max(fee_floor)
+    }
+
+    /// Sets the expected net edge in bps below which the spread is widened by the shortfall, or
+    /// quoting is suspended when `suspend` is set. `None` quotes whatever the expected edge.
+    pub fn set_min_edge(&mut self, min_edge_bps: Option<f64>, suspend: bool) {
+        self.min_edge_bps = min_edge_bps;
+        self.suspend_on_low_edge = suspend;
+    }
+
+    /// Returns the expected net edge in bps of a round trip at the current spread.
+    ///
+    /// The spread earned by a fill is its realized spread at the markout horizon, which is the
+    /// spread captured less the adverse markout, and the round-trip maker fee is deducted from
+    /// it. The average starts from a prior of fills earning the full quoted spread, so a fresh
+    /// bot quotes until enough fills are measured.
+    pub fn expected_net_edge_bps(&self) -> f64 {
+        let (sum, count) = self.realized_spread_bps;
+        let prior = self.min_profitable_spread() * EDGE_PRIOR_FILLS;
+        let realized = (prior + sum) / (EDGE_PRIOR_FILLS + count as f64);
+        realized - 2.0 * self.maker_fee * 10000.0
+    }
+
+    /// Returns how many bps the expected net edge is below the minimum edge, 0 when it is not.
+    fn edge_shortfall(&self) -> f64 {
+        match self.min_edge_bps {
+            Some(min_edge) => (min_edge - self.expected_net_edge_bps()).max(0.0),
+            None => 0.0,
+        }
+    }
+
+    /// Sets the spread multipliers applied during given hours of the day.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - A list of `(start_hour, end_hour, multiplier)` in UTC. A window covers the
+    ///   hours from `start_hour` up to but excluding `end_hour`, and wraps around midnight when
+    ///   `start_hour` is after `end_hour`. The first matching window is used.
+    ///
+    /// # Panics
+    ///
+    /// If an hour is above 24 or a multiplier is not positive.
+    pub fn set_spread_schedule(&mut self, schedule: Vec<(u32, u32, f64)>) {
+        for &(start, end, multiplier) in &schedule {
+            assert!(
+                start <= 24 && end <= 24,
+                "Schedule hours must be between 0 and 24"
+            );
+            assert!(multiplier > 0.0, "Spread multipliers must be positive");
+        }
+        self.spread_schedule = schedule;
+    }
+
+    /// Sets the curve widening the spread and shrinking the sizes as the drawdown deepens.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_drawdown_usd` - The drawdown in USD from the peak PnL the curve is scaled to, the
+    ///   curve is not applied when `None`.
+    /// * `curve` - A list of `(drawdown_fraction, spread_multiplier, size_multiplier)` breakpoints
+    ///   with increasing fractions of `max_drawdown_usd`. The multipliers are interpolated
+    ///   linearly from 1.0 at no drawdown and the last breakpoint holds beyond it.
+    ///
+    /// # Panics
+    ///
+    /// If `max_drawdown_usd` is not positive, the fractions are not positive and increasing, a
+    /// spread multiplier is below 1.0 or a size multiplier is not between 0.0 and 1.0.
+    pub fn set_drawdown_curve(
+        &mut self,
+        max_drawdown_usd: Option<f64>,
+        curve: Vec<(f64, f64, f64)>,
+    ) {
+        if let Some(max) = max_drawdown_usd {
+            assert!(max > 0.0, "Maximum drawdown must be positive");
+        }
+        let mut previous = 0.0;
+        for &(fraction, spread, size) in &curve {
+            assert!(
+                fraction > previous,
+                "Drawdown fractions must be positive and increasing"
+            );
+            assert!(
+                spread >= 1.0,
+                "Drawdown spread multipliers must be at least 1.0"
+            );
+            assert!(
+                (0.0..=1.0).contains(&size),
+                "Drawdown size multipliers must be between 0.0 and 1.0"
+            );
+            previous = fraction;
+        }
+        self.max_drawdown_usd = max_drawdown_usd;
+        self.drawdown_curve = curve;
+    }
+
+    /// Returns the spread and size multipliers of the drawdown from the peak PnL, marking the
+    /// position to the mid price of the book.
+    fn drawdown_multipliers(&mut self, book: &LocalBook) -> (f64, f64) {
+        let pnl = self.realized_pnl + self.position_qty * (book.mid_price - self.avg_entry_price);
+        self.peak_pnl = self.peak_pnl.max(pnl);
+        let max = match self.max_drawdown_usd {
+            Some(max) if !self.drawdown_curve.is_empty() => max,
+            _ => return (1.0, 1.0),
+        };
+        let drawdown = (self.peak_pnl - pnl) / max;
+
+        let mut previous = (0.0, 1.0, 1.0);
+        for &point in &self.drawdown_curve {
+            if drawdown < point.0 {
+                let t = (drawdown - previous.0) / (point.0 - previous.0);
+                return (
+                    previous.1 + t * (point.1 - previous.1),
+                    previous.2 + t * (point.2 - previous.2),
+                );
+            }
+            previous = point;
+        }
+        (previous.1, previous.2)
+    }
+
+    /// Returns the spread multiplier of the schedule window active at the current UTC hour, or
+    /// 1.0 outside of every window.
+    fn spread_multiplier(&self) -> f64 {
+        let hour = ((self.clock)() / 3_600_000 % 24) as u32;
+        self.spread_schedule
+            .iter()
+            .find(|&&(start, end, _)| {
+                if start <= end {
+                    (start..end).contains(&hour)
+                } else {
+                    hour >= start || hour < end
+                }
+            })
+            .map_or(1.0, |&(_, _, multiplier)| multiplier)
+    }
+
+    /// Sets the number of orders quoted on each side of the grid independently.
+    ///
+    /// # Arguments
+    ///
+    /// * `bids` - The number of buy orders.
+    /// * `asks` - The number of sell orders.
+    pub fn set_orders_per_side(&mut self, bids: usize, asks: usize) {
+        self.orders_per_bid = bids;
+        self.orders_per_ask = asks;
+    }
+
+    /// Sets the layers quoted together as one combined grid.
+    ///
+    /// An empty list falls back to a single grid using the configured orders per side.
+    pub fn set_grid_layers(&mut self, layers: Vec<GridLayer>) {
+        self.grid_layers = layers;
+    }
+
+    /// Sets how long a filled price level is left unquoted before it can be requoted.
+    ///
+    /// A cooldown of 0 disables the suppression.
+    pub fn set_requote_cooldown(&mut self, cooldown_ms: u64) {
+        self.requote_cooldown_ms = cooldown_ms;
+    }
+
+    /// Sets whether a fill of the grid requotes its side on the same update instead of waiting
+    /// for the price to leave the bounds.
+    ///
+    /// The filled levels are still left out of the new side until their cooldown elapses.
+    pub fn set_requote_on_fill(&mut self, requote: bool) {
+        self.requote_on_fill = requote;
+        self.filled_sides = [false; 2];
+    }
+
+    /// Returns the side left with a gap by a fill, bids first.
+    fn filled_side(&self) -> Option<i32> {
+        match self.filled_sides {
+            [true, _] => Some(1),
+            [false, true] => Some(-1),
+            _ => None,
+        }
+    }
+
+    /// Sets how long an order may rest before its side is cancelled and requoted, even when the
+    /// mid price stays within the bounds.
+    ///
+    /// An age of 0 lets the orders rest until the bounds are breached.
+    pub fn set_max_order_age(&mut self, max_age_ms: u64) {
+        self.max_order_age_ms = max_age_ms;
+    }
+
+    /// Keeps the grid resting while its best bid and ask are the best quotes of the book and the
+    /// mid price stays within the bounds, so aged orders are not refreshed and lose no queue
+    /// priority.
+    pub fn set_hold_touch(&mut self, enabled: bool) {
+        self.hold_touch = enabled;
+    }
+
+    /// Returns `true` if the highest live bid is the best bid and the lowest live ask is the
+    /// best ask of the book.
+    fn holding_touch(&self, book: &LocalBook) -> bool {
+        let half_tick = book.tick_size / 2.0;
+        let best_bid = self
+            .live_buys_orders
+            .iter()
+            .map(|o| o.price)
+            .reduce(f64::max);
+        let best_ask = self
+            .live_sells_orders
+            .iter()
+            .map(|o| o.price)
+            .reduce(f64::min);
+        match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => {
+                (bid - book.best_bid.price).abs() <= half_tick
+                    && (ask - book.best_ask.price).abs() <= half_tick
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets the longest random delay before the orders of a requote are placed.
+    ///
+    /// The delay desynchronizes the grids of several symbols or instances reacting to the same
+    /// book event. A jitter of 0 places the orders immediately.
+    ///
+    /// # Panics
+    ///
+    /// If `jitter_ms` is above 500 ms.
+    pub fn set_placement_jitter(&mut self, jitter_ms: u64) {
+        assert!(
+            jitter_ms <= MAX_PLACEMENT_JITTER_MS,
+            "Placement jitter must not exceed 500 ms"
+        );
+        self.placement_jitter_ms = jitter_ms;
+    }
+
+    /// Sets the time waited between cancelling a side of the grid and placing its new orders.
+    ///
+    /// The wait gives the exchange time to take the cancelled orders off the book, so the new
+    /// orders are not rejected as self-trades against them. A delay of 0 replaces at once.
+    ///
+    /// # Panics
+    ///
+    /// If `delay_ms` is above 500 ms.
+    pub fn set_replace_delay(&mut self, delay_ms: u64) {
+        assert!(
+            delay_ms <= MAX_REPLACE_DELAY_MS,
+            "Replace delay must not exceed {} ms",
+            MAX_REPLACE_DELAY_MS
+        );
+        self.replace_delay_ms = delay_ms;
+    }
+
+    /// Sets the gap between book updates after which the market streams are taken to have
+    /// reconnected, matching the timeout they are reconnected after.
+    pub fn set_heartbeat_timeout(&mut self, timeout_ms: u64) {
+        self.heartbeat_timeout_ms = timeout_ms;
+    }
+
+    /// Returns a random delay in milliseconds within the configured placement jitter.
+    fn placement_delay(&self) -> u64 {
+        if self.placement_jitter_ms == 0 {
+            0
+        } else {
+            rand::thread_rng().gen_range(0..=self.placement_jitter_ms)
+        }
+    }
+
+    /// Removes the filled levels whose cooldown has elapsed at `now`.
+    fn prune_recent_fills(&mut self, now: u64) {
+        let cooldown = self.requote_cooldown_ms;
+        self.recent_fills
+            .retain(|(_, time)| time.saturating_add(cooldown) > now);
+    }
+
+    /// Returns the layers to quote, or a single full size layer when none are configured.
+    fn layers(&self) -> Vec<GridLayer> {
+        if self.grid_layers.is_empty() {
+            vec![GridLayer {
+                spread_multiplier: 1.0,
+                orders_per_bid: self.orders_per_bid,
+                orders_per_ask: self.orders_per_ask,
+                size_fraction: 1.0,
+            }]
+        } else {
+            self.grid_layers.clone()
+        }
+    }
+
+    /// Sets how long before and after each funding time quoting is paused.
+    ///
+    /// A window of 0 disables the pause.
+    pub fn set_funding_pause_window(&mut self, window_ms: u64) {
+        self.funding_pause_window_ms = window_ms;
+    }
+
+    /// Updates the upcoming funding time, keeping the previous one to pause after it passed.
+    pub fn set_next_funding_time(&mut self, time: u64) {
+        if time > self.funding_times.0 {
+            self.funding_times = (time, self.funding_times.0);
+        }
+    }
+
+    /// Returns `true` if `now` is within the pause window of the upcoming or previous funding.
+    pub fn in_funding_window(&self, now: u64) -> bool {
+        let window = self.funding_pause_window_ms;
+        let (next, prev) = self.funding_times;
+        window > 0
+            && [next, prev]
+                .iter()
+                .any(|&time| time > 0 && now.abs_diff(time) <= window)
+    }
+
+    /// Returns `true` while order placement is paused after repeated exchange errors.
+    pub fn is_paused(&self, now: u64) -> bool {
+        now < self.paused_until
+    }
+
+    /// Records the outcome of an exchange request for the circuit breaker.
+    ///
+    /// A success resets the consecutive error count. After `MAX_CONSECUTIVE_ERRORS` failures in
+    /// a row, order placement is paused for `ERROR_BACKOFF_MS` milliseconds from `now`.
+    fn record_request(&mut self, success: bool, now: u64) {
+        if success {
+            self.consecutive_errors = 0;
+            return;
+        }
+        self.consecutive_errors += 1;
+        if self.consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+            self.paused_until = now + ERROR_BACKOFF_MS;
+            self.consecutive_errors = 0;
+            eprintln!(
+                "{} consecutive exchange errors, pausing orders for {} ms",
+                MAX_CONSECUTIVE_ERRORS, ERROR_BACKOFF_MS
+            );
+            self.log_event(
+                LogLevel::Warning,
+                "circuit_breaker",
+                &[
+                    ("errors", MAX_CONSECUTIVE_ERRORS as f64),
+                    ("pause_ms", ERROR_BACKOFF_MS as f64),
+                ],
+            );
+        }
+    }
+
+    /// Updates the inventory delta based on the quantity and price.
+    ///
+    /// This function calculates the inventory delta by dividing the amount by the maximum position qty.
+    /// The result is then assigned to the `inventory_delta` field.
+    ///
+    /// # Parameters
+    ///
+    /// * `mid_price`: The mid price of the asset.
+    ///
+    /// # Details
+    ///
+    /// The inventory delta is a measure of the position's exposure to the market. It is calculated
+    /// by dividing the amount multiplied by the mid price by the maximum position qty. The maximum
+    /// position qty is the maximum amount of qty that can be allocated for the trading position,
+    /// after considering the safety margin of 5%.
+    ///
+    /// The result is then assigned to the `inventory_delta` field, which is a measure of the
+    /// position's deviation from the inventory target.
+    pub fn inventory_delta(&mut self) {
+        // Calculate the inventory delta by dividing the price multiplied by the quantity by the
+        // maximum position USD, relative to the target.
+        self.inventory_delta = self.position / self.max_position_usd - self.inventory_target;
+    }
+
+    /// Sets the neutral position the grid works towards as a fraction of the maximum position.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The target between -1 (fully short) and 1 (fully long).
+    ///
+    /// # Panics
+    ///
+    /// If `target` is outside of [-1, 1].
+    pub fn set_inventory_target(&mut self, target: f64) {
+        assert!(
+            (-1.0..=1.0).contains(&target),
+            "Inventory target must be between -1 and 1"
+        );
+        self.inventory_target = target;
+    }
+
+    /// Returns the position in USD relative to the inventory target.
+    fn target_offset(&self) -> f64 {
+        self.position - self.inventory_target * self.max_position_usd
+    }
+
+    /// Adjusts the spread by clipping it to a minimum spread and a maximum spread.
+    ///
+    /// This function calculates the adjusted spread by calling the `get_spread` method on the
+    /// `book` parameter and clipping the result to a minimum spread and a maximum spread.
+    /// 1 bps = 0.01% = 0.0001
+    /// Calculate the preferred spread as a percentage of the start price.
+    ///
+    /// # Parameters
+    ///
+    /// * `preferred_spread`: The preferred spread in bps as a `f64`.
+    /// * `min_spread_ticks`: The minimum spread in ticks as a `f64`.
+    /// * `book`: The order book to get the spread from.
+    ///
+    /// # Returns
+    ///
+    /// The adjusted spread as a `f64`.
+    fn adjusted_spread(preferred_spread: f64, min_spread_ticks: f64, book: &LocalBook) -> f64 {
+        // Calculate the minimum spread by converting the preferred spread to decimal format,
+        // keeping it at least the minimum number of ticks wide for low priced symbols.
+        let min_spread = (bps_to_decimal(preferred_spread) * book.get_mid_price())
+            .max(min_spread_ticks * book.tick_size);
+
+        // Get the spread from the order book and clip it to the minimum spread and a maximum
+        // spread of 3.7 times the minimum spread.
+        book.get_spread().clip(min_spread, min_spread * 3.7)
+    }
+
+    /// Generates quotes based on the given parameters.
+    ///
+    /// # Parameters
+    ///
+    /// * `symbol`: The symbol of the quote.
+    /// * `book`: The order book to get the mid price from.
+    /// * `imbalance`: The imbalance of the order book.
+    /// * `skew`: The skew value.
+    /// * `volatility`: The realized volatility in bps used to scale the sizes.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `BatchOrder` objects representing the generated quotes.
+    ///
+    /// This function generates quotes based on the given parameters. It calculates the adjusted
+    /// spread, half spread, aggression, and generates the orders based on the skew value. It
+    /// also adds the symbol to each order.
+    ///
+    /// NOTES: From Cartea, 2018
+    /// If imbalance is buy heavy use positive skew quotes, for sell heavy use negative skew quotes
+    /// but for liquidations use the opposite, buy = negative skew & sell = positive skew meaning
+    /// sell orders are easily filled in these periods and buy orders also
+    pub(crate) fn generate_quotes(
+        &mut self,
+        symbol: String,
+        book: &LocalBook,
+        imbalance: f64,
+        skew: f64,
+        volatility: f64,
+    ) -> Vec<BatchOrder> {
+        // Orders can not be rounded without the tick and lot sizes of the instrument.
+        if !book.has_instrument_info() {
+            if !self.missing_instrument_logged {
+                eprintln!("No tick or lot size for {}, not quoting it", symbol);
+                self.missing_instrument_logged = true;
+            }
+            return Vec::new();
+        }
+        // A crossed book would give quotes with a negative spread.
+        if book.is_crossed() {
+            return Vec::new();
+        }
+
+        // Get the start price from the order book.
+        let start = self.anchor_price(book);
+        self.price_max_position(start);
+
+        // Keep the volatility for the size computation of the order builders.
+        self.volatility = volatility;
+
+        // Use the configured spread, never below the fee-adjusted break-even spread.
+        let mut preferred_spread = self.min_profitable_spread();
+        // Widen the spread by the shortfall of the expected edge unless quoting is suspended.
+        if !self.suspend_on_low_edge {
+            preferred_spread += self.edge_shortfall();
+        }
+
+        // Calculate the adjusted spread by calling the `adjusted_spread` method.
+        let curr_spread =
+            QuoteGenerator::adjusted_spread(preferred_spread, self.min_spread_ticks, book);
+
+        // Widen the spread and shrink the sizes as the drawdown deepens.
+        let (drawdown_spread, drawdown_size) = self.drawdown_multipliers(book);
+        let curr_spread = curr_spread * drawdown_spread;
+
+        let notional = book.min_notional;
+
+        // Keep the aggression within the configured bounds.
+        let aggression = imbalance
+            .abs()
+            .clip(self.aggression_min, self.aggression_max);
+
+        // Generate the orders of each layer based on the skew value.
+        let mut orders = Vec::new();
+        let mut short = false;
+        for layer in self.layers() {
+            // Widen the spread for the layer and calculate the half spread.
+            let layer_spread = curr_spread * layer.spread_multiplier;
+            let half_spread = layer_spread / 2.0;
+
+            let mut layer_orders = if skew.abs() < self.skew_deadband {
+                // Treat a skew within the deadband as no skew to avoid reshaping the grid.
+                self.neutral_orders(half_spread, layer_spread, start, notional, book, &layer)
+            } else if skew >= 0.0 {
+                self.positive_skew_orders(
+                    half_spread,
+                    layer_spread,
+                    start,
+                    aggression,
+                    notional,
+                    book,
+                    &layer,
+                )
+            } else {
+                self.negative_skew_orders(
+                    half_spread,
+                    layer_spread,
+                    start,
+                    aggression,
+                    notional,
+                    book,
+                    &layer,
+                )
+            };
+
+            // Note when levels were dropped below the minimum notional.
+            let (bids, asks) = self.expected_levels(&layer);
+            let quoted = |side: i32| layer_orders.iter().filter(|o| o.3 == side).count();
+            short |= quoted(1) < bids || quoted(-1) < asks;
+
+            // Move the side the trades aggress to lean into or fade the flow.
+            if let Some((bias, mode)) = self.trade_flow_bias {
+                flow_biased(
+                    &mut layer_orders,
+                    book,
+                    self.trade_flow,
+                    bias * half_spread,
+                    mode,
+                );
+            }
+            orders.extend(layer_orders);
+        }
+        self.log_short_grid(short, &symbol);
+
+        // Keep the orders out of the spread, the post-only orders inside it would only be rejected.
+        // A thin touch threshold joins the thick touches when no clamp is configured.
+        let clamp = self
+            .touch_clamp
+            .or(self.thin_touch_qty.map(|_| TouchClamp::Join));
+        if let Some(clamp) = clamp {
+            clamp_to_touch(&mut orders, book, clamp, self.thin_touch_qty);
+        }
+
+        // Merge orders from different layers that landed on the same price.
+        let orders = merge_price_collisions(orders);
+
+        // Split orders above the post-only size limit into several orders at the same price.
+        let mut orders = split_post_only(orders, book);
+
+        // Skip the levels within one tick of a fill that is still cooling down.
+        let tick = book.tick_size + f64::EPSILON;
+        orders.retain(|o| {
+            self.recent_fills
+                .iter()
+                .all(|(price, _)| (o.1 - price).abs() > tick)
+        });
+
+        // Shrink the orders to the portfolio budget left once the position is accounted for.
+        if let Some(budget) = self.portfolio_budget {
+            orders = budget_scaled(orders, budget - self.position.abs(), book);
+        }
+        if drawdown_size < 1.0 {
+            orders = size_scaled(orders, drawdown_size, book);
+        }
+
+        // Add the symbol to each order and only display a fraction of its size if configured.
+        for v in orders.iter_mut() {
+            v.2 = symbol.clone();
+            if let Some(fraction) = self.iceberg_display_fraction {
+                v.5 = round_size(v.0 * fraction, book).clamp(book.lot_size, v.0);
+            }
+        }
+
+        // Tag each order with a client order id carrying its level on its side.
+        let now = (self.clock)();
+        for side in [1, -1] {
+            let mut levels: Vec<f64> = orders.iter().filter(|o| o.3 == side).map(|o| o.1).collect();
+            levels.sort_by(|a, b| {
+                if side > 0 {
+                    b.total_cmp(a)
+                } else {
+                    a.total_cmp(b)
+                }
+            });
+            levels.dedup();
+            for order in orders.iter_mut().filter(|o| o.3 == side) {
+                let level = levels.iter().position(|p| *p == order.1).unwrap_or(0);
+                self.order_seq = (self.order_seq + 1) % 1_000_000;
+                order.4 = client_order_id(&self.order_tag, side, level, now, self.order_seq);
+            }
+        }
+
+        orders
+    }
+
+    /// Generates a list of batch orders for positive skew.
+    ///
+    /// # Arguments
+    ///
+    /// * `half_spread` - The half spread.
+    /// * `curr_spread` - The current spread.
+    /// * `skew` - The skew value.
+    /// * `start` - The start price.
+    /// * `aggression` - The aggression value.
+    /// * `layer` - The grid layer providing the order counts and size fraction.
+    ///
+    /// # Returns
+    ///
+    /// A vector of batch orders.
+    #[allow(clippy::too_many_arguments)]
+    fn positive_skew_orders(
+        &self,
+        half_spread: f64,
+        curr_spread: f64,
+        start: f64,
+        aggression: f64,
+        notional: f64,
+        book: &LocalBook,
+        layer: &GridLayer,
+    ) -> Vec<BatchOrder> {
+        // Calculate the best bid and ask prices.
+        let best_bid = start - (half_spread * (1.0 - aggression));
+        let best_ask = best_bid + curr_spread;
+
+        // Calculate the end prices for bid and ask prices.
+        let end = curr_spread * self.final_order_distance;
+        let bid_end = best_bid - end;
+        let ask_end = best_ask + end;
+
+        // Generate the bid and ask prices.
+        let bid_prices = grid_prices(best_bid, bid_end, layer.orders_per_bid);
+        let mut ask_prices = grid_prices(ask_end, best_ask, layer.orders_per_ask);
+        ask_prices.reverse();
+
+        // Generate the bid sizes.
+        let bid_sizes = if bid_prices.is_empty() || self.inventory_delta >= MAX_INVENTORY_DELTA {
+            vec![]
+        } else {
+            // Calculate the maximum buy quantity.
+            let max_buy_qty =
+                ((self.max_position_usd / 2.0) - self.target_offset()) * layer.size_fraction;
+            // Calculate the size weights.
+            let size_weights = self.size_distribution.weights(layer.orders_per_bid, false);
+            // Calculate the sizes.
+            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_buy_qty).collect();
+
+            self.fill_weighted(self.risk_scaled(sizes), &bid_prices, start)
+        };
+
+        // Generate the ask sizes.
+        let ask_sizes = if ask_prices.is_empty() || self.inventory_delta <= -MAX_INVENTORY_DELTA {
+            vec![]
+        } else {
+            // Calculate the maximum sell quantity.
+            let max_sell_qty =
+                ((self.max_position_usd / 2.0) + self.target_offset()) * layer.size_fraction;
+            // Calculate the size weights.
+            let size_weights = self.size_distribution.weights(layer.orders_per_ask, true);
+            // Calculate the sizes.
+            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_sell_qty).collect();
+
+            self.fill_weighted(self.risk_scaled(sizes), &ask_prices, start)
+        };
+
+        // Generate the batch orders.
+        let orders = interleave_orders(
+            &bid_prices,
+            &bid_sizes,
+            &ask_prices,
+            &ask_sizes,
+            self.unit_price(start),
+            book,
+        );
+
+        // Drop or backfill the levels below the minimum notional.
+        self.fit_grid(orders, notional, layer, book)
+    }
+
+    /// Generates a list of batch orders centered on the start price with the same size shape on
+    /// both sides.
+    ///
+    /// # Arguments
+    ///
+    /// * `half_spread` - The half spread.
+    /// * `curr_spread` - The current spread.
+    /// * `start` - The start price.
+    /// * `notional` - The minimum notional of an order.
+    /// * `book` - The order book.
+    /// * `layer` - The grid layer providing the order counts and size fraction.
+    ///
+    /// # Returns
+    ///
+    /// A vector of batch orders.
+    fn neutral_orders(
+        &self,
+        half_spread: f64,
+        curr_spread: f64,
+        start: f64,
+        notional: f64,
+        book: &LocalBook,
+        layer: &GridLayer,
+    ) -> Vec<BatchOrder> {
+        // Quote the best bid and ask at the same distance from the start price.
+        let best_bid = start - half_spread;
+        let best_ask = start + half_spread;
+
+        // Calculate the end prices for bid and ask prices.
+        let end = curr_spread * self.final_order_distance;
+        let bid_prices = grid_prices(best_bid, best_bid - end, layer.orders_per_bid);
+        let mut ask_prices = grid_prices(best_ask + end, best_ask, layer.orders_per_ask);
+        ask_prices.reverse();
+
+        // Both sides use the passive size ratio, ordered from the best price.
+        let bid_sizes = if bid_prices.is_empty() || self.inventory_delta >= MAX_INVENTORY_DELTA {
+            vec![]
+        } else {
+            let max_buy_qty =
+                ((self.max_position_usd / 2.0) - self.target_offset()) * layer.size_fraction;
+            let size_weights = self.size_distribution.weights(layer.orders_per_bid, true);
+            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_buy_qty).collect();
+
+            self.fill_weighted(self.risk_scaled(sizes), &bid_prices, start)
+        };
+        let ask_sizes = if ask_prices.is_empty() || self.inventory_delta <= -MAX_INVENTORY_DELTA {
+            vec![]
+        } else {
+            let max_sell_qty =
+                ((self.max_position_usd / 2.0) + self.target_offset()) * layer.size_fraction;
+            let size_weights = self.size_distribution.weights(layer.orders_per_ask, true);
+            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_sell_qty).collect();
+
+            self.fill_weighted(self.risk_scaled(sizes), &ask_prices, start)
+        };
+
+        // Generate the batch orders and filter them based on notional.
+        let orders = interleave_orders(
+            &bid_prices,
+            &bid_sizes,
+            &ask_prices,
+            &ask_sizes,
+            self.unit_price(start),
+            book,
+        );
+        // Drop or backfill the levels below the minimum notional.
+        self.fit_grid(orders, notional, layer, book)
+    }
+
+    /// Generate a list of batch orders based on negative skew.
+    ///
+    /// # Arguments
+    ///
+    /// * `half_spread` - The half spread between best ask and best bid prices.
+    /// * `curr_spread` - The current spread between best ask and best bid prices.
+    /// * `skew` - The skew value.
+    /// * `start` - The starting price.
+    /// * `aggression` - The aggression value.
+    /// * `layer` - The grid layer providing the order counts and size fraction.
+    ///
+    /// # Returns
+    ///
+    /// A vector of batch orders.
+    #[allow(clippy::too_many_arguments)]
+    fn negative_skew_orders(
+        &self,
+        half_spread: f64,
+        curr_spread: f64,
+        start: f64,
+        aggression: f64,
+        notional: f64,
+        book: &LocalBook,
+        layer: &GridLayer,
+    ) -> Vec<BatchOrder> {
+        // Calculate the best bid and ask prices.
+        let best_ask = start + (half_spread * (1.0 - aggression));
+        let best_bid = best_ask - curr_spread;
+
+        // Calculate the end prices for bid and ask prices.
+        let end = curr_spread * self.final_order_distance;
+        let bid_end = best_bid - end;
+        let ask_end = best_ask + end;
+
+        // Generate the bid and ask prices.
+        let bid_prices = grid_prices(best_bid, bid_end, layer.orders_per_bid);
+        let mut ask_prices = grid_prices(ask_end, best_ask, layer.orders_per_ask);
+        ask_prices.reverse();
+
+        // Generate the bid sizes.
+        let bid_sizes = if bid_prices.is_empty() || self.inventory_delta >= MAX_INVENTORY_DELTA {
+            vec![]
+        } else {
+            let max_bid_qty =
+                ((self.max_position_usd / 2.0) - self.target_offset()) * layer.size_fraction;
+            let size_weights = self.size_distribution.weights(layer.orders_per_bid, true);
+            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_bid_qty).collect();
+
+            self.fill_weighted(self.risk_scaled(sizes), &bid_prices, start)
+        };
+        // Generate the ask sizes.
+        let ask_sizes = if ask_prices.is_empty() || self.inventory_delta <= -MAX_INVENTORY_DELTA {
+            vec![]
+        } else {
+            let max_sell_qty =
+                ((self.max_position_usd / 2.0) + self.target_offset()) * layer.size_fraction;
+            let size_weights = self.size_distribution.weights(layer.orders_per_ask, false);
+            let sizes: Vec<f64> = size_weights.iter().map(|w| w * max_sell_qty).collect();
+
+            self.fill_weighted(self.risk_scaled(sizes), &ask_prices, start)
+        };
+
+        // Generate the batch orders.
+        let orders = interleave_orders(
+            &bid_prices,
+            &bid_sizes,
+            &ask_prices,
+            &ask_sizes,
+            self.unit_price(start),
+            book,
+        );
+
+        // Drop or backfill the levels below the minimum notional.
+        self.fit_grid(orders, notional, layer, book)
+    }
+
+    /// Sends a batch of orders to the exchange asynchronously.
+    ///
+    /// # Arguments
+    ///
+    /// * `orders` - A vector of `BatchOrder` containing the orders to send.
+    /// * `now` - The current time in milliseconds.
+    ///
+    /// This function sends the orders to the exchange in chunks of 10, the maximum batch size
+    /// accepted by the exchange. It awaits each response and pushes the placed orders to the
+    /// appropriate queue. If a chunk fails, the remaining chunks are still sent unless the
+    /// failure trips the circuit breaker. The remaining chunks are dropped once the mid has
+    /// moved past the last look tolerance, leaving the next update to regenerate them.
+    async fn send_batch_orders(&mut self, orders: Vec<BatchOrder>, now: u64) {
+        for chunk in orders.chunks(MAX_BATCH_ORDERS) {
+            if self.is_paused(now) {
+                break;
+            }
+            if self.quotes_stale() {
+                eprintln!("Mid moved since the quotes were generated, skipping submission");
+                break;
+            }
+            // Send the batch orders to the exchange and await the response.
+            let placed = self.place_batch(chunk.to_vec()).await;
+            self.apply_placement(placed, now);
+        }
+    }
+
+    /// Records the outcome of a batch placement and tracks the placed orders.
+    ///
+    /// # Arguments
+    ///
+    /// * `placed` - The buy queue followed by the sell queue of the placed orders of this
+    ///   symbol, or the error of the request.
+    /// * `now` - The time the orders were placed in milliseconds.
+    pub fn apply_placement(
+        &mut self,
+        placed: Result<Vec<VecDeque<LiveOrder>>, OrderError>,
+        now: u64,
+    ) {
+        match placed {
+            Ok(v) => {
+                self.record_request(true, now);
+                for (index, orders) in v.iter().enumerate() {
+                    let side = if index == 0 { 1.0 } else { -1.0 };
+                    for order in orders {
+                        let distance = self.distance_from_mid(order.price);
+                        self.fill_stats.record_placement(distance);
+                        self.log_event(
+                            LogLevel::Info,
+                            "order_placed",
+                            &[("side", side), ("price", order.price), ("qty", order.qty)],
+                        );
+                    }
+                }
+                self.track_live_orders(v, now);
+            }
+            Err(e) => {
+                eprintln!("Batch order error: {}", e);
+                self.record_request(false, now);
+            }
+        }
+    }
+
+    /// Pushes the orders returned by a successful batch placement to the live order queues.
+    ///
+    /// # Arguments
+    ///
+    /// * `placed` - The buy queue followed by the sell queue, as returned by `batch_place_order`.
+    /// * `now` - The time the orders were placed in milliseconds.
+    fn track_live_orders(&mut self, placed: Vec<VecDeque<LiveOrder>>, now: u64) {
+        let mut placed = placed.into_iter().map(|orders| {
+            orders.into_iter().map(|o| LiveOrder {
+                created_at: now,
+                ..o
+            })
+        });
+        // Push the orders from the first response to the live buys queue.
+        if let Some(buys) = placed.next() {
+            self.live_buys_orders.extend(buys);
+            // Sort the live buys queue and update it.
+            self.live_buys_orders = sort_grid(self.live_buys_orders.clone(), -1);
+        }
+        // Push the orders from the second response to the live sells queue.
+        if let Some(sells) = placed.next() {
+            self.live_sells_orders.extend(sells);
+            // Sort the live sells queue and update it.
+            self.live_sells_orders = sort_grid(self.live_sells_orders.clone(), 1);
+        }
+    }
+
+    fn check_for_fills(&mut self, data: PrivateData) {
+        let fills = match data {
+            PrivateData::Bybit(data) => data.executions,
+            PrivateData::Binance(data) => data.into_fastexec(),
+        };
+
+        for FastExecData {
+            order_id,
+            exec_id,
+            exec_price,
+            exec_qty,
+            side,
+            exec_time,
+            ..
+        } in fills
+        {
+            let exec_qty = exec_qty.parse::<f64>().unwrap_or(0.0);
+            if exec_qty > 0.0 {
+                // Fall back to the last book update when the fill time is missing.
+                let fill_time = exec_time.parse::<u64>().unwrap_or(self.time_limit);
+                let side = if side == "Buy" { 1 } else { -1 };
+                let price = exec_price.parse::<f64>().unwrap_or(self.quoted_mid);
+                if self.apply_flatten_fill(side, &order_id, &exec_id, price, exec_qty, fill_time) {
+                    continue;
+                }
+                self.apply_fill(side, &order_id, exec_id, exec_qty, fill_time);
+            }
+        }
+    }
+
+    /// Applies a possibly partial fill to the matching live order.
+    ///
+    /// The position grows by the executed quantity only. The order stays live with its remaining
+    /// quantity reduced until it is fully filled. Executions that were already applied are
+    /// ignored, since the private data keeps past executions around.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - 1 for a buy fill and -1 for a sell fill.
+    /// * `order_id` - The id of the filled order.
+    /// * `exec_id` - The id of the execution.
+    /// * `exec_qty` - The executed quantity.
+    /// * `fill_time` - The time of the fill in milliseconds.
+    fn apply_fill(
+        &mut self,
+        side: i32,
+        order_id: &str,
+        exec_id: String,
+        exec_qty: f64,
+        fill_time: u64,
+    ) {
+        let orders = if side > 0 {
+            &mut self.live_buys_orders
+        } else {
+            &mut self.live_sells_orders
+        };
+        let Some(i) = orders.iter().position(|o| o.order_id == order_id) else {
+            return;
+        };
+        let order = &mut orders[i];
+        if order.exec_ids.contains(&exec_id) {
+            return;
+        }
+        let first_fill = order.exec_ids.is_empty();
+        let filled = exec_qty.min(order.remaining);
+        order.remaining -= filled;
+        order.exec_ids.push(exec_id);
+        let price = order.price;
+        if order.remaining <= f64::EPSILON {
+            orders.remove(i);
+        }
+
+        if first_fill {
+            self.fill_stats.record_fill(self.distance_from_mid(price));
+        }
+        if self.requote_on_fill {
+            self.filled_sides[if side > 0 { 0 } else { 1 }] = true;
+        }
+        self.book_fill(side, price, filled, fill_time);
+        if let Some(hedge) = &mut self.hedge {
+            hedge.record_fill(side, filled);
+        }
+    }
+
+    /// Adds a filled quantity to the position and the fill statistics.
+    fn book_fill(&mut self, side: i32, price: f64, qty: f64, time: u64) {
+        self.log_event(
+            LogLevel::Info,
+            "fill",
+            &[("side", side as f64), ("price", price), ("qty", qty)],
+        );
+        self.position += side as f64 * price * qty;
+        self.track_entry(side, price, qty);
+        self.record_fill(price, time);
+        self.record_capture(side, price, qty);
+        self.record_rebate(price, qty);
+        self.pending_markouts
+            .push_back((side, price, time + self.markout_horizon_ms));
+    }
+
+    /// Measures the realized spread of the fills whose markout horizon has passed against the
+    /// current mid price.
+    ///
+    /// The realized spread is `2 * side * (price - mid)` with the side of the aggressor, so it is
+    /// positive when the price did not move through the fill.
+    fn resolve_markouts(&mut self, book: &LocalBook) {
+        if book.mid_price <= 0.0 {
+            return;
+        }
+        while let Some(&(side, price, due)) = self.pending_markouts.front() {
+            if due > book.last_update {
+                break;
+            }
+            self.pending_markouts.pop_front();
+            let realized = 2.0 * -side as f64 * (price - book.mid_price);
+            self.realized_spread.0 += realized;
+            self.realized_spread.1 += 1;
+            self.realized_spread_bps.0 += realized / price * 10000.0;
+            self.realized_spread_bps.1 += 1;
+        }
+    }
+
+    /// Adds the maker rebate of a fill when the maker fee is negative.
+    ///
+    /// Every order is post-only, so every fill pays the maker fee.
+    fn record_rebate(&mut self, price: f64, qty: f64) {
+        if self.maker_fee < 0.0 {
+            self.rebates_earned += price * qty * -self.maker_fee;
+        }
+    }
+
+    /// Returns the distance in bps of `price` from the mid price the grid was last quoted at.
+    fn distance_from_mid(&self, price: f64) -> f64 {
+        if self.last_update_price == 0.0 {
+            0.0
+        } else {
+            (price - self.last_update_price).abs() / self.last_update_price * 10000.0
+        }
+    }
+
+    /// Returns the estimated probability that an order `distance_bps` away from the mid price
+    /// gets filled, based on the orders placed and filled so far.
+    pub fn fill_probability(&self, distance_bps: f64) -> f64 {
+        self.fill_stats.fill_probability(distance_bps)
+    }
+
+    /// Scales each size by the fill probability of its level when fill weighting is enabled.
+    ///
+    /// The total size of the side is kept, so capital moves towards the levels that fill.
+    fn fill_weighted(&self, sizes: Vec<f64>, prices: &[f64], mid: f64) -> Vec<f64> {
+        if !self.fill_weighting || sizes.is_empty() {
+            return sizes;
+        }
+        let weighted: Vec<f64> = sizes
+            .iter()
+            .zip(prices)
+            .map(|(size, price)| {
+                let distance = (price - mid).abs() / mid * 10000.0;
+                size * self.fill_probability(distance)
+            })
+            .collect();
+        let total: f64 = weighted.iter().sum();
+        if total <= 0.0 {
+            return sizes;
+        }
+        let scale = sizes.iter().sum::<f64>() / total;
+        weighted.iter().map(|size| size * scale).collect()
+    }
+
+    /// Shrinks the sizes so the average level risks about the configured dollar volatility.
+    ///
+    /// The sizes are scaled by one factor, keeping the shape of the side, and are never scaled
+    /// above the sizes derived from the maximum position.
+    fn risk_scaled(&self, sizes: Vec<f64>) -> Vec<f64> {
+        let Some(risk) = self.risk_per_level_usd else {
+            return sizes;
+        };
+        if sizes.is_empty() || self.volatility <= 0.0 {
+            return sizes;
+        }
+        let mean = sizes.iter().sum::<f64>() / sizes.len() as f64;
+        let level_risk = mean * bps_to_decimal(self.volatility);
+        if level_risk <= 0.0 {
+            return sizes;
+        }
+        let scale = (risk / level_risk).min(1.0);
+        sizes.iter().map(|size| size * scale).collect()
+    }
+
+    /// Records a filled price level so it is not requoted until the cooldown elapses.
+    fn record_fill(&mut self, price: f64, time: u64) {
+        if self.requote_cooldown_ms > 0 {
+            self.recent_fills.push_back((price, time));
+        }
+    }
+
+    /// Matches a fill against the oldest opposing fills and books the captured spread.
+    ///
+    /// The spread of each matched round trip is measured in bps of the opening fill's price,
+    /// net of the maker fee paid on both legs. Any unmatched quantity is kept open until an
+    /// opposing fill arrives.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - 1 for a buy fill and -1 for a sell fill.
+    /// * `price` - The fill price.
+    /// * `qty` - The filled quantity.
+    fn record_capture(&mut self, side: i32, price: f64, qty: f64) {
+        let mut remaining = qty;
+        while remaining > 0.0 {
+            let open = match self.open_fills.front_mut() {
+                Some(open) if open.0 != side => open,
+                _ => break,
+            };
+            let matched = remaining.min(open.2);
+            let (buy, sell) = if side > 0 {
+                (price, open.1)
+            } else {
+                (open.1, price)
+            };
+            let edge = (sell - buy) / open.1 * 10000.0 - 2.0 * self.maker_fee * 10000.0;
+            self.captured_bps += edge * matched;
+            self.captured_qty += matched;
+            open.2 -= matched;
+            remaining -= matched;
+            if open.2 <= f64::EPSILON {
+                self.open_fills.pop_front();
+            }
+        }
+        if remaining > f64::EPSILON {
+            self.open_fills.push_back((side, price, remaining));
+        }
+    }
+
+    /// Updates the average entry price of the position and realizes the PnL of the quantity a
+    /// fill closes.
+    fn track_entry(&mut self, side: i32, price: f64, qty: f64) {
+        let held = self.position_qty;
+        let signed = side as f64 * qty;
+        if held * signed < 0.0 {
+            let closed = qty.min(held.abs());
+            self.realized_pnl += closed * (price - self.avg_entry_price) * held.signum();
+            if qty > held.abs() {
+                // The fill flipped the position, the rest opens at the fill price.
+                self.avg_entry_price = price;
+            } else if closed == held.abs() {
+                self.avg_entry_price = 0.0;
+            }
+        } else {
+            self.avg_entry_price =
+                (self.avg_entry_price * held.abs() + price * qty) / (held.abs() + qty);
+        }
+        self.position_qty = held + signed;
+    }
+
+    /// Returns the PnL in USD realized by the fills that reduced the position.
+    pub fn realized_pnl(&self) -> f64 {
+        self.realized_pnl
+    }
+
+    /// Returns the maker rebates in USD earned since the start, not included in the PnL.
+    pub fn rebates_earned(&self) -> f64 {
+        self.rebates_earned
+    }
+
+    /// Returns the average realized spread in price units of the fills measured so far.
+    pub fn realized_spread(&self) -> f64 {
+        match self.realized_spread {
+            (_, 0) => 0.0,
+            (sum, count) => sum / count as f64,
+        }
+    }
+
+    /// Returns the state kept across restarts.
+    pub fn state(&self) -> SymbolState {
+        SymbolState {
+            position: self.position,
+            position_qty: self.position_qty,
+            avg_entry_price: self.avg_entry_price,
+            realized_pnl: self.realized_pnl,
+            captured_bps: self.captured_bps,
+            captured_qty: self.captured_qty,
+        }
+    }
+
+    /// Resumes from the state saved before a restart.
+    ///
+    /// The position is corrected by the exchange position once it is streamed.
+    pub fn restore(&mut self, state: &SymbolState) {
+        self.position = state.position;
+        self.position_qty = state.position_qty;
+        self.avg_entry_price = state.avg_entry_price;
+        self.realized_pnl = state.realized_pnl;
+        self.captured_bps = state.captured_bps;
+        self.captured_qty = state.captured_qty;
+        self.inventory_delta();
+    }
+
+    /// Accumulates the time since the previous update as quoted when live orders were near the
+    /// touch then.
+    fn record_uptime(&mut self, book: &LocalBook) {
+        let now = book.last_update;
+        if let Some((time, quoted)) = self.last_uptime_sample {
+            let elapsed = now.saturating_sub(time);
+            self.uptime_ms.1 += elapsed;
+            if quoted {
+                self.uptime_ms.0 += elapsed;
+            }
+        }
+        self.last_uptime_sample = Some((now, self.quoting_near_touch(book)));
+    }
+
+    /// Returns whether a live order rests within `UPTIME_DISTANCE_BPS` of the best price of its
+    /// side.
+    fn quoting_near_touch(&self, book: &LocalBook) -> bool {
+        let near = |orders: &VecDeque<LiveOrder>, touch: f64| {
+            touch > 0.0
+                && orders
+                    .iter()
+                    .any(|o| (o.price - touch).abs() / touch * 10000.0 <= UPTIME_DISTANCE_BPS)
+        };
+        near(&self.live_buys_orders, book.best_bid.price)
+            || near(&self.live_sells_orders, book.best_ask.price)
+    }
+
+    /// Returns the fraction of the time since the first update that live orders rested near the
+    /// touch on at least one side.
+    pub fn quote_uptime_fraction(&self) -> f64 {
+        match self.uptime_ms {
+            (_, 0) => 0.0,
+            (quoted, total) => quoted as f64 / total as f64,
+        }
+    }
+
+    /// Returns the average spread captured per matched round trip in bps, weighted by quantity.
+    pub fn avg_captured_bps(&self) -> f64 {
+        if self.captured_qty == 0.0 {
+            0.0
+        } else {
+            self.captured_bps / self.captured_qty
+        }
+    }
+
+    /// Checks whether the anchor price moved out of the bounds of the grid and cancels the stale
+    /// side.
+    ///
+    /// The side the anchor price moved towards is stale and is cancelled, while the other side
+    /// keeps its resting orders and their queue positions.
+    ///
+    /// # Returns
+    ///
+    /// The side to requote, 1 for the bids and -1 for the asks, 0 for the whole grid when no
+    /// orders are live, or `None` when the grid is still within bounds.
+    async fn out_of_bounds(&mut self, book: &LocalBook, symbol: String) -> Option<i32> {
+        let (current_bid_bounds, current_ask_bounds) = self.requote_bounds();
+        let anchor = self.anchor_price(book);
+
+        // If there are no live orders, requote the whole grid.
+        if self.live_buys_orders.is_empty() && self.live_sells_orders.is_empty() {
+            self.last_update_price = anchor;
+            self.filled_sides = [false; 2];
+            return Some(0);
+        }
+        if self.last_update_price == 0.0 || self.cancel_limit <= 1 {
+            return None;
+        }
+
+        // An anchor price above the ask bound makes the asks stale and one below the bid bound
+        // makes the bids stale.
+        let side = if anchor > current_ask_bounds {
+            -1
+        } else if anchor < current_bid_bounds {
+            1
+        } else if let Some(side) = self.filled_side() {
+            // Fill the gap a fill left in its side.
+            side
+        } else if self.hold_touch && self.holding_touch(book) {
+            // Requoting the best quotes of the book would only lose their queue priority.
+            return None;
+        } else {
+            // Refresh the orders that rested too long, even within the bounds.
+            self.aged_side(book.last_update)?
+        };
+        if self.cancel_side(side, &symbol, book.last_update).await {
+            let name = if side > 0 { "buy" } else { "sell" };
+            println!("Cancelling {} orders for {}", name, symbol);
+            self.last_update_price = anchor;
+            self.filled_sides[if side > 0 { 0 } else { 1 }] = false;
+            Some(side)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the side holding an order older than the maximum order age, bids first.
+    fn aged_side(&self, now: u64) -> Option<i32> {
+        if self.max_order_age_ms == 0 {
+            return None;
+        }
+        let aged = |orders: &VecDeque<LiveOrder>| {
+            orders.iter().any(|o| {
+                o.created_at > 0 && now.saturating_sub(o.created_at) >= self.max_order_age_ms
+            })
+        };
+        if aged(&self.live_buys_orders) {
+            Some(1)
+        } else if aged(&self.live_sells_orders) {
+            Some(-1)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the (bid, ask) bounds of the mid price beyond which the grid is requoted.
+    fn requote_bounds(&self) -> (f64, f64) {
+        let bounds = self.last_update_price
+            * bps_to_decimal(self.minimum_spread * self.requote_threshold_mult);
+        (
+            self.last_update_price - bounds,
+            self.last_update_price + bounds,
+        )
+    }
+
+    /// Cancels the live orders of one side of the grid in batches.
+    ///
+    /// # Returns
+    ///
+    /// `true` if every order of the side was cancelled.
+    async fn cancel_side(&mut self, side: i32, symbol: &str, now: u64) -> bool {
+        let orders: Vec<LiveOrder> = if side > 0 {
+            self.live_buys_orders.iter().cloned().collect()
+        } else {
+            self.live_sells_orders.iter().cloned().collect()
+        };
+        for chunk in orders.chunks(MAX_BATCH_ORDERS) {
+            let permit = self.request_permit().await;
+            let cancelled = self
+                .client
+                .batch_cancel(chunk.to_vec(), symbol)
+                .await
+                .is_ok();
+            drop(permit);
+            self.record_request(cancelled, now);
+            if !cancelled {
+                return false;
+            }
+            // The cancelled orders are no longer live and must not be taken for fills.
+            let live = if side > 0 {
+                &mut self.live_buys_orders
+            } else {
+                &mut self.live_sells_orders
+            };
+            live.retain(|o| !chunk.contains(o));
+        }
+        self.log_event(
+            LogLevel::Info,
+            "cancel",
+            &[("side", side as f64), ("orders", orders.len() as f64)],
+        );
+        true
+    }
+
+    /// Cancels every live order of the symbol and clears the live queues once cancelled.
+    async fn cancel_grid(&mut self, symbol: &str, now: u64) {
+        self.scheduled_orders = None;
+        if self.live_buys_orders.is_empty() && self.live_sells_orders.is_empty() {
+            return;
+        }
+        let permit = self.request_permit().await;
+        let cancelled = self.client.cancel_all(symbol).await.is_ok();
+        drop(permit);
+        self.record_request(cancelled, now);
+        if cancelled {
+            self.log_event(
+                LogLevel::Info,
+                "cancel",
+                &[
+                    ("side", 0.0),
+                    (
+                        "orders",
+                        (self.live_buys_orders.len() + self.live_sells_orders.len()) as f64,
+                    ),
+                ],
+            );
+            self.live_buys_orders.clear();
+            self.live_sells_orders.clear();
+            self.pending_levels.clear();
+        }
+    }
+
+    /// Cancels every order of the symbol, including those placed outside this generator, before
+    /// it stops quoting.
+    ///
+    /// Returns whether the orders were cancelled.
+    pub async fn stop(&mut self, symbol: &str) -> bool {
+        let permit = self.request_permit().await;
+        let cancelled = self.client.cancel_all(symbol).await.is_ok();
+        drop(permit);
+        if cancelled {
+            self.live_buys_orders.clear();
+            self.live_sells_orders.clear();
+            self.pending_levels.clear();
+            self.scheduled_orders = None;
+        }
+        cancelled
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_backend(&mut self, backend: Box<dyn OrderBackend>) {
+        self.client = OrderManagement(backend);
+    }
+
+    /// Updates the grid of orders with the current wallet data, skew, imbalance,
+    /// order book, symbol, and price fluctuation.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet` - Private data of the wallet.
+    /// * `skew` - Skew of the order book.
+    /// * `imbalance` - Imbalance of the order book.
+    /// * `volatility` - Realized volatility in bps.
+    /// * `book` - Current order book.
+    /// * `symbol` - String representing the symbol.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update_grid(
+        &mut self,
+        private_data: PrivateData,
+        skew: f64,
+        imbalance: f64,
+        volatility: f64,
+        book: LocalBook,
+        symbol: String,
+        rate_limit: u32,
+    ) {
+        // Update the inventory delta.
+        self.inventory_delta();
+
+        if self.time_limit > 1 {
+            let condition = (book.last_update - self.time_limit) > 1000;
+            if condition {
+                self.rate_limit = rate_limit;
+                self.cancel_limit = rate_limit;
+            }
+        }
+
+        // Track the live wallet balance before checking for fills.
+        self.update_wallet(&private_data);
+        let streamed = exchange_position(&private_data, &symbol);
+        self.check_for_fills(private_data);
+        // Correct the fills booked above with the position the exchange reports.
+        self.snap_position(streamed, &symbol);
+        if let Some(hedge) = &mut self.hedge {
+            hedge.flush().await;
+        }
+        self.record_uptime(&book);
+        self.quoted_mid = book.mid_price;
+        self.resolve_markouts(&book);
+        self.record_anchor_sample(&book);
+        // Drop the filled levels whose cooldown has elapsed.
+        self.prune_recent_fills(book.last_update);
+        // A gap in the market data means the streams reconnected and updates may be missed.
+        let gap = book.last_update.saturating_sub(self.last_book_update);
+        let reconnected = gap > self.heartbeat_timeout_ms;
+        if reconnected && self.last_book_update > 0 {
+            self.log_event(LogLevel::Warning, "reconnect", &[("gap_ms", gap as f64)]);
+        }
+        self.last_book_update = book.last_update;
+        // Stop issuing requests while the circuit breaker is tripped.
+        if self.is_paused(book.last_update) {
+            return;
+        }
+        // Pull the grid and unwind the position instead of quoting while the kill switch is on.
+        if self.kill_switch {
+            self.cancel_grid(&symbol, book.last_update).await;
+            self.flatten_position(&book, &symbol).await;
+            return;
+        }
+        // Pull the grid and stay out of the book while the funding settles.
+        if self.in_funding_window(book.last_update) {
+            self.cancel_grid(&symbol, book.last_update).await;
+            return;
+        }
+        // Rebuild the live orders from the exchange periodically and after a reconnect.
+        if reconnected
+            || book.last_update.saturating_sub(self.last_reconcile) >= RECONCILE_INTERVAL_MS
+        {
+            self.reconcile(&symbol).await;
+            self.last_reconcile = book.last_update;
+        }
+        // A crossed book is a feed glitch, so wait for a sane book before quoting from it.
+        if self.crossed_book(&book, &symbol) {
+            if self.cancel_on_crossed_book {
+                self.cancel_grid(&symbol, book.last_update).await;
+            }
+            return;
+        }
+        // Quoting inside a spread tighter than the edge only adds adverse selection.
+        if self.spread_too_tight(&book) {
+            if self.cancel_on_tight_spread {
+                self.cancel_grid(&symbol, book.last_update).await;
+            }
+            return;
+        }
+        // A blown out spread means thin liquidity, so stay out of the book until it recovers.
+        if self.spread_blown_out(&book, &symbol) {
+            self.cancel_grid(&symbol, book.last_update).await;
+            return;
+        }
+        // Stay out of the book while the adverse selection eats the expected edge.
+        if self.suspend_on_low_edge && self.edge_shortfall() > 0.0 {
+            self.cancel_grid(&symbol, book.last_update).await;
+            return;
+        }
+        // Fit the orders adopted from the exchange into the grid before requoting it.
+        if self.fit_adopted {
+            let grid = self.generate_quotes(symbol.clone(), &book, imbalance, skew, volatility);
+            self.fit_adopted_orders(&symbol, grid, &book).await;
+            self.time_limit = book.last_update;
+            return;
+        }
+        // Send the orders held back by the placement delay once the book time passes it, and leave
+        // the grid as it is until then.
+        if let Some((due, _)) = self.scheduled_orders {
+            if book.last_update >= due {
+                let (_, orders) = self.scheduled_orders.take().unwrap();
+                self.submit_orders(orders, book.last_update).await;
+                self.time_limit = book.last_update;
+            }
+            return;
+        }
+        // Check if the order book is out of bounds with the given symbol.
+        if let Some(side) = self.out_of_bounds(&book, symbol.clone()).await {
+            // Generate quotes for the grid based on the order book, symbol, imbalance, skew,
+            // and price fluctuation.
+            let mut orders =
+                self.generate_quotes(symbol.clone(), &book, imbalance, skew, volatility);
+            // Only replace the side that was cancelled, once the cancels settled.
+            let mut settle_ms = 0;
+            if side != 0 {
+                orders.retain(|o| o.3 == side);
+                settle_ms = self.replace_delay_ms;
+            }
+
+            // Place the innermost levels now and the rest over the next updates.
+            if let Some(per_side) = self.levels_per_tick {
+                self.pending_levels.retain(|o| side != 0 && o.3 != side);
+                self.pending_levels.extend(orders);
+                orders = self.next_levels(per_side);
+            }
+
+            // Send the generated orders to the book.
+            self.place_orders(orders, book.last_update, settle_ms).await;
+            //Updates the time limit
+            self.time_limit = book.last_update;
+        } else if let Some(per_side) = self.levels_per_tick {
+            // Keep filling in the grid while it is within bounds.
+            if !self.pending_levels.is_empty() {
+                let orders = self.next_levels(per_side);
+                self.place_orders(orders, book.last_update, 0).await;
+                self.time_limit = book.last_update;
+            }
+        }
+
+        // Update the time limit
+    }
+
+    /// Submits the orders unless the rate limit is spent.
+    ///
+    /// The orders are held until a later update `settle_ms` after `now` plus the random placement
+    /// jitter, so the other symbols keep being updated meanwhile.
+    async fn place_orders(&mut self, orders: Vec<BatchOrder>, now: u64, settle_ms: u64) {
+        if self.rate_limit <= 1 {
+            return;
+        }
+        // Wait a random delay so grids placing their own batches in reaction to the same event do
+        // not place at once.
+        let delay = if self.defer_placement {
+            settle_ms
+        } else {
+            settle_ms + self.placement_delay()
+        };
+        if delay > 0 {
+            self.scheduled_orders = Some((now + delay, orders));
+        } else {
+            self.submit_orders(orders, now).await;
+        }
+        self.rate_limit -= 1;
+    }
+
+    /// Sends the orders to the book, or holds them for the market maker when it places the orders
+    /// in shared batches.
+    async fn submit_orders(&mut self, orders: Vec<BatchOrder>, now: u64) {
+        if self.defer_placement {
+            // The market maker places them with the orders of the other symbols.
+            self.deferred_orders.extend(orders);
+        } else {
+            self.send_batch_orders(orders, now).await;
+        }
+    }
+
+    /// Takes up to `per_side` of the innermost pending levels of each side, the highest bids and
+    /// the lowest asks, leaving the others pending.
+    fn next_levels(&mut self, per_side: usize) -> Vec<BatchOrder> {
+        let (mut bids, mut asks): (Vec<BatchOrder>, Vec<BatchOrder>) =
+            self.pending_levels.drain(..).partition(|o| o.3 > 0);
+        bids.sort_by(|a, b| b.1.total_cmp(&a.1));
+        asks.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut next = Vec::new();
+        for levels in [&mut bids, &mut asks] {
+            let count = per_side.min(levels.len());
+            next.extend(levels.drain(..count));
+        }
+        self.pending_levels = bids;
+        self.pending_levels.extend(asks);
+        next
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LiveOrder {
+    pub price: f64,
+    pub qty: f64,
+    pub order_id: String,
+    /// The quantity that has not been filled yet.
+    pub remaining: f64,
+    /// The executions already applied to the order.
+    pub exec_ids: Vec<String>,
+    /// The client order id, empty when the order was not tagged.
+    pub link_id: String,
+    /// The quantity visible on the book, equal to `qty` unless the order is an iceberg.
+    pub display_qty: f64,
+    /// The time in milliseconds the order was placed or adopted, 0 when unknown.
+    pub created_at: u64,
+}
+
+impl LiveOrder {
+    pub fn new(price: f64, qty: f64, order_id: String) -> Self {
+        LiveOrder {
+            price,
+            qty,
+            order_id,
+            remaining: qty,
+            exec_ids: Vec::new(),
+            link_id: String::new(),
+            display_qty: qty,
+            created_at: 0,
+        }
+    }
+
+    pub fn with_link_id(mut self, link_id: String) -> Self {
+        self.link_id = link_id;
+        self
+    }
+
+    pub fn with_display_qty(mut self, display_qty: f64) -> Self {
+        self.display_qty = display_qty;
+        self
+    }
+}
+
+impl PartialEq for LiveOrder {
+    fn eq(&self, other: &Self) -> bool {
+        self.order_id == other.order_id
+    }
+}
+
+impl PartialOrd for LiveOrder {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.price.partial_cmp(&other.price)
+    }
+}
+
+fn bps_to_decimal(bps: f64) -> f64 {
+    bps / 10000.0
+}
+
+fn _bps_offset(book: &LocalBook, bps: f64) -> f64 {
+    book.mid_price + (book.mid_price * bps_to_decimal(bps))
+}
+
+fn _offset(book: &LocalBook, offset: f64) -> f64 {
+    book.mid_price + (book.mid_price * offset)
+}
+
+/// Rounds the price of an order on `side` to the tick size, away from the mid price.
+fn round_price(book: &LocalBook, price: f64, side: i32) -> f64 {
+    if book.tick_size > 0.0 {
+        round_price_passive(book, price, side)
+    } else {
+        price
+    }
+}
+
+/// Returns the highest buy price or the lowest sell price `max_slippage_bps` away from the mid
+/// price, rounded to a tick towards the mid price.
+pub fn slippage_bound(book: &LocalBook, is_buy: bool, max_slippage_bps: f64) -> f64 {
+    let offset = book.mid_price * max_slippage_bps / 10_000.0;
+    if is_buy {
+        round_price(book, book.mid_price + offset, 1)
+    } else {
+        round_price(book, book.mid_price - offset, -1)
+    }
+}
+
+/// Rounds a bid price down and an ask price up to the tick size of the book, so rounding never
+/// moves an order towards the mid price.
+///
+/// # Arguments
+///
+/// * `side` - 1 for a bid and -1 for an ask.
+pub fn round_price_passive(book: &LocalBook, price: f64, side: i32) -> f64 {
+    // Prices already on a tick can land just off it after the division.
+    let ticks = price / book.tick_size;
+    let ticks = if side > 0 {
+        (ticks + 1e-9).floor()
+    } else {
+        (ticks - 1e-9).ceil()
+    };
+    let decimals = book.tick_size.count_decimal_places();
+    (ticks * book.tick_size).round_to(decimals as u8)
+}
+
+fn round_size(qty: f64, book: &LocalBook) -> f64 {
+    round_step(qty, book.lot_size)
+}
+
+/// Returns the latest positive wallet equity reported in the private data.
+fn wallet_equity(data: &PrivateData) -> Option<f64> {
+    let equity = match data {
+        PrivateData::Bybit(data) => data
+            .wallet
+            .back()
+            .and_then(|wallet| wallet.total_equity.parse::<f64>().ok()),
+        PrivateData::Binance(data) => data
+            .wallet
+            .back()
+            .and_then(|balance| balance.wallet_balance.parse::<f64>().ok()),
+    };
+    equity.filter(|v| *v > 0.0)
+}
+
+/// Returns the latest signed position notional in USD the exchange reports for `symbol`.
+fn exchange_position(data: &PrivateData, symbol: &str) -> Option<f64> {
+    match data {
+        PrivateData::Bybit(data) => {
+            let position = data.positions.iter().rev().find(|p| p.symbol == symbol)?;
+            let value = position.position_value.parse::<f64>().ok()?;
+            Some(if position.side == "Sell" {
+                -value
+            } else {
+                value
+            })
+        }
+        PrivateData::Binance(data) => {
+            let position = data.positions.iter().rev().find(|p| p.symbol == symbol)?;
+            let amount = position.position_amount.parse::<f64>().ok()?;
+            let entry = position.entry_price.parse::<f64>().ok()?;
+            Some(amount * entry)
+        }
+    }
+}
+
+/// Generates `count` geometrically spaced prices between `start` and `end`.
+/// Returns an empty vector when no orders are requested for the side.
+fn grid_prices(start: f64, end: f64, count: usize) -> Vec<f64> {
+    if count == 0 {
+        vec![]
+    } else {
+        geomspace(start, end, count)
+    }
+}
+
+/// Interleaves the bid and ask orders level by level.
+///
+/// Each side is bounds-checked on its own, so the two sides can hold a different number of
+/// levels and a side without sizes produces no orders. The sizes are converted to quantities at
+/// `unit_price` when given, and at the price of their level otherwise.
+fn interleave_orders(
+    bid_prices: &[f64],
+    bid_sizes: &[f64],
+    ask_prices: &[f64],
+    ask_sizes: &[f64],
+    unit_price: Option<f64>,
+    book: &LocalBook,
+) -> Vec<BatchOrder> {
+    let levels = bid_prices.len().max(ask_prices.len());
+    let mut orders = Vec::with_capacity(levels * 2);
+    for i in 0..levels {
+        // Create a new batch order with the bid size, price, and quantity.
+        if let (Some(bid), Some(size)) = (bid_prices.get(i), bid_sizes.get(i)) {
+            orders.push(BatchOrder::new(
+                round_size(size / unit_price.unwrap_or(*bid), book),
+                round_price(book, *bid, 1),
+                1,
+            ));
+        }
+        // Create a new batch order with the ask size, price, and quantity.
+        if let (Some(ask), Some(size)) = (ask_prices.get(i), ask_sizes.get(i)) {
+            orders.push(BatchOrder::new(
+                round_size(size / unit_price.unwrap_or(*ask), book),
+                round_price(book, *ask, -1),
+                -1,
+            ));
+        }
+    }
+    orders
+}
+
+/// Redistributes the notional of each side so every level is worth at least `floor`.
+///
+/// A side worth `n` times the floor keeps its `n` levels closest to the touch. Each kept level
+/// gets the floor plus a share of the rest of the side in proportion to its original notional.
+/// The quantities are rounded to the lot size, up when that keeps a level at the floor, so a
+/// side may grow by a lot per level.
+fn floored_levels(orders: Vec<BatchOrder>, floor: f64, book: &LocalBook) -> Vec<BatchOrder> {
+    let floor_side = |side: i32| {
+        // The orders of each side run from the touch outward.
+        let mut levels: Vec<BatchOrder> = orders.iter().filter(|o| o.3 == side).cloned().collect();
+        let total: f64 = levels.iter().map(|o| o.0 * o.1).sum();
+        let fits = ((total / floor) as usize).min(levels.len());
+        if levels.iter().all(|o| o.0 * o.1 >= floor) {
+            return levels;
+        }
+        levels.truncate(fits);
+        let kept: f64 = levels.iter().map(|o| o.0 * o.1).sum();
+        let spare = total - floor * fits as f64;
+        for order in levels.iter_mut() {
+            let share = if kept > 0.0 {
+                order.0 * order.1 / kept
+            } else {
+                1.0 / fits as f64
+            };
+            let mut qty = round_size((floor + spare * share) / order.1, book);
+            if qty * order.1 < floor {
+                qty += book.lot_size;
+            }
+            order.0 = qty;
+            order.5 = qty;
+        }
+        levels
+    };
+    let bids = floor_side(1);
+    let asks = floor_side(-1);
+
+    let mut floored = Vec::with_capacity(bids.len() + asks.len());
+    for i in 0..bids.len().max(asks.len()) {
+        floored.extend(bids.get(i).cloned());
+        floored.extend(asks.get(i).cloned());
+    }
+    floored
+}
+
+/// Drops the levels at or below the minimum `notional` and keeps at most the number of levels
+/// of `layer` on each side, the interleaving of the sides is kept.
+///
+/// With `backfill` set, the size of a dropped level is carried to the next level away from the
+/// touch, so the levels combine until they clear the notional, and whatever is left past the
+/// last level goes to the outermost quoted level. The size of a side is then conserved as long
+/// as one of its levels is quoted.
+fn fit_levels(
+    orders: Vec<BatchOrder>,
+    notional: f64,
+    layer: &GridLayer,
+    backfill: bool,
+    book: &LocalBook,
+) -> Vec<BatchOrder> {
+    let fit_side = |side: i32, max_levels: usize| {
+        let mut kept: Vec<BatchOrder> = Vec::new();
+        let mut carry = 0.0;
+        // The orders of each side run from the touch outward.
+        for mut order in orders.iter().filter(|o| o.3 == side).cloned() {
+            if carry > 0.0 {
+                order.0 = round_size(order.0 + carry, book);
+                order.5 = order.0;
+                carry = 0.0;
+            }
+            if order.0 * order.1 > notional {
+                kept.push(order);
+            } else if backfill {
+                carry = order.0;
+            }
+        }
+        if let (Some(last), true) = (kept.last_mut(), carry > 0.0) {
+            last.0 = round_size(last.0 + carry, book);
+            last.5 = last.0;
+        }
+        kept.truncate(max_levels);
+        kept
+    };
+    let bids = fit_side(1, layer.orders_per_bid);
+    let asks = fit_side(-1, layer.orders_per_ask);
+
+    let mut fitted = Vec::with_capacity(bids.len() + asks.len());
+    for i in 0..bids.len().max(asks.len()) {
+        fitted.extend(bids.get(i).cloned());
+        fitted.extend(asks.get(i).cloned());
+    }
+    fitted
+}
+
+/// Shifts the orders on the side of the trade flow by `max_shift` scaled by the strength of the
+/// flow, toward the mid price in `Lean` mode and away from it in `Fade` mode.
+///
+/// The bids follow buy flow and the asks follow sell flow, the other side is left in place.
+fn flow_biased(
+    orders: &mut [BatchOrder],
+    book: &LocalBook,
+    flow: f64,
+    max_shift: f64,
+    mode: TradeFlowMode,
+) {
+    let side = if flow > 0.0 { 1 } else { -1 };
+    // Positive shifts move the orders toward the mid price.
+    let shift = match mode {
+        TradeFlowMode::Lean => flow.abs() * max_shift,
+        TradeFlowMode::Fade => -flow.abs() * max_shift,
+    };
+    if shift == 0.0 {
+        return;
+    }
+    for order in orders.iter_mut().filter(|o| o.3 == side) {
+        order.1 = round_price(book, order.1 + side as f64 * shift, side);
+    }
+}
+
+/// Moves the bids above the best bid and the asks below the best ask back to the touch, or one
+/// tick behind it.
+///
+/// A side whose best level holds less than `thin_touch_qty` is improved by a tick instead, as
+/// long as that stays inside the spread, so the orders generated that far get queue priority.
+fn clamp_to_touch(
+    orders: &mut [BatchOrder],
+    book: &LocalBook,
+    clamp: TouchClamp,
+    thin_touch_qty: Option<f64>,
+) {
+    let offset = match clamp {
+        TouchClamp::Join => 0.0,
+        TouchClamp::Behind => book.tick_size,
+    };
+    let thin = |qty: f64| thin_touch_qty.is_some_and(|threshold| qty < threshold);
+    // Improving a side by a tick must leave at least a tick to the other side.
+    let room = book.best_ask.price - book.best_bid.price > 1.5 * book.tick_size;
+    let bid_limit = if thin(book.best_bid.qty) && room {
+        book.best_bid.price + book.tick_size
+    } else {
+        book.best_bid.price - offset
+    };
+    let ask_limit = if thin(book.best_ask.qty) && room {
+        book.best_ask.price - book.tick_size
+    } else {
+        book.best_ask.price + offset
+    };
+    for order in orders.iter_mut() {
+        if order.3 > 0 && book.best_bid.price > 0.0 {
+            order.1 = order.1.min(round_price(book, bid_limit, 1));
+        } else if order.3 < 0 && book.best_ask.price > 0.0 {
+            order.1 = order.1.max(round_price(book, ask_limit, -1));
+        }
+    }
+}
+
+/// Scales the quantities down so the total notional of the orders stays within `available`.
+///
+/// The quantities are rounded down to the lot size and the orders left below the minimum
+/// notional are dropped, so nothing is quoted once the budget is exhausted.
+fn budget_scaled(orders: Vec<BatchOrder>, available: f64, book: &LocalBook) -> Vec<BatchOrder> {
+    let total: f64 = orders.iter().map(|o| o.0 * o.1).sum();
+    if total <= available {
+        return orders;
+    }
+    size_scaled(orders, available.max(0.0) / total, book)
+}
+
+/// Scales the size of every order down by `factor`, dropping the orders left below the lot size
+/// or the minimum notional.
+fn size_scaled(orders: Vec<BatchOrder>, factor: f64, book: &LocalBook) -> Vec<BatchOrder> {
+    orders
+        .into_iter()
+        .filter_map(|mut o| {
+            o.0 = (o.0 * factor / book.lot_size).floor() * book.lot_size;
+            o.5 = o.0;
+            (o.0 > 0.0 && o.0 * o.1 >= book.min_notional).then_some(o)
+        })
+        .collect()
+}
+
+/// Merges orders on the same side and price into one order with the combined quantity.
+///
+/// Layers can round to the same price level, so the sizes are summed to keep a single order
+/// per level in the combined book.
+fn merge_price_collisions(orders: Vec<BatchOrder>) -> Vec<BatchOrder> {
+    let mut merged: Vec<BatchOrder> = Vec::with_capacity(orders.len());
+    for order in orders {
+        match merged.iter_mut().find(|o| o.3 == order.3 && o.1 == order.1) {
+            Some(existing) => {
+                existing.0 += order.0;
+                existing.5 += order.5;
+            }
+            None => merged.push(order),
+        }
+    }
+    merged
+}
+
+/// Splits orders larger than the book's post-only maximum quantity into several orders.
+///
+/// Each level is split into at most `MAX_ORDERS_PER_LEVEL` orders of the maximum size, and any
+/// quantity beyond that is dropped. A remainder below the minimum notional is dropped as well.
+/// Orders are left unchanged when the book has no post-only limit.
+fn split_post_only(orders: Vec<BatchOrder>, book: &LocalBook) -> Vec<BatchOrder> {
+    let max_qty = book.post_only_max;
+    if max_qty <= 0.0 {
+        return orders;
+    }
+    let mut split = Vec::with_capacity(orders.len());
+    for order in orders {
+        let mut remaining = order.0;
+        for _ in 0..MAX_ORDERS_PER_LEVEL {
+            if remaining <= 0.0 {
+                break;
+            }
+            let qty = round_size(remaining.min(max_qty), book);
+            if qty <= 0.0 || qty * order.1 <= book.min_notional {
+                break;
+            }
+            split.push(BatchOrder(
+                qty,
+                order.1,
+                order.2.clone(),
+                order.3,
+                order.4.clone(),
+                qty,
+            ));
+            remaining -= qty;
+        }
+    }
+    split
+}
+
+/// Builds the client order id of an order as `{tag}-{b|s}{level}-{time}-{seq}`.
+///
+/// # Arguments
+///
+/// * `tag` - The order tag of the bot.
+/// * `side` - 1 for a buy order and -1 for a sell order.
+/// * `level` - The level of the order on its side, 0 being closest to the mid price.
+/// * `time` - The time the order was generated at in milliseconds.
+/// * `seq` - A sequence number keeping the ids of one batch unique.
+pub fn client_order_id(tag: &str, side: i32, level: usize, time: u64, seq: u64) -> String {
+    let side = if side > 0 { 'b' } else { 's' };
+    format!("{}-{}{}-{}-{}", tag, side, level, time, seq)
+}
+
+/// Parses a client order id built by `client_order_id` with the given tag.
+///
+/// # Returns
+///
+/// The side and level of the order, or `None` if the id does not carry the tag.
+pub fn parse_client_order_id(id: &str, tag: &str) -> Option<(i32, usize)> {
+    let rest = id.strip_prefix(tag)?.strip_prefix('-')?;
+    let level = rest.split('-').next()?;
+    let side = match level.chars().next()? {
+        'b' => 1,
+        's' => -1,
+        _ => return None,
+    };
+    Some((side, level[1..].parse().ok()?))
+}
+
+/// This function takes a `VecDeque` of `LiveOrder`s and a `side` integer as input.
+/// It sorts the `VecDeque` in ascending order if the `side` is greater than 1.
+/// Otherwise, it sorts the `VecDeque` in descending order.
+/// It then returns a new `VecDeque` with the sorted orders.
+fn sort_grid(orders: VecDeque<LiveOrder>, side: i32) -> VecDeque<LiveOrder> {
+    // Create a new `Vec` by consuming the `VecDeque`
+    let mut vec = Vec::from(orders);
+
+    // Sort the `Vec` either in ascending or descending order based on the `side`
+    if side > 0 {
+        vec.sort_by(|a, b| a.partial_cmp(b).unwrap()); // Sort the Vec in ascending order
+    } else {
+        vec.sort_by(|a, b| b.partial_cmp(a).unwrap()); // Sort the Vec in descending order
+    }
+
+    // Create a new `VecDeque` by consuming the sorted `Vec`
+    let sorted_vecdeque: VecDeque<LiveOrder> = VecDeque::from(vec);
+
+    // Return the sorted `VecDeque`
+    sorted_vecdeque
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::model::EventPosition;
+    use bybit::model::{Ask, Bid, WalletData};
+    use skeleton::exchanges::{
+        ex_binance::{BinanceClient, BinancePrivate},
+        ex_bybit::{BybitClient, BybitPrivate},
+    };
+
+    use super::super::mock_backend::{MockBackend, MockRequest};
+    use super::*;
+
+    fn test_book() -> LocalBook {
+        let mut book = LocalBook::new();
+        book.tick_size = 0.01;
+        book.lot_size = 0.001;
+        book.mid_price = 100.0;
+        book
+    }
+
+    #[test]
+    fn test_realized_pnl_tracks_entry_price() {
+        let mut generator = test_generator(3);
+        generator.book_fill(1, 100.0, 1.0, 0);
+        generator.book_fill(1, 102.0, 1.0, 0);
+        assert_eq!(generator.state().avg_entry_price, 101.0);
+
+        generator.book_fill(-1, 103.0, 1.5, 0);
+        assert_eq!(generator.realized_pnl(), 3.0);
+
+        // Selling through the position closes the rest and opens a short at the fill price.
+        generator.book_fill(-1, 99.0, 1.0, 0);
+        assert_eq!(generator.realized_pnl(), 2.0);
+        assert_eq!(generator.state().avg_entry_price, 99.0);
+        assert_eq!(generator.state().position_qty, -0.5);
+    }
+
+    #[test]
+    fn test_touch_clamp_pulls_orders_to_touch() {
+        // The grid spread is capped at 3.7 times the 25 bps default, inside this wide book.
+        let mut book = test_book();
+        book.best_bid.price = 99.0;
+        book.best_ask.price = 101.0;
+        let touch = |generator: &mut QuoteGenerator| {
+            let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+            let bid = orders
+                .iter()
+                .filter(|o| o.3 > 0)
+                .map(|o| o.1)
+                .fold(0.0, f64::max);
+            let ask = orders
+                .iter()
+                .filter(|o| o.3 < 0)
+                .map(|o| o.1)
+                .fold(f64::MAX, f64::min);
+            (bid, ask)
+        };
+
+        let mut generator = test_generator(3);
+        let (bid, ask) = touch(&mut generator);
+        assert!(bid > 99.0 && ask < 101.0);
+
+        generator.set_touch_clamp(Some(TouchClamp::Join));
+        assert_eq!(touch(&mut generator), (99.0, 101.0));
+
+        generator.set_touch_clamp(Some(TouchClamp::Behind));
+        assert_eq!(touch(&mut generator), (98.99, 101.01));
+    }
+
+    #[test]
+    fn test_thin_touch_improved_by_a_tick() {
+        let mut book = test_book();
+        book.best_bid = Bid {
+            price: 99.0,
+            qty: 0.1,
+        };
+        book.best_ask = Ask {
+            price: 101.0,
+            qty: 5.0,
+        };
+        let touch = |generator: &mut QuoteGenerator, book: &LocalBook| {
+            let orders = generator.generate_quotes("BTCUSDT".to_string(), book, 0.0, 0.0, 0.0);
+            let bid = orders
+                .iter()
+                .filter(|o| o.3 > 0)
+                .map(|o| o.1)
+                .fold(0.0, f64::max);
+            let ask = orders
+                .iter()
+                .filter(|o| o.3 < 0)
+                .map(|o| o.1)
+                .fold(f64::MAX, f64::min);
+            (bid, ask)
+        };
+
+        let mut generator = test_generator(3);
+        generator.set_thin_touch_qty(Some(1.0));
+        // The thin bid is improved, the thick ask is joined.
+        assert_eq!(touch(&mut generator, &book), (99.01, 101.0));
+
+        book.best_bid.qty = 5.0;
+        assert_eq!(touch(&mut generator, &book), (99.0, 101.0));
+
+        // The thick side stays behind the touch with the clamp.
+        book.best_ask.qty = 0.1;
+        generator.set_touch_clamp(Some(TouchClamp::Behind));
+        assert_eq!(touch(&mut generator, &book), (98.99, 100.99));
+    }
+
+    #[test]
+    fn test_microprice_anchor_leans_toward_pressure() {
+        let mut book = test_book();
+        book.load_snapshot(
+            vec![
+                Bid {
+                    price: 99.9,
+                    qty: 9.0,
+                },
+                Bid {
+                    price: 99.8,
+                    qty: 6.0,
+                },
+            ],
+            vec![
+                Ask {
+                    price: 100.1,
+                    qty: 1.0,
+                },
+                Ask {
+                    price: 100.2,
+                    qty: 4.0,
+                },
+            ],
+            1,
+        );
+        // The bids hold 15 against 5 on the asks, the microprice sits at 100.05.
+        assert!((book.get_microprice(2) - 100.05).abs() < 1e-9);
+        let center = |generator: &mut QuoteGenerator| {
+            let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+            let prices: Vec<f64> = orders.iter().map(|o| o.1).collect();
+            prices.iter().sum::<f64>() / prices.len() as f64
+        };
+
+        let mut generator = test_generator(3);
+        let mid_center = center(&mut generator);
+        generator.set_anchor(QuoteAnchor::Microprice { depth: 2 });
+        let micro_center = center(&mut generator);
+        assert!((mid_center - book.mid_price).abs() < 0.02);
+        assert!(micro_center > mid_center + 0.03);
+    }
+
+    #[test]
+    fn test_twap_anchor_averages_mid() {
+        let mut generator = test_generator(3);
+        generator.set_anchor(QuoteAnchor::Twap { window_ms: 1_000 });
+        let mut book = test_book();
+        for (time, mid) in [(0, 90.0), (500, 100.0), (1_000, 110.0), (1_500, 100.0)] {
+            book.last_update = time;
+            book.mid_price = mid;
+            generator.record_anchor_sample(&book);
+        }
+        book.last_update = 2_000;
+        // The 90 sample fell out of the window, the others held for 500ms each.
+        assert!((generator.anchor_price(&book) - (100.0 + 110.0 + 100.0) / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trade_flow_moves_aligned_side() {
+        let book = test_book();
+        let touch = |generator: &mut QuoteGenerator| {
+            let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+            let bid = orders
+                .iter()
+                .filter(|o| o.3 > 0)
+                .map(|o| o.1)
+                .fold(0.0, f64::max);
+            let ask = orders
+                .iter()
+                .filter(|o| o.3 < 0)
+                .map(|o| o.1)
+                .fold(f64::MAX, f64::min);
+            (bid, ask)
+        };
+
+        let mut generator = test_generator(3);
+        // Buy heavy flow moves nothing until a bias is configured.
+        generator.set_trade_flow(0.8);
+        let (bid, ask) = touch(&mut generator);
+
+        // Leaning into the buyers bids closer to the mid price.
+        generator.set_trade_flow_bias(0.5, TradeFlowMode::Lean);
+        let (lean_bid, lean_ask) = touch(&mut generator);
+        assert!(lean_bid > bid && lean_bid < book.mid_price);
+        assert_eq!(lean_ask, ask);
+
+        // Fading the buyers bids further from it.
+        generator.set_trade_flow_bias(0.5, TradeFlowMode::Fade);
+        let (fade_bid, fade_ask) = touch(&mut generator);
+        assert!(fade_bid < bid);
+        assert_eq!(fade_ask, ask);
+    }
+
+    #[test]
+    fn test_quote_uptime_fraction() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        let mut generator = test_generator(3);
+        let mut sample = |generator: &mut QuoteGenerator, time: u64| {
+            book.last_update = time;
+            generator.record_uptime(&book);
+        };
+
+        // Nothing rests until 2000, a bid within 10 bps of the touch rests until 4000.
+        sample(&mut generator, 1000);
+        generator
+            .live_buys_orders
+            .push_back(LiveOrder::new(99.85, 0.1, "b1".to_string()));
+        sample(&mut generator, 2000);
+        generator.live_buys_orders.clear();
+        // An ask far from the touch does not count as quoting.
+        generator
+            .live_sells_orders
+            .push_back(LiveOrder::new(101.0, 0.1, "s1".to_string()));
+        sample(&mut generator, 4000);
+        sample(&mut generator, 5000);
+
+        assert_eq!(generator.quote_uptime_fraction(), 0.5);
+    }
+
+    #[test]
+    fn test_missing_instrument_info_skips_quoting() {
+        let mut generator = test_generator(3);
+        let mut book = test_book();
+        book.tick_size = 0.0;
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(orders.is_empty());
+
+        let mut book = test_book();
+        book.lot_size = 0.0;
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(orders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_old_order_refreshed_within_bounds() {
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_max_order_age(60_000);
+        generator.last_update_price = 100.0;
+        let b1 = LiveOrder {
+            created_at: 1_000,
+            ..LiveOrder::new(99.87, 0.1, "b1".to_string())
+        };
+        rest_live(&mut generator, &mock, 1, b1);
+        let s1 = LiveOrder {
+            created_at: 50_000,
+            ..LiveOrder::new(100.13, 0.1, "s1".to_string())
+        };
+        rest_live(&mut generator, &mock, -1, s1);
+        let mut book = test_book();
+
+        // The mid price has not moved and the orders are still young.
+        book.last_update = 30_000;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            None
+        );
+
+        // Only the bid has outlived the maximum age.
+        book.last_update = 61_000;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            Some(1)
+        );
+        assert_eq!(cancelled_ids(&mock), vec!["b1"]);
+        assert!(generator.live_buys_orders.is_empty());
+        assert_eq!(generator.live_sells_orders.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_touch_held_without_requoting() {
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_spread(25.0);
+        generator.set_max_order_age(60_000);
+        generator.set_hold_touch(true);
+        generator.last_update_price = 100.0;
+        for (side, price, id) in [(1, 99.9, "b1"), (-1, 100.1, "s1")] {
+            let order = LiveOrder {
+                created_at: 1_000,
+                ..LiveOrder::new(price, 0.1, id.to_string())
+            };
+            rest_live(&mut generator, &mock, side, order);
+        }
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+
+        // The orders outlived the maximum age but hold the touch through a small wiggle.
+        book.last_update = 61_000;
+        book.mid_price = 100.02;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            None
+        );
+        assert!(mock.requests().is_empty());
+
+        // Once the bid is outbid the aged orders are refreshed.
+        book.best_bid.price = 99.95;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            Some(1)
+        );
+        assert_eq!(cancelled_ids(&mock), vec!["b1"]);
+    }
+
+    /// Creates a generator trading on a mock exchange.
+    fn mock_generator(orders_per_side: usize) -> (QuoteGenerator, MockBackend) {
+        let mock = MockBackend::new();
+        let mut generator = test_generator(orders_per_side);
+        generator.set_backend(Box::new(mock.clone()));
+        (generator, mock)
+    }
+
+    /// Rests `order` on the mock exchange and tracks it as live on a side of the generator.
+    fn rest_live(generator: &mut QuoteGenerator, mock: &MockBackend, side: i32, order: LiveOrder) {
+        mock.rest("BTCUSDT", side, order.clone());
+        if side > 0 {
+            generator.live_buys_orders.push_back(order);
+        } else {
+            generator.live_sells_orders.push_back(order);
+        }
+    }
+
+    /// Returns the ids of the orders the mock exchange was asked to cancel, oldest first.
+    fn cancelled_ids(mock: &MockBackend) -> Vec<String> {
+        mock.requests()
+            .into_iter()
+            .filter_map(|r| match r {
+                MockRequest::Cancel { order_id } => Some(order_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the kinds of the requests received by the mock exchange, a run of requests of the
+    /// same kind counting once.
+    fn request_kinds(mock: &MockBackend) -> Vec<&'static str> {
+        let mut kinds: Vec<&'static str> = mock
+            .requests()
+            .iter()
+            .map(|r| match r {
+                MockRequest::Place { .. } => "place",
+                MockRequest::Immediate { .. } => "immediate",
+                MockRequest::Market { .. } => "market",
+                MockRequest::Amend { .. } => "amend",
+                MockRequest::Cancel { .. } => "cancel",
+                MockRequest::CancelAll { .. } => "cancel all",
+            })
+            .collect();
+        kinds.dedup();
+        kinds
+    }
+
+    /// Creates a generator trading on a mock exchange and places its grid on `book`.
+    async fn mock_grid(book: &LocalBook) -> (QuoteGenerator, MockBackend) {
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_spread(25.0);
+        update_with_book(&mut generator, book.clone()).await;
+        (generator, mock)
+    }
+
+    fn mock_book() -> LocalBook {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 1;
+        book
+    }
+
+    #[tokio::test]
+    async fn test_update_grid_on_mock_exchange() {
+        let (generator, mock) = mock_grid(&mock_book()).await;
+
+        // Every order of the grid rests on the exchange and is tracked as live.
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 6);
+        assert!(requests
+            .iter()
+            .all(|r| matches!(r, MockRequest::Place { symbol, .. } if symbol == "BTCUSDT")));
+        let ids = |orders: Vec<LiveOrder>| {
+            let mut ids: Vec<String> = orders.into_iter().map(|o| o.order_id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(
+            ids(mock.resting(1)),
+            ids(generator.live_buys_orders.iter().cloned().collect())
+        );
+        assert_eq!(
+            ids(mock.resting(-1)),
+            ids(generator.live_sells_orders.iter().cloned().collect())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_out_of_bounds_on_mock_exchange() {
+        let mut book = mock_book();
+        let (mut generator, mock) = mock_grid(&book).await;
+        let asks: Vec<String> = mock.resting(-1).into_iter().map(|o| o.order_id).collect();
+
+        // A move within the bounds leaves the grid resting.
+        book.mid_price = 100.1;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            None
+        );
+        assert_eq!(mock.requests().len(), 6);
+
+        // A move above the ask bound cancels the asks only.
+        book.mid_price = 101.0;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            Some(-1)
+        );
+        let cancels: Vec<MockRequest> = asks
+            .into_iter()
+            .map(|order_id| MockRequest::Cancel { order_id })
+            .collect();
+        assert_eq!(mock.requests()[6..], cancels[..]);
+        assert!(mock.resting(-1).is_empty());
+        assert!(generator.live_sells_orders.is_empty());
+        assert_eq!(mock.resting(1).len(), generator.live_buys_orders.len());
+    }
+
+    #[tokio::test]
+    async fn test_fills_from_mock_exchange() {
+        let (mut generator, mock) = mock_grid(&mock_book()).await;
+        let bid = generator
+            .live_buys_orders
+            .iter()
+            .max_by(|a, b| a.price.total_cmp(&b.price))
+            .unwrap()
+            .clone();
+
+        // A partial fill keeps the rest of the order live.
+        let half = bid.qty / 2.0;
+        let partial = mock.fill(&bid.order_id, half, 2);
+        generator.check_for_fills(partial.clone());
+        assert!((generator.position - bid.price * half).abs() < 1e-9);
+        let live = generator
+            .live_buys_orders
+            .iter()
+            .find(|o| o.order_id == bid.order_id)
+            .unwrap();
+        assert!((live.remaining - (bid.qty - half)).abs() < 1e-9);
+
+        // The stream repeating an execution does not book it twice.
+        generator.check_for_fills(partial);
+        assert!((generator.position - bid.price * half).abs() < 1e-9);
+
+        // Filling the rest takes the order off the book and out of the live orders.
+        generator.check_for_fills(mock.fill(&bid.order_id, bid.qty, 3));
+        assert!((generator.position - bid.price * bid.qty).abs() < 1e-9);
+        assert!(generator
+            .live_buys_orders
+            .iter()
+            .all(|o| o.order_id != bid.order_id));
+        assert!(mock.resting(1).iter().all(|o| o.order_id != bid.order_id));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_books_only_confirmed_fills() {
+        let (mut generator, mock) = mock_grid(&mock_book()).await;
+        let bids = mock.resting(1);
+        let asks = mock.resting(-1);
+
+        // One bid vanishes without an execution, one ask fills half while disconnected and
+        // the other bid fills in full after it was partially booked from the stream.
+        mock.drop_order(&bids[0].order_id);
+        let half = asks[0].qty / 2.0;
+        mock.fill(&asks[0].order_id, half, 2);
+        generator.check_for_fills(mock.fill(&bids[1].order_id, half, 2));
+        mock.fill(&bids[1].order_id, bids[1].qty, 3);
+        generator.reconcile("BTCUSDT").await;
+
+        // Only the executions are booked, the dropped bid is treated as cancelled.
+        let expected = bids[1].price * bids[1].qty - asks[0].price * half;
+        assert!((generator.position - expected).abs() < 1e-9);
+        assert!(generator
+            .live_buys_orders
+            .iter()
+            .all(|o| o.order_id != bids[0].order_id && o.order_id != bids[1].order_id));
+        assert_eq!(generator.live_buys_orders.len(), bids.len() - 2);
+        assert_eq!(generator.live_sells_orders.len(), asks.len());
+    }
+
+    #[test]
+    fn test_round_price_passive() {
+        let book = test_book();
+        assert_eq!(round_price_passive(&book, 100.137, 1), 100.13);
+        assert_eq!(round_price_passive(&book, 100.137, -1), 100.14);
+        // Prices on a tick are kept on both sides.
+        assert_eq!(round_price_passive(&book, 100.13, 1), 100.13);
+        assert_eq!(round_price_passive(&book, 100.13, -1), 100.13);
+    }
+
+    /// Runs a grid update on `book` without any private data.
+    async fn update_with_book(generator: &mut QuoteGenerator, book: LocalBook) {
+        generator
+            .update_grid(
+                PrivateData::Bybit(BybitPrivate::default()),
+                0.0,
+                0.0,
+                0.0,
+                book,
+                "BTCUSDT".to_string(),
+                10,
+            )
+            .await;
+    }
+
+    fn test_generator(orders_per_side: usize) -> QuoteGenerator {
+        let client = ExchangeClient::Bybit(BybitClient::default());
+        let mut generator = QuoteGenerator::new(client, 1000.0, 1.0, orders_per_side, 5.0, 10);
+        generator.update_max();
+        generator
+    }
+
+    #[test]
+    fn test_unequal_orders_per_side() {
+        let book = test_book();
+        let mut generator = test_generator(5);
+        generator.set_orders_per_side(3, 5);
+        let layer = generator.layers()[0];
+
+        let orders = generator.positive_skew_orders(0.1, 0.2, 100.0, 0.5, 0.0, &book, &layer);
+        assert_eq!(orders.iter().filter(|o| o.3 > 0).count(), 3);
+        assert_eq!(orders.iter().filter(|o| o.3 < 0).count(), 5);
+
+        let orders = generator.negative_skew_orders(0.1, 0.2, 100.0, 0.5, 0.0, &book, &layer);
+        assert_eq!(orders.iter().filter(|o| o.3 > 0).count(), 3);
+        assert_eq!(orders.iter().filter(|o| o.3 < 0).count(), 5);
+    }
+
+    #[test]
+    fn test_full_inventory_suppresses_bids() {
+        let book = test_book();
+        let mut generator = test_generator(5);
+        generator.inventory_delta = 0.95;
+        let layer = generator.layers()[0];
+
+        let orders = generator.positive_skew_orders(0.1, 0.2, 100.0, 0.5, 0.0, &book, &layer);
+        assert!(!orders.is_empty());
+        assert!(orders.iter().all(|o| o.3 < 0));
+
+        let orders = generator.negative_skew_orders(0.1, 0.2, 100.0, 0.5, 0.0, &book, &layer);
+        assert!(!orders.is_empty());
+        assert!(orders.iter().all(|o| o.3 < 0));
+    }
+
+    #[test]
+    fn test_full_short_inventory_suppresses_asks() {
+        let book = test_book();
+        let mut generator = test_generator(5);
+        generator.inventory_delta = -0.95;
+        let layer = generator.layers()[0];
+
+        let orders = generator.positive_skew_orders(0.1, 0.2, 100.0, 0.5, 0.0, &book, &layer);
+        assert!(!orders.is_empty());
+        assert!(orders.iter().all(|o| o.3 > 0));
+    }
+
+    #[test]
+    fn test_layers_quote_tight_and_wide() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.set_grid_layers(vec![
+            GridLayer::new(1.0, 3, 0.5),
+            GridLayer::new(4.0, 3, 0.5),
+        ]);
+
+        // The adjusted spread defaults to 25 bps, so the inner layer starts 0.125 from mid
+        // and the outer layer 0.5 from mid.
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        let has_price = |side: i32, price: f64| {
+            orders
+                .iter()
+                .any(|o| o.3 == side && (o.1 - price).abs() < 0.011)
+        };
+        assert!(has_price(1, 99.875));
+        assert!(has_price(-1, 100.125));
+        assert!(has_price(1, 99.5));
+        assert!(has_price(-1, 100.5));
+
+        // Every price level appears once per side in the combined book.
+        for (i, a) in orders.iter().enumerate() {
+            assert!(orders[i + 1..].iter().all(|b| a.3 != b.3 || a.1 != b.1));
+        }
+    }
+
+    #[test]
+    fn test_filled_level_skipped_until_cooldown() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.set_requote_cooldown(1000);
+
+        // The best bid of the grid sits at 99.87 with the default 25 bps spread, rounded down.
+        let has_best_bid = |orders: &[BatchOrder]| {
+            orders
+                .iter()
+                .any(|o| o.3 == 1 && (o.1 - 99.87).abs() < 1e-9)
+        };
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(has_best_bid(&orders));
+
+        // Fill a live order at that level.
+        generator
+            .live_buys_orders
+            .push_back(LiveOrder::new(99.87, 0.1, "1".to_string()));
+        let mut private = BybitPrivate::default();
+        private.executions.push_back(FastExecData {
+            category: "linear".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            exec_id: "1".to_string(),
+            exec_price: "99.87".to_string(),
+            exec_qty: "0.1".to_string(),
+            order_id: "1".to_string(),
+            order_link_id: "".to_string(),
+            side: "Buy".to_string(),
+            exec_time: "5000".to_string(),
+            seq: 1,
+        });
+        generator.check_for_fills(PrivateData::Bybit(private));
+
+        generator.prune_recent_fills(5500);
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(!has_best_bid(&orders));
+
+        generator.prune_recent_fills(6000);
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(has_best_bid(&orders));
+    }
+
+    fn wallet_update(total_equity: &str) -> PrivateData {
+        let mut private = BybitPrivate::default();
+        private.wallet.push_back(WalletData {
+            account_im_rate: "0".to_string(),
+            account_mm_rate: "0".to_string(),
+            total_equity: total_equity.to_string(),
+            total_wallet_balance: total_equity.to_string(),
+            total_margin_balance: total_equity.to_string(),
+            total_available_balance: total_equity.to_string(),
+            total_perp_upl: "0".to_string(),
+            total_initial_margin: "0".to_string(),
+            total_maintenance_margin: "0".to_string(),
+            coin: vec![],
+            account_ltv: "0".to_string(),
+            account_type: None,
+        });
+        PrivateData::Bybit(private)
+    }
+
+    #[test]
+    fn test_wallet_update_resizes_max_position() {
+        let mut generator = test_generator(3);
+        assert_eq!(generator.max_position_usd, 950.0);
+
+        generator.update_wallet(&wallet_update("2000"));
+        assert_eq!(generator.max_position_usd, 1900.0);
+
+        // A transient zero balance keeps the last known capacity.
+        generator.update_wallet(&wallet_update("0"));
+        assert_eq!(generator.max_position_usd, 1900.0);
+    }
+
+    fn position_update(symbol: &str, amount: &str, entry_price: &str) -> PrivateData {
+        let mut private = BinancePrivate::default();
+        private.positions.push_back(EventPosition {
+            symbol: symbol.to_string(),
+            position_amount: amount.to_string(),
+            entry_price: entry_price.to_string(),
+            accumulated_realized: "0".to_string(),
+            unrealized_pnl: "0".to_string(),
+            margin_type: "cross".to_string(),
+            isolated_wallet: "0".to_string(),
+            position_side: "BOTH".to_string(),
+        });
+        PrivateData::Binance(private)
+    }
+
+    #[test]
+    fn test_sync_position_snaps_to_exchange() {
+        let mut generator = test_generator(3);
+        generator.position = 100.0;
+
+        // Within 1% of the maximum position the tracked position is kept.
+        generator.sync_position(&position_update("BTCUSDT", "0.0021", "50000"), "BTCUSDT");
+        assert_eq!(generator.position, 100.0);
+
+        // Positions of other symbols are ignored.
+        generator.sync_position(&position_update("ETHUSDT", "-1", "3000"), "BTCUSDT");
+        assert_eq!(generator.position, 100.0);
+
+        // A short position missed locally replaces the tracked position.
+        generator.sync_position(&position_update("BTCUSDT", "-0.004", "50000"), "BTCUSDT");
+        assert_eq!(generator.position, -200.0);
+        assert_eq!(generator.inventory_delta, -200.0 / 950.0);
+
+        // The same exchange position does not undo fills booked since.
+        generator.position = 0.0;
+        generator.sync_position(&position_update("BTCUSDT", "-0.004", "50000"), "BTCUSDT");
+        assert_eq!(generator.position, 0.0);
+    }
+
+    #[test]
+    fn test_consecutive_errors_trip_breaker() {
+        let mut generator = test_generator(3);
+        for _ in 0..MAX_CONSECUTIVE_ERRORS - 1 {
+            generator.record_request(false, 1000);
+        }
+        assert!(!generator.is_paused(1000));
+
+        // A success resets the count, so the next failures start over.
+        generator.record_request(true, 1000);
+        for _ in 0..MAX_CONSECUTIVE_ERRORS - 1 {
+            generator.record_request(false, 1000);
+        }
+        assert!(!generator.is_paused(1000));
+
+        generator.record_request(false, 1000);
+        assert!(generator.is_paused(1000));
+        assert!(generator.is_paused(1000 + ERROR_BACKOFF_MS - 1));
+        assert!(!generator.is_paused(1000 + ERROR_BACKOFF_MS));
+    }
+
+    #[test]
+    fn test_min_profitable_spread_covers_fees() {
+        let mut generator = test_generator(3);
+        generator.set_spread(1.0);
+
+        // A 2 bps maker fee costs 4 bps round trip, plus the margin.
+        generator.set_maker_fee(0.0002);
+        assert!((generator.min_profitable_spread() - 6.0).abs() < 1e-9);
+
+        // A wider configured spread is kept as is.
+        generator.set_spread(10.0);
+        assert!((generator.min_profitable_spread() - 10.0).abs() < 1e-9);
+
+        // A rebate lowers the floor below the configured spread.
+        generator.set_spread(1.0);
+        generator.set_maker_fee(-0.00025);
+        assert!((generator.min_profitable_spread() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rebates_accumulate_on_fills() {
+        let mut generator = test_generator(3);
+        // A fee is paid, not earned.
+        generator.set_maker_fee(0.0002);
+        generator.book_fill(1, 100.0, 2.0, 0);
+        assert_eq!(generator.rebates_earned(), 0.0);
+
+        // A 2.5 bps rebate on 200 then 150 USD of fills.
+        generator.set_maker_fee(-0.00025);
+        generator.book_fill(1, 100.0, 2.0, 0);
+        generator.book_fill(-1, 100.0, 1.5, 0);
+        assert!((generator.rebates_earned() - 0.0875).abs() < 1e-12);
+        assert_eq!(generator.realized_pnl(), 0.0);
+    }
+
+    #[test]
+    fn test_realized_spread_after_markout() {
+        let mut generator = test_generator(3);
+        generator.set_markout_horizon(1_000);
+        let mut book = LocalBook::new();
+        book.mid_price = 100.0;
+
+        // Bought at 99.9 and sold at 100.2, measured a second later.
+        generator.book_fill(1, 99.9, 1.0, 0);
+        generator.book_fill(-1, 100.2, 1.0, 500);
+        book.last_update = 999;
+        generator.resolve_markouts(&book);
+        assert_eq!(generator.realized_spread(), 0.0);
+
+        // The mid price rose to 100.05 once the buy was due, it earned 2 * 0.15.
+        book.mid_price = 100.05;
+        book.last_update = 1_000;
+        generator.resolve_markouts(&book);
+        assert!((generator.realized_spread() - 0.3).abs() < 1e-9);
+
+        // The sell is marked out at 100.3, the price moved through it and it lost 2 * 0.1.
+        book.mid_price = 100.3;
+        book.last_update = 1_500;
+        generator.resolve_markouts(&book);
+        assert!((generator.realized_spread() - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_post_only_max_splits_orders() {
+        let mut book = test_book();
+        book.post_only_max = 0.5;
+        let orders = vec![
+            BatchOrder::new(1.2, 100.0, 1),
+            BatchOrder::new(0.3, 99.0, 1),
+            BatchOrder::new(5.0, 98.0, -1),
+        ];
+
+        let orders = split_post_only(orders, &book);
+        assert!(orders.iter().all(|o| o.0 <= 0.5));
+        let qty_at = |price: f64| -> Vec<f64> {
+            orders
+                .iter()
+                .filter(|o| o.1 == price)
+                .map(|o| o.0)
+                .collect()
+        };
+        assert_eq!(qty_at(100.0), vec![0.5, 0.5, 0.2]);
+        assert_eq!(qty_at(99.0), vec![0.3]);
+        // Levels are clamped once the split budget is used up.
+        assert_eq!(qty_at(98.0), vec![0.5; MAX_ORDERS_PER_LEVEL]);
+    }
+
+    #[tokio::test]
+    async fn test_order_backends_as_trait_objects() {
+        let backends = [
+            OrderManagement::new(ExchangeClient::Bybit(BybitClient::default())),
+            OrderManagement::new(ExchangeClient::Binance(BinanceClient::default())),
+        ];
+        // Requests without valid credentials are rejected by both exchanges.
+        for backend in backends.iter() {
+            assert!(backend.cancel_all("BTCUSDT").await.is_err());
+        }
+        // Binance batch cancels and amends are not supported yet.
+        assert_eq!(
+            backends[1].batch_cancel(vec![], "BTCUSDT").await,
+            Ok(vec![])
+        );
+        assert!(backends[1].batch_amend(vec![], "BTCUSDT").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_limiter_caps_requests_in_flight() {
+        let mock = MockBackend::new();
+        mock.set_latency(5);
+        let limiter = Arc::new(Semaphore::new(2));
+        let mut generators: Vec<QuoteGenerator> = (0..5)
+            .map(|i| {
+                let mut generator = test_generator(3);
+                generator.set_backend(Box::new(mock.clone()));
+                generator.set_request_limiter(limiter.clone());
+                let order = LiveOrder::new(100.2, 0.1, format!("s{}", i));
+                rest_live(&mut generator, &mock, -1, order);
+                generator
+            })
+            .collect();
+
+        let [a, b, c, d, e] = &mut generators[..] else {
+            unreachable!()
+        };
+        let results = tokio::join!(
+            a.cancel_side(-1, "BTCUSDT", 0),
+            b.cancel_side(-1, "BTCUSDT", 0),
+            c.cancel_side(-1, "BTCUSDT", 0),
+            d.cancel_side(-1, "BTCUSDT", 0),
+            e.cancel_side(-1, "BTCUSDT", 0),
+        );
+        assert!(results.0 && results.1 && results.2 && results.3 && results.4);
+        assert_eq!(cancelled_ids(&mock).len(), 5);
+        assert_eq!(mock.max_in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fill_triggers_hedge_order() {
+        let mock = MockBackend::new();
+        let mut generator = test_generator(3);
+        generator.set_hedge(HedgeExecutor::with_backend(
+            Box::new(mock.clone()),
+            "BTCUSDT".to_string(),
+        ));
+        generator
+            .live_buys_orders
+            .push_back(LiveOrder::new(99.8, 0.5, "b1".to_string()));
+        generator
+            .live_sells_orders
+            .push_back(LiveOrder::new(100.2, 0.25, "s1".to_string()));
+
+        // Fills between two flushes are netted into a single market order.
+        generator.apply_fill(1, "b1", "e1".to_string(), 0.5, 1000);
+        generator.apply_fill(-1, "s1", "e2".to_string(), 0.25, 1000);
+        assert_eq!(generator.hedge.as_ref().unwrap().pending(), -0.25);
+        assert!(generator.hedge.as_mut().unwrap().flush().await);
+        let hedge = MockRequest::Market {
+            side: -1,
+            qty: 0.25,
+            symbol: "BTCUSDT".to_string(),
+        };
+        assert_eq!(mock.requests(), vec![hedge]);
+
+        // Nothing is sent once the fills are hedged.
+        assert!(generator.hedge.as_mut().unwrap().flush().await);
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tight_spread_skips_quoting() {
+        let mut book = test_book();
+        book.best_bid.price = 99.99;
+        book.best_ask.price = 100.01;
+        book.last_update = 1;
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_min_quoting_spread(5.0, false);
+
+        // A 2 bps spread is below the 5 bps threshold, so nothing is placed.
+        update_with_book(&mut generator, book.clone()).await;
+        assert!(mock.requests().is_empty());
+
+        // Once the spread widens to 20 bps the grid is quoted.
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 2;
+        update_with_book(&mut generator, book).await;
+        assert_eq!(request_kinds(&mock), vec!["place"]);
+    }
+
+    #[tokio::test]
+    async fn test_blown_out_spread_suspends_quoting() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 1;
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_max_quoting_spread(Some(50.0));
+        update_with_book(&mut generator, book.clone()).await;
+        assert_eq!(request_kinds(&mock), vec!["place"]);
+
+        // A 200 bps spread cancels the grid and nothing is quoted while it lasts.
+        book.best_bid.price = 99.0;
+        book.best_ask.price = 101.0;
+        for time in [2, 3] {
+            book.last_update = time;
+            update_with_book(&mut generator, book.clone()).await;
+        }
+        assert_eq!(request_kinds(&mock), vec!["place", "cancel all"]);
+        assert!(generator.live_buys_orders.is_empty());
+
+        // Quoting resumes once the spread is back to 20 bps.
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 4;
+        update_with_book(&mut generator, book).await;
+        assert_eq!(request_kinds(&mock), vec!["place", "cancel all", "place"]);
+        assert!(!generator.live_buys_orders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_crossed_book_skips_quoting() {
+        let mut book = test_book();
+        book.best_bid.price = 100.2;
+        book.best_ask.price = 100.0;
+        book.last_update = 1;
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_cancel_on_crossed_book(true);
+        assert!(generator
+            .generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0)
+            .is_empty());
+        update_with_book(&mut generator, book.clone()).await;
+        assert!(mock.requests().is_empty());
+
+        // Quoting starts once the book uncrosses.
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 2;
+        update_with_book(&mut generator, book.clone()).await;
+        assert_eq!(request_kinds(&mock), vec!["place"]);
+
+        // A crossed book pulls the grid and nothing is quoted while it lasts.
+        book.best_bid.price = 100.1;
+        for time in [3, 4] {
+            book.last_update = time;
+            update_with_book(&mut generator, book.clone()).await;
+        }
+        assert_eq!(request_kinds(&mock), vec!["place", "cancel all"]);
+        assert!(generator.live_buys_orders.is_empty());
+        assert!(generator.live_sells_orders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_quotes_not_submitted() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 1;
+        let (mut generator, mock) = mock_generator(3);
+        let (mid, live_mid) = watch::channel(0.0);
+        generator.set_live_mid(live_mid);
+        generator.set_last_look(Some(5.0));
+
+        // The mid moved 20 bps after the book the quotes are generated from.
+        mid.send(100.2).unwrap();
+        update_with_book(&mut generator, book.clone()).await;
+        assert!(mock.requests().is_empty());
+        assert!(generator.live_buys_orders.is_empty());
+
+        // The next update regenerates the quotes and submits them within the tolerance.
+        mid.send(100.01).unwrap();
+        book.last_update = 2;
+        update_with_book(&mut generator, book).await;
+        assert_eq!(request_kinds(&mock), vec!["place"]);
+        assert!(!generator.live_buys_orders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_adverse_markouts_widen_or_suspend_quoting() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 1;
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_markout_horizon(1_000);
+        generator.set_min_edge(Some(5.0), false);
+        let quoted_spread = |generator: &mut QuoteGenerator| {
+            let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+            let bid = orders
+                .iter()
+                .filter(|o| o.3 > 0)
+                .map(|o| o.1)
+                .fold(0.0, f64::max);
+            let ask = orders
+                .iter()
+                .filter(|o| o.3 < 0)
+                .map(|o| o.1)
+                .fold(f64::MAX, f64::min);
+            ask - bid
+        };
+
+        // Without any fill the prior expects the full quoted spread.
+        assert_eq!(generator.expected_net_edge_bps(), 25.0);
+        let spread = quoted_spread(&mut generator);
+        update_with_book(&mut generator, book.clone()).await;
+        assert_eq!(request_kinds(&mock), vec!["place"]);
+
+        // Every buy is followed by the mid falling 90 bps below it.
+        let mut markout_book = book.clone();
+        markout_book.mid_price = 99.0;
+        for time in 0..5 {
+            generator.book_fill(1, 99.9, 0.1, time);
+        }
+        markout_book.last_update = 2_000;
+        generator.resolve_markouts(&markout_book);
+        assert!(generator.expected_net_edge_bps() < 5.0);
+        assert!(quoted_spread(&mut generator) > spread);
+
+        // Suspending quoting instead cancels the grid and places nothing.
+        generator.set_min_edge(Some(5.0), true);
+        book.last_update = 3_000;
+        update_with_book(&mut generator, book).await;
+        assert_eq!(request_kinds(&mock), vec!["place", "cancel all"]);
+        assert!(generator.live_buys_orders.is_empty());
+    }
+
+    #[test]
+    fn test_size_distributions() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        let mut generator = test_generator(5);
+        // Returns the notional of the bids ordered from the touch outward.
+        let bid_sizes = |generator: &mut QuoteGenerator| {
+            let mut bids: Vec<BatchOrder> = generator
+                .generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0)
+                .into_iter()
+                .filter(|o| o.3 > 0)
+                .collect();
+            bids.sort_by(|a, b| b.1.total_cmp(&a.1));
+            bids.iter().map(|o| o.0 * o.1).collect::<Vec<f64>>()
+        };
+
+        // The quantities are rounded to the lot size, worth 0.1 USD.
+        generator.set_size_distribution(SizeDistribution::Flat);
+        let flat = bid_sizes(&mut generator);
+        assert_eq!(flat.len(), 5);
+        assert!(flat.iter().all(|&size| (size - flat[0]).abs() < 0.1));
+
+        generator.set_size_distribution(SizeDistribution::Linear);
+        let linear = bid_sizes(&mut generator);
+        assert_eq!(linear.len(), 5);
+        assert!(linear.windows(2).all(|w| w[0] < w[1]));
+        // The same total is spread over the levels.
+        let total = |sizes: &[f64]| sizes.iter().sum::<f64>();
+        assert!((total(&linear) - total(&flat)).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_sizing_modes() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        let mut generator = test_generator(5);
+        generator.set_size_distribution(SizeDistribution::Flat);
+
+        // Each of the 5 levels of a side quotes a fifth of half the 950 USD maximum position.
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert_eq!(orders.len(), 10);
+        assert!(orders.iter().all(|o| (o.0 * o.1 - 95.0).abs() < 0.1));
+        assert!(orders.iter().any(|o| o.0 != orders[0].0));
+
+        // Each level quotes a fifth of half the 4 coins maximum position whatever its price.
+        generator.set_sizing_mode(SizingMode::Quantity { max_position: 4.0 });
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert_eq!(orders.len(), 10);
+        assert!(orders.iter().all(|o| o.0 == 0.4));
+        assert_eq!(generator.max_position_usd, 400.0);
+
+        // The balance still caps a maximum position worth more than it.
+        generator.set_sizing_mode(SizingMode::Quantity { max_position: 20.0 });
+        generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert_eq!(generator.max_position_usd, 950.0);
+    }
+
+    #[test]
+    fn test_levels_below_min_notional_backfilled() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        let mut generator = test_generator(5);
+        generator.set_size_distribution(SizeDistribution::Linear);
+        // Returns the number of levels and the total quantity of each side.
+        let sides = |generator: &mut QuoteGenerator, book: &LocalBook| {
+            let orders = generator.generate_quotes("BTCUSDT".to_string(), book, 0.0, 0.0, 0.0);
+            [1, -1].map(|side| {
+                let side: Vec<&BatchOrder> = orders.iter().filter(|o| o.3 == side).collect();
+                (side.len(), side.iter().map(|o| o.0).sum::<f64>())
+            })
+        };
+        let full = sides(&mut generator, &book);
+        assert!(full.iter().all(|(levels, _)| *levels == 5));
+
+        // The levels of about 32 and 63 USD are dropped with their size.
+        book.min_notional = 70.0;
+        let dropped = sides(&mut generator, &book);
+        assert!(generator.short_grid_logged);
+        for ((levels, qty), (_, full_qty)) in dropped.iter().zip(full) {
+            assert_eq!(*levels, 3);
+            assert!(*qty < full_qty);
+        }
+
+        // The first level is carried into the second, which then clears the notional.
+        generator.set_backfill_levels(true);
+        let backfilled = sides(&mut generator, &book);
+        for ((levels, qty), (_, full_qty)) in backfilled.iter().zip(full) {
+            assert_eq!(*levels, 4);
+            assert!((qty - full_qty).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_min_level_notional_quotes_fewer_valid_levels() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.min_notional = 5.0;
+        let mut generator = test_generator(20);
+        // A 100 USD position leaves 50 USD a side, 2.5 USD a level over 20 levels.
+        generator.set_max_notional(Some(100.0));
+        generator.set_size_distribution(SizeDistribution::Flat);
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(orders.is_empty());
+
+        // A 12 USD floor fits 4 levels a side, each worth the floor plus a share of the rest.
+        generator.set_min_level_notional(Some(12.0));
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        for side in [1, -1] {
+            let levels: Vec<&BatchOrder> = orders.iter().filter(|o| o.3 == side).collect();
+            assert_eq!(levels.len(), 4);
+            assert!(levels.iter().all(|o| o.0 * o.1 >= 12.0));
+            let total: f64 = levels.iter().map(|o| o.0 * o.1).sum();
+            // The quantities are rounded to the lot size, worth 0.1 USD.
+            assert!((total - 50.0).abs() < 1.0);
+        }
+        // The levels closest to the touch are kept.
+        let best_bid = orders
+            .iter()
+            .filter(|o| o.3 > 0)
+            .map(|o| o.1)
+            .fold(0.0, f64::max);
+        assert!(best_bid > 99.8);
+    }
+
+    #[test]
+    fn test_drawdown_widens_spread_and_shrinks_sizes() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        let mut generator = test_generator(5);
+        generator.set_drawdown_curve(Some(100.0), vec![(0.5, 2.0, 0.5), (1.0, 4.0, 0.25)]);
+
+        // Returns the distance between the best quotes and the total size quoted.
+        let quote = |generator: &mut QuoteGenerator, pnl: f64| {
+            generator.realized_pnl = pnl;
+            let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+            let bid = orders
+                .iter()
+                .filter(|o| o.3 > 0)
+                .map(|o| o.1)
+                .fold(0.0, f64::max);
+            let ask = orders
+                .iter()
+                .filter(|o| o.3 < 0)
+                .map(|o| o.1)
+                .fold(f64::MAX, f64::min);
+            (ask - bid, orders.iter().map(|o| o.0).sum::<f64>())
+        };
+
+        let (spread, size) = quote(&mut generator, 0.0);
+        let (shallow_spread, shallow_size) = quote(&mut generator, -25.0);
+        let (deep_spread, deep_size) = quote(&mut generator, -80.0);
+        let (max_spread, max_size) = quote(&mut generator, -150.0);
+        assert!(spread < shallow_spread && shallow_spread < deep_spread);
+        assert!(deep_spread < max_spread);
+        assert!(size > shallow_size && shallow_size > deep_size && deep_size > max_size);
+
+        // Recovering the peak quotes the full grid again.
+        assert_eq!(quote(&mut generator, 10.0), (spread, size));
+    }
+
+    #[tokio::test]
+    async fn test_grid_placed_over_several_updates() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        let (mut generator, mock) = mock_generator(10);
+        generator.set_levels_per_tick(Some(4));
+        let grid = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        let bids = grid.iter().filter(|o| o.3 > 0).count();
+        let asks = grid.len() - bids;
+        assert!(bids > 8 && asks > 8);
+        let best_bid = grid
+            .iter()
+            .filter(|o| o.3 > 0)
+            .map(|o| o.1)
+            .fold(0.0, f64::max);
+
+        // Only the innermost 4 levels of each side are placed on the first update.
+        book.last_update = 1;
+        update_with_book(&mut generator, book.clone()).await;
+        assert_eq!(generator.live_buys_orders.len(), 4);
+        assert_eq!(generator.live_sells_orders.len(), 4);
+        assert_eq!(generator.live_buys_orders[0].price, best_bid);
+
+        // The rest of the grid fills in over the next updates.
+        for time in 2..6 {
+            book.last_update = time;
+            update_with_book(&mut generator, book.clone()).await;
+        }
+        assert_eq!(mock.resting(1).len(), bids);
+        assert_eq!(mock.resting(-1).len(), asks);
+        assert_eq!(generator.live_buys_orders.len(), bids);
+        assert_eq!(generator.live_sells_orders.len(), asks);
+    }
+
+    #[tokio::test]
+    async fn test_funding_window_suppresses_quoting() {
+        let mut book = test_book();
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_funding_pause_window(60_000);
+        generator.set_next_funding_time(28_800_000);
+        let b1 = LiveOrder::new(99.8, 0.1, "b1".to_string());
+        rest_live(&mut generator, &mock, 1, b1);
+
+        // Well before the funding, quoting goes on.
+        assert!(!generator.in_funding_window(28_700_000));
+
+        // Inside the window the grid is cancelled and nothing is quoted.
+        book.last_update = 28_750_000;
+        update_with_book(&mut generator, book).await;
+        assert_eq!(request_kinds(&mock), vec!["cancel all"]);
+        assert!(mock.resting(1).is_empty());
+        assert!(generator.live_buys_orders.is_empty());
+        assert!(generator.live_sells_orders.is_empty());
+
+        // The window still holds after the funding once the next funding time is known.
+        generator.set_next_funding_time(57_600_000);
+        assert!(generator.in_funding_window(28_830_000));
+        assert!(!generator.in_funding_window(28_900_000));
+
+        // No window means no pause.
+        generator.set_funding_pause_window(0);
+        assert!(!generator.in_funding_window(28_800_000));
+    }
+
+    #[test]
+    fn test_sizes_shrink_with_volatility() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.asset = 10_000.0;
+        generator.update_max();
+        generator.set_risk_per_level(Some(1.0));
+        let total_qty = |orders: Vec<BatchOrder>| -> f64 { orders.iter().map(|o| o.0).sum() };
+
+        let calm = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.5, 10.0);
+        let volatile = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.5, 40.0);
+        assert_eq!(calm.len(), volatile.len());
+        // Four times the volatility quotes about a quarter of the size.
+        let ratio = total_qty(volatile) / total_qty(calm);
+        assert!((ratio - 0.25).abs() < 0.02, "ratio {}", ratio);
+
+        // Without a risk target the volatility does not change the sizes.
+        generator.set_risk_per_level(None);
+        let calm = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.5, 10.0);
+        let volatile = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.5, 40.0);
+        assert_eq!(total_qty(calm), total_qty(volatile));
+    }
 
-                    // Push the orders from the second response to the live sells queue.
-                    for order in v[1].clone() {
-                        self.live_sells_orders.push_back(order);
-                    }
-                    // Sort the live sells queue and update it.
-                    let sorted_sells = sort_grid(self.live_sells_orders.clone(), 1);
-                    self.live_sells_orders = sorted_sells;
-                }
-                // If there is an error, print the error message.
-                _ => {}
-            }
-        } else {
-            let mut start_index = 0;
-            let mut end_index = 10;
-            for _ in 0..(count - 1) {
-               let order_response = self.client.batch_place_order(orders[start_index..end_index].to_vec()).await; 
-                match order_response {
-                // If the response is successful, process the orders.
-                Ok(v) => {
-                    // Push the orders from the first response to the live buys queue.
-                    for order in v[0].clone() {
-                        self.live_buys_orders.push_back(order);
-                    }
-                    // Sort the live buys queue and update it.
-                    let sorted_buys = sort_grid(self.live_buys_orders.clone(), -1);
-                    self.live_buys_orders = sorted_buys;
+    #[test]
+    fn test_flatten_slices_large_positions() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        assert_eq!(generator.flatten_slice(&book), None);
 
-                    // Push the orders from the second response to the live sells queue.
-                    for order in v[1].clone() {
-                        self.live_sells_orders.push_back(order);
-                    }
-                    // Sort the live sells queue and update it.
-                    let sorted_sells = sort_grid(self.live_sells_orders.clone(), 1);
-                    self.live_sells_orders = sorted_sells;
-                }
-                // If there is an error, print the error message.
-                _ => {}
-            }
-                start_index += 10;
-                end_index += 10;
-            }
+        // A long position is sold in one go by default.
+        generator.position = 500.0;
+        let (qty, is_buy) = generator.flatten_slice(&book).unwrap();
+        assert!((qty - round_size(500.0 / book.mid_price, &book)).abs() < 1e-9);
+        assert!(!is_buy);
 
-            let last_response = self.client.batch_place_order(orders[start_index..].to_vec()).await;
-             match last_response {
-                // If the response is successful, process the orders.
-                Ok(v) => {
-                    // Push the orders from the first response to the live buys queue.
-                    for order in v[0].clone() {
-                        self.live_buys_orders.push_back(order);
-                    }
-                    // Sort the live buys queue and update it.
-                    let sorted_buys = sort_grid(self.live_buys_orders.clone(), -1);
-                    self.live_buys_orders = sorted_buys;
+        // A short position is bought back in slices of the configured notional.
+        generator.position = -500.0;
+        generator.set_flatten_slice_notional(Some(200.0));
+        let (qty, is_buy) = generator.flatten_slice(&book).unwrap();
+        assert!((qty - round_size(200.0 / book.mid_price, &book)).abs() < 1e-9);
+        assert!(is_buy);
+    }
 
-                    // Push the orders from the second response to the live sells queue.
-                    for order in v[1].clone() {
-                        self.live_sells_orders.push_back(order);
-                    }
-                    // Sort the live sells queue and update it.
-                    let sorted_sells = sort_grid(self.live_sells_orders.clone(), 1);
-                    self.live_sells_orders = sorted_sells;
-                }
-                // If there is an error, print the error message.
-                _ => {}
-            }
+    #[tokio::test]
+    async fn test_flatten_with_limit_falls_back_to_market() {
+        let mut book = test_book();
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_flatten_limit(Some((10.0, TimeInForce::Ioc)));
+        generator.position = -100.0;
+
+        // The short is bought back with IOC orders capped 10 bps above the mid price, then with a
+        // market order once they left the position unchanged.
+        for _ in 0..=FLATTEN_LIMIT_ATTEMPTS {
+            assert!(!generator.flatten_position(&book, "BTCUSDT").await);
+            // The IOC orders expire unfilled, so the next one waits out the fill timeout.
+            book.last_update += FLATTEN_FILL_TIMEOUT_MS;
         }
+        let ioc = MockRequest::Immediate {
+            side: 1,
+            price: 100.1,
+            qty: 1.0,
+            symbol: "BTCUSDT".to_string(),
+        };
+        let mut expected = vec![ioc; FLATTEN_LIMIT_ATTEMPTS as usize];
+        expected.push(MockRequest::Market {
+            side: 1,
+            qty: 1.0,
+            symbol: "BTCUSDT".to_string(),
+        });
+        assert_eq!(mock.requests(), expected);
+
+        // A partial fill starts the limit orders over, a long is sold 10 bps below the mid.
+        generator.position = 50.0;
+        generator.flatten_position(&book, "BTCUSDT").await;
+        let ioc = MockRequest::Immediate {
+            side: -1,
+            price: 99.9,
+            qty: 0.5,
+            symbol: "BTCUSDT".to_string(),
+        };
+        assert_eq!(mock.requests().last(), Some(&ioc));
     }
 
-    fn check_for_fills(&mut self, data: PrivateData) {
-        let fills = match data {
-            PrivateData::Bybit(data) => data.executions,
-            PrivateData::Binance(data) => data.into_fastexec(),
+    #[tokio::test]
+    async fn test_kill_switch_flattens_one_slice_at_a_time() {
+        let (mut generator, mock) = mock_generator(3);
+        let mut book = mock_book();
+        rest_live(
+            &mut generator,
+            &mock,
+            1,
+            LiveOrder::new(99.8, 0.1, "b1".to_string()),
+        );
+        generator.position = 300.0;
+        generator.set_flatten_slice_notional(Some(100.0));
+        generator.set_kill_switch(true);
+
+        // The grid is pulled and the first slice of the long is sold.
+        update_with_book(&mut generator, book.clone()).await;
+        let sell = MockRequest::Market {
+            side: -1,
+            qty: 1.0,
+            symbol: "BTCUSDT".to_string(),
         };
+        assert_eq!(request_kinds(&mock), vec!["cancel all", "market"]);
+        assert_eq!(mock.requests().last(), Some(&sell));
 
-        for FastExecData {
-            order_id,
-            exec_qty,
-            side,
-            ..
-        } in fills
-        {
-            if exec_qty.parse::<f64>().unwrap() > 0.0 {
-                if side == "Buy" {
-                    for (i, order) in self.live_buys_orders.clone().iter().enumerate() {
-                        if order.order_id == order_id {
-                            self.position += order.price * order.qty;
-                            self.live_buys_orders.remove(i);
-                        }
-                    }
-                } else {
-                    for (i, order) in self.live_sells_orders.clone().iter().enumerate() {
-                        if order.order_id == order_id {
-                            self.position -= order.price * order.qty;
-                            self.live_sells_orders.remove(i);
-                        }
-                    }
-                }
-            }
-        }
+        // The next slice waits for the fills of the first one.
+        book.last_update = 2;
+        update_with_book(&mut generator, book.clone()).await;
+        assert_eq!(mock.requests().len(), 2);
+
+        // Once the first slice is filled the next one is sized from the reduced position.
+        let order_id = generator.flatten_order.as_ref().unwrap().0.order_id.clone();
+        let fill = mock.execute(&order_id, 100.0, 1.0, 3);
+        book.last_update = 3;
+        generator
+            .update_grid(fill, 0.0, 0.0, 0.0, book, "BTCUSDT".to_string(), 10)
+            .await;
+        assert_eq!(generator.position, 200.0);
+        assert_eq!(mock.requests().len(), 3);
+        assert_eq!(mock.requests().last(), Some(&sell));
     }
 
-    async fn out_of_bounds(&mut self, book: &LocalBook, symbol: String) -> bool {
-        // Initialize the `out_of_bounds` boolean to `false`.
-        let mut out_of_bounds = false;
-        let bounds = self.last_update_price * bps_to_decimal(self.minimum_spread * 1.5);
-        let (current_bid_bounds, current_ask_bounds) = (
-            self.last_update_price - bounds,
-            self.last_update_price + bounds,
-        );
+    #[test]
+    fn test_placement_delay_within_jitter() {
+        let mut generator = test_generator(3);
+        assert_eq!(generator.placement_delay(), 0);
 
-        // If there are no live orders, return `true`.
-        if self.live_buys_orders.is_empty() && self.live_sells_orders.is_empty() {
-            out_of_bounds = true;
-            self.last_update_price = book.mid_price;
-            return out_of_bounds;
-        } else if self.last_update_price != 0.0 {
-            // Set the `out_of_bounds` boolean to `true`.
-            if self.cancel_limit > 1 {
-                if book.mid_price < current_bid_bounds || book.mid_price > current_ask_bounds {
-                    if let Ok(_) = self.client.cancel_all(symbol.as_str()).await {
-                        out_of_bounds = true;
-                        println!("Cancelling all orders for {}", symbol);
-                        self.last_update_price = book.mid_price;
-                    }
-                }
-            }
-        }
-        // Return the `out_of_bounds` boolean.
-        out_of_bounds
+        generator.set_placement_jitter(50);
+        let delays: Vec<u64> = (0..1000).map(|_| generator.placement_delay()).collect();
+        assert!(delays.iter().all(|&d| d <= 50));
+        // The delays are spread over the range rather than fixed.
+        assert!(delays.iter().any(|&d| d != delays[0]));
     }
 
-    /// Updates the grid of orders with the current wallet data, skew, imbalance,
-    /// order book, symbol, and price fluctuation.
-    ///
-    /// # Arguments
-    ///
-    /// * `wallet` - Private data of the wallet.
-    /// * `skew` - Skew of the order book.
-    /// * `imbalance` - Imbalance of the order book.
-    /// * `book` - Current order book.
-    /// * `symbol` - String representing the symbol.
-    /// * `price_flu` - Price fluctuation of the order book.
-    pub async fn update_grid(
-        &mut self,
-        private_data: PrivateData,
-        skew: f64,
-        imbalance: f64,
-        book: LocalBook,
-        symbol: String,
-        rate_limit: u32,
-    ) {
-        // Update the inventory delta.
-        self.inventory_delta();
+    #[tokio::test]
+    async fn test_jittered_orders_placed_on_later_update() {
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_placement_jitter(50);
+        let mut book = mock_book();
+        update_with_book(&mut generator, book.clone()).await;
 
-        if self.time_limit > 1 {
-            let condition = (book.last_update - self.time_limit) > 1000;
-            if condition == true {
-                self.rate_limit = rate_limit;
-                self.cancel_limit = rate_limit;
-            }
+        // The grid is held until the book time passes its random delay, a delay of 0 places it
+        // at once.
+        let due = generator
+            .scheduled_orders
+            .as_ref()
+            .map_or(1, |(due, _)| *due);
+        assert!(due <= 51);
+        if due > 1 {
+            assert!(mock.requests().is_empty());
+            book.last_update = due - 1;
+            update_with_book(&mut generator, book.clone()).await;
+            assert!(mock.requests().is_empty());
         }
 
-        self.check_for_fills(private_data);
-        // Check if the order book is out of bounds with the given symbol.
-        match self.out_of_bounds(&book, symbol.clone()).await {
-            true => {
-                // Generate quotes for the grid based on the order book, symbol, imbalance, skew,
-                // and price fluctuation.
-                let orders = self.generate_quotes(symbol.clone(), &book, imbalance, skew);
-
-                // Send the generated orders to the book.
-                if self.rate_limit > 1 {
-                    self.send_batch_orders(orders.clone()).await;
-                    self.rate_limit -= 1;
-                }
-                //Updates the time limit
-                self.time_limit = book.last_update;
-            }
+        book.last_update = due;
+        update_with_book(&mut generator, book).await;
+        assert_eq!(request_kinds(&mock), vec!["place"]);
+        assert!(generator.scheduled_orders.is_none());
+        assert_eq!(mock.resting(1).len(), generator.live_buys_orders.len());
+        assert!(!generator.live_buys_orders.is_empty());
+    }
 
-            false => {}
+    #[tokio::test]
+    async fn test_fill_requotes_its_side() {
+        let fill_best_bid = |generator: &QuoteGenerator, mock: &MockBackend| {
+            let bid = generator
+                .live_buys_orders
+                .iter()
+                .max_by(|a, b| a.price.total_cmp(&b.price))
+                .unwrap();
+            mock.fill(&bid.order_id, bid.qty, 2)
+        };
+        async fn update(generator: &mut QuoteGenerator, private: PrivateData) {
+            let mut book = mock_book();
+            book.last_update = 2;
+            generator
+                .update_grid(private, 0.0, 0.0, 0.0, book, "BTCUSDT".to_string(), 10)
+                .await;
         }
 
-        // Update the time limit
+        // By default the filled level stays empty while the price holds within the bounds.
+        let (mut generator, mock) = mock_grid(&mock_book()).await;
+        let fill = fill_best_bid(&generator, &mock);
+        update(&mut generator, fill).await;
+        assert_eq!(mock.requests().len(), 6);
+        assert_eq!(generator.live_buys_orders.len(), 2);
+
+        // Requoting on fills replaces the bids on the same update and leaves the asks resting.
+        let (mut generator, mock) = mock_grid(&mock_book()).await;
+        generator.set_requote_on_fill(true);
+        let asks = mock.resting(-1);
+        let fill = fill_best_bid(&generator, &mock);
+        update(&mut generator, fill).await;
+        let requests = mock.requests()[6..].to_vec();
+        assert!(requests[..2]
+            .iter()
+            .all(|r| matches!(r, MockRequest::Cancel { .. })));
+        assert!(requests[2..]
+            .iter()
+            .all(|r| matches!(r, MockRequest::Place { side: 1, .. })));
+        assert_eq!(generator.live_buys_orders.len(), 3);
+        assert_eq!(mock.resting(-1), asks);
+        assert_eq!(generator.filled_side(), None);
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct LiveOrder {
-    pub price: f64,
-    pub qty: f64,
-    pub order_id: String,
-}
+    #[tokio::test]
+    async fn test_replace_waits_after_cancel() {
+        let mut book = mock_book();
+        let (mut generator, mock) = mock_grid(&book).await;
+        generator.set_replace_delay(50);
 
-impl LiveOrder {
-    pub fn new(price: f64, qty: f64, order_id: String) -> Self {
-        LiveOrder {
-            price,
-            qty,
-            order_id,
-        }
+        // A move above the ask bound cancels the asks at once.
+        book.mid_price = 101.0;
+        book.best_bid.price = 100.9;
+        book.best_ask.price = 101.1;
+        book.last_update = 2;
+        update_with_book(&mut generator, book.clone()).await;
+        assert!(mock.requests()[6..]
+            .iter()
+            .all(|r| matches!(r, MockRequest::Cancel { .. })));
+
+        // The new asks are placed on the first update after the delay.
+        book.last_update = 51;
+        update_with_book(&mut generator, book.clone()).await;
+        assert!(mock.requests()[6..]
+            .iter()
+            .all(|r| matches!(r, MockRequest::Cancel { .. })));
+        book.last_update = 52;
+        update_with_book(&mut generator, book).await;
+        let requests = mock.requests()[6..].to_vec();
+        let first_place = requests
+            .iter()
+            .position(|r| matches!(r, MockRequest::Place { .. }))
+            .unwrap();
+        assert!(first_place > 0);
+        assert!(requests[..first_place]
+            .iter()
+            .all(|r| matches!(r, MockRequest::Cancel { .. })));
+        assert!(requests[first_place..]
+            .iter()
+            .all(|r| matches!(r, MockRequest::Place { side: -1, .. })));
     }
-}
 
-impl PartialEq for LiveOrder {
-    fn eq(&self, other: &Self) -> bool {
-        self.order_id == other.order_id
+    #[test]
+    #[should_panic]
+    fn test_placement_jitter_is_bounded() {
+        test_generator(3).set_placement_jitter(MAX_PLACEMENT_JITTER_MS + 1);
     }
 
-    fn ne(&self, other: &Self) -> bool {
-        self.order_id != other.order_id
+    #[test]
+    fn test_iceberg_display_size() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.asset = 10_000.0;
+        generator.update_max();
+        // Every order is fully displayed by default.
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(orders.iter().all(|o| o.5 == o.0));
+
+        generator.set_iceberg_display_fraction(Some(0.25));
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        assert!(!orders.is_empty());
+        for order in orders {
+            assert!((order.5 - round_size(order.0 * 0.25, &book)).abs() < 1e-9);
+            assert!(order.5 < order.0);
+        }
     }
-}
 
-impl PartialOrd for LiveOrder {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.price.partial_cmp(&other.price)
+    #[test]
+    fn test_requote_bounds_scale_with_threshold() {
+        let mut generator = test_generator(3);
+        generator.set_spread(20.0);
+        generator.last_update_price = 100.0;
+        // 1.5 times 20 bps on either side by default.
+        let (bid, ask) = generator.requote_bounds();
+        assert!((bid - 99.7).abs() < 1e-9 && (ask - 100.3).abs() < 1e-9);
+
+        generator.set_requote_threshold(3.0);
+        let (bid, ask) = generator.requote_bounds();
+        assert!((bid - 99.4).abs() < 1e-9 && (ask - 100.6).abs() < 1e-9);
     }
-}
 
-fn bps_to_decimal(bps: f64) -> f64 {
-    bps / 10000.0
-}
+    #[test]
+    #[should_panic]
+    fn test_requote_threshold_must_exceed_one() {
+        test_generator(3).set_requote_threshold(1.0);
+    }
 
-fn _bps_offset(book: &LocalBook, bps: f64) -> f64 {
-    book.mid_price + (book.mid_price * bps_to_decimal(bps))
-}
+    #[tokio::test]
+    async fn test_upward_move_cancels_only_asks() {
+        let mut book = test_book();
+        let (mut generator, mock) = mock_generator(3);
+        generator.set_spread(25.0);
+        generator.last_update_price = 100.0;
+        for (side, price, id) in [
+            (1, 99.8, "b1"),
+            (1, 99.7, "b2"),
+            (-1, 100.2, "s1"),
+            (-1, 100.3, "s2"),
+        ] {
+            rest_live(
+                &mut generator,
+                &mock,
+                side,
+                LiveOrder::new(price, 0.1, id.to_string()),
+            );
+        }
 
-fn _offset(book: &LocalBook, offset: f64) -> f64 {
-    book.mid_price + (book.mid_price * offset)
-}
+        // Within the bounds nothing is cancelled.
+        book.mid_price = 100.3;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            None
+        );
 
-fn round_price(book: &LocalBook, price: f64) -> f64 {
-    let val = book.tick_size.count_decimal_places();
-    price.round_to(val as u8)
-}
+        // Past the ask bound only the asks are cancelled and requoted.
+        book.mid_price = 100.5;
+        assert_eq!(
+            generator.out_of_bounds(&book, "BTCUSDT".to_string()).await,
+            Some(-1)
+        );
+        assert_eq!(cancelled_ids(&mock), vec!["s1", "s2"]);
+        assert!(generator.live_sells_orders.is_empty());
+        assert_eq!(generator.live_buys_orders.len(), 2);
+        assert_eq!(generator.last_update_price, 100.5);
+    }
 
-fn round_size(qty: f64, book: &LocalBook) -> f64 {
-    round_step(qty, book.lot_size)
-}
+    #[test]
+    fn test_spread_capture_round_trip() {
+        let mut generator = test_generator(3);
+        generator.record_capture(1, 100.0, 1.0);
+        assert_eq!(generator.avg_captured_bps(), 0.0);
 
-/// This function takes a `VecDeque` of `LiveOrder`s and a `side` integer as input.
-/// It sorts the `VecDeque` in ascending order if the `side` is greater than 1.
-/// Otherwise, it sorts the `VecDeque` in descending order.
-/// It then returns a new `VecDeque` with the sorted orders.
-fn sort_grid(orders: VecDeque<LiveOrder>, side: i32) -> VecDeque<LiveOrder> {
-    // Create a new `Vec` by consuming the `VecDeque`
-    let mut vec = Vec::from(orders);
+        generator.record_capture(-1, 100.5, 1.0);
+        assert!((generator.avg_captured_bps() - 50.0).abs() < 1e-9);
+        assert!(generator.open_fills.is_empty());
 
-    // Sort the `Vec` either in ascending or descending order based on the `side`
-    if side > 0 {
-        vec.sort_by(|a, b| a.partial_cmp(b).unwrap()); // Sort the Vec in ascending order
-    } else {
-        vec.sort_by(|a, b| b.partial_cmp(a).unwrap()); // Sort the Vec in descending order
+        // The maker fee on both legs is taken out of the captured spread.
+        let mut generator = test_generator(3);
+        generator.set_maker_fee(0.0002);
+        generator.record_capture(-1, 100.5, 1.0);
+        generator.record_capture(1, 100.0, 1.0);
+        assert!((generator.avg_captured_bps() - (0.5 / 100.5 * 10000.0 - 4.0)).abs() < 1e-9);
     }
 
-    // Create a new `VecDeque` by consuming the sorted `Vec`
-    let sorted_vecdeque: VecDeque<LiveOrder> = VecDeque::from(vec);
+    #[test]
+    fn test_partial_fill_keeps_order_live() {
+        let mut generator = test_generator(3);
+        generator
+            .live_sells_orders
+            .push_back(LiveOrder::new(100.5, 0.1, "7".to_string()));
+        let mut private = BybitPrivate::default();
+        let mut fill = |exec_id: &str, exec_qty: &str| {
+            private.executions.push_back(FastExecData {
+                category: "linear".to_string(),
+                symbol: "BTCUSDT".to_string(),
+                exec_id: exec_id.to_string(),
+                exec_price: "100.5".to_string(),
+                exec_qty: exec_qty.to_string(),
+                order_id: "7".to_string(),
+                order_link_id: "".to_string(),
+                side: "Sell".to_string(),
+                exec_time: "5000".to_string(),
+                seq: 1,
+            });
+            PrivateData::Bybit(private.clone())
+        };
 
-    // Return the sorted `VecDeque`
-    sorted_vecdeque
-}
+        // A partial fill reduces the remaining quantity without removing the order.
+        let data = fill("a", "0.04");
+        generator.check_for_fills(data.clone());
+        // Executions that were already applied are not applied again.
+        generator.check_for_fills(data);
+        assert_eq!(generator.live_sells_orders.len(), 1);
+        assert!((generator.live_sells_orders[0].remaining - 0.06).abs() < 1e-12);
+        assert!((generator.position + 100.5 * 0.04).abs() < 1e-9);
 
-impl OrderManagement {
-    async fn place_buy_limit(&self, qty: f64, price: f64, symbol: &str) -> Result<LiveOrder, ()> {
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                if let Ok(v) = client
-                    .place_futures_limit_order(
-                        bybit::model::Category::Linear,
-                        symbol,
-                        Side::Buy,
-                        qty,
-                        price,
-                        0,
-                    )
-                    .await
-                {
-                    Ok(LiveOrder::new(price, qty, v.result.order_id))
-                } else {
-                    Err(())
-                }
-            }
-            OrderManagement::Binance(trader) => {
-                let symbol = symbol.to_owned();
-                let client = trader.clone();
-                let task = task::spawn_blocking(move || {
-                    if let Ok(v) = client.binance_trader().limit_buy(
-                        symbol,
-                        qty,
-                        price,
-                        binance::futures::account::TimeInForce::GTC,
-                    ) {
-                        Ok(LiveOrder::new(price, qty, v.order_id.to_string()))
-                    } else {
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
-        }
+        // The completing fill removes the order.
+        generator.check_for_fills(fill("b", "0.06"));
+        assert!(generator.live_sells_orders.is_empty());
+        assert!((generator.position + 100.5 * 0.1).abs() < 1e-9);
     }
 
-    async fn place_sell_limit(&self, qty: f64, price: f64, symbol: &str) -> Result<LiveOrder, ()> {
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                if let Ok(v) = client
-                    .place_futures_limit_order(
-                        bybit::model::Category::Linear,
-                        symbol,
-                        Side::Sell,
-                        qty,
-                        price,
-                        2,
-                    )
-                    .await
-                {
-                    Ok(LiveOrder::new(price, qty, v.result.order_id))
-                } else {
-                    Err(())
-                }
-            }
-            OrderManagement::Binance(trader) => {
-                let symbol = symbol.to_owned();
-                let client = trader.clone();
-                let task = tokio::task::spawn_blocking(move || {
-                    if let Ok(v) = client.binance_trader().limit_sell(
-                        symbol,
-                        qty,
-                        price,
-                        binance::futures::account::TimeInForce::GTC,
-                    ) {
-                        Ok(LiveOrder::new(price, qty, v.order_id.to_string()))
-                    } else {
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
-        }
+    #[test]
+    fn test_grid_symmetric_at_inventory_target() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.set_inventory_target(0.2);
+        generator.position = 0.2 * generator.max_position_usd;
+        generator.inventory_delta();
+        assert!(generator.inventory_delta.abs() < 1e-12);
+
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        let side_notional = |side: i32| -> f64 {
+            orders
+                .iter()
+                .filter(|o| o.3 == side)
+                .map(|o| o.0 * o.1)
+                .sum()
+        };
+        let best_bid = orders
+            .iter()
+            .filter(|o| o.3 > 0)
+            .map(|o| o.1)
+            .fold(f64::MIN, f64::max);
+        let best_ask = orders
+            .iter()
+            .filter(|o| o.3 < 0)
+            .map(|o| o.1)
+            .fold(f64::MAX, f64::min);
+
+        assert!((side_notional(1) - side_notional(-1)).abs() < 1.0);
+        // The best quotes are equally far from the mid price, up to rounding to the tick size.
+        let distances = (book.mid_price - best_bid) - (best_ask - book.mid_price);
+        assert!(distances.abs() <= book.tick_size + 1e-9);
     }
 
-    async fn market_buy(&self, qty: f64, symbol: &str) -> Result<LiveOrder, ()> {
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let req = OrderRequest {
-                    category: bybit::model::Category::Linear,
-                    symbol: Cow::Owned(symbol.to_string()),
-                    side: Side::Buy,
-                    order_type: bybit::model::OrderType::Market,
-                    qty,
-                    ..Default::default()
-                };
-                if let Ok(v) = client.place_custom_order(req).await {
-                    Ok(LiveOrder::new(0.0, qty, v.result.order_id))
-                } else {
-                    println!("Could not place market order for {} qty", qty);
-                    Err(())
-                }
-            }
-            OrderManagement::Binance(trader) => {
-                let symbol = symbol.to_owned();
-                let client = trader.clone();
-                let task = tokio::task::spawn_blocking(move || {
-                    if let Ok(v) = client.binance_trader().market_buy(symbol, qty) {
-                        Ok(LiveOrder::new(v.avg_price, qty, v.order_id.to_string()))
-                    } else {
-                        println!("Could not place market order for {} qty", qty);
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
+    #[test]
+    fn test_client_order_ids() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.set_order_tag("bot1".to_string());
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+
+        for order in &orders {
+            assert!(order.4.starts_with("bot1-"));
+            let live =
+                LiveOrder::new(order.1, order.0, "1".to_string()).with_link_id(order.4.clone());
+            let (side, level) = parse_client_order_id(&live.link_id, "bot1").unwrap();
+            assert_eq!(side, order.3);
+            assert!(level < 3);
         }
+        // The best quote of each side is level 0.
+        let best_bid = orders
+            .iter()
+            .filter(|o| o.3 > 0)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        assert_eq!(parse_client_order_id(&best_bid.4, "bot1"), Some((1, 0)));
+        // The ids are unique and manual orders are not recognized.
+        let mut ids: Vec<&String> = orders.iter().map(|o| &o.4).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), orders.len());
+        assert_eq!(parse_client_order_id("manual-b0-1-1", "bot1"), None);
+        assert_eq!(parse_client_order_id("bot10-b0-1-1", "bot1"), None);
     }
 
-    async fn market_sell(&self, qty: f64, symbol: &str) -> Result<LiveOrder, ()> {
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let req = OrderRequest {
-                    category: bybit::model::Category::Linear,
-                    symbol: Cow::Owned(symbol.to_string()),
-                    side: Side::Sell,
-                    order_type: bybit::model::OrderType::Market,
-                    qty,
-                    time_in_force: Some(Cow::Borrowed("IOC")),
-                    ..Default::default()
-                };
-                if let Ok(v) = client.place_custom_order(req).await {
-                    Ok(LiveOrder::new(0.0, qty, v.result.order_id))
-                } else {
-                    println!("Could not place market order for {} qty", qty);
-                    Err(())
-                }
-            }
-            OrderManagement::Binance(trader) => {
-                let symbol = symbol.to_owned();
-                let client = trader.clone();
-                let task = tokio::task::spawn_blocking(move || {
-                    if let Ok(v) = client.binance_trader().market_sell(symbol, qty) {
-                        Ok(LiveOrder::new(v.avg_price, qty, v.order_id.to_string()))
-                    } else {
-                        println!("Could not place market order for {} qty", qty);
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
+    #[test]
+    fn test_reconcile_infers_missed_fills() {
+        let mut generator = test_generator(3);
+        for (price, id) in [(99.9, "1"), (99.8, "2"), (99.7, "3")] {
+            generator
+                .live_buys_orders
+                .push_back(LiveOrder::new(price, 0.1, id.to_string()));
         }
+        generator
+            .live_sells_orders
+            .push_back(LiveOrder::new(100.1, 0.1, "4".to_string()));
+
+        // The exchange only has one of the bids left, partially filled, and an order of ours
+        // that is not tracked locally, plus a manual order.
+        let mut partial = LiveOrder::new(99.8, 0.1, "2".to_string());
+        partial.remaining = 0.04;
+        let adopted = LiveOrder::new(99.6, 0.1, "5".to_string()).with_link_id(client_order_id(
+            DEFAULT_ORDER_TAG,
+            1,
+            3,
+            1,
+            1,
+        ));
+        let manual = LiveOrder::new(99.5, 0.1, "6".to_string());
+        let open = vec![
+            VecDeque::from([partial, adopted, manual]),
+            VecDeque::from([LiveOrder::new(100.1, 0.1, "4".to_string())]),
+        ];
+        // Order 1 was filled in full and order 3 was cancelled without an execution.
+        let executed = HashMap::from([("1".to_string(), 0.1), ("3".to_string(), 0.0)]);
+        generator.apply_open_orders(open, &executed, 5000);
+
+        // Order 1 was booked in full and order 2 for 0.06.
+        let bought = 99.9 * 0.1 + 99.8 * 0.06;
+        assert!((generator.position - bought).abs() < 1e-9);
+        let ids: Vec<&str> = generator
+            .live_buys_orders
+            .iter()
+            .map(|o| o.order_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["2", "5"]);
+        assert!((generator.live_buys_orders[0].remaining - 0.04).abs() < 1e-12);
+        assert_eq!(generator.live_sells_orders.len(), 1);
     }
 
-    async fn amend_order(
-        &self,
-        order: LiveOrder,
-        qty: f64,
-        price: Option<f64>,
-        symbol: &str,
-    ) -> Result<LiveOrder, ()> {
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let req = AmendOrderRequest {
-                    category: bybit::model::Category::Linear,
-                    order_id: Some(Cow::Borrowed(order.order_id.as_str())),
-                    price,
-                    qty,
-                    ..Default::default()
-                };
-                if let Ok(v) = client.amend_order(req).await {
-                    Ok(LiveOrder::new(
-                        price.unwrap_or(order.price),
-                        qty,
-                        v.result.order_id,
-                    ))
-                } else {
-                    Err(())
-                }
-            }
-            OrderManagement::Binance(trader) => {
-                // TODO: binance crate doesn't have an amend_order fn. so this cancels the current and places a new one then returns the new order id
-                let symbol = symbol.to_owned();
-                let client = trader.clone();
-                let task = tokio::task::spawn_blocking(move || {
-                    if let Ok(_) = client
-                        .binance_trader()
-                        .cancel_order(symbol.clone(), order.order_id.parse::<u64>().unwrap())
-                    {
-                        if let Ok(v) = client.binance_trader().limit_sell(
-                            symbol,
-                            qty,
-                            price.unwrap(),
-                            binance::futures::account::TimeInForce::GTC,
-                        ) {
-                            Ok(LiveOrder::new(price.unwrap(), qty, v.order_id.to_string()))
-                        } else {
-                            Err(())
-                        }
-                    } else {
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
+    #[tokio::test]
+    async fn test_adopted_orders_fit_into_grid() {
+        let mut book = test_book();
+        book.best_bid.price = 99.9;
+        book.best_ask.price = 100.1;
+        book.last_update = 1;
+        let (mut generator, mock) = mock_generator(3);
+        let grid = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 0.0, 0.0);
+        let mut bids: Vec<f64> = grid.iter().filter(|o| o.3 > 0).map(|o| o.1).collect();
+        bids.sort_by(|a, b| b.total_cmp(a));
+
+        // Orders left by the previous run, two on the new grid and one far below it.
+        let tagged = |price: f64, id: &str, level: usize| {
+            LiveOrder::new(price, 0.1, id.to_string()).with_link_id(client_order_id(
+                DEFAULT_ORDER_TAG,
+                1,
+                level,
+                0,
+                level as u64,
+            ))
+        };
+        let open = vec![
+            VecDeque::from([
+                tagged(bids[0], "kept0", 0),
+                tagged(bids[1], "kept1", 1),
+                tagged(90.0, "stale", 2),
+            ]),
+            VecDeque::new(),
+        ];
+        for order in &open[0] {
+            mock.rest("BTCUSDT", 1, order.clone());
         }
+        generator.apply_open_orders(open, &HashMap::new(), 1);
+
+        // The stale order is cancelled and only the levels without an order are placed.
+        book.last_update = 2;
+        update_with_book(&mut generator, book.clone()).await;
+        assert_eq!(cancelled_ids(&mock), vec!["stale"]);
+        assert_eq!(request_kinds(&mock), vec!["cancel", "place"]);
+        let sent = mock.requests().len();
+        let mut prices: Vec<f64> = generator.live_buys_orders.iter().map(|o| o.price).collect();
+        prices.sort_by(|a, b| b.total_cmp(a));
+        assert_eq!(prices, bids);
+        let ids: Vec<&str> = generator
+            .live_buys_orders
+            .iter()
+            .map(|o| o.order_id.as_str())
+            .collect();
+        assert!(ids.contains(&"kept0") && ids.contains(&"kept1"));
+        assert_eq!(
+            generator.live_sells_orders.len(),
+            grid.iter().filter(|o| o.3 < 0).count()
+        );
+
+        // The fitted grid is within bounds, so nothing is placed on top of it.
+        book.last_update = 3;
+        update_with_book(&mut generator, book).await;
+        assert_eq!(mock.requests().len(), sent);
     }
 
-    async fn cancel_order(&self, order: LiveOrder, symbol: &str) -> Result<LiveOrder, ()> {
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let req = CancelOrderRequest {
-                    category: bybit::model::Category::Linear,
-                    symbol: Cow::Borrowed(symbol),
-                    order_id: Some(Cow::Borrowed(order.order_id.as_str())),
-                    order_filter: None,
-                    order_link_id: None,
-                };
-                if let Ok(v) = client.cancel_order(req).await {
-                    Ok(LiveOrder::new(order.price, order.qty, v.result.order_id))
-                } else {
-                    Err(())
-                }
-            }
+    #[test]
+    fn test_skew_deadband_quotes_symmetric_grid() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.set_skew_deadband(0.1);
 
-            OrderManagement::Binance(trader) => {
-                let symbol = symbol.to_owned();
-                let client = trader.clone();
-                let task = task::spawn_blocking(move || {
-                    if let Ok(v) = client
-                        .binance_trader()
-                        .cancel_order(symbol, order.order_id.parse::<u64>().unwrap())
-                    {
-                        Ok(LiveOrder::new(
-                            order.price,
-                            order.qty,
-                            v.order_id.to_string(),
-                        ))
-                    } else {
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.6, 0.05, 0.0);
+        let mut bids: Vec<&BatchOrder> = orders.iter().filter(|o| o.3 > 0).collect();
+        let mut asks: Vec<&BatchOrder> = orders.iter().filter(|o| o.3 < 0).collect();
+        bids.sort_by(|a, b| b.1.total_cmp(&a.1));
+        asks.sort_by(|a, b| a.1.total_cmp(&b.1));
+        assert_eq!(bids.len(), asks.len());
+        for (bid, ask) in bids.iter().zip(&asks) {
+            // Each level is as far from the mid price and as large on both sides.
+            let distance = (book.mid_price - bid.1) - (ask.1 - book.mid_price);
+            assert!(distance.abs() <= book.tick_size + 1e-9);
+            assert!((bid.0 * bid.1 - ask.0 * ask.1).abs() < 0.5);
         }
+
+        // A skew outside of the deadband still skews the grid.
+        let skewed = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.6, 0.5, 0.0);
+        let best_bid = skewed
+            .iter()
+            .filter(|o| o.3 > 0)
+            .map(|o| o.1)
+            .fold(f64::MIN, f64::max);
+        assert!(best_bid > bids[0].1);
     }
 
-    async fn cancel_all(&self, symbol: &str) -> Result<Vec<LiveOrder>, ()> {
-        let mut arr = vec![];
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let req = CancelallRequest {
-                    category: bybit::model::Category::Linear,
-                    symbol: symbol,
-                    ..Default::default()
-                };
-                if let Ok(v) = client.cancel_all_orders(req).await {
-                    for d in v.result.list {
-                        arr.push(LiveOrder::new(0.0, 0.0, d.order_id));
-                    }
-                    Ok(arr)
-                } else {
-                    Err(())
-                }
-            }
-            OrderManagement::Binance(trader) => {
-                // TODO
-                let symbol = symbol.to_owned();
-                let client = trader.clone();
-                let task = task::spawn_blocking(move || {
-                    if let Ok(_) = client.binance_trader().cancel_all_open_orders(symbol) {
-                        Ok(arr)
-                    } else {
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
-        }
+    #[test]
+    fn test_notional_cap_limits_max_position() {
+        let mut generator = test_generator(3);
+        generator.set_max_notional(Some(500.0));
+        assert_eq!(generator.max_position_usd, 500.0);
+
+        // The cap keeps binding when the wallet grows.
+        generator.update_wallet(&wallet_update("2000"));
+        assert_eq!(generator.max_position_usd, 500.0);
+
+        // A cap above the leverage based value does not raise it.
+        generator.set_max_notional(Some(5000.0));
+        assert_eq!(generator.max_position_usd, 1900.0);
+        generator.set_max_notional(None);
+        assert_eq!(generator.max_position_usd, 1900.0);
     }
 
-    async fn batch_cancel(
-        &self,
-        orders: Vec<LiveOrder>,
-        symbol: &str,
-    ) -> Result<Vec<LiveOrder>, ()> {
-        let mut arr = vec![];
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let req = BatchCancelRequest {
-                    category: bybit::model::Category::Linear,
-                    requests: {
-                        let mut li = vec![];
-                        for v in orders {
-                            let order_id_string = v.order_id.clone();
-                            li.push(CancelOrderRequest {
-                                category: bybit::model::Category::Linear,
-                                symbol: Cow::Borrowed(symbol),
-                                order_id: Some(Cow::Owned(order_id_string)), // Changed to Cow::Owned
-                                order_filter: None,
-                                order_link_id: None,
-                            });
-                        }
-                        li
-                    },
-                };
-                if let Ok(v) = client.batch_cancel_order(req).await {
-                    for d in v.result.list {
-                        arr.push(LiveOrder::new(0.0, 0.0, d.order_id));
-                    }
-                    Ok(arr)
-                } else {
-                    Err(())
-                }
-            }
+    #[test]
+    fn test_min_spread_in_ticks() {
+        // A cheap symbol where 25 bps is a fraction of a tick.
+        let mut book = test_book();
+        book.mid_price = 0.05;
+        book.tick_size = 0.001;
 
-            OrderManagement::Binance(_) => {
-                // TODO:  Write batch cancel for binance
-                Ok(arr)
-            }
-        }
+        let spread = QuoteGenerator::adjusted_spread(25.0, 0.0, &book);
+        assert!(spread < book.tick_size);
+
+        let spread = QuoteGenerator::adjusted_spread(25.0, 2.0, &book);
+        assert!((spread - 0.002).abs() < 1e-12);
+
+        // The bps floor is kept when it is already wider than the ticks floor.
+        let book = test_book();
+        let spread = QuoteGenerator::adjusted_spread(25.0, 2.0, &book);
+        assert!((spread - 0.25).abs() < 1e-12);
     }
 
-    /// Asynchronously places a batch of orders for a given symbol and returns a vector of queues
-    /// containing the live orders.
-    ///
-    /// # Arguments
-    ///
-    /// * `order_array` - A vector of `BatchOrder` structs representing the orders to be placed.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<Vec<VecDeque<LiveOrder>>, ()>` - A vector of queues containing the live orders,
-    /// or an error if the batch placement fails.
-    async fn batch_place_order(
-        &self,
-        order_array: Vec<BatchOrder>,
-    ) -> Result<Vec<VecDeque<LiveOrder>>, ()> {
-        // Clone the order array for later use
-        let order_array_clone = order_array.clone();
-
-        // Initialize tracking variables for sell orders
-        let mut tracking_sells = vec![];
-        let mut index = 0;
-
-        // Create the order requests for Bybit
-        let order_arr = {
-            let mut arr = vec![];
-            for BatchOrder(qty, price, symbol, side) in order_array_clone {
-                arr.push(OrderRequest {
-                    category: bybit::model::Category::Linear,
-                    symbol: Cow::Owned(symbol),
-                    order_type: bybit::model::OrderType::Limit,
-                    side: {
-                        if side < 0 {
-                            tracking_sells.push(index);
-                            index += 1;
-                            bybit::model::Side::Sell
-                        } else {
-                            bybit::model::Side::Buy
-                        }
-                    },
-                    qty,
-                    price: Some(price),
-                    time_in_force: Some(Cow::Borrowed("PostOnly")),
-                    ..Default::default()
-                });
-            }
-            arr
+    #[test]
+    fn test_aggression_bounds() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        generator.set_aggression(0.2, 0.6);
+        let best_bid = |orders: &[BatchOrder]| {
+            orders
+                .iter()
+                .filter(|o| o.3 > 0)
+                .map(|o| o.1)
+                .fold(f64::MIN, f64::max)
         };
 
-        // Place the orders with Bybit
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let od_clone = order_array.clone();
-                let req = BatchPlaceRequest {
-                    category: bybit::model::Category::Linear,
-                    requests: order_arr,
-                };
-                if let Ok(v) = client.batch_place_order(req).await {
-                    let mut arr = vec![];
-                    let mut buy_array = VecDeque::new();
-                    let mut sell_array = VecDeque::new();
-                    for (i, d) in v.result.list.iter().enumerate() {
-                        for pos in tracking_sells.clone() {
-                            if i == pos {
-                                sell_array.push_back(LiveOrder::new(
-                                    od_clone[i].1.clone(),
-                                    od_clone[i].0.clone(),
-                                    d.order_id.to_string(),
-                                ));
-                            } else {
-                                buy_array.push_back(LiveOrder::new(
-                                    od_clone[i].1.clone(),
-                                    od_clone[i].0.clone(),
-                                    d.order_id.to_string(),
-                                ));
-                            }
-                        }
-                    }
-                    arr.push(buy_array);
-                    arr.push(sell_array);
-                    Ok(arr)
-                } else {
-                    Err(())
-                }
-            }
-            OrderManagement::Binance(trader) => {
-                // Place the orders with Binance
-                let client = trader.clone();
-                let order_vec = order_array.clone();
-                let order_requests = {
-                    let mut arr = vec![];
-                    for BatchOrder(qty, price, symbol, side) in order_vec {
-                        arr.push(CustomOrderRequest {
-                            symbol,
-                            qty: Some(qty),
-                            side: if side < 0 {
-                                OrderSide::Sell
-                            } else {
-                                OrderSide::Buy
-                            },
-                            price: Some(price),
-                            order_type: binance::futures::account::OrderType::Limit,
-                            time_in_force: Some(binance::futures::account::TimeInForce::GTC),
-                            position_side: None,
-                            stop_price: None,
-                            close_position: None,
-                            activation_price: None,
-                            callback_rate: None,
-                            working_type: None,
-                            price_protect: None,
-                            reduce_only: None,
-                        });
-                    }
-                    arr
-                };
-                let task = task::spawn_blocking(move || {
-                    if let Ok(_) = client
-                        .binance_trader()
-                        .custom_batch_orders(order_array.len().try_into().unwrap(), order_requests)
-                    {
-                        // TODO: Implement live order tracking for Binance
-                        let arr = vec![];
-                        Ok(arr)
-                    } else {
-                        Err(())
-                    }
-                });
-                task.await.unwrap()
-            }
-        }
+        // An imbalance above the maximum is quoted at the maximum aggression.
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.95, 1.0, 0.0);
+        assert!((best_bid(&orders) - 99.95).abs() < 1e-9);
+
+        // An imbalance below the minimum is quoted at the minimum aggression.
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.0, 1.0, 0.0);
+        assert!((best_bid(&orders) - 99.9).abs() < 1e-9);
     }
 
-    async fn batch_amend(
-        &self,
-        orders: Vec<LiveOrder>,
-        symbol: &str,
-    ) -> Result<Vec<LiveOrder>, ()> {
-        match self {
-            OrderManagement::Bybit(trader) => {
-                let client = trader.clone().bybit_trader();
-                let order_clone = orders.clone();
-                let req = BatchAmendRequest {
-                    category: bybit::model::Category::Linear,
-                    requests: {
-                        let mut arr = vec![];
-                        for v in orders {
-                            arr.push(AmendOrderRequest {
-                                category: bybit::model::Category::Linear,
-                                symbol: Cow::Borrowed(symbol),
-                                order_id: Some(Cow::Owned(v.order_id)),
-                                ..Default::default()
-                            });
-                        }
-                        arr
-                    },
-                };
-                if let Ok(v) = client.batch_amend_order(req).await {
-                    let mut arr = vec![];
-                    for (i, d) in v.result.list.iter().enumerate() {
-                        arr.push(LiveOrder::new(
-                            order_clone[i].price,
-                            order_clone[i].qty,
-                            d.order_id.clone().to_string(),
-                        ));
-                    }
-                    Ok(arr)
-                } else {
-                    Err(())
-                }
+    #[test]
+    fn test_fill_weighting_concentrates_size() {
+        let book = test_book();
+        let mut generator = test_generator(3);
+        let best_bid = |orders: &[BatchOrder]| {
+            orders
+                .iter()
+                .filter(|o| o.3 > 0)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|o| (o.1, o.0))
+                .unwrap()
+        };
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.5, 1.0, 0.0);
+        let (price, unweighted_qty) = best_bid(&orders);
+
+        // Only the best bid has ever been filled.
+        let best_distance = (book.mid_price - price) / book.mid_price * 10000.0;
+        for bucket in 0..40 {
+            for _ in 0..20 {
+                generator
+                    .fill_stats
+                    .record_placement(bucket as f64 * FILL_BUCKET_BPS);
             }
-            OrderManagement::Binance(_) => Err(()),
         }
+        for _ in 0..20 {
+            generator.fill_stats.record_fill(best_distance);
+        }
+        assert!(generator.fill_probability(best_distance) > 0.9);
+
+        generator.set_fill_weighting(true);
+        let orders = generator.generate_quotes("BTCUSDT".to_string(), &book, 0.5, 1.0, 0.0);
+        let (_, weighted_qty) = best_bid(&orders);
+        assert!(weighted_qty > unweighted_qty);
+    }
+
+    #[test]
+    fn test_spread_schedule_multiplier() {
+        let mut generator = test_generator(3);
+        generator.set_spread(20.0);
+        generator.set_spread_schedule(vec![(2, 4, 2.0), (22, 1, 1.5)]);
+
+        // 03:15 UTC falls in the first window.
+        generator.clock = || 3 * 3_600_000 + 15 * 60_000;
+        assert_eq!(generator.min_profitable_spread(), 40.0);
+
+        // 23:00 UTC falls in the window wrapping around midnight.
+        generator.clock = || 23 * 3_600_000;
+        assert_eq!(generator.min_profitable_spread(), 30.0);
+
+        // 12:00 UTC is outside of every window.
+        generator.clock = || 12 * 3_600_000;
+        assert_eq!(generator.min_profitable_spread(), 20.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_aggression_bounds_outside_unit_range() {
+        let mut generator = test_generator(3);
+        generator.set_aggression(0.2, 1.5);
     }
 }
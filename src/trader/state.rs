@@ -0,0 +1,39 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// The state of a symbol kept across restarts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SymbolState {
+    /// The signed position notional in USD.
+    pub position: f64,
+    /// The signed position quantity.
+    pub position_qty: f64,
+    pub avg_entry_price: f64,
+    pub realized_pnl: f64,
+    /// The captured spread in bps summed over the matched quantity.
+    pub captured_bps: f64,
+    pub captured_qty: f64,
+}
+
+/// Loads the state of each symbol from the file at `path`.
+///
+/// A missing file yields no state, so the first run starts flat.
+pub fn load_state(path: &str) -> io::Result<HashMap<String, SymbolState>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(io::Error::from),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the state of each symbol to the file at `path`.
+///
+/// The state is written to a temporary file first and moved over the previous one, so a crash
+/// while saving never leaves a truncated file behind.
+pub fn save_state(path: &str, state: &HashMap<String, SymbolState>) -> io::Result<()> {
+    let mut tmp = Path::new(path).as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&tmp, path)
+}